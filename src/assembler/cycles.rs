@@ -0,0 +1,249 @@
+//! 6502 Cycle Timing
+//!
+//! Cycle counts for the NMOS 6502, derived from the addressing mode and the
+//! kind of memory access an instruction performs. Penalties that depend on
+//! runtime values (page crossings, taken branches) are reported separately.
+//!
+//! The undocumented NMOS opcodes take as long as the documented instruction
+//! with the same kind of access; DCP and ISC in the indirect modes take 8.
+//!
+//! 65C02 instructions, including the W65C02's bit instructions, are timed
+//! from the WDC datasheet. Instructions the two CPUs share keep their NMOS
+//! timing apart from `JMP (addr)`, which takes one more cycle on the 65C02.
+//! The 65C02 is never slower otherwise, except for the extra cycle ADC and
+//! SBC take in decimal mode.
+//!
+//! 65816 timings are for 8-bit registers in bank 0 with the direct page at
+//! $0000; [`wide_penalty`] adds the cycles 16-bit registers cost.
+
+use super::{AddressingMode, Cpu};
+use AddressingMode::*;
+
+/// Timing of a single instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycles {
+    /// Cycles when no penalty applies
+    pub base: u8,
+    /// One extra cycle when indexing crosses a page boundary
+    pub page_penalty: bool,
+    /// Conditional branch: one extra cycle when taken, another when the
+    /// target is on a different page
+    pub branch: bool,
+}
+
+impl Cycles {
+    const fn fixed(base: u8) -> Self {
+        Self {
+            base,
+            page_penalty: false,
+            branch: false,
+        }
+    }
+
+    /// Worst-case cycle count
+    pub fn max(&self) -> u8 {
+        if self.branch {
+            self.base + 2
+        } else {
+            self.base + self.page_penalty as u8
+        }
+    }
+}
+
+/// How an instruction accesses its operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+fn access(mnemonic: &str) -> Access {
+    match mnemonic {
+        "STA" | "STX" | "STY" | "STZ" | "SAX" => Access::Write,
+        "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" | "TSB" | "TRB" | "DCP" | "ISC" => {
+            Access::ReadModifyWrite
+        }
+        _ if mnemonic.starts_with("RMB") || mnemonic.starts_with("SMB") => Access::ReadModifyWrite,
+        _ => Access::Read,
+    }
+}
+
+/// Cycle timing of an instruction, or None for an invalid combination
+pub fn cycles(mnemonic: &str, mode: AddressingMode) -> Option<Cycles> {
+    if !Cpu::any_has_mode(mnemonic, mode) {
+        return None;
+    }
+
+    let timing = match (mode, access(mnemonic)) {
+        (Implied, _) => Cycles::fixed(match mnemonic {
+            "PHA" | "PHP" | "PHX" | "PHY" | "PHB" | "PHK" | "XBA" | "WAI" | "STP" => 3,
+            "PLA" | "PLP" | "PLX" | "PLY" | "PLB" | "PHD" => 4,
+            "PLD" => 5,
+            "RTS" | "RTI" | "RTL" => 6,
+            "BRK" => 7,
+            _ => 2,
+        }),
+        (Immediate, _) if mnemonic == "REP" || mnemonic == "SEP" => Cycles::fixed(3),
+        (Immediate, _) if mnemonic == "COP" => Cycles::fixed(7),
+        (Accumulator | Immediate, _) => Cycles::fixed(2),
+        // BRA is always taken, so its page crossing is the only penalty
+        (Relative, _) if mnemonic == "BRA" => Cycles {
+            base: 3,
+            page_penalty: true,
+            branch: false,
+        },
+        (Relative, _) => Cycles {
+            base: 2,
+            page_penalty: false,
+            branch: true,
+        },
+        (ZeroPageRelative, _) => Cycles {
+            base: 5,
+            page_penalty: false,
+            branch: true,
+        },
+        (RelativeLong, _) if mnemonic == "PER" => Cycles::fixed(6),
+        (RelativeLong, _) => Cycles::fixed(4),
+        (ZeroPage, Access::ReadModifyWrite) => Cycles::fixed(5),
+        (ZeroPage, _) => Cycles::fixed(3),
+        (ZeroPageX | ZeroPageY, Access::ReadModifyWrite) => Cycles::fixed(6),
+        (ZeroPageX | ZeroPageY, _) => Cycles::fixed(4),
+        (Absolute, _) if mnemonic == "JMP" => Cycles::fixed(3),
+        (Absolute, _) if mnemonic == "JSR" => Cycles::fixed(6),
+        (Absolute, _) if mnemonic == "PEA" => Cycles::fixed(5),
+        (Absolute, Access::ReadModifyWrite) => Cycles::fixed(6),
+        (Absolute, _) => Cycles::fixed(4),
+        (AbsoluteX | AbsoluteY, Access::ReadModifyWrite) => Cycles::fixed(7),
+        (AbsoluteX | AbsoluteY, Access::Write) => Cycles::fixed(5),
+        (AbsoluteX | AbsoluteY, Access::Read) => Cycles {
+            base: 4,
+            page_penalty: true,
+            branch: false,
+        },
+        (ZeroPageIndirect, _) if mnemonic == "PEI" => Cycles::fixed(6),
+        (Indirect | ZeroPageIndirect, _) => Cycles::fixed(5),
+        (AbsoluteIndexedIndirect, _) if mnemonic == "JSR" => Cycles::fixed(8),
+        (IndexedIndirect, Access::ReadModifyWrite) => Cycles::fixed(8),
+        (IndexedIndirect | AbsoluteIndexedIndirect, _) => Cycles::fixed(6),
+        (IndirectIndexed, Access::Read) => Cycles {
+            base: 5,
+            page_penalty: true,
+            branch: false,
+        },
+        (IndirectIndexed, Access::ReadModifyWrite) => Cycles::fixed(8),
+        (IndirectIndexed, _) => Cycles::fixed(6),
+        (StackRelative, _) => Cycles::fixed(4),
+        (StackRelativeIndirectIndexed, _) => Cycles::fixed(7),
+        (DirectIndirectLong | DirectIndirectLongIndexed, _) => Cycles::fixed(6),
+        (AbsoluteLong, _) if mnemonic == "JSL" => Cycles::fixed(8),
+        (AbsoluteLong, _) if mnemonic == "JML" => Cycles::fixed(4),
+        (AbsoluteLong | AbsoluteLongX, _) => Cycles::fixed(5),
+        (AbsoluteIndirectLong, _) => Cycles::fixed(6),
+        // Per byte moved; the instruction repeats until the count runs out
+        (BlockMove, _) => Cycles::fixed(7),
+    };
+    Some(timing)
+}
+
+/// Cycle timing of an opcode byte on the given CPU
+pub fn opcode_cycles(cpu: Cpu, code: u8) -> Option<Cycles> {
+    let op = cpu.decode(code)?;
+    let mut timing = cycles(op.mnemonic, op.mode)?;
+    if cpu.has_cmos_instructions() && !cpu.has_native_mode() && op.mode == Indirect {
+        // The 65C02 fixed the page-wrap bug at the cost of a cycle
+        timing.base += 1;
+    }
+    Some(timing)
+}
+
+/// Extra cycles a 65816 instruction takes while the register its operand
+/// follows is 16-bit: one per extra byte read or written, two for
+/// read-modify-write
+pub fn wide_penalty(mnemonic: &str, mode: AddressingMode, accumulator: bool, index: bool) -> u8 {
+    let wide = match mnemonic {
+        "LDX" | "LDY" | "STX" | "STY" | "CPX" | "CPY" | "PHX" | "PHY" | "PLX" | "PLY" => index,
+        "LDA" | "STA" | "STZ" | "ADC" | "SBC" | "AND" | "ORA" | "EOR" | "CMP" | "BIT" | "TSB"
+        | "TRB" | "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" | "PHA" | "PLA" => accumulator,
+        _ => false,
+    };
+    match (mode, access(mnemonic)) {
+        _ if !wide => 0,
+        (Accumulator, _) => 0,
+        (_, Access::ReadModifyWrite) => 2,
+        _ => 1,
+    }
+}
+
+/// Whether two addresses are on different 256-byte pages
+pub fn crosses_page(a: u16, b: u16) -> bool {
+    a & 0xFF00 != b & 0xFF00
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_timings() {
+        assert_eq!(cycles("LDA", Immediate).unwrap().base, 2);
+        assert_eq!(cycles("LDA", ZeroPage).unwrap().base, 3);
+        assert_eq!(cycles("INC", ZeroPageX).unwrap().base, 6);
+        assert_eq!(cycles("JSR", Absolute).unwrap().base, 6);
+        assert_eq!(cycles("JMP", Indirect).unwrap().base, 5);
+        assert_eq!(cycles("BRK", Implied).unwrap().base, 7);
+        assert_eq!(cycles("PLA", Implied).unwrap().base, 4);
+        assert!(cycles("STA", Immediate).is_none());
+        assert_eq!(cycles("STZ", AbsoluteX).unwrap().base, 5);
+        assert_eq!(cycles("TSB", ZeroPage).unwrap().base, 5);
+        assert_eq!(cycles("PLX", Implied).unwrap().base, 4);
+        assert_eq!(cycles("JMP", AbsoluteIndexedIndirect).unwrap().base, 6);
+        assert_eq!(cycles("SAX", ZeroPageY).unwrap().base, 4);
+        assert_eq!(cycles("DCP", AbsoluteY).unwrap().base, 7);
+        assert_eq!(cycles("ISC", IndirectIndexed).unwrap().base, 8);
+        assert!(cycles("LAX", AbsoluteY).unwrap().page_penalty);
+        assert_eq!(cycles("SMB3", ZeroPage).unwrap().base, 5);
+        let bbr = cycles("BBR0", ZeroPageRelative).unwrap();
+        assert_eq!((bbr.base, bbr.branch, bbr.max()), (5, true, 7));
+    }
+
+    #[test]
+    fn test_penalties() {
+        let lda = cycles("LDA", AbsoluteX).unwrap();
+        assert_eq!((lda.base, lda.page_penalty, lda.max()), (4, true, 5));
+        assert!(!cycles("STA", AbsoluteX).unwrap().page_penalty);
+        assert_eq!(cycles("STA", IndirectIndexed).unwrap().base, 6);
+        assert_eq!(cycles("ASL", AbsoluteX).unwrap().base, 7);
+
+        let bne = opcode_cycles(Cpu::Nmos6502, 0xD0).unwrap();
+        assert!(bne.branch);
+        assert_eq!(bne.max(), 4);
+        assert!(crosses_page(0x80FE, 0x8101));
+        assert!(!crosses_page(0x8000, 0x80FF));
+
+        let bra = opcode_cycles(Cpu::Cmos65C02, 0x80).unwrap();
+        assert_eq!((bra.base, bra.branch, bra.max()), (3, false, 4));
+        assert_eq!(opcode_cycles(Cpu::Nmos6502, 0x80), None);
+    }
+
+    #[test]
+    fn test_65816_timings() {
+        assert_eq!(cycles("LDA", StackRelative).unwrap().base, 4);
+        assert_eq!(cycles("STA", AbsoluteLongX).unwrap().base, 5);
+        assert_eq!(cycles("JSL", AbsoluteLong).unwrap().base, 8);
+        assert_eq!(cycles("REP", Immediate).unwrap().base, 3);
+        assert_eq!(opcode_cycles(Cpu::W65C816, 0x6C).unwrap().base, 5);
+        assert_eq!(wide_penalty("LDA", Immediate, true, false), 1);
+        assert_eq!(wide_penalty("INC", Absolute, true, false), 2);
+        assert_eq!(wide_penalty("INC", Accumulator, true, true), 0);
+        assert_eq!(wide_penalty("LDX", Absolute, true, false), 0);
+        assert_eq!(wide_penalty("PHY", Implied, false, true), 1);
+    }
+
+    #[test]
+    fn test_every_opcode_has_timing() {
+        for op in Cpu::ALL.into_iter().flat_map(Cpu::opcodes) {
+            assert!(cycles(op.mnemonic, op.mode).is_some(), "{}", op.mnemonic);
+        }
+    }
+}
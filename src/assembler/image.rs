@@ -0,0 +1,192 @@
+//! Binary Image Formats
+//!
+//! Writes an [`Assembly`] as a raw ROM image, Intel HEX, Motorola S-records
+//! or a Commodore `.prg`. The record formats only carry the memory sections
+//! (from `wraith.toml`) that hold assembled bytes, each from its first to its
+//! last byte, so a programmer never writes the empty space between sections.
+
+use super::Assembly;
+use crate::config::Section;
+
+/// Bytes of data per Intel HEX or S-record line
+const RECORD_SIZE: usize = 16;
+
+/// Value of bytes the program never wrote ($FF matches erased EPROM)
+pub const FILL: u8 = 0xFF;
+
+/// Supported image formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Raw bytes from the lowest to the highest assembled address
+    Raw,
+    /// Intel HEX records
+    IntelHex,
+    /// Motorola S-records (S1 data, S9 start address)
+    SRecord,
+    /// Commodore program file: a little-endian load address, then the raw image
+    Prg,
+}
+
+impl ImageFormat {
+    /// Parse a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" | "bin" => Some(ImageFormat::Raw),
+            "ihex" | "hex" => Some(ImageFormat::IntelHex),
+            "srec" | "s19" => Some(ImageFormat::SRecord),
+            "prg" => Some(ImageFormat::Prg),
+            _ => None,
+        }
+    }
+
+    /// File extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Raw => "bin",
+            ImageFormat::IntelHex => "hex",
+            ImageFormat::SRecord => "srec",
+            ImageFormat::Prg => "prg",
+        }
+    }
+
+    /// Render an assembled program; `name` goes in the S-record header
+    pub fn write(self, assembly: &Assembly, sections: &[Section], name: &str) -> Vec<u8> {
+        match self {
+            ImageFormat::Raw => assembly.to_binary(FILL),
+            ImageFormat::IntelHex => write_intel_hex(&regions(assembly, sections)).into_bytes(),
+            ImageFormat::SRecord => {
+                write_srecords(&regions(assembly, sections), name, entry_point(assembly))
+                    .into_bytes()
+            }
+            ImageFormat::Prg => {
+                let load = assembly.start_address().unwrap_or(0);
+                let mut image = load.to_le_bytes().to_vec();
+                image.extend(assembly.to_binary(FILL));
+                image
+            }
+        }
+    }
+}
+
+/// A contiguous block of the image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub data: Vec<u8>,
+}
+
+/// Assembled bytes grouped by memory section
+///
+/// Each section holding bytes becomes one region from its first to its last
+/// assembled byte, with gaps filled. Bytes outside every section (the vector
+/// table, for instance) keep their own segments. Regions are sorted by address.
+pub fn regions(assembly: &Assembly, sections: &[Section]) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let mut outside = Vec::new();
+
+    for section in sections {
+        let mut span: Option<(u16, u16)> = None;
+        for segment in assembly.segments.iter().filter(|s| !s.data.is_empty()) {
+            let first = segment.start.max(section.start);
+            let last = ((segment.end() - 1) as u16).min(section.end);
+            if first <= last {
+                span = Some(span.map_or((first, last), |(start, end)| {
+                    (start.min(first), end.max(last))
+                }));
+            }
+        }
+        if let Some((first, last)) = span {
+            let data = (first..=last)
+                .map(|addr| assembly.read(addr).unwrap_or(FILL))
+                .collect();
+            regions.push(Region { start: first, data });
+        }
+    }
+
+    // Runs of bytes that no section covers
+    for segment in &assembly.segments {
+        let mut run: Option<Region> = None;
+        for (offset, &byte) in segment.data.iter().enumerate() {
+            let addr = segment.start + offset as u16;
+            if sections.iter().any(|section| section.contains(addr)) {
+                outside.extend(run.take());
+            } else {
+                run.get_or_insert_with(|| Region {
+                    start: addr,
+                    data: Vec::new(),
+                })
+                .data
+                .push(byte);
+            }
+        }
+        outside.extend(run);
+    }
+
+    regions.extend(outside);
+    regions.sort_by_key(|region| region.start);
+    regions
+}
+
+/// The reset vector, if the program set one
+fn entry_point(assembly: &Assembly) -> u16 {
+    match (assembly.read(0xFFFC), assembly.read(0xFFFD)) {
+        (Some(lo), Some(hi)) => u16::from_le_bytes([lo, hi]),
+        _ => 0,
+    }
+}
+
+/// Split regions into (address, bytes) records of at most [`RECORD_SIZE`] bytes
+fn records(regions: &[Region]) -> impl Iterator<Item = (u16, &[u8])> {
+    regions.iter().flat_map(|region| {
+        region
+            .data
+            .chunks(RECORD_SIZE)
+            .enumerate()
+            .map(|(i, chunk)| (region.start.wrapping_add((i * RECORD_SIZE) as u16), chunk))
+    })
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Intel HEX: `:` count, address, type, data, two's complement checksum
+pub fn write_intel_hex(regions: &[Region]) -> String {
+    let line = |addr: u16, kind: u8, data: &[u8]| {
+        let [hi, lo] = addr.to_be_bytes();
+        let mut bytes = vec![data.len() as u8, hi, lo, kind];
+        bytes.extend_from_slice(data);
+        let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        format!(":{}{:02X}\n", hex_bytes(&bytes), sum.wrapping_neg())
+    };
+
+    let mut out = String::new();
+    for (addr, data) in records(regions) {
+        out.push_str(&line(addr, 0x00, data));
+    }
+    out.push_str(&line(0, 0x01, &[]));
+    out
+}
+
+/// Motorola S-records: an S0 header, S1 data, S5 record count and S9 start address
+pub fn write_srecords(regions: &[Region], name: &str, entry: u16) -> String {
+    let line = |kind: char, addr: u16, data: &[u8]| {
+        let [hi, lo] = addr.to_be_bytes();
+        let mut bytes = vec![data.len() as u8 + 3, hi, lo];
+        bytes.extend_from_slice(data);
+        let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        format!("S{}{}{:02X}\n", kind, hex_bytes(&bytes), !sum)
+    };
+
+    // The header is free text; keep it short enough for any loader
+    let header = &name.as_bytes()[..name.len().min(32)];
+    let mut out = line('0', 0, header);
+    let mut count = 0u16;
+    for (addr, data) in records(regions) {
+        out.push_str(&line('1', addr, data));
+        count += 1;
+    }
+    out.push_str(&line('5', count, &[]));
+    out.push_str(&line('9', entry, &[]));
+    out
+}
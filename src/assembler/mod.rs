@@ -0,0 +1,903 @@
+//! Built-in 6502 Assembler
+//!
+//! Turns the assembly text produced by the code generator into machine code,
+//! so a ROM image can be written without an external assembler.
+//!
+//! Assembly runs in two passes: the first assigns an address to every label,
+//! the second encodes instructions with all symbols resolved. Instruction
+//! sizes depend only on operand syntax (symbols are always 16-bit, `$XX`
+//! literals are zero page), so sizes computed before layout are exact.
+//! On the 65816 an immediate also depends on the register width, which is
+//! followed through `REP`/`SEP` with constant operands the way ca65's
+//! `.smart` mode does, or set with `.A8`/`.A16`/`.I8`/`.I16`.
+//!
+//! Only documented NMOS 6502 instructions are accepted until a `.SETCPU` line
+//! enables the 65C02 additions (`"65C02"`), those plus the Rockwell/WDC bit
+//! instructions (`"W65C02"`), the 65816's native-mode set (`"65816"`), or the
+//! stable undocumented NMOS opcodes (`"6502X"`).
+
+pub mod cycles;
+pub mod image;
+pub mod opcodes;
+pub mod parse;
+
+pub use opcodes::{AddressingMode, Cpu};
+
+use parse::{DataItem, Directive, Expr, Index, Operand, ParsedLine, Statement};
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+/// An error found while assembling, with the 1-based source line
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A contiguous run of assembled bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start: u16,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// Address one past the last byte (may be $10000)
+    pub fn end(&self) -> u32 {
+        self.start as u32 + self.data.len() as u32
+    }
+}
+
+/// The bytes produced by one source line
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembledLine {
+    /// 1-based line in the assembly source
+    pub line: usize,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// Register widths on the 65816, as far as the assembler can follow them
+///
+/// Both start at 8 bits, and `.SETCPU` resets them. They only decide the
+/// size of immediates; switching to native mode is up to the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RegisterWidths {
+    /// 16-bit accumulator and memory accesses (M flag clear)
+    pub accumulator: bool,
+    /// 16-bit X and Y (X flag clear)
+    pub index: bool,
+}
+
+impl RegisterWidths {
+    /// Follow a `REP`/`SEP` with a constant operand; other instructions change nothing
+    pub fn follow(&mut self, mnemonic: &str, operand: &str) {
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        if let Ok(operand) = parse::parse_instruction_operand(&mnemonic, operand) {
+            self.update(&Statement::Instruction { mnemonic, operand });
+        }
+    }
+
+    /// Size in bytes of an instruction of any supported CPU at these widths
+    pub fn instruction_size(self, mnemonic: &str, operand: &str) -> Result<u16, String> {
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let operand = parse::parse_instruction_operand(&mnemonic, operand)?;
+        let mode = resolve_mode_any(&mnemonic, &operand)?;
+        Ok(mode.instruction_size() + self.extra_immediate_byte(&mnemonic, mode))
+    }
+
+    fn update(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Instruction {
+                mnemonic,
+                operand: Operand::Immediate(bits),
+            } if mnemonic == "REP" || mnemonic == "SEP" => {
+                let Ok(bits) = bits.eval(&HashMap::default(), 0) else {
+                    return;
+                };
+                let wide = mnemonic == "REP";
+                if bits & 0x20 != 0 {
+                    self.accumulator = wide;
+                }
+                if bits & 0x10 != 0 {
+                    self.index = wide;
+                }
+            }
+            Statement::Directive(Directive::RegisterWidth { index: true, wide }) => {
+                self.index = *wide;
+            }
+            Statement::Directive(Directive::RegisterWidth { index: false, wide }) => {
+                self.accumulator = *wide;
+            }
+            Statement::Directive(Directive::SetCpu(_)) => *self = Self::default(),
+            _ => {}
+        }
+    }
+
+    /// Whether an immediate operand of the instruction is 16-bit
+    pub fn wide_immediate(self, mnemonic: &str) -> bool {
+        match mnemonic {
+            "ADC" | "AND" | "BIT" | "CMP" | "EOR" | "LDA" | "ORA" | "SBC" => self.accumulator,
+            "CPX" | "CPY" | "LDX" | "LDY" => self.index,
+            _ => false,
+        }
+    }
+
+    fn extra_immediate_byte(self, mnemonic: &str, mode: AddressingMode) -> u16 {
+        (mode == AddressingMode::Immediate && self.wide_immediate(mnemonic)) as u16
+    }
+}
+
+/// Result of assembling a program
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    /// Assembled bytes, one segment per `.ORG` block
+    pub segments: Vec<Segment>,
+    /// Final value of every label and equate
+    pub symbols: HashMap<String, u16>,
+    /// Every line that produced bytes, in source order
+    pub lines: Vec<AssembledLine>,
+    /// Instruction set selected by the last `.SETCPU`
+    pub cpu: Cpu,
+}
+
+impl Assembly {
+    /// Lowest address containing assembled bytes
+    pub fn start_address(&self) -> Option<u16> {
+        self.segments.iter().map(|s| s.start).min()
+    }
+
+    /// Highest address containing assembled bytes
+    pub fn end_address(&self) -> Option<u16> {
+        self.segments.iter().map(|s| (s.end() - 1) as u16).max()
+    }
+
+    /// Total number of assembled bytes
+    pub fn size(&self) -> usize {
+        self.segments.iter().map(|s| s.data.len()).sum()
+    }
+
+    /// Read an assembled byte, if the address was written
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        self.segments.iter().find_map(|s| {
+            let offset = addr.checked_sub(s.start)? as usize;
+            s.data.get(offset).copied()
+        })
+    }
+
+    /// Look up the address of a label or equate
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Flatten into a raw image spanning the lowest to highest written address
+    ///
+    /// Gaps between segments are filled with `fill` ($FF matches erased EPROM).
+    pub fn to_binary(&self, fill: u8) -> Vec<u8> {
+        let (Some(start), Some(end)) = (self.start_address(), self.end_address()) else {
+            return Vec::new();
+        };
+        let mut image = vec![fill; (end - start) as usize + 1];
+        for segment in &self.segments {
+            let offset = (segment.start - start) as usize;
+            image[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        image
+    }
+}
+
+/// Assemble a complete program
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let lines = parse_source(source)?;
+    let symbols = assign_addresses(&lines)?;
+    let (segments, assembled_lines) = encode(&lines, &symbols)?;
+    check_overlaps(&segments)?;
+    let cpu = lines
+        .iter()
+        .rev()
+        .find_map(|(_, line)| match &line.statement {
+            Some(Statement::Directive(Directive::SetCpu(cpu))) => Some(*cpu),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    Ok(Assembly {
+        segments,
+        lines: assembled_lines,
+        cpu,
+        symbols: symbols
+            .into_iter()
+            .map(|(name, value)| (name, value as u16))
+            .collect(),
+    })
+}
+
+/// Exact size in bytes of a fragment of code or data
+///
+/// Labels need not be defined; `.ORG` directives are ignored. Instructions of
+/// any supported CPU are accepted.
+pub fn code_size(source: &str) -> Result<u16, AsmError> {
+    let lines = parse_source(source)?;
+    let no_symbols = HashMap::default();
+    let mut widths = RegisterWidths::default();
+    let mut total: u32 = 0;
+    for (line_no, line) in &lines {
+        if let Some(stmt) = &line.statement
+            && !matches!(stmt, Statement::Directive(Directive::Org(_)))
+        {
+            total += statement_size(stmt, None, widths, &no_symbols, 0, *line_no)? as u32;
+            widths.update(stmt);
+        }
+    }
+    u16::try_from(total).map_err(|_| AsmError::new(0, "code larger than 64K"))
+}
+
+/// Symbols referenced by a program but never defined in it, sorted by name
+///
+/// In ca65 output these are the names that must be `.import`ed.
+pub fn undefined_symbols(source: &str) -> Result<Vec<String>, AsmError> {
+    let lines = parse_source(source)?;
+    let mut defined: HashSet<&str> = HashSet::default();
+    let mut referenced: HashSet<&str> = HashSet::default();
+    for (_, line) in &lines {
+        defined.extend(line.labels.iter().map(String::as_str));
+        let exprs: Vec<&Expr> = match &line.statement {
+            None => Vec::new(),
+            Some(Statement::Equate { name, value }) => {
+                defined.insert(name);
+                vec![value]
+            }
+            Some(Statement::Instruction { operand, .. }) => match operand {
+                Operand::None | Operand::Accumulator => Vec::new(),
+                Operand::Immediate(e)
+                | Operand::Direct(e, _)
+                | Operand::Indirect(e)
+                | Operand::IndexedIndirect(e)
+                | Operand::IndirectIndexed(e)
+                | Operand::StackIndirectIndexed(e)
+                | Operand::IndirectLong(e)
+                | Operand::IndirectLongIndexed(e)
+                | Operand::Long(e, _) => vec![e],
+                Operand::BitBranch(first, second) | Operand::BlockMove(first, second) => {
+                    vec![first, second]
+                }
+            },
+            Some(Statement::Directive(directive)) => match directive {
+                Directive::Org(e) => vec![e],
+                Directive::Byte(items) => items
+                    .iter()
+                    .filter_map(|item| match item {
+                        DataItem::Expr(e) => Some(e),
+                        DataItem::String(_) => None,
+                    })
+                    .collect(),
+                Directive::Word(values) => values.iter().collect(),
+                Directive::Res(count, fill) => std::iter::once(count).chain(fill).collect(),
+                Directive::Import(names) => {
+                    defined.extend(names.iter().map(String::as_str));
+                    Vec::new()
+                }
+                Directive::Segment(_)
+                | Directive::Export(_)
+                | Directive::SetCpu(_)
+                | Directive::RegisterWidth { .. }
+                | Directive::Smart => Vec::new(),
+            },
+        };
+        for expr in exprs {
+            referenced.extend(expr.symbols());
+        }
+    }
+
+    let mut undefined: Vec<String> = referenced
+        .difference(&defined)
+        .map(|name| name.to_string())
+        .collect();
+    undefined.sort();
+    Ok(undefined)
+}
+
+/// Size in bytes of a single instruction of any supported CPU, with 8-bit registers
+pub fn instruction_size(mnemonic: &str, operand: &str) -> Result<u16, String> {
+    RegisterWidths::default().instruction_size(mnemonic, operand)
+}
+
+fn parse_source(source: &str) -> Result<Vec<(usize, ParsedLine)>, AsmError> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| {
+            parse::parse_line(text)
+                .map(|line| (i + 1, line))
+                .map_err(|msg| AsmError::new(i + 1, msg))
+        })
+        .collect()
+}
+
+/// Choose the addressing mode for an instruction from its operand syntax
+pub fn resolve_mode(
+    cpu: Cpu,
+    mnemonic: &str,
+    operand: &Operand,
+) -> Result<AddressingMode, String> {
+    use AddressingMode::*;
+
+    if !cpu.is_mnemonic(mnemonic) {
+        return Err(
+            match Cpu::ALL
+                .into_iter()
+                .find(|other| other.is_mnemonic(mnemonic))
+            {
+                Some(other) => format!("{} requires .SETCPU \"{}\"", mnemonic, other.name()),
+                None => format!("unknown instruction '{}'", mnemonic),
+            },
+        );
+    }
+    let pick = |short: AddressingMode, long: AddressingMode, expr: &Expr| {
+        if expr.is_zero_page() && cpu.has_mode(mnemonic, short) {
+            short
+        } else {
+            long
+        }
+    };
+
+    let mode = match operand {
+        Operand::None if cpu.has_mode(mnemonic, Implied) => Implied,
+        Operand::None if !cpu.has_mode(mnemonic, Accumulator) => {
+            return Err(format!("{} requires an operand", mnemonic));
+        }
+        Operand::None | Operand::Accumulator => Accumulator,
+        Operand::Immediate(_) => Immediate,
+        Operand::Direct(_, None) if cpu.has_mode(mnemonic, Relative) => Relative,
+        Operand::Direct(_, None) if cpu.has_mode(mnemonic, RelativeLong) => RelativeLong,
+        // JSL and JML only take 24-bit addresses
+        Operand::Direct(expr, None)
+            if expr.is_long()
+                || (!cpu.has_mode(mnemonic, Absolute) && cpu.has_mode(mnemonic, AbsoluteLong)) =>
+        {
+            AbsoluteLong
+        }
+        Operand::Direct(expr, None) => pick(ZeroPage, Absolute, expr),
+        Operand::Direct(expr, Some(Index::X)) if expr.is_long() => AbsoluteLongX,
+        Operand::Direct(expr, Some(Index::X)) => pick(ZeroPageX, AbsoluteX, expr),
+        Operand::Direct(expr, Some(Index::Y)) => pick(ZeroPageY, AbsoluteY, expr),
+        Operand::Direct(_, Some(Index::S)) => StackRelative,
+        Operand::Long(_, None) => AbsoluteLong,
+        Operand::Long(..) => AbsoluteLongX,
+        Operand::Indirect(expr) => pick(ZeroPageIndirect, Indirect, expr),
+        Operand::IndexedIndirect(_) if cpu.has_mode(mnemonic, AbsoluteIndexedIndirect) => {
+            AbsoluteIndexedIndirect
+        }
+        Operand::IndexedIndirect(_) => IndexedIndirect,
+        Operand::IndirectIndexed(_) => IndirectIndexed,
+        Operand::BitBranch(..) => ZeroPageRelative,
+        Operand::StackIndirectIndexed(_) => StackRelativeIndirectIndexed,
+        Operand::IndirectLong(_) if cpu.has_mode(mnemonic, AbsoluteIndirectLong) => {
+            AbsoluteIndirectLong
+        }
+        Operand::IndirectLong(_) => DirectIndirectLong,
+        Operand::IndirectLongIndexed(_) => DirectIndirectLongIndexed,
+        Operand::BlockMove(..) => BlockMove,
+    };
+
+    if cpu.has_mode(mnemonic, mode) {
+        Ok(mode)
+    } else {
+        Err(format!(
+            "{} does not support {:?} addressing",
+            mnemonic, mode
+        ))
+    }
+}
+
+/// Addressing mode on the first CPU that accepts the instruction
+fn resolve_mode_any(mnemonic: &str, operand: &Operand) -> Result<AddressingMode, String> {
+    let mut first_error = None;
+    for cpu in Cpu::ALL {
+        match resolve_mode(cpu, mnemonic, operand) {
+            Ok(mode) => return Ok(mode),
+            Err(msg) if cpu.is_mnemonic(mnemonic) => {
+                first_error.get_or_insert(msg);
+            }
+            Err(_) => {}
+        }
+    }
+    Err(first_error.unwrap_or_else(|| format!("unknown instruction '{}'", mnemonic)))
+}
+
+/// Size of a statement; `.RES` counts must already be resolvable
+///
+/// Without a CPU, instructions of any supported CPU are sized.
+fn statement_size(
+    stmt: &Statement,
+    cpu: Option<Cpu>,
+    widths: RegisterWidths,
+    symbols: &HashMap<String, i64>,
+    pc: u16,
+    line_no: usize,
+) -> Result<u16, AsmError> {
+    match stmt {
+        Statement::Instruction { mnemonic, operand } => match cpu {
+            Some(cpu) => resolve_mode(cpu, mnemonic, operand),
+            None => resolve_mode_any(mnemonic, operand),
+        }
+        .map(|mode| mode.instruction_size() + widths.extra_immediate_byte(mnemonic, mode))
+        .map_err(|msg| AsmError::new(line_no, msg)),
+        Statement::Directive(Directive::Byte(items)) => Ok(items
+            .iter()
+            .map(|item| match item {
+                DataItem::Expr(_) => 1,
+                DataItem::String(bytes) => bytes.len() as u16,
+            })
+            .sum()),
+        Statement::Directive(Directive::Word(values)) => Ok(values.len() as u16 * 2),
+        Statement::Directive(Directive::Res(count, _)) => {
+            let count = eval(count, symbols, pc, line_no)?;
+            u16::try_from(count)
+                .map_err(|_| AsmError::new(line_no, format!(".RES count {} out of range", count)))
+        }
+        Statement::Directive(
+            Directive::Org(_)
+            | Directive::Segment(_)
+            | Directive::Export(_)
+            | Directive::Import(_)
+            | Directive::SetCpu(_)
+            | Directive::RegisterWidth { .. }
+            | Directive::Smart,
+        )
+        | Statement::Equate { .. } => Ok(0),
+    }
+}
+
+fn eval(expr: &Expr, symbols: &HashMap<String, i64>, pc: u16, line_no: usize) -> Result<i64, AsmError> {
+    expr.eval(symbols, pc)
+        .map_err(|name| AsmError::new(line_no, format!("undefined symbol '{}'", name)))
+}
+
+fn define(
+    symbols: &mut HashMap<String, i64>,
+    name: &str,
+    value: i64,
+    line_no: usize,
+) -> Result<(), AsmError> {
+    if symbols.insert(name.to_string(), value).is_some() {
+        return Err(AsmError::new(line_no, format!("duplicate symbol '{}'", name)));
+    }
+    Ok(())
+}
+
+/// Pass 1: assign addresses to labels and evaluate equates
+fn assign_addresses(lines: &[(usize, ParsedLine)]) -> Result<HashMap<String, i64>, AsmError> {
+    let mut symbols: HashMap<String, i64> = HashMap::default();
+    let mut pending: Vec<(usize, &str, &Expr)> = Vec::new();
+    let mut pc: u32 = 0;
+    let mut cpu = Cpu::default();
+    let mut widths = RegisterWidths::default();
+
+    for (line_no, line) in lines {
+        let line_no = *line_no;
+        for label in &line.labels {
+            define(&mut symbols, label, pc as i64, line_no)?;
+        }
+        match &line.statement {
+            None => {}
+            Some(Statement::Equate { name, value }) => match value.eval(&symbols, pc as u16) {
+                Ok(v) => define(&mut symbols, name, v, line_no)?,
+                Err(_) => pending.push((line_no, name, value)),
+            },
+            Some(Statement::Directive(Directive::Org(addr))) => {
+                let addr = eval(addr, &symbols, pc as u16, line_no)?;
+                if !(0..=0xFFFF).contains(&addr) {
+                    return Err(AsmError::new(line_no, format!(".ORG ${:X} out of range", addr)));
+                }
+                pc = addr as u32;
+            }
+            Some(Statement::Directive(Directive::SetCpu(selected))) => cpu = *selected,
+            Some(Statement::Directive(Directive::Segment(_) | Directive::Import(_))) => {
+                return Err(AsmError::new(
+                    line_no,
+                    "segments and imports require an external linker (ca65/ld65)",
+                ));
+            }
+            Some(stmt) => {
+                pc += statement_size(stmt, Some(cpu), widths, &symbols, pc as u16, line_no)?
+                    as u32;
+                if pc > 0x10000 {
+                    return Err(AsmError::new(line_no, "program counter overflowed past $FFFF"));
+                }
+            }
+        }
+        if let Some(stmt) = &line.statement {
+            widths.update(stmt);
+        }
+    }
+
+    // Equates that referenced later labels
+    while !pending.is_empty() {
+        let before = pending.len();
+        let mut still_pending = Vec::new();
+        for (line_no, name, value) in pending {
+            match value.eval(&symbols, 0) {
+                Ok(v) => define(&mut symbols, name, v, line_no)?,
+                Err(_) => still_pending.push((line_no, name, value)),
+            }
+        }
+        if still_pending.len() == before {
+            let (line_no, _, value) = still_pending[0];
+            eval(value, &symbols, 0, line_no)?;
+        }
+        pending = still_pending;
+    }
+
+    Ok(symbols)
+}
+
+/// Pass 2: encode every statement into segments
+fn encode(
+    lines: &[(usize, ParsedLine)],
+    symbols: &HashMap<String, i64>,
+) -> Result<(Vec<Segment>, Vec<AssembledLine>), AsmError> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut assembled_lines: Vec<AssembledLine> = Vec::new();
+    let mut current = Segment {
+        start: 0,
+        data: Vec::new(),
+    };
+    let mut cpu = Cpu::default();
+    let mut widths = RegisterWidths::default();
+
+    for (line_no, line) in lines {
+        let line_no = *line_no;
+        let pc = current.start.wrapping_add(current.data.len() as u16);
+        let Some(stmt) = &line.statement else {
+            continue;
+        };
+        let start_len = current.data.len();
+
+        match stmt {
+            Statement::Equate { .. }
+            | Statement::Directive(
+                Directive::Segment(_)
+                | Directive::Export(_)
+                | Directive::Import(_)
+                | Directive::RegisterWidth { .. }
+                | Directive::Smart,
+            ) => {}
+            Statement::Directive(Directive::SetCpu(selected)) => cpu = *selected,
+            Statement::Directive(Directive::Org(addr)) => {
+                let addr = eval(addr, symbols, pc, line_no)? as u16;
+                let finished = std::mem::replace(
+                    &mut current,
+                    Segment {
+                        start: addr,
+                        data: Vec::new(),
+                    },
+                );
+                if !finished.data.is_empty() {
+                    segments.push(finished);
+                }
+            }
+            Statement::Directive(Directive::Byte(items)) => {
+                for item in items {
+                    match item {
+                        DataItem::Expr(expr) => {
+                            let value = eval(expr, symbols, pc, line_no)?;
+                            current.data.push(byte_value(value, line_no)?);
+                        }
+                        DataItem::String(bytes) => current.data.extend_from_slice(bytes),
+                    }
+                }
+            }
+            Statement::Directive(Directive::Word(values)) => {
+                for expr in values {
+                    let value = word_value(eval(expr, symbols, pc, line_no)?, line_no)?;
+                    current.data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Statement::Directive(Directive::Res(count, fill)) => {
+                let count = eval(count, symbols, pc, line_no)? as usize;
+                let fill = match fill {
+                    Some(expr) => byte_value(eval(expr, symbols, pc, line_no)?, line_no)?,
+                    None => 0,
+                };
+                current.data.extend(std::iter::repeat_n(fill, count));
+            }
+            Statement::Instruction { mnemonic, operand } => {
+                let wide = widths.wide_immediate(mnemonic);
+                let out = &mut current.data;
+                encode_instruction(cpu, mnemonic, operand, wide, symbols, pc, line_no, out)?;
+            }
+        }
+        widths.update(stmt);
+
+        // An .ORG starts a fresh segment, so `start_len` only applies to data
+        if current.data.len() > start_len {
+            assembled_lines.push(AssembledLine {
+                line: line_no,
+                address: pc,
+                bytes: current.data[start_len..].to_vec(),
+            });
+        }
+    }
+
+    if !current.data.is_empty() {
+        segments.push(current);
+    }
+    Ok((segments, assembled_lines))
+}
+
+/// Encode one instruction; `wide` makes an immediate operand 16-bit
+#[allow(clippy::too_many_arguments)]
+fn encode_instruction(
+    cpu: Cpu,
+    mnemonic: &str,
+    operand: &Operand,
+    wide: bool,
+    symbols: &HashMap<String, i64>,
+    pc: u16,
+    line_no: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), AsmError> {
+    let mode = resolve_mode(cpu, mnemonic, operand).map_err(|msg| AsmError::new(line_no, msg))?;
+    // resolve_mode only returns modes present in the table
+    let code = cpu.lookup(mnemonic, mode).unwrap_or_default();
+    out.push(code);
+
+    let expr = match operand {
+        Operand::None | Operand::Accumulator => return Ok(()),
+        Operand::BitBranch(zp, target) => {
+            out.push(byte_value(eval(zp, symbols, pc, line_no)?, line_no)?);
+            let target = word_value(eval(target, symbols, pc, line_no)?, line_no)?;
+            out.push(branch_offset(target, pc.wrapping_add(3), line_no)?);
+            return Ok(());
+        }
+        // The object code has the destination bank first
+        Operand::BlockMove(source, destination) => {
+            out.push(byte_value(eval(destination, symbols, pc, line_no)?, line_no)?);
+            out.push(byte_value(eval(source, symbols, pc, line_no)?, line_no)?);
+            return Ok(());
+        }
+        Operand::Immediate(e)
+        | Operand::Direct(e, _)
+        | Operand::Indirect(e)
+        | Operand::IndexedIndirect(e)
+        | Operand::IndirectIndexed(e)
+        | Operand::StackIndirectIndexed(e)
+        | Operand::IndirectLong(e)
+        | Operand::IndirectLongIndexed(e)
+        | Operand::Long(e, _) => e,
+    };
+    let value = eval(expr, symbols, pc, line_no)?;
+
+    match mode {
+        AddressingMode::Relative => {
+            let target = word_value(value, line_no)?;
+            out.push(branch_offset(target, pc.wrapping_add(2), line_no)?);
+        }
+        // BRL and PER reach anywhere in the bank
+        AddressingMode::RelativeLong => {
+            let target = word_value(value, line_no)?;
+            let offset = target.wrapping_sub(pc.wrapping_add(3));
+            out.extend_from_slice(&offset.to_le_bytes());
+        }
+        AddressingMode::AbsoluteLong | AddressingMode::AbsoluteLongX => {
+            if !(0..=0xFF_FFFF).contains(&value) {
+                return Err(AsmError::new(
+                    line_no,
+                    format!("value {} does not fit in 24 bits", value),
+                ));
+            }
+            out.extend_from_slice(&(value as u32).to_le_bytes()[..3]);
+        }
+        AddressingMode::Immediate if wide => {
+            out.extend_from_slice(&word_value(value, line_no)?.to_le_bytes());
+        }
+        _ if mode.operand_size() == 1 => out.push(byte_value(value, line_no)?),
+        _ => out.extend_from_slice(&word_value(value, line_no)?.to_le_bytes()),
+    }
+    Ok(())
+}
+
+/// Signed offset from the instruction that follows a branch to its target
+fn branch_offset(target: u16, next: u16, line_no: usize) -> Result<u8, AsmError> {
+    let offset = target as i64 - next as i64;
+    if !(-128..=127).contains(&offset) {
+        return Err(AsmError::new(
+            line_no,
+            format!("branch target out of range ({} bytes)", offset),
+        ));
+    }
+    Ok(offset as i8 as u8)
+}
+
+fn byte_value(value: i64, line_no: usize) -> Result<u8, AsmError> {
+    if (-128..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(AsmError::new(line_no, format!("value {} does not fit in a byte", value)))
+    }
+}
+
+fn word_value(value: i64, line_no: usize) -> Result<u16, AsmError> {
+    if (-0x8000..=0xFFFF).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(AsmError::new(line_no, format!("value {} does not fit in a word", value)))
+    }
+}
+
+fn check_overlaps(segments: &[Segment]) -> Result<(), AsmError> {
+    let mut sorted: Vec<&Segment> = segments.iter().collect();
+    sorted.sort_by_key(|s| s.start);
+    for pair in sorted.windows(2) {
+        if pair[0].end() > pair[1].start as u32 {
+            return Err(AsmError::new(
+                0,
+                format!(
+                    "code at ${:04X}-${:04X} overlaps code at ${:04X}",
+                    pair[0].start,
+                    pair[0].end() - 1,
+                    pair[1].start
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_forward_and_backward_labels() {
+        let asm = assemble(
+            ".ORG $8000\nstart:\n    LDX #$00\nloop:\n    INX\n    BNE loop\n    JMP done\ndone:\n    RTS\n",
+        )
+        .unwrap();
+        assert_eq!(
+            asm.segments[0].data,
+            vec![0xA2, 0x00, 0xE8, 0xD0, 0xFD, 0x4C, 0x08, 0x80, 0x60]
+        );
+        assert_eq!(asm.symbol("done"), Some(0x8008));
+    }
+
+    #[test]
+    fn test_zero_page_vs_absolute_operands() {
+        let asm = assemble(".ORG $0300\n    LDA $40\n    LDA $0040\n    STA PORT\nPORT = $10\n").unwrap();
+        assert_eq!(
+            asm.segments[0].data,
+            vec![0xA5, 0x40, 0xAD, 0x40, 0x00, 0x8D, 0x10, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_byte_select_and_data_directives() {
+        let asm = assemble(
+            ".ORG $C000\ntable:\n    .BYTE $01, \"AB\"\n    .WORD table, $1234\n    .RES 2, $EA\n    LDA #<table\n    LDY #>table\n",
+        )
+        .unwrap();
+        assert_eq!(
+            asm.segments[0].data,
+            vec![0x01, b'A', b'B', 0x00, 0xC0, 0x34, 0x12, 0xEA, 0xEA, 0xA9, 0x00, 0xA0, 0xC0]
+        );
+    }
+
+    #[test]
+    fn test_branch_out_of_range() {
+        let err = assemble(".ORG $8000\n    BNE far\n    .RES 200\nfar:\n    RTS\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("out of range"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(assemble("    FOO $10\n").unwrap_err().message.contains("unknown instruction"));
+        assert!(assemble("    JMP nowhere\n").unwrap_err().message.contains("undefined symbol"));
+        assert!(assemble("a:\na:\n").unwrap_err().message.contains("duplicate"));
+        assert!(assemble("    LDA ($1234),X\n").is_err());
+        assert!(
+            assemble(".ORG $8000\n    NOP\n    NOP\n.ORG $8001\n    NOP\n")
+                .unwrap_err()
+                .message
+                .contains("overlaps")
+        );
+    }
+
+    #[test]
+    fn test_65c02_instructions_need_setcpu() {
+        let err = assemble(".ORG $8000\n    STZ $40\n").unwrap_err();
+        assert!(err.message.contains(".SETCPU"));
+        assert!(assemble(".ORG $8000\n    LDA ($40)\n").is_err());
+
+        let asm = assemble(
+            ".SETCPU \"65C02\"\n.ORG $8000\nstart:\n    STZ $40\n    LDA ($40)\n    INC A\n    PHX\n    JMP (table,X)\n    BRA start\ntable:\n",
+        )
+        .unwrap();
+        assert_eq!(asm.cpu, Cpu::Cmos65C02);
+        assert_eq!(
+            asm.segments[0].data,
+            vec![0x64, 0x40, 0xB2, 0x40, 0x1A, 0xDA, 0x7C, 0x0B, 0x80, 0x80, 0xF5]
+        );
+    }
+
+    #[test]
+    fn test_bit_instructions() {
+        let source = ".ORG $8000\nstart:\n    SMB3 $40\n    BBS3 $40,start\n    RMB0 $40\n";
+        assert!(assemble(&format!(".SETCPU \"65C02\"\n{}", source)).is_err());
+
+        let asm = assemble(&format!(".SETCPU \"W65C02\"\n{}", source)).unwrap();
+        assert_eq!(
+            asm.segments[0].data,
+            vec![0xB7, 0x40, 0xBF, 0x40, 0xFB, 0x07, 0x40]
+        );
+        assert_eq!(instruction_size("BBR7", "$40,start"), Ok(3));
+    }
+
+    #[test]
+    fn test_65816_instructions() {
+        let err = assemble(".SETCPU \"W65C02\"\n    REP #$20\n").unwrap_err();
+        assert!(err.message.contains(".SETCPU \"65816\""));
+
+        let asm = assemble(
+            ".SETCPU \"65816\"\n.ORG $8000\nstart:\n    REP #$20\n    LDA #$1234\n    LDX #$05\n    SEP #$20\n    LDA #$12\n.A16\n    LDA #$0001\n    LDA $03,S\n    LDA [$40],Y\n    STA f:$40\n    JSL start\n    MVN $01,$02\n    BRL start\n",
+        )
+        .unwrap();
+        assert_eq!(
+            asm.segments[0].data,
+            vec![
+                0xC2, 0x20, 0xA9, 0x34, 0x12, 0xA2, 0x05, 0xE2, 0x20, 0xA9, 0x12, 0xA9, 0x01, 0x00,
+                0xA3, 0x03, 0xB7, 0x40, 0x8F, 0x40, 0x00, 0x00, 0x22, 0x00, 0x80, 0x00, 0x54, 0x02,
+                0x01, 0x82, 0xE0, 0xFF
+            ]
+        );
+        assert_eq!(
+            code_size(".SETCPU \"65816\"\nf:\n    REP #$30\n    LDY #$0000\n    SEP #$10\n    LDY #$00\n"),
+            Ok(9)
+        );
+    }
+
+    #[test]
+    fn test_binary_image_fills_gaps() {
+        let asm = assemble(".ORG $FFFA\n    .WORD $1234\n.ORG $FFF8\n    NOP\n").unwrap();
+        assert_eq!(asm.to_binary(0xFF), vec![0xEA, 0xFF, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn test_code_size() {
+        assert_eq!(code_size("f:\n    LDA $40\n    STA $0200,X\n    JSR g\n    RTS\n"), Ok(9));
+        assert_eq!(instruction_size("LDA", "$40,Y"), Ok(3));
+        assert_eq!(instruction_size("LDX", "$40,Y"), Ok(2));
+    }
+
+    #[test]
+    fn test_undefined_symbols() {
+        let source = ".import putc\nPORT = $6000\nmain:\n    JSR putc\n    JSR mul16\n    STA PORT\n    .WORD main, irq\n";
+        assert_eq!(
+            undefined_symbols(source),
+            Ok(vec!["irq".to_string(), "mul16".to_string()])
+        );
+        assert!(
+            assemble(".segment \"CODE\"\n    RTS\n")
+                .unwrap_err()
+                .message
+                .contains("external linker")
+        );
+    }
+}
@@ -0,0 +1,673 @@
+//! 6502 Opcode Table
+//!
+//! Maps mnemonic/addressing-mode pairs to opcode bytes for the NMOS 6502, the
+//! instructions the WDC 65C02 adds to it, the Rockwell/WDC bit instructions,
+//! the stable undocumented NMOS opcodes and the 65816's native-mode additions.
+
+/// 6502 addressing modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressingMode {
+    /// No operand: `RTS`
+    Implied,
+    /// Operates on the accumulator: `ASL` / `ASL A`
+    Accumulator,
+    /// `LDA #$10`
+    Immediate,
+    /// `LDA $10`
+    ZeroPage,
+    /// `LDA $10,X`
+    ZeroPageX,
+    /// `LDX $10,Y`
+    ZeroPageY,
+    /// `LDA $1234`
+    Absolute,
+    /// `LDA $1234,X`
+    AbsoluteX,
+    /// `LDA $1234,Y`
+    AbsoluteY,
+    /// `JMP ($1234)`
+    Indirect,
+    /// `LDA ($10,X)`
+    IndexedIndirect,
+    /// `LDA ($10),Y`
+    IndirectIndexed,
+    /// `LDA ($10)` (65C02)
+    ZeroPageIndirect,
+    /// `JMP ($1234,X)` (65C02)
+    AbsoluteIndexedIndirect,
+    /// Branch target, encoded as a signed offset from the next instruction
+    Relative,
+    /// `BBR0 $10,label`: a zero-page byte, then a branch offset (R65C02/W65C02)
+    ZeroPageRelative,
+    /// `LDA $03,S`: offset from the stack pointer (65816)
+    StackRelative,
+    /// `LDA ($03,S),Y` (65816)
+    StackRelativeIndirectIndexed,
+    /// `LDA [$10]`: 24-bit pointer in the direct page (65816)
+    DirectIndirectLong,
+    /// `LDA [$10],Y` (65816)
+    DirectIndirectLongIndexed,
+    /// `LDA $123456` or `LDA f:label` (65816)
+    AbsoluteLong,
+    /// `LDA $123456,X` (65816)
+    AbsoluteLongX,
+    /// `JML [$1234]`: 24-bit pointer in bank 0 (65816)
+    AbsoluteIndirectLong,
+    /// `BRL label`: a 16-bit offset from the next instruction (65816)
+    RelativeLong,
+    /// `MVN $01,$02`: source and destination bank (65816)
+    BlockMove,
+}
+
+impl AddressingMode {
+    /// Number of operand bytes following the opcode
+    pub fn operand_size(self) -> u16 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Immediate
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::IndexedIndirect
+            | AddressingMode::IndirectIndexed
+            | AddressingMode::ZeroPageIndirect
+            | AddressingMode::Relative
+            | AddressingMode::StackRelative
+            | AddressingMode::StackRelativeIndirectIndexed
+            | AddressingMode::DirectIndirectLong
+            | AddressingMode::DirectIndirectLongIndexed => 1,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect
+            | AddressingMode::AbsoluteIndexedIndirect
+            | AddressingMode::ZeroPageRelative
+            | AddressingMode::AbsoluteIndirectLong
+            | AddressingMode::RelativeLong
+            | AddressingMode::BlockMove => 2,
+            AddressingMode::AbsoluteLong | AddressingMode::AbsoluteLongX => 3,
+        }
+    }
+
+    /// Total instruction size in bytes (opcode + operand)
+    ///
+    /// On the 65816 an immediate is a byte longer while its register is 16-bit.
+    pub fn instruction_size(self) -> u16 {
+        1 + self.operand_size()
+    }
+}
+
+/// A single entry in the opcode table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub code: u8,
+}
+
+use AddressingMode::*;
+
+const fn op(mnemonic: &'static str, mode: AddressingMode, code: u8) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        code,
+    }
+}
+
+/// All documented NMOS 6502 opcodes
+pub const OPCODES: &[Opcode] = &[
+    // Load/store
+    op("LDA", Immediate, 0xA9),
+    op("LDA", ZeroPage, 0xA5),
+    op("LDA", ZeroPageX, 0xB5),
+    op("LDA", Absolute, 0xAD),
+    op("LDA", AbsoluteX, 0xBD),
+    op("LDA", AbsoluteY, 0xB9),
+    op("LDA", IndexedIndirect, 0xA1),
+    op("LDA", IndirectIndexed, 0xB1),
+    op("LDX", Immediate, 0xA2),
+    op("LDX", ZeroPage, 0xA6),
+    op("LDX", ZeroPageY, 0xB6),
+    op("LDX", Absolute, 0xAE),
+    op("LDX", AbsoluteY, 0xBE),
+    op("LDY", Immediate, 0xA0),
+    op("LDY", ZeroPage, 0xA4),
+    op("LDY", ZeroPageX, 0xB4),
+    op("LDY", Absolute, 0xAC),
+    op("LDY", AbsoluteX, 0xBC),
+    op("STA", ZeroPage, 0x85),
+    op("STA", ZeroPageX, 0x95),
+    op("STA", Absolute, 0x8D),
+    op("STA", AbsoluteX, 0x9D),
+    op("STA", AbsoluteY, 0x99),
+    op("STA", IndexedIndirect, 0x81),
+    op("STA", IndirectIndexed, 0x91),
+    op("STX", ZeroPage, 0x86),
+    op("STX", ZeroPageY, 0x96),
+    op("STX", Absolute, 0x8E),
+    op("STY", ZeroPage, 0x84),
+    op("STY", ZeroPageX, 0x94),
+    op("STY", Absolute, 0x8C),
+    // Arithmetic and logic
+    op("ADC", Immediate, 0x69),
+    op("ADC", ZeroPage, 0x65),
+    op("ADC", ZeroPageX, 0x75),
+    op("ADC", Absolute, 0x6D),
+    op("ADC", AbsoluteX, 0x7D),
+    op("ADC", AbsoluteY, 0x79),
+    op("ADC", IndexedIndirect, 0x61),
+    op("ADC", IndirectIndexed, 0x71),
+    op("SBC", Immediate, 0xE9),
+    op("SBC", ZeroPage, 0xE5),
+    op("SBC", ZeroPageX, 0xF5),
+    op("SBC", Absolute, 0xED),
+    op("SBC", AbsoluteX, 0xFD),
+    op("SBC", AbsoluteY, 0xF9),
+    op("SBC", IndexedIndirect, 0xE1),
+    op("SBC", IndirectIndexed, 0xF1),
+    op("AND", Immediate, 0x29),
+    op("AND", ZeroPage, 0x25),
+    op("AND", ZeroPageX, 0x35),
+    op("AND", Absolute, 0x2D),
+    op("AND", AbsoluteX, 0x3D),
+    op("AND", AbsoluteY, 0x39),
+    op("AND", IndexedIndirect, 0x21),
+    op("AND", IndirectIndexed, 0x31),
+    op("ORA", Immediate, 0x09),
+    op("ORA", ZeroPage, 0x05),
+    op("ORA", ZeroPageX, 0x15),
+    op("ORA", Absolute, 0x0D),
+    op("ORA", AbsoluteX, 0x1D),
+    op("ORA", AbsoluteY, 0x19),
+    op("ORA", IndexedIndirect, 0x01),
+    op("ORA", IndirectIndexed, 0x11),
+    op("EOR", Immediate, 0x49),
+    op("EOR", ZeroPage, 0x45),
+    op("EOR", ZeroPageX, 0x55),
+    op("EOR", Absolute, 0x4D),
+    op("EOR", AbsoluteX, 0x5D),
+    op("EOR", AbsoluteY, 0x59),
+    op("EOR", IndexedIndirect, 0x41),
+    op("EOR", IndirectIndexed, 0x51),
+    op("BIT", ZeroPage, 0x24),
+    op("BIT", Absolute, 0x2C),
+    // Compare
+    op("CMP", Immediate, 0xC9),
+    op("CMP", ZeroPage, 0xC5),
+    op("CMP", ZeroPageX, 0xD5),
+    op("CMP", Absolute, 0xCD),
+    op("CMP", AbsoluteX, 0xDD),
+    op("CMP", AbsoluteY, 0xD9),
+    op("CMP", IndexedIndirect, 0xC1),
+    op("CMP", IndirectIndexed, 0xD1),
+    op("CPX", Immediate, 0xE0),
+    op("CPX", ZeroPage, 0xE4),
+    op("CPX", Absolute, 0xEC),
+    op("CPY", Immediate, 0xC0),
+    op("CPY", ZeroPage, 0xC4),
+    op("CPY", Absolute, 0xCC),
+    // Increment/decrement
+    op("INC", ZeroPage, 0xE6),
+    op("INC", ZeroPageX, 0xF6),
+    op("INC", Absolute, 0xEE),
+    op("INC", AbsoluteX, 0xFE),
+    op("DEC", ZeroPage, 0xC6),
+    op("DEC", ZeroPageX, 0xD6),
+    op("DEC", Absolute, 0xCE),
+    op("DEC", AbsoluteX, 0xDE),
+    op("INX", Implied, 0xE8),
+    op("INY", Implied, 0xC8),
+    op("DEX", Implied, 0xCA),
+    op("DEY", Implied, 0x88),
+    // Shifts and rotates
+    op("ASL", Accumulator, 0x0A),
+    op("ASL", ZeroPage, 0x06),
+    op("ASL", ZeroPageX, 0x16),
+    op("ASL", Absolute, 0x0E),
+    op("ASL", AbsoluteX, 0x1E),
+    op("LSR", Accumulator, 0x4A),
+    op("LSR", ZeroPage, 0x46),
+    op("LSR", ZeroPageX, 0x56),
+    op("LSR", Absolute, 0x4E),
+    op("LSR", AbsoluteX, 0x5E),
+    op("ROL", Accumulator, 0x2A),
+    op("ROL", ZeroPage, 0x26),
+    op("ROL", ZeroPageX, 0x36),
+    op("ROL", Absolute, 0x2E),
+    op("ROL", AbsoluteX, 0x3E),
+    op("ROR", Accumulator, 0x6A),
+    op("ROR", ZeroPage, 0x66),
+    op("ROR", ZeroPageX, 0x76),
+    op("ROR", Absolute, 0x6E),
+    op("ROR", AbsoluteX, 0x7E),
+    // Jumps and subroutines
+    op("JMP", Absolute, 0x4C),
+    op("JMP", Indirect, 0x6C),
+    op("JSR", Absolute, 0x20),
+    op("RTS", Implied, 0x60),
+    op("RTI", Implied, 0x40),
+    op("BRK", Implied, 0x00),
+    // Branches
+    op("BCC", Relative, 0x90),
+    op("BCS", Relative, 0xB0),
+    op("BEQ", Relative, 0xF0),
+    op("BNE", Relative, 0xD0),
+    op("BMI", Relative, 0x30),
+    op("BPL", Relative, 0x10),
+    op("BVC", Relative, 0x50),
+    op("BVS", Relative, 0x70),
+    // Register transfers
+    op("TAX", Implied, 0xAA),
+    op("TAY", Implied, 0xA8),
+    op("TXA", Implied, 0x8A),
+    op("TYA", Implied, 0x98),
+    op("TSX", Implied, 0xBA),
+    op("TXS", Implied, 0x9A),
+    // Stack
+    op("PHA", Implied, 0x48),
+    op("PHP", Implied, 0x08),
+    op("PLA", Implied, 0x68),
+    op("PLP", Implied, 0x28),
+    // Flags
+    op("CLC", Implied, 0x18),
+    op("SEC", Implied, 0x38),
+    op("CLI", Implied, 0x58),
+    op("SEI", Implied, 0x78),
+    op("CLD", Implied, 0xD8),
+    op("SED", Implied, 0xF8),
+    op("CLV", Implied, 0xB8),
+    op("NOP", Implied, 0xEA),
+];
+
+/// Instructions the WDC 65C02 adds to the NMOS set
+pub const CMOS_OPCODES: &[Opcode] = &[
+    op("BRA", Relative, 0x80),
+    op("PHX", Implied, 0xDA),
+    op("PLX", Implied, 0xFA),
+    op("PHY", Implied, 0x5A),
+    op("PLY", Implied, 0x7A),
+    op("STZ", ZeroPage, 0x64),
+    op("STZ", ZeroPageX, 0x74),
+    op("STZ", Absolute, 0x9C),
+    op("STZ", AbsoluteX, 0x9E),
+    op("TSB", ZeroPage, 0x04),
+    op("TSB", Absolute, 0x0C),
+    op("TRB", ZeroPage, 0x14),
+    op("TRB", Absolute, 0x1C),
+    op("INC", Accumulator, 0x1A),
+    op("DEC", Accumulator, 0x3A),
+    op("BIT", Immediate, 0x89),
+    op("BIT", ZeroPageX, 0x34),
+    op("BIT", AbsoluteX, 0x3C),
+    op("JMP", AbsoluteIndexedIndirect, 0x7C),
+    op("ORA", ZeroPageIndirect, 0x12),
+    op("AND", ZeroPageIndirect, 0x32),
+    op("EOR", ZeroPageIndirect, 0x52),
+    op("ADC", ZeroPageIndirect, 0x72),
+    op("STA", ZeroPageIndirect, 0x92),
+    op("LDA", ZeroPageIndirect, 0xB2),
+    op("CMP", ZeroPageIndirect, 0xD2),
+    op("SBC", ZeroPageIndirect, 0xF2),
+];
+
+/// Instructions the WDC 65816 adds to the 65C02 set
+///
+/// The eight accumulator instructions gain stack-relative and 24-bit long
+/// modes; the rest handle the wider registers, banks and native mode.
+pub const NATIVE_OPCODES: &[Opcode] = &[
+    op("ORA", StackRelative, 0x03),
+    op("ORA", DirectIndirectLong, 0x07),
+    op("ORA", AbsoluteLong, 0x0F),
+    op("ORA", StackRelativeIndirectIndexed, 0x13),
+    op("ORA", DirectIndirectLongIndexed, 0x17),
+    op("ORA", AbsoluteLongX, 0x1F),
+    op("AND", StackRelative, 0x23),
+    op("AND", DirectIndirectLong, 0x27),
+    op("AND", AbsoluteLong, 0x2F),
+    op("AND", StackRelativeIndirectIndexed, 0x33),
+    op("AND", DirectIndirectLongIndexed, 0x37),
+    op("AND", AbsoluteLongX, 0x3F),
+    op("EOR", StackRelative, 0x43),
+    op("EOR", DirectIndirectLong, 0x47),
+    op("EOR", AbsoluteLong, 0x4F),
+    op("EOR", StackRelativeIndirectIndexed, 0x53),
+    op("EOR", DirectIndirectLongIndexed, 0x57),
+    op("EOR", AbsoluteLongX, 0x5F),
+    op("ADC", StackRelative, 0x63),
+    op("ADC", DirectIndirectLong, 0x67),
+    op("ADC", AbsoluteLong, 0x6F),
+    op("ADC", StackRelativeIndirectIndexed, 0x73),
+    op("ADC", DirectIndirectLongIndexed, 0x77),
+    op("ADC", AbsoluteLongX, 0x7F),
+    op("STA", StackRelative, 0x83),
+    op("STA", DirectIndirectLong, 0x87),
+    op("STA", AbsoluteLong, 0x8F),
+    op("STA", StackRelativeIndirectIndexed, 0x93),
+    op("STA", DirectIndirectLongIndexed, 0x97),
+    op("STA", AbsoluteLongX, 0x9F),
+    op("LDA", StackRelative, 0xA3),
+    op("LDA", DirectIndirectLong, 0xA7),
+    op("LDA", AbsoluteLong, 0xAF),
+    op("LDA", StackRelativeIndirectIndexed, 0xB3),
+    op("LDA", DirectIndirectLongIndexed, 0xB7),
+    op("LDA", AbsoluteLongX, 0xBF),
+    op("CMP", StackRelative, 0xC3),
+    op("CMP", DirectIndirectLong, 0xC7),
+    op("CMP", AbsoluteLong, 0xCF),
+    op("CMP", StackRelativeIndirectIndexed, 0xD3),
+    op("CMP", DirectIndirectLongIndexed, 0xD7),
+    op("CMP", AbsoluteLongX, 0xDF),
+    op("SBC", StackRelative, 0xE3),
+    op("SBC", DirectIndirectLong, 0xE7),
+    op("SBC", AbsoluteLong, 0xEF),
+    op("SBC", StackRelativeIndirectIndexed, 0xF3),
+    op("SBC", DirectIndirectLongIndexed, 0xF7),
+    op("SBC", AbsoluteLongX, 0xFF),
+    // Register widths and native mode
+    op("REP", Immediate, 0xC2),
+    op("SEP", Immediate, 0xE2),
+    op("XCE", Implied, 0xFB),
+    op("XBA", Implied, 0xEB),
+    // Transfers
+    op("TCD", Implied, 0x5B),
+    op("TDC", Implied, 0x7B),
+    op("TCS", Implied, 0x1B),
+    op("TSC", Implied, 0x3B),
+    op("TXY", Implied, 0x9B),
+    op("TYX", Implied, 0xBB),
+    // Stack
+    op("PHB", Implied, 0x8B),
+    op("PLB", Implied, 0xAB),
+    op("PHD", Implied, 0x0B),
+    op("PLD", Implied, 0x2B),
+    op("PHK", Implied, 0x4B),
+    op("PEA", Absolute, 0xF4),
+    op("PEI", ZeroPageIndirect, 0xD4),
+    op("PER", RelativeLong, 0x62),
+    // Jumps, calls and branches
+    op("JML", AbsoluteLong, 0x5C),
+    op("JML", AbsoluteIndirectLong, 0xDC),
+    op("JSL", AbsoluteLong, 0x22),
+    op("RTL", Implied, 0x6B),
+    op("JSR", AbsoluteIndexedIndirect, 0xFC),
+    op("BRL", RelativeLong, 0x82),
+    // Block moves
+    op("MVP", BlockMove, 0x44),
+    op("MVN", BlockMove, 0x54),
+    // Interrupts and the processor
+    op("COP", Immediate, 0x02),
+    op("WDM", Immediate, 0x42),
+    op("WAI", Implied, 0xCB),
+    op("STP", Implied, 0xDB),
+];
+
+/// Bit instructions of the Rockwell R65C02 and WDC W65C02S
+///
+/// RMBn/SMBn clear/set bit n of a zero-page byte; BBRn/BBSn branch when it is
+/// clear/set.
+pub const BIT_OPCODES: &[Opcode] = &[
+    op("RMB0", ZeroPage, 0x07),
+    op("RMB1", ZeroPage, 0x17),
+    op("RMB2", ZeroPage, 0x27),
+    op("RMB3", ZeroPage, 0x37),
+    op("RMB4", ZeroPage, 0x47),
+    op("RMB5", ZeroPage, 0x57),
+    op("RMB6", ZeroPage, 0x67),
+    op("RMB7", ZeroPage, 0x77),
+    op("SMB0", ZeroPage, 0x87),
+    op("SMB1", ZeroPage, 0x97),
+    op("SMB2", ZeroPage, 0xA7),
+    op("SMB3", ZeroPage, 0xB7),
+    op("SMB4", ZeroPage, 0xC7),
+    op("SMB5", ZeroPage, 0xD7),
+    op("SMB6", ZeroPage, 0xE7),
+    op("SMB7", ZeroPage, 0xF7),
+    op("BBR0", ZeroPageRelative, 0x0F),
+    op("BBR1", ZeroPageRelative, 0x1F),
+    op("BBR2", ZeroPageRelative, 0x2F),
+    op("BBR3", ZeroPageRelative, 0x3F),
+    op("BBR4", ZeroPageRelative, 0x4F),
+    op("BBR5", ZeroPageRelative, 0x5F),
+    op("BBR6", ZeroPageRelative, 0x6F),
+    op("BBR7", ZeroPageRelative, 0x7F),
+    op("BBS0", ZeroPageRelative, 0x8F),
+    op("BBS1", ZeroPageRelative, 0x9F),
+    op("BBS2", ZeroPageRelative, 0xAF),
+    op("BBS3", ZeroPageRelative, 0xBF),
+    op("BBS4", ZeroPageRelative, 0xCF),
+    op("BBS5", ZeroPageRelative, 0xDF),
+    op("BBS6", ZeroPageRelative, 0xEF),
+    op("BBS7", ZeroPageRelative, 0xFF),
+];
+
+/// Undocumented NMOS instructions that behave the same on every NMOS part
+///
+/// The unstable ones (`LAX #imm`, `SHA`, `ANE` and friends) are left out.
+pub const UNDOCUMENTED_OPCODES: &[Opcode] = &[
+    // LAX: LDA and LDX at once
+    op("LAX", ZeroPage, 0xA7),
+    op("LAX", ZeroPageY, 0xB7),
+    op("LAX", Absolute, 0xAF),
+    op("LAX", AbsoluteY, 0xBF),
+    op("LAX", IndexedIndirect, 0xA3),
+    op("LAX", IndirectIndexed, 0xB3),
+    // SAX: store A & X, flags untouched
+    op("SAX", ZeroPage, 0x87),
+    op("SAX", ZeroPageY, 0x97),
+    op("SAX", Absolute, 0x8F),
+    op("SAX", IndexedIndirect, 0x83),
+    // DCP: DEC memory, then CMP with it
+    op("DCP", ZeroPage, 0xC7),
+    op("DCP", ZeroPageX, 0xD7),
+    op("DCP", Absolute, 0xCF),
+    op("DCP", AbsoluteX, 0xDF),
+    op("DCP", AbsoluteY, 0xDB),
+    op("DCP", IndexedIndirect, 0xC3),
+    op("DCP", IndirectIndexed, 0xD3),
+    // ISC: INC memory, then SBC it
+    op("ISC", ZeroPage, 0xE7),
+    op("ISC", ZeroPageX, 0xF7),
+    op("ISC", Absolute, 0xEF),
+    op("ISC", AbsoluteX, 0xFF),
+    op("ISC", AbsoluteY, 0xFB),
+    op("ISC", IndexedIndirect, 0xE3),
+    op("ISC", IndirectIndexed, 0xF3),
+];
+
+/// Processor the code runs on, which decides the available instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Cpu {
+    /// NMOS 6502, documented instructions only
+    #[default]
+    Nmos6502,
+    /// NMOS 6502 including the stable undocumented opcodes
+    Nmos6502Undocumented,
+    /// WDC 65C02
+    Cmos65C02,
+    /// WDC W65C02S or Rockwell R65C02: the 65C02 plus the bit instructions
+    W65C02,
+    /// Ricoh 2A03 (NES): an NMOS 6502 whose decimal flag has no effect
+    Ricoh2A03,
+    /// WDC 65816: the 65C02 plus 16-bit registers and 24-bit addressing
+    W65C816,
+}
+
+impl Cpu {
+    pub const ALL: [Cpu; 6] = [
+        Cpu::Nmos6502,
+        Cpu::Nmos6502Undocumented,
+        Cpu::Cmos65C02,
+        Cpu::W65C02,
+        Cpu::Ricoh2A03,
+        Cpu::W65C816,
+    ];
+
+    /// Name used by `.SETCPU` (and ca65)
+    pub fn name(self) -> &'static str {
+        match self {
+            Cpu::Nmos6502 => "6502",
+            Cpu::Nmos6502Undocumented => "6502X",
+            Cpu::Cmos65C02 => "65C02",
+            Cpu::W65C02 => "W65C02",
+            Cpu::Ricoh2A03 => "2A03",
+            Cpu::W65C816 => "65816",
+        }
+    }
+
+    /// Parse a `.SETCPU` name; `R65C02` is accepted for the W65C02
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("R65C02") {
+            return Some(Cpu::W65C02);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|cpu| cpu.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Whether STZ, BRA, PHX/PLX, (zp) addressing and the other 65C02 additions exist
+    pub fn has_cmos_instructions(self) -> bool {
+        matches!(self, Cpu::Cmos65C02 | Cpu::W65C02 | Cpu::W65C816)
+    }
+
+    /// Whether SMB/RMB and BBR/BBS exist
+    pub fn has_bit_instructions(self) -> bool {
+        self == Cpu::W65C02
+    }
+
+    /// Whether there is a native mode with REP/SEP-sized registers and 24-bit
+    /// long addressing
+    pub fn has_native_mode(self) -> bool {
+        self == Cpu::W65C816
+    }
+
+    /// Whether SED makes ADC and SBC work in BCD
+    pub fn has_decimal_mode(self) -> bool {
+        self != Cpu::Ricoh2A03
+    }
+
+    /// Whether LAX, SAX, DCP and ISC may be used
+    pub fn has_undocumented_opcodes(self) -> bool {
+        self == Cpu::Nmos6502Undocumented
+    }
+
+    /// Every instruction this processor executes
+    pub fn opcodes(self) -> impl Iterator<Item = &'static Opcode> {
+        let (extra, bits): (&'static [Opcode], &'static [Opcode]) = match self {
+            Cpu::Nmos6502 | Cpu::Ricoh2A03 => (&[], &[]),
+            Cpu::Nmos6502Undocumented => (UNDOCUMENTED_OPCODES, &[]),
+            Cpu::Cmos65C02 => (CMOS_OPCODES, &[]),
+            Cpu::W65C02 => (CMOS_OPCODES, BIT_OPCODES),
+            Cpu::W65C816 => (CMOS_OPCODES, NATIVE_OPCODES),
+        };
+        OPCODES.iter().chain(extra).chain(bits)
+    }
+
+    pub fn lookup(self, mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+        self.opcodes()
+            .find(|op| op.mnemonic == mnemonic && op.mode == mode)
+            .map(|op| op.code)
+    }
+
+    pub fn has_mode(self, mnemonic: &str, mode: AddressingMode) -> bool {
+        self.lookup(mnemonic, mode).is_some()
+    }
+
+    pub fn is_mnemonic(self, mnemonic: &str) -> bool {
+        self.opcodes().any(|op| op.mnemonic == mnemonic)
+    }
+
+    pub fn decode(self, code: u8) -> Option<&'static Opcode> {
+        self.opcodes().find(|op| op.code == code)
+    }
+
+    /// Whether any supported processor has the mnemonic in the addressing mode
+    pub fn any_has_mode(mnemonic: &str, mode: AddressingMode) -> bool {
+        Self::ALL.iter().any(|cpu| cpu.has_mode(mnemonic, mode))
+    }
+}
+
+/// Look up the opcode byte for a mnemonic in a given addressing mode (NMOS)
+pub fn lookup(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    Cpu::Nmos6502.lookup(mnemonic, mode)
+}
+
+/// Check whether a mnemonic supports a given addressing mode (NMOS)
+pub fn has_mode(mnemonic: &str, mode: AddressingMode) -> bool {
+    lookup(mnemonic, mode).is_some()
+}
+
+/// Check whether a mnemonic is a known instruction (NMOS)
+pub fn is_mnemonic(mnemonic: &str) -> bool {
+    Cpu::Nmos6502.is_mnemonic(mnemonic)
+}
+
+/// Decode an opcode byte back into its table entry (NMOS)
+pub fn decode(code: u8) -> Option<&'static Opcode> {
+    Cpu::Nmos6502.decode(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcodes_are_unique() {
+        for cpu in Cpu::ALL {
+            let all: Vec<&Opcode> = cpu.opcodes().collect();
+            for (i, a) in all.iter().enumerate() {
+                for b in &all[i + 1..] {
+                    assert_ne!(
+                        a.code, b.code,
+                        "{} and {} share an opcode",
+                        a.mnemonic, b.mnemonic
+                    );
+                    assert!(
+                        a.mnemonic != b.mnemonic || a.mode != b.mode,
+                        "duplicate entry for {} {:?}",
+                        a.mnemonic,
+                        a.mode
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_documented_opcode_count() {
+        assert_eq!(OPCODES.len(), 151);
+    }
+
+    #[test]
+    fn test_lookup_and_decode() {
+        assert_eq!(lookup("LDA", Immediate), Some(0xA9));
+        assert_eq!(lookup("LDA", ZeroPageY), None);
+        assert_eq!(decode(0x6C).map(|op| op.mode), Some(Indirect));
+        assert!(is_mnemonic("BRK"));
+        assert!(!is_mnemonic("STZ"));
+        assert_eq!(Cpu::Cmos65C02.lookup("STZ", Absolute), Some(0x9C));
+        assert_eq!(Cpu::Cmos65C02.decode(0x7C).map(|op| op.mode), Some(AbsoluteIndexedIndirect));
+        assert_eq!(Cpu::Nmos6502.decode(0x1A), None);
+        assert_eq!(
+            Cpu::Nmos6502Undocumented.lookup("LAX", ZeroPageY),
+            Some(0xB7)
+        );
+        assert_eq!(Cpu::Nmos6502Undocumented.decode(0x1A), None);
+        assert_eq!(Cpu::Nmos6502.decode(0xC7), None);
+        assert_eq!(Cpu::from_name("6502x"), Some(Cpu::Nmos6502Undocumented));
+        assert_eq!(Cpu::W65C02.lookup("BBS7", ZeroPageRelative), Some(0xFF));
+        assert_eq!(Cpu::W65C02.lookup("STZ", Absolute), Some(0x9C));
+        assert_eq!(Cpu::Cmos65C02.decode(0x87), None);
+        assert_eq!(Cpu::from_name("R65C02"), Some(Cpu::W65C02));
+        assert_eq!(Cpu::from_name("2a03"), Some(Cpu::Ricoh2A03));
+        assert!(!Cpu::Ricoh2A03.has_decimal_mode());
+        assert_eq!(Cpu::Ricoh2A03.lookup("SED", Implied), Some(0xF8));
+        assert_eq!(Cpu::from_name("65816"), Some(Cpu::W65C816));
+        assert_eq!(Cpu::W65C816.lookup("LDA", StackRelative), Some(0xA3));
+        assert_eq!(Cpu::W65C816.lookup("STA", AbsoluteLongX), Some(0x9F));
+        assert_eq!(Cpu::W65C816.lookup("STZ", Absolute), Some(0x9C));
+        assert_eq!(Cpu::W65C816.decode(0xB7).map(|op| op.mnemonic), Some("LDA"));
+        assert_eq!(Cpu::W65C02.decode(0xC2), None);
+        assert_eq!(AbsoluteLong.instruction_size(), 4);
+    }
+}
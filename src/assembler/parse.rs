@@ -0,0 +1,657 @@
+//! Assembly Line Parser
+//!
+//! Parses individual lines of 6502 assembly into labels, instructions,
+//! directives and equates. Operand syntax alone decides the addressing mode,
+//! which keeps instruction sizes independent of symbol values.
+
+use super::opcodes::Cpu;
+use rustc_hash::FxHashMap as HashMap;
+
+/// Index register used by an indexed addressing mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Index {
+    X,
+    Y,
+    /// The stack pointer of the 65816's stack-relative modes
+    S,
+}
+
+/// Low/high byte selection (`<expr` / `>expr`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteSelect {
+    Low,
+    High,
+}
+
+/// A single term of an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// Numeric literal; `wide` is set when it was written with more than two
+    /// hex digits (or is larger than a byte), forcing absolute addressing
+    Number { value: i64, wide: bool },
+    /// Reference to a label or equate
+    Symbol(String),
+    /// Current program counter (`*`)
+    Pc,
+}
+
+/// An operand expression: a sum of terms with optional byte selection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    /// Terms with their sign (`true` = subtract)
+    pub terms: Vec<(bool, Term)>,
+    pub select: Option<ByteSelect>,
+}
+
+impl Expr {
+    /// Whether this expression can be encoded in zero-page form
+    ///
+    /// Only byte-sized numeric literals qualify. Symbols are always treated
+    /// as 16-bit so sizes never depend on where a label ends up.
+    pub fn is_zero_page(&self) -> bool {
+        if self.select.is_some() {
+            return true;
+        }
+        let mut value = 0i64;
+        for (negate, term) in &self.terms {
+            match term {
+                Term::Number { value: v, wide } => {
+                    if *wide {
+                        return false;
+                    }
+                    value += if *negate { -v } else { *v };
+                }
+                Term::Symbol(_) | Term::Pc => return false,
+            }
+        }
+        (0..=0xFF).contains(&value)
+    }
+
+    /// Whether this is a numeric address above $FFFF, which needs long addressing
+    pub fn is_long(&self) -> bool {
+        let mut value = 0i64;
+        for (negate, term) in &self.terms {
+            match term {
+                Term::Number { value: v, .. } => value += if *negate { -v } else { *v },
+                Term::Symbol(_) | Term::Pc => return false,
+            }
+        }
+        self.select.is_none() && value > 0xFFFF
+    }
+
+    /// Names of all symbols referenced by this expression
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.terms.iter().filter_map(|(_, term)| match term {
+            Term::Symbol(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Evaluate against a symbol table; returns the first undefined symbol on failure
+    pub fn eval(&self, symbols: &HashMap<String, i64>, pc: u16) -> Result<i64, String> {
+        let mut value = 0i64;
+        for (negate, term) in &self.terms {
+            let v = match term {
+                Term::Number { value, .. } => *value,
+                Term::Symbol(name) => *symbols.get(name).ok_or_else(|| name.clone())?,
+                Term::Pc => pc as i64,
+            };
+            value += if *negate { -v } else { v };
+        }
+        Ok(match self.select {
+            Some(ByteSelect::Low) => value & 0xFF,
+            Some(ByteSelect::High) => (value >> 8) & 0xFF,
+            None => value,
+        })
+    }
+}
+
+/// Instruction operand, classified by syntax
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr, Option<Index>),
+    Indirect(Expr),
+    IndexedIndirect(Expr),
+    IndirectIndexed(Expr),
+    /// `$10,label` of BBR/BBS
+    BitBranch(Expr, Expr),
+    /// `($03,S),Y`
+    StackIndirectIndexed(Expr),
+    /// `[$10]` or `[$1234]`
+    IndirectLong(Expr),
+    /// `[$10],Y`
+    IndirectLongIndexed(Expr),
+    /// `f:label` or `f:label,X`, forcing a 24-bit address
+    Long(Expr, Option<Index>),
+    /// Source and destination bank of MVN/MVP
+    BlockMove(Expr, Expr),
+}
+
+/// An item in a `.BYTE` list
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataItem {
+    Expr(Expr),
+    String(Vec<u8>),
+}
+
+/// Assembler directives
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    Org(Expr),
+    Byte(Vec<DataItem>),
+    Word(Vec<Expr>),
+    Res(Expr, Option<Expr>),
+    /// ca65 `.segment "NAME"`; placement is left to the linker
+    Segment(String),
+    /// ca65 `.export`
+    Export(Vec<String>),
+    /// ca65 `.import`
+    Import(Vec<String>),
+    /// ca65 `.setcpu "65C02"`; selects the instruction set for following lines
+    SetCpu(Cpu),
+    /// ca65 `.A8`/`.A16`/`.I8`/`.I16`: the 65816 register width of following lines
+    RegisterWidth { index: bool, wide: bool },
+    /// ca65 `.smart`; REP and SEP are always followed, so it changes nothing
+    Smart,
+}
+
+/// The statement part of a line (after any labels)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Instruction { mnemonic: String, operand: Operand },
+    Directive(Directive),
+    Equate { name: String, value: Expr },
+}
+
+/// A parsed source line
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParsedLine {
+    pub labels: Vec<String>,
+    pub statement: Option<Statement>,
+}
+
+/// Parse one line of assembly
+pub fn parse_line(line: &str) -> Result<ParsedLine, String> {
+    let mut rest = strip_comment(line).trim();
+    let mut parsed = ParsedLine::default();
+
+    // Leading labels (`name:`), possibly indented and followed by an instruction
+    while let Some(len) = identifier_len(rest) {
+        let after = &rest[len..];
+        if after.starts_with(':') && !after.starts_with("::") {
+            parsed.labels.push(rest[..len].to_string());
+            rest = after[1..].trim_start();
+        } else {
+            break;
+        }
+    }
+
+    if rest.is_empty() {
+        return Ok(parsed);
+    }
+
+    // Equate: NAME = expr
+    if let Some(len) = identifier_len(rest) {
+        let after = rest[len..].trim_start();
+        if let Some(value) = after.strip_prefix('=') {
+            parsed.statement = Some(Statement::Equate {
+                name: rest[..len].to_string(),
+                value: parse_expr(value.trim())?,
+            });
+            return Ok(parsed);
+        }
+    }
+
+    if let Some(directive) = rest.strip_prefix('.') {
+        parsed.statement = Some(Statement::Directive(parse_directive(directive)?));
+        return Ok(parsed);
+    }
+
+    let (mnemonic, operand) = match rest.find(char::is_whitespace) {
+        Some(pos) => (&rest[..pos], rest[pos..].trim()),
+        None => (rest, ""),
+    };
+    if !mnemonic.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("unexpected '{}'", rest));
+    }
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    let operand = parse_instruction_operand(&mnemonic, operand)?;
+    parsed.statement = Some(Statement::Instruction { mnemonic, operand });
+    Ok(parsed)
+}
+
+/// Parse the operand of a specific instruction
+///
+/// A bare `A` means the accumulator only for instructions that have an
+/// accumulator mode on some CPU (`INC A` is the 65C02's); otherwise it is a
+/// symbol (e.g. `STA A` for `addr A`).
+pub fn parse_instruction_operand(mnemonic: &str, text: &str) -> Result<Operand, String> {
+    if Cpu::any_has_mode(mnemonic, super::AddressingMode::ZeroPageRelative) {
+        let (zp, target) = text
+            .split_once(',')
+            .ok_or_else(|| format!("{} expects 'zp,label', found '{}'", mnemonic, text.trim()))?;
+        return Ok(Operand::BitBranch(parse_expr(zp)?, parse_expr(target)?));
+    }
+    if Cpu::any_has_mode(mnemonic, super::AddressingMode::BlockMove) {
+        // ca65 also accepts `#` before each bank
+        let bank = |text: &str| parse_expr(text.trim().trim_start_matches('#'));
+        let (source, destination) = text.split_once(',').ok_or_else(|| {
+            format!("{} expects 'source,destination', found '{}'", mnemonic, text.trim())
+        })?;
+        return Ok(Operand::BlockMove(bank(source)?, bank(destination)?));
+    }
+    match parse_operand(text)? {
+        Operand::Accumulator
+            if !Cpu::any_has_mode(mnemonic, super::AddressingMode::Accumulator) =>
+        {
+            Ok(Operand::Direct(parse_expr(text)?, None))
+        }
+        operand => Ok(operand),
+    }
+}
+
+/// Remove a trailing `;` comment, ignoring semicolons inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ';' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+/// Length of the identifier at the start of `s`, if any
+fn identifier_len(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices();
+    match chars.next() {
+        Some((_, c)) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return None,
+    }
+    Some(
+        chars
+            .find(|(_, c)| !(c.is_ascii_alphanumeric() || *c == '_'))
+            .map(|(i, _)| i)
+            .unwrap_or(s.len()),
+    )
+}
+
+fn parse_directive(text: &str) -> Result<Directive, String> {
+    let (name, args) = match text.find(char::is_whitespace) {
+        Some(pos) => (&text[..pos], text[pos..].trim()),
+        None => (text, ""),
+    };
+
+    match name.to_ascii_uppercase().as_str() {
+        "ORG" => Ok(Directive::Org(parse_expr(args)?)),
+        "BYTE" | "DB" => {
+            let mut items = Vec::new();
+            for arg in split_args(args) {
+                if let Some(s) = arg.strip_prefix('"') {
+                    let s = s
+                        .strip_suffix('"')
+                        .ok_or_else(|| format!("unterminated string {}", arg))?;
+                    items.push(DataItem::String(s.as_bytes().to_vec()));
+                } else {
+                    items.push(DataItem::Expr(parse_expr(arg)?));
+                }
+            }
+            Ok(Directive::Byte(items))
+        }
+        "WORD" | "DW" => Ok(Directive::Word(
+            split_args(args)
+                .into_iter()
+                .map(parse_expr)
+                .collect::<Result<_, _>>()?,
+        )),
+        "RES" | "DS" => {
+            let parts = split_args(args);
+            match parts.as_slice() {
+                [count] => Ok(Directive::Res(parse_expr(count)?, None)),
+                [count, fill] => Ok(Directive::Res(parse_expr(count)?, Some(parse_expr(fill)?))),
+                _ => Err(".RES expects a count and an optional fill value".to_string()),
+            }
+        }
+        "SEGMENT" => {
+            let segment = args
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .ok_or_else(|| format!(".SEGMENT expects a quoted name, found '{}'", args))?;
+            Ok(Directive::Segment(segment.to_string()))
+        }
+        "EXPORT" => Ok(Directive::Export(parse_name_list(args)?)),
+        "IMPORT" => Ok(Directive::Import(parse_name_list(args)?)),
+        "SETCPU" => args
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .and_then(Cpu::from_name)
+            .map(Directive::SetCpu)
+            .ok_or_else(|| format!(".SETCPU expects \"6502\" or \"65C02\", found '{}'", args)),
+        "A8" | "A16" | "I8" | "I16" => Ok(Directive::RegisterWidth {
+            index: name.starts_with(['I', 'i']),
+            wide: name.ends_with("16"),
+        }),
+        "SMART" => Ok(Directive::Smart),
+        _ => Err(format!("unknown directive .{}", name)),
+    }
+}
+
+/// Parse a comma-separated list of symbol names
+fn parse_name_list(args: &str) -> Result<Vec<String>, String> {
+    split_args(args)
+        .into_iter()
+        .map(|name| {
+            if identifier_len(name) == Some(name.len()) {
+                Ok(name.to_string())
+            } else {
+                Err(format!("invalid symbol name '{}'", name))
+            }
+        })
+        .collect()
+}
+
+/// Split a comma-separated argument list, respecting quotes
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ',' => {
+                parts.push(args[start..i].trim());
+                start = i + 1;
+            }
+            None => {}
+        }
+    }
+    let last = args[start..].trim();
+    if !last.is_empty() || !parts.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+/// Classify an instruction operand by its syntax
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(Operand::None);
+    }
+    if text.eq_ignore_ascii_case("A") {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(imm) = text.strip_prefix('#') {
+        return Ok(Operand::Immediate(parse_expr(imm.trim())?));
+    }
+    if let Some(far) = text.strip_prefix("f:").or_else(|| text.strip_prefix("F:")) {
+        return match parse_operand(far)? {
+            Operand::Direct(expr, index @ (None | Some(Index::X))) => Ok(Operand::Long(expr, index)),
+            _ => Err(format!("invalid long operand '{}'", text)),
+        };
+    }
+    if let Some(inner) = text.strip_prefix('[') {
+        let close = inner
+            .find(']')
+            .ok_or_else(|| format!("missing ']' in operand '{}'", text))?;
+        let (body, after) = (parse_expr(&inner[..close])?, inner[close + 1..].trim());
+        if after.is_empty() {
+            return Ok(Operand::IndirectLong(body));
+        }
+        if let Some(index) = after.strip_prefix(',')
+            && parse_index(index) == Some(Index::Y)
+        {
+            return Ok(Operand::IndirectLongIndexed(body));
+        }
+        return Err(format!("invalid indirect operand '{}'", text));
+    }
+    if let Some(inner) = text.strip_prefix('(') {
+        let close = inner
+            .find(')')
+            .ok_or_else(|| format!("missing ')' in operand '{}'", text))?;
+        let (body, after) = (inner[..close].trim(), inner[close + 1..].trim());
+        if after.is_empty() {
+            if let Some((expr, index)) = body.rsplit_once(',') {
+                if parse_index(index) == Some(Index::X) {
+                    return Ok(Operand::IndexedIndirect(parse_expr(expr.trim())?));
+                }
+                return Err(format!("invalid indirect operand '{}'", text));
+            }
+            return Ok(Operand::Indirect(parse_expr(body)?));
+        }
+        if let Some(index) = after.strip_prefix(',')
+            && parse_index(index) == Some(Index::Y)
+        {
+            return match body.rsplit_once(',') {
+                Some((expr, index)) if parse_index(index) == Some(Index::S) => {
+                    Ok(Operand::StackIndirectIndexed(parse_expr(expr.trim())?))
+                }
+                Some(_) => Err(format!("invalid indirect operand '{}'", text)),
+                None => Ok(Operand::IndirectIndexed(parse_expr(body)?)),
+            };
+        }
+        return Err(format!("invalid indirect operand '{}'", text));
+    }
+    if let Some((expr, index)) = text.rsplit_once(',') {
+        let index =
+            parse_index(index).ok_or_else(|| format!("invalid index register in '{}'", text))?;
+        return Ok(Operand::Direct(parse_expr(expr.trim())?, Some(index)));
+    }
+    Ok(Operand::Direct(parse_expr(text)?, None))
+}
+
+fn parse_index(text: &str) -> Option<Index> {
+    match text.trim() {
+        "X" | "x" => Some(Index::X),
+        "Y" | "y" => Some(Index::Y),
+        "S" | "s" => Some(Index::S),
+        _ => None,
+    }
+}
+
+/// Parse an expression: `[<|>] term (('+'|'-') term)*`
+pub fn parse_expr(text: &str) -> Result<Expr, String> {
+    let mut s = text.trim();
+    if s.is_empty() {
+        return Err("expected expression".to_string());
+    }
+
+    let select = if let Some(rest) = s.strip_prefix('<') {
+        s = rest.trim_start();
+        Some(ByteSelect::Low)
+    } else if let Some(rest) = s.strip_prefix('>') {
+        s = rest.trim_start();
+        Some(ByteSelect::High)
+    } else {
+        None
+    };
+
+    let mut terms = Vec::new();
+    let mut negate = false;
+    if let Some(rest) = s.strip_prefix('-') {
+        negate = true;
+        s = rest.trim_start();
+    }
+
+    loop {
+        let (term, len) = parse_term(s).ok_or_else(|| format!("invalid expression '{}'", text))?;
+        terms.push((negate, term));
+        s = s[len..].trim_start();
+
+        if s.is_empty() {
+            break;
+        }
+        negate = match s.as_bytes()[0] {
+            b'+' => false,
+            b'-' => true,
+            _ => return Err(format!("invalid expression '{}'", text)),
+        };
+        s = s[1..].trim_start();
+    }
+
+    Ok(Expr { terms, select })
+}
+
+/// Parse a single term, returning it with the number of bytes consumed
+fn parse_term(s: &str) -> Option<(Term, usize)> {
+    let bytes = s.as_bytes();
+    match bytes.first()? {
+        b'$' => {
+            let len = s[1..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(s.len() - 1);
+            let value = i64::from_str_radix(&s[1..1 + len], 16).ok()?;
+            Some((Term::Number { value, wide: len > 2 }, 1 + len))
+        }
+        b'%' => {
+            let len = s[1..]
+                .find(|c: char| c != '0' && c != '1')
+                .unwrap_or(s.len() - 1);
+            let value = i64::from_str_radix(&s[1..1 + len], 2).ok()?;
+            Some((Term::Number { value, wide: len > 8 }, 1 + len))
+        }
+        b'\'' => {
+            let c = *bytes.get(1)?;
+            (bytes.get(2) == Some(&b'\'')).then_some((
+                Term::Number {
+                    value: c as i64,
+                    wide: false,
+                },
+                3,
+            ))
+        }
+        b'*' => Some((Term::Pc, 1)),
+        c if c.is_ascii_digit() => {
+            let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            let value: i64 = s[..len].parse().ok()?;
+            Some((
+                Term::Number {
+                    value,
+                    wide: value > 0xFF,
+                },
+                len,
+            ))
+        }
+        _ => {
+            let len = identifier_len(s)?;
+            Some((Term::Symbol(s[..len].to_string()), len))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(line: &str) -> (String, Operand) {
+        match parse_line(line).unwrap().statement {
+            Some(Statement::Instruction { mnemonic, operand }) => (mnemonic, operand),
+            other => panic!("expected instruction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_labels_and_instruction() {
+        let parsed = parse_line("    halt: JMP halt ; spin").unwrap();
+        assert_eq!(parsed.labels, vec!["halt"]);
+        assert!(matches!(
+            parsed.statement,
+            Some(Statement::Instruction { ref mnemonic, .. }) if mnemonic == "JMP"
+        ));
+    }
+
+    #[test]
+    fn test_parse_operand_modes() {
+        assert_eq!(instruction("    RTS").1, Operand::None);
+        assert_eq!(instruction("    LSR A").1, Operand::Accumulator);
+        assert!(matches!(instruction("    LDA #$10").1, Operand::Immediate(_)));
+        assert!(matches!(instruction("    LDA ($30),Y").1, Operand::IndirectIndexed(_)));
+        assert!(matches!(instruction("    LDA ($30,X)").1, Operand::IndexedIndirect(_)));
+        assert!(matches!(instruction("    JMP ($30)").1, Operand::Indirect(_)));
+        assert!(matches!(instruction("    bbs3 $30, done").1, Operand::BitBranch(..)));
+        assert!(matches!(
+            instruction("    LDA table+1,X").1,
+            Operand::Direct(_, Some(Index::X))
+        ));
+    }
+
+    #[test]
+    fn test_parse_65816_operands() {
+        assert!(matches!(instruction("    LDA $03,S").1, Operand::Direct(_, Some(Index::S))));
+        assert!(matches!(instruction("    LDA ($03,s),Y").1, Operand::StackIndirectIndexed(_)));
+        assert!(matches!(instruction("    LDA [$10]").1, Operand::IndirectLong(_)));
+        assert!(matches!(instruction("    LDA [$10],Y").1, Operand::IndirectLongIndexed(_)));
+        assert!(matches!(instruction("    STA f:PORT,X").1, Operand::Long(_, Some(Index::X))));
+        assert!(matches!(instruction("    MVN #$01, #$02").1, Operand::BlockMove(..)));
+        assert!(parse_line("    LDA ($03,X),Y").is_err());
+        assert!(parse_expr("$012345").unwrap().is_long());
+        assert!(!parse_expr("$FFFF").unwrap().is_long());
+        assert_eq!(
+            parse_line(".a16").unwrap().statement,
+            Some(Statement::Directive(Directive::RegisterWidth {
+                index: false,
+                wide: true
+            }))
+        );
+    }
+
+    #[test]
+    fn test_zero_page_detection() {
+        assert!(parse_expr("$40").unwrap().is_zero_page());
+        assert!(!parse_expr("$0040").unwrap().is_zero_page());
+        assert!(!parse_expr("label").unwrap().is_zero_page());
+        assert!(parse_expr("<label").unwrap().is_zero_page());
+        assert!(!parse_expr("300").unwrap().is_zero_page());
+    }
+
+    #[test]
+    fn test_equate_and_comment_with_quotes() {
+        let parsed = parse_line("PORT = $6000").unwrap();
+        assert!(matches!(parsed.statement, Some(Statement::Equate { ref name, .. }) if name == "PORT"));
+
+        let parsed = parse_line("    .BYTE \"a;b\", $00 ; trailing").unwrap();
+        match parsed.statement {
+            Some(Statement::Directive(Directive::Byte(items))) => assert_eq!(items.len(), 2),
+            other => panic!("expected .BYTE, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_linker_directives() {
+        let parsed = parse_line(".segment \"CODE\"").unwrap();
+        assert_eq!(
+            parsed.statement,
+            Some(Statement::Directive(Directive::Segment("CODE".to_string())))
+        );
+
+        let parsed = parse_line(".import mul16, div16").unwrap();
+        assert_eq!(
+            parsed.statement,
+            Some(Statement::Directive(Directive::Import(vec![
+                "mul16".to_string(),
+                "div16".to_string()
+            ])))
+        );
+
+        assert!(parse_line(".export 1abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_setcpu() {
+        assert_eq!(
+            parse_line(".setcpu \"65C02\"").unwrap().statement,
+            Some(Statement::Directive(Directive::SetCpu(Cpu::Cmos65C02)))
+        );
+        assert!(parse_line(".setcpu \"Z80\"").is_err());
+        assert_eq!(instruction("    INC A").1, Operand::Accumulator);
+    }
+}
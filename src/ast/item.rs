@@ -30,6 +30,13 @@ pub enum FnAttribute {
     Org(u16),
     /// Place in specific memory section
     Section(String),
+    /// Unit test, only compiled and run by `wraith test`
+    Test,
+    /// Worst-case cycle budget, checked by the timing analysis
+    MaxCycles(u32),
+    /// Deepest nesting of the function's calls to itself, which bounds the
+    /// stack its recursion uses
+    Recursion(u32),
 }
 
 /// A struct field definition
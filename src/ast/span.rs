@@ -1,5 +1,14 @@
 //! Source span tracking for error messages
 
+/// A file in a [`SourceMap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+pub struct FileId(pub u32);
+
+impl FileId {
+    /// The file being compiled; imported modules get later ids
+    pub const ROOT: FileId = FileId(0);
+}
+
 /// A span in the source code
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
 pub struct Span {
@@ -7,6 +16,8 @@ pub struct Span {
     pub start: usize,
     /// End byte offset (exclusive)
     pub end: usize,
+    /// File the offsets are in
+    pub file: FileId,
 }
 
 /// Line and column position in source code (1-indexed)
@@ -17,9 +28,18 @@ pub struct LineCol {
 }
 
 impl Span {
-    /// Create a new span
+    /// Create a new span in the root file
     pub fn new(start: usize, end: usize) -> Self {
-        Self { start, end }
+        Self {
+            start,
+            end,
+            file: FileId::ROOT,
+        }
+    }
+
+    /// The same offsets in another file
+    pub fn in_file(self, file: FileId) -> Self {
+        Self { file, ..self }
     }
 
     /// Create a dummy span for testing
@@ -32,6 +52,7 @@ impl Span {
         Span {
             start: self.start.min(other.start),
             end: self.end.max(other.end),
+            file: self.file,
         }
     }
 
@@ -128,6 +149,115 @@ impl Span {
     }
 }
 
+/// The text of every file in a program, so that a span can be shown against
+/// the file it points into along with the imports that pulled that file in
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceMapFile>,
+}
+
+#[derive(Debug, Clone)]
+struct SourceMapFile {
+    name: String,
+    source: String,
+    /// The import path of the `import` that loaded the file
+    imported_from: Option<Span>,
+}
+
+impl SourceMap {
+    /// A map holding the root file
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            files: vec![SourceMapFile {
+                name: name.into(),
+                source: source.into(),
+                imported_from: None,
+            }],
+        }
+    }
+
+    /// Record a module loaded by the import at `imported_from`
+    pub fn add_import(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<String>,
+        imported_from: Span,
+    ) -> FileId {
+        // Keep the root's id for the root even when its text isn't known
+        if self.files.is_empty() {
+            *self = Self::new("", "");
+        }
+        self.files.push(SourceMapFile {
+            name: name.into(),
+            source: source.into(),
+            imported_from: Some(imported_from),
+        });
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        self.files
+            .get(file.0 as usize)
+            .map_or("", |file| file.name.as_str())
+    }
+
+    pub fn source(&self, file: FileId) -> &str {
+        self.files
+            .get(file.0 as usize)
+            .map_or("", |file| file.source.as_str())
+    }
+
+    /// The imports that led to `file`, innermost first
+    pub fn import_chain(&self, file: FileId) -> Vec<Span> {
+        let mut chain = Vec::new();
+        let mut current = file;
+        while let Some(span) = self
+            .files
+            .get(current.0 as usize)
+            .and_then(|file| file.imported_from)
+        {
+            chain.push(span);
+            current = span.file;
+        }
+        chain
+    }
+
+    /// Format a span as "file:line:col"
+    pub fn format_location(&self, span: Span) -> String {
+        format!(
+            "{}:{}",
+            self.name(span.file),
+            span.format_position(self.source(span.file))
+        )
+    }
+
+    /// Like [`Span::format_error_context`], against the span's own file and
+    /// followed by the chain of imports that loaded it:
+    ///
+    /// ```text
+    ///   --> lib/math.wr:3:19
+    ///    |
+    ///  3 |     let x: bool = 1;
+    ///    |                   ^ expected bool, found u8
+    ///    = note: imported from main.wr:1:21
+    /// ```
+    pub fn format_error_context(&self, span: Span, message: &str) -> String {
+        let name = self.name(span.file);
+        let mut out = span.format_error_context(
+            self.source(span.file),
+            (!name.is_empty()).then_some(name),
+            message,
+        );
+        for import in self.import_chain(span.file) {
+            out.push_str(&format!(
+                "\n    = note: imported from {}",
+                self.format_location(import)
+            ));
+        }
+        out
+    }
+}
+
 /// Get a specific line from source code (1-indexed)
 fn get_line(source: &str, line_num: usize) -> &str {
     source.lines().nth(line_num.saturating_sub(1)).unwrap_or("")
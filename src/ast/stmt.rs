@@ -94,10 +94,16 @@ pub enum Stmt {
     While {
         condition: Spanned<Expr>,
         body: Box<Spanned<Stmt>>,
+        /// Maximum iterations, from `#[bound(N)]`
+        bound: Option<u32>,
     },
 
     /// Infinite loop
-    Loop { body: Box<Spanned<Stmt>> },
+    Loop {
+        body: Box<Spanned<Stmt>>,
+        /// Maximum iterations, from `#[bound(N)]`
+        bound: Option<u32>,
+    },
 
     /// For loop: for i in 0..10 { } or for i: u8 in 0..10 { }
     For {
@@ -105,6 +111,8 @@ pub enum Stmt {
         var_type: Option<Spanned<TypeExpr>>,
         range: Range,
         body: Box<Spanned<Stmt>>,
+        /// Maximum iterations, from `#[bound(N)]`
+        bound: Option<u32>,
     },
 
     /// For-each over slice/string: for item in data { } or for item: u8 in data { }
@@ -116,6 +124,8 @@ pub enum Stmt {
         body: Box<Spanned<Stmt>>,
         /// Optional index variable name for tuple destructuring: for (i, c) in msg { }
         index_var: Option<Spanned<String>>,
+        /// Maximum iterations, from `#[bound(N)]`
+        bound: Option<u32>,
     },
 
     /// Match statement
@@ -3,8 +3,12 @@
 //! Helper for generating formatted 6502 assembly code.
 
 use super::memory_layout::{MemoryLayout, TempAllocator};
+use super::peephole::PeepholeOptions;
 use super::regstate::{RegisterState, RegisterValue};
-use super::CommentVerbosity;
+use super::stack_frame::StackFrame;
+use super::{CodegenError, CommentVerbosity, OutputMode};
+use crate::assembler::Cpu;
+use crate::ast::Span;
 
 /// Loop context for break/continue statements
 #[derive(Debug, Clone)]
@@ -32,14 +36,34 @@ pub struct Emitter {
     inline_depth: u32,
     /// Suffix for uniquifying labels in current inline expansion
     inline_label_suffix: Option<usize>,
-    /// Current byte count (tracks code size during generation)
-    byte_count: u16,
     /// Track if the last instruction was a terminal instruction (RTS, RTI, or unconditional JMP)
     last_was_terminal: bool,
     /// Comment verbosity level
     pub verbosity: CommentVerbosity,
+    /// How code and data are placed (`.ORG` or ca65 segments)
+    pub output_mode: OutputMode,
+    /// Generate `#[test]` functions (test images only)
+    pub include_tests: bool,
+    /// Processor the code is generated for
+    pub cpu: Cpu,
+    /// Peephole passes run on the output, and on functions when sizing them
+    pub peephole: PeepholeOptions,
+    /// Every placement emitted so far, as (address, segment)
+    pub placements: Vec<(u16, String)>,
+    /// Source span of the statement currently being generated
+    current_span: Option<Span>,
+    /// Byte offset in `output` of each instruction with a known span
+    instruction_spans: Vec<(usize, Span)>,
     /// Current function being generated (for tail call detection)
     current_function: Option<String>,
+    /// Iteration bounds of loops, as (label, most jumps back to it)
+    pub loop_bounds: Vec<(String, u32)>,
+    /// 65816 stack frame of the function being generated
+    frame: Option<StackFrame>,
+    /// Frame of the last function generated, once it's finished
+    pub last_frame: Option<StackFrame>,
+    /// Locals kept in stack frames, as (function, zero-page address)
+    pub stack_locals: Vec<(String, u8)>,
     /// Track if mul16 stdlib function is needed
     pub needs_mul16: bool,
     /// Track if div16 stdlib function is needed
@@ -67,16 +91,32 @@ impl Emitter {
             loop_stack: Vec::new(),
             inline_depth: 0,
             inline_label_suffix: None,
-            byte_count: 0,
             last_was_terminal: false,
             verbosity,
+            output_mode: OutputMode::default(),
+            include_tests: false,
+            cpu: Cpu::default(),
+            peephole: PeepholeOptions::default(),
+            placements: Vec::new(),
+            current_span: None,
+            instruction_spans: Vec::new(),
             current_function: None,
+            loop_bounds: Vec::new(),
+            frame: None,
+            last_frame: None,
+            stack_locals: Vec::new(),
             needs_mul16: false,
             needs_div16: false,
             needs_mod16: false,
         }
     }
 
+    /// Generate code for `layout`, moving the temp pools with it
+    pub fn set_memory_layout(&mut self, layout: MemoryLayout) {
+        self.temp_alloc = TempAllocator::for_layout(&layout);
+        self.memory_layout = layout;
+    }
+
     /// Check if verbosity is set to minimal
     pub fn is_minimal(&self) -> bool {
         self.verbosity == CommentVerbosity::Minimal
@@ -106,6 +146,64 @@ impl Emitter {
     }
 
     pub fn emit_inst(&mut self, mnemonic: &str, operand: &str) {
+        let Some(frame) = &mut self.frame else {
+            self.push_inst(mnemonic, operand);
+            return;
+        };
+        let rewritten = frame.rewrite(mnemonic, operand);
+        frame.track(mnemonic, operand);
+        if mnemonic == "RTS" {
+            self.release_frame();
+        }
+        match rewritten {
+            Some(instructions) => {
+                for (mnemonic, operand) in instructions {
+                    self.push_inst(mnemonic, &operand);
+                }
+            }
+            None => self.push_inst(mnemonic, operand),
+        }
+    }
+
+    /// Reserve a stack frame for `locals` and send their accesses to it
+    pub fn begin_frame(&mut self, locals: &[(u8, u8)]) {
+        let frame = StackFrame::new(locals);
+        self.emit_comment(&format!("Stack frame: {} bytes of locals", frame.size()));
+        self.push_inst("REP", "#$20");
+        self.push_inst("TSC", "");
+        self.push_inst("SEC", "");
+        self.push_inst("SBC", &format!("#${:04X}", frame.size()));
+        self.push_inst("TCS", "");
+        self.push_inst("SEP", "#$20");
+        self.reg_state.invalidate_all();
+        self.frame = Some(frame);
+    }
+
+    /// Stop rewriting locals at the end of the function
+    pub fn end_frame(&mut self) {
+        self.last_frame = self.frame.take();
+    }
+
+    /// Give the frame back before returning; the result stays in A and Y
+    fn release_frame(&mut self) {
+        let Some(frame) = &self.frame else {
+            return;
+        };
+        let size = frame.size();
+        self.push_inst("TAX", "");
+        self.push_inst("REP", "#$20");
+        self.push_inst("TSC", "");
+        self.push_inst("CLC", "");
+        self.push_inst("ADC", &format!("#${:04X}", size));
+        self.push_inst("TCS", "");
+        self.push_inst("SEP", "#$20");
+        self.push_inst("TXA", "");
+    }
+
+    fn push_inst(&mut self, mnemonic: &str, operand: &str) {
+        if let Some(span) = self.current_span {
+            self.instruction_spans.push((self.output.len(), span));
+        }
         self.output.push_str("    ");
         self.output.push_str(mnemonic);
         if !operand.is_empty() {
@@ -114,9 +212,6 @@ impl Emitter {
         }
         self.output.push('\n');
 
-        // Track byte count
-        self.byte_count += Self::instruction_size(mnemonic, operand);
-
         // Track if this is a terminal instruction (RTS, RTI, or unconditional JMP)
         self.last_was_terminal = matches!(mnemonic, "RTS" | "RTI" | "JMP");
     }
@@ -136,6 +231,22 @@ impl Emitter {
         self.output.push_str(&format!(".ORG ${:04X}\n", address));
     }
 
+    /// Start a placement block: `.ORG` in absolute mode, `.segment` in ca65 mode
+    pub fn emit_placement(&mut self, address: u16, segment: &str) {
+        self.placements.push((address, segment.to_string()));
+        match self.output_mode {
+            OutputMode::Absolute => self.emit_org(address),
+            OutputMode::Ca65 => self
+                .output
+                .push_str(&format!(".segment \"{}\"\n", segment)),
+        }
+    }
+
+    /// Check if output uses ca65 segments instead of absolute addresses
+    pub fn is_ca65(&self) -> bool {
+        self.output_mode == OutputMode::Ca65
+    }
+
     pub fn emit_word(&mut self, value: u16) {
         self.output.push_str(&format!(".WORD ${:04X}\n", value));
     }
@@ -146,7 +257,6 @@ impl Emitter {
 
     pub fn emit_byte(&mut self, value: u8) {
         self.output.push_str(&format!(".BYTE ${:02X}\n", value));
-        self.byte_count += 1;
     }
 
     pub fn emit_bytes(&mut self, values: &[u8]) {
@@ -162,7 +272,6 @@ impl Emitter {
             self.output.push_str(&format!("${:02X}", byte));
         }
         self.output.push('\n');
-        self.byte_count += values.len() as u16;
     }
 
     pub fn finish(mut self) -> String {
@@ -173,72 +282,39 @@ impl Emitter {
         self.output
     }
 
-    /// Get the current byte count (code size)
-    pub fn byte_count(&self) -> u16 {
-        self.byte_count
+    /// Finish and also return the source span of each output line
+    pub fn finish_with_spans(self) -> (String, Vec<Option<Span>>) {
+        let mut offsets = self.instruction_spans.iter().peekable();
+        let mut line_spans = Vec::new();
+        let mut line_start = 0;
+        for line in self.output.split_inclusive('\n') {
+            let mut span = None;
+            while let Some(&&(offset, s)) = offsets.peek()
+                && offset < line_start + line.len()
+            {
+                span = Some(s);
+                offsets.next();
+            }
+            line_spans.push(span);
+            line_start += line.len();
+        }
+        (self.finish(), line_spans)
     }
 
-    /// Reset the byte counter (used for measuring individual functions)
-    pub fn reset_byte_count(&mut self) {
-        self.byte_count = 0;
-    }
+    // ========================================================================
+    // SOURCE SPAN TRACKING (for listings and source maps)
+    // ========================================================================
 
-    /// Calculate the size of a 6502 instruction in bytes
-    fn instruction_size(mnemonic: &str, operand: &str) -> u16 {
-        if operand.is_empty() {
-            // Implied or accumulator mode (1 byte)
-            match mnemonic {
-                "RTS" | "RTI" | "PHA" | "PLA" | "PHP" | "PLP" | "TAX" | "TAY" | "TXA" | "TYA"
-                | "TXS" | "TSX" | "INX" | "INY" | "DEX" | "DEY" | "CLC" | "SEC" | "CLI" | "SEI"
-                | "CLD" | "SED" | "CLV" | "NOP" | "BRK" | "ASL" | "LSR" | "ROL" | "ROR" => 1,
-                _ => 1, // Default for unknown implied
-            }
-        } else if operand.starts_with('#') {
-            // Immediate mode (2 bytes)
-            2
-        } else if operand.starts_with('(') {
-            // Indirect modes
-            if operand.contains("),Y") || operand.contains("),y") {
-                // Indirect indexed: (zp),Y (2 bytes)
-                2
-            } else if operand.contains(",X)") || operand.contains(",x)") {
-                // Indexed indirect: (zp,X) (2 bytes)
-                2
-            } else {
-                // Indirect: (addr) (3 bytes for JMP)
-                3
-            }
-        } else if operand.contains(",X")
-            || operand.contains(",x")
-            || operand.contains(",Y")
-            || operand.contains(",y")
-        {
-            // Indexed addressing
-            if operand.starts_with('$') && operand.len() <= 4 {
-                // $XX format
-                // Zero page indexed (2 bytes)
-                2
-            } else {
-                // Absolute indexed (3 bytes)
-                3
-            }
-        } else if mnemonic.starts_with('B') && mnemonic != "BIT" {
-            // Branch instructions (2 bytes)
-            2
-        } else if operand.starts_with('$') {
-            // Direct addressing
-            let hex_part = operand.trim_start_matches('$');
-            if hex_part.len() <= 2 {
-                // Zero page (2 bytes)
-                2
-            } else {
-                // Absolute (3 bytes)
-                3
-            }
-        } else {
-            // Label reference or symbol - assume 3 bytes (absolute)
-            3
+    /// Attribute the following instructions to a source span
+    ///
+    /// Returns the previous span so nested statements can restore it.
+    /// Inline expansions keep the call site's span.
+    pub fn set_span(&mut self, span: Option<Span>) -> Option<Span> {
+        let previous = self.current_span;
+        if !self.is_inlining() {
+            self.current_span = span;
         }
+        previous
     }
 
     // ========================================================================
@@ -314,6 +390,33 @@ impl Emitter {
         self.reg_state.modify_a();
     }
 
+    /// Load A from an addr declaration above $FFFF (65816 long addressing)
+    pub fn emit_lda_long_symbol(&mut self, symbol: &str) -> Result<(), CodegenError> {
+        self.check_long_addressing(symbol)?;
+        self.emit_inst("LDA", &format!("f:{}", symbol));
+        self.reg_state.modify_a();
+        Ok(())
+    }
+
+    /// Store A to an addr declaration above $FFFF (65816 long addressing)
+    pub fn emit_sta_long_symbol(&mut self, symbol: &str) -> Result<(), CodegenError> {
+        self.check_long_addressing(symbol)?;
+        self.emit_inst("STA", &format!("f:{}", symbol));
+        self.reg_state.modify_a();
+        Ok(())
+    }
+
+    fn check_long_addressing(&self, symbol: &str) -> Result<(), CodegenError> {
+        if self.cpu.has_native_mode() {
+            return Ok(());
+        }
+        Err(CodegenError::UnsupportedOperation(format!(
+            "'{}' is above $FFFF, which the {} cannot address; use --cpu 65816",
+            symbol,
+            self.cpu.name()
+        )))
+    }
+
     /// Invalidate all register tracking (call on branches, function calls, etc.)
     pub fn invalidate_registers(&mut self) {
         self.reg_state.invalidate_all();
@@ -394,6 +497,11 @@ impl Emitter {
         });
     }
 
+    /// Record that the loop at `label` jumps back to it at most `iterations` times
+    pub fn record_loop_bound(&mut self, label: &str, iterations: u32) {
+        self.loop_bounds.push((label.to_string(), iterations));
+    }
+
     /// Pop the current loop context from the stack
     pub fn pop_loop(&mut self) {
         self.loop_stack.pop();
@@ -505,10 +613,11 @@ impl Emitter {
     /// After push, $FF is incremented by 8
     pub fn push_params(&mut self) {
         let param_base = self.memory_layout.param_base;
+        let stack_pointer = format!("${:02X}", self.memory_layout.stack_pointer);
 
         // X will be used as index for the push loop
         // Load stack pointer into X
-        self.emit_inst("LDX", "$FF");
+        self.emit_inst("LDX", &stack_pointer);
 
         // Push all 8 parameter bytes
         for i in 0..8u8 {
@@ -521,7 +630,7 @@ impl Emitter {
 
         // Increment stack pointer by 8
         self.emit_inst("INX", ""); // One more to point to next free spot
-        self.emit_inst("STX", "$FF");
+        self.emit_inst("STX", &stack_pointer);
 
         // Invalidate register state after stack operations
         self.reg_state.invalidate_all();
@@ -531,13 +640,14 @@ impl Emitter {
     /// Decrements stack pointer by 8, then loads 8 bytes
     pub fn pop_params(&mut self) {
         let param_base = self.memory_layout.param_base;
+        let stack_pointer = format!("${:02X}", self.memory_layout.stack_pointer);
 
         // Decrement stack pointer by 8
-        self.emit_inst("LDX", "$FF");
+        self.emit_inst("LDX", &stack_pointer);
         for _ in 0..8 {
             self.emit_inst("DEX", "");
         }
-        self.emit_inst("STX", "$FF");
+        self.emit_inst("STX", &stack_pointer);
 
         // Pop all 8 parameter bytes
         for i in 0..8u8 {
@@ -42,17 +42,18 @@ pub(super) fn generate_index(
 
         // Get string pointer
         generate_expr(object, emitter, info, string_collector)?;
-        emitter.emit_inst("STA", "$F0");
-        emitter.emit_inst("STX", "$F1");
+        let string_ptr = emitter.memory_layout.operand_save;
+        emitter.emit_inst("STA", &format!("${:02X}", string_ptr));
+        emitter.emit_inst("STX", &format!("${:02X}", string_ptr + 1));
 
         // Skip length prefix (add 1 to pointer for u8 length)
         if emitter.is_verbose() {
             emitter.emit_comment("Add 1 to pointer to skip u8 length prefix");
         }
         let skip_label = emitter.next_label("si");
-        emitter.emit_inst("INC", "$F0");
+        emitter.emit_inst("INC", &format!("${:02X}", string_ptr));
         emitter.emit_inst("BNE", &skip_label);
-        emitter.emit_inst("INC", "$F1");
+        emitter.emit_inst("INC", &format!("${:02X}", string_ptr + 1));
         emitter.emit_label(&skip_label);
 
         // Get index in Y
@@ -60,7 +61,7 @@ pub(super) fn generate_index(
         emitter.emit_inst("TAY", "");
 
         // Load byte
-        emitter.emit_inst("LDA", "($F0),Y");
+        emitter.emit_inst("LDA", &format!("(${:02X}),Y", string_ptr));
         emitter.reg_state.modify_a();
 
         return Ok(());
@@ -123,6 +124,12 @@ pub(super) fn generate_index(
                     emitter.reg_state.modify_a();
                     Ok(())
                 }
+                crate::sema::table::SymbolLocation::Long(_) => {
+                    Err(CodegenError::UnsupportedOperation(format!(
+                        "cannot index '{}', which is not an array",
+                        name
+                    )))
+                }
                 crate::sema::table::SymbolLocation::None => {
                     // Compile-time constants don't have runtime storage
                     Err(CodegenError::UnsupportedOperation(
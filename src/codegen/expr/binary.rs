@@ -19,6 +19,7 @@ use super::{
 
 // Import generate_expr from parent module for recursive calls
 use super::generate_expr;
+use crate::codegen::stmt::word_operand;
 
 /// Check if an expression is "simple" - can be re-evaluated cheaply without side effects
 fn is_simple_expr(expr: &Expr) -> bool {
@@ -101,46 +102,64 @@ pub(super) fn generate_binary(
             }
 
             // Optimization: x / 256 → x.high (for u16 only)
-            crate::ast::BinaryOp::Div => {
-                if is_u16 && val_u64 == 256 {
-                    if emitter.is_verbose() {
-                        emitter.emit_comment("Strength reduction: x / 256 → x.high");
-                    }
+            crate::ast::BinaryOp::Div if is_u16 && val_u64 == 256 => {
+                if emitter.is_verbose() {
+                    emitter.emit_comment("Strength reduction: x / 256 → x.high");
+                }
 
-                    // Generate left operand (result in A=low, Y=high)
-                    generate_expr(left, emitter, info, string_collector)?;
+                // Generate left operand (result in A=low, Y=high)
+                generate_expr(left, emitter, info, string_collector)?;
 
-                    // Move high byte to A
-                    emitter.emit_inst("TYA", "");
-                    if emitter.is_verbose() {
-                        emitter.emit_comment("Extract high byte");
-                    }
-                    return Ok(());
+                // Move high byte to A
+                emitter.emit_inst("TYA", "");
+                if emitter.is_verbose() {
+                    emitter.emit_comment("Extract high byte");
                 }
+                return Ok(());
             }
 
             // Optimization: x % 256 → x.low (for u16 only)
-            crate::ast::BinaryOp::Mod => {
-                if is_u16 && val_u64 == 256 {
-                    if emitter.is_verbose() {
-                        emitter.emit_comment("Strength reduction: x % 256 → x.low");
-                    }
+            crate::ast::BinaryOp::Mod if is_u16 && val_u64 == 256 => {
+                if emitter.is_verbose() {
+                    emitter.emit_comment("Strength reduction: x % 256 → x.low");
+                }
 
-                    // Generate left operand (result in A=low, Y=high)
-                    generate_expr(left, emitter, info, string_collector)?;
+                // Generate left operand (result in A=low, Y=high)
+                generate_expr(left, emitter, info, string_collector)?;
 
-                    // Low byte is already in A, just clear Y to indicate u8 result
-                    if emitter.is_verbose() {
-                        emitter.emit_comment("Low byte already in A");
-                    }
-                    return Ok(());
+                // Low byte is already in A, just clear Y to indicate u8 result
+                if emitter.is_verbose() {
+                    emitter.emit_comment("Low byte already in A");
                 }
+                return Ok(());
             }
 
             _ => {}
         }
     }
 
+    // The 65816 handles whole words with its 16-bit accumulator
+    let is_word = left_type.is_some_and(|ty| {
+        matches!(
+            ty,
+            Type::Primitive(crate::ast::PrimitiveType::U16)
+                | Type::Primitive(crate::ast::PrimitiveType::I16)
+        )
+    });
+    if is_word
+        && emitter.cpu.has_native_mode()
+        && matches!(
+            op,
+            crate::ast::BinaryOp::Add
+                | crate::ast::BinaryOp::Sub
+                | crate::ast::BinaryOp::BitAnd
+                | crate::ast::BinaryOp::BitOr
+                | crate::ast::BinaryOp::BitXor
+        )
+    {
+        return generate_wide_binary(left, op, right, emitter, info, string_collector);
+    }
+
     // Optimization: Avoid stack if left operand is simple (variable or literal)
     let use_stack = !is_simple_expr(&left.node);
 
@@ -170,7 +189,7 @@ pub(super) fn generate_binary(
 
         if is_u16 {
             // 2a. For u16: Save BOTH bytes to allocated temp storage
-            let save_addr = left_save_addr.unwrap_or(0xF2); // Fallback to hardcoded if alloc failed
+            let save_addr = left_save_addr.unwrap_or(emitter.memory_layout.operand_save + 2); // Fallback if alloc failed
             emitter.emit_inst("STA", &format!("${:02X}", save_addr));
             emitter.emit_inst("STY", &format!("${:02X}", save_addr + 1));
         } else {
@@ -199,7 +218,7 @@ pub(super) fn generate_binary(
         // 5. Restore left operand
         if is_u16 {
             // 5a. For u16: Load BOTH bytes from allocated temp storage
-            let save_addr = left_save_addr.unwrap_or(0xF2);
+            let save_addr = left_save_addr.unwrap_or(emitter.memory_layout.operand_save + 2);
             emitter.emit_inst("LDA", &format!("${:02X}", save_addr));
             emitter.emit_inst("LDY", &format!("${:02X}", save_addr + 1));
             emitter.reg_state.invalidate_all();
@@ -384,6 +403,81 @@ pub(super) fn generate_binary(
 
     Ok(())
 }
+/// Word `+`, `-`, `&`, `|` and `^` with the 65816 accumulator switched to 16 bits
+///
+/// Variables and constants are used in place. Other operands are computed
+/// into A/Y first and parked in temp storage, the left one before the right
+/// as in the byte-pair path. The result goes back to A (low) and Y (high)
+/// through the hidden B accumulator.
+fn generate_wide_binary(
+    left: &Spanned<Expr>,
+    op: crate::ast::BinaryOp,
+    right: &Spanned<Expr>,
+    emitter: &mut Emitter,
+    info: &ProgramInfo,
+    string_collector: &mut StringCollector,
+) -> Result<(), CodegenError> {
+    use crate::ast::BinaryOp;
+
+    let mut left_save = None;
+    let left_operand = match word_operand(left, info) {
+        Some(operand) => operand,
+        None => {
+            // A call may clobber parameters the right operand reads
+            let needs_param_save = matches!(left.node, Expr::Call { .. });
+            if needs_param_save {
+                emitter.push_params();
+            }
+            generate_expr(left, emitter, info, string_collector)?;
+            left_save = emitter.temp_alloc.alloc_high(2);
+            let save_addr = left_save.unwrap_or(emitter.memory_layout.operand_save + 2);
+            emitter.emit_inst("STA", &format!("${:02X}", save_addr));
+            emitter.emit_inst("STY", &format!("${:02X}", save_addr + 1));
+            if needs_param_save {
+                emitter.pop_params();
+            }
+            format!("${:02X}", save_addr)
+        }
+    };
+    let right_operand = match word_operand(right, info) {
+        Some(operand) => operand,
+        None => {
+            generate_expr(right, emitter, info, string_collector)?;
+            let temp_reg = emitter.memory_layout.temp_reg();
+            emitter.emit_sta_zp(temp_reg);
+            emitter.emit_inst("STY", &format!("${:02X}", temp_reg + 1));
+            format!("${:02X}", temp_reg)
+        }
+    };
+
+    emitter.emit_inst("REP", "#$20");
+    emitter.emit_inst("LDA", &left_operand);
+    match op {
+        BinaryOp::Add => {
+            emitter.emit_inst("CLC", "");
+            emitter.emit_inst("ADC", &right_operand);
+        }
+        BinaryOp::Sub => {
+            emitter.emit_inst("SEC", "");
+            emitter.emit_inst("SBC", &right_operand);
+        }
+        BinaryOp::BitAnd => emitter.emit_inst("AND", &right_operand),
+        BinaryOp::BitOr => emitter.emit_inst("ORA", &right_operand),
+        BinaryOp::BitXor => emitter.emit_inst("EOR", &right_operand),
+        _ => unreachable!("only word arithmetic and bitwise ops are generated wide"),
+    }
+    emitter.emit_inst("SEP", "#$20");
+    emitter.emit_inst("XBA", "");
+    emitter.emit_inst("TAY", "");
+    emitter.emit_inst("XBA", "");
+    emitter.reg_state.invalidate_all();
+
+    if let Some(save_addr) = left_save {
+        emitter.temp_alloc.free_high(save_addr, 2);
+    }
+    Ok(())
+}
+
 // Shift helper functions
 // A contains value to shift, emitter.memory_layout.temp_reg() contains shift amount
 
@@ -407,6 +501,7 @@ fn generate_shift_left(emitter: &mut Emitter, _is_u16: bool) -> Result<(), Codeg
 
     // Loop: shift left once per iteration
     emitter.emit_label(&loop_label);
+    emitter.record_loop_bound(&loop_label, 254);
     emitter.emit_inst("ASL", "A"); // Arithmetic shift left
     emitter.emit_inst("DEX", "");
     emitter.emit_inst("BNE", &loop_label);
@@ -450,6 +545,7 @@ fn generate_shift_right(emitter: &mut Emitter, is_u16: bool) -> Result<(), Codeg
 
         let loop_label = emitter.next_label("sr");
         emitter.emit_label(&loop_label);
+        emitter.record_loop_bound(&loop_label, 254);
         emitter.emit_inst("LSR", "A");
         emitter.emit_inst("DEX", "");
         emitter.emit_inst("BNE", &loop_label);
@@ -469,6 +565,7 @@ fn generate_shift_right(emitter: &mut Emitter, is_u16: bool) -> Result<(), Codeg
 
         // Loop: shift right once per iteration
         emitter.emit_label(&loop_label);
+        emitter.record_loop_bound(&loop_label, 254);
         emitter.emit_inst("LSR", "A"); // Logical shift right
         emitter.emit_inst("DEX", "");
         emitter.emit_inst("BNE", &loop_label);
@@ -507,8 +604,14 @@ fn generate_multiply_u8(emitter: &mut Emitter) -> Result<(), CodegenError> {
     //     multiplier >>= 1
 
     // Allocate temp storage
-    let multiplicand = emitter.temp_alloc.alloc_high(1).unwrap_or(0xF0);
-    let result_addr = emitter.temp_alloc.alloc_primary(1).unwrap_or(0x22);
+    let multiplicand = emitter
+        .temp_alloc
+        .alloc_high(1)
+        .unwrap_or(emitter.memory_layout.operand_save);
+    let result_addr = emitter
+        .temp_alloc
+        .alloc_primary(1)
+        .unwrap_or(emitter.memory_layout.temp_reg() + 2);
     let temp = emitter.memory_layout.temp_reg();
 
     let loop_label = emitter.next_label("ml");
@@ -526,6 +629,7 @@ fn generate_multiply_u8(emitter: &mut Emitter) -> Result<(), CodegenError> {
 
     // Loop for each bit
     emitter.emit_label(&loop_label);
+    emitter.record_loop_bound(&loop_label, 7);
 
     // Check if multiplier bit 0 is set
     emitter.emit_inst("LDA", &format!("${:02X}", temp));
@@ -571,16 +675,17 @@ fn generate_multiply_u16(emitter: &mut Emitter) -> Result<(), CodegenError> {
     emitter.needs_mul16 = true;
 
     // mul16 expects parameters at $80-$83
+    let params = emitter.memory_layout.param_base;
     // Store left operand (A:Y) to $80-$81
-    emitter.emit_inst("STA", "$80"); // Store low byte
-    emitter.emit_inst("STY", "$81"); // Store high byte
+    emitter.emit_inst("STA", &format!("${:02X}", params)); // Store low byte
+    emitter.emit_inst("STY", &format!("${:02X}", params + 1)); // Store high byte
 
     // Store right operand (TEMP:TEMP+1) to $82-$83
     let temp = emitter.memory_layout.temp_reg();
     emitter.emit_inst("LDA", &format!("${:02X}", temp)); // Load right.low
-    emitter.emit_inst("STA", "$82");
+    emitter.emit_inst("STA", &format!("${:02X}", params + 2));
     emitter.emit_inst("LDA", &format!("${:02X}", temp + 1)); // Load right.high
-    emitter.emit_inst("STA", "$83");
+    emitter.emit_inst("STA", &format!("${:02X}", params + 3));
 
     // Call mul16
     emitter.emit_inst("JSR", "mul16");
@@ -601,7 +706,10 @@ fn generate_divide(emitter: &mut Emitter, is_u16: bool) -> Result<(), CodegenErr
     // Result (quotient) in A
 
     // Allocate temp storage
-    let quotient_addr = emitter.temp_alloc.alloc_primary(2).unwrap_or(0x22);
+    let quotient_addr = emitter
+        .temp_alloc
+        .alloc_primary(2)
+        .unwrap_or(emitter.memory_layout.temp_reg() + 2);
     let dividend_addr = quotient_addr + 1;
 
     let loop_label = emitter.next_label("dl");
@@ -621,6 +729,7 @@ fn generate_divide(emitter: &mut Emitter, is_u16: bool) -> Result<(), CodegenErr
 
     // Loop: subtract divisor from dividend until dividend < divisor
     emitter.emit_label(&loop_label);
+    emitter.record_loop_bound(&loop_label, 255);
     emitter.emit_inst("LDA", &format!("${:02X}", dividend_addr));
     emitter.emit_inst("CMP", &format!("${:02X}", emitter.memory_layout.temp_reg()));
     emitter.emit_inst("BCC", &end_label); // If dividend < divisor, done
@@ -656,16 +765,17 @@ fn generate_divide_u16(emitter: &mut Emitter) -> Result<(), CodegenError> {
     emitter.needs_div16 = true;
 
     // div16 expects parameters at $80-$83
+    let params = emitter.memory_layout.param_base;
     // Store left operand (A:Y) to $80-$81
-    emitter.emit_inst("STA", "$80"); // Store low byte
-    emitter.emit_inst("STY", "$81"); // Store high byte
+    emitter.emit_inst("STA", &format!("${:02X}", params)); // Store low byte
+    emitter.emit_inst("STY", &format!("${:02X}", params + 1)); // Store high byte
 
     // Store right operand (TEMP:TEMP+1) to $82-$83
     let temp = emitter.memory_layout.temp_reg();
     emitter.emit_inst("LDA", &format!("${:02X}", temp)); // Load right.low
-    emitter.emit_inst("STA", "$82");
+    emitter.emit_inst("STA", &format!("${:02X}", params + 2));
     emitter.emit_inst("LDA", &format!("${:02X}", temp + 1)); // Load right.high
-    emitter.emit_inst("STA", "$83");
+    emitter.emit_inst("STA", &format!("${:02X}", params + 3));
 
     // Call div16
     emitter.emit_inst("JSR", "div16");
@@ -686,7 +796,10 @@ fn generate_modulo(emitter: &mut Emitter, is_u16: bool) -> Result<(), CodegenErr
     // Result (remainder) in A
 
     // Allocate temp storage
-    let dividend_addr = emitter.temp_alloc.alloc_primary(1).unwrap_or(0x23);
+    let dividend_addr = emitter
+        .temp_alloc
+        .alloc_primary(1)
+        .unwrap_or(emitter.memory_layout.temp_reg() + 3);
 
     let loop_label = emitter.next_label("md");
     let end_label = emitter.next_label("mx");
@@ -701,6 +814,7 @@ fn generate_modulo(emitter: &mut Emitter, is_u16: bool) -> Result<(), CodegenErr
 
     // Loop: subtract divisor from dividend until dividend < divisor
     emitter.emit_label(&loop_label);
+    emitter.record_loop_bound(&loop_label, 255);
     emitter.emit_inst("LDA", &format!("${:02X}", dividend_addr));
     emitter.emit_inst("CMP", &format!("${:02X}", emitter.memory_layout.temp_reg()));
     emitter.emit_inst("BCC", &end_label); // If dividend < divisor, done (A has remainder)
@@ -733,16 +847,17 @@ fn generate_modulo_u16(emitter: &mut Emitter) -> Result<(), CodegenError> {
     emitter.needs_mod16 = true;
 
     // mod16 expects parameters at $80-$83
+    let params = emitter.memory_layout.param_base;
     // Store left operand (A:Y) to $80-$81
-    emitter.emit_inst("STA", "$80"); // Store low byte
-    emitter.emit_inst("STY", "$81"); // Store high byte
+    emitter.emit_inst("STA", &format!("${:02X}", params)); // Store low byte
+    emitter.emit_inst("STY", &format!("${:02X}", params + 1)); // Store high byte
 
     // Store right operand (TEMP:TEMP+1) to $82-$83
     let temp = emitter.memory_layout.temp_reg();
     emitter.emit_inst("LDA", &format!("${:02X}", temp)); // Load right.low
-    emitter.emit_inst("STA", "$82");
+    emitter.emit_inst("STA", &format!("${:02X}", params + 2));
     emitter.emit_inst("LDA", &format!("${:02X}", temp + 1)); // Load right.high
-    emitter.emit_inst("STA", "$83");
+    emitter.emit_inst("STA", &format!("${:02X}", params + 3));
 
     // Call mod16
     emitter.emit_inst("JSR", "mod16");
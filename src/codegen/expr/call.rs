@@ -117,7 +117,10 @@ pub(super) fn generate_call(
     }
 
     // Allocate temp storage for all arguments at once
-    let temp_base = emitter.temp_alloc.alloc_arg(total_bytes).unwrap_or(0xF4);
+    let temp_base = emitter
+        .temp_alloc
+        .alloc_arg(total_bytes)
+        .unwrap_or(emitter.memory_layout.arg_temps);
     let mut temp_offset = 0u8;
     let mut arg_info = Vec::new(); // Track argument sizes and temp locations
 
@@ -466,6 +469,8 @@ fn generate_inline_call(
             tail_call_info: info.tail_call_info.clone(),
             resolved_struct_names: info.resolved_struct_names.clone(),
             string_pool: info.string_pool.clone(),
+            memory_config: info.memory_config.clone(),
+            memory_layout: info.memory_layout.clone(),
         };
 
         use crate::codegen::stmt::generate_stmt;
@@ -524,7 +529,10 @@ pub fn generate_tail_recursive_update(
     }
 
     // Allocate temp storage for all arguments at once
-    let temp_base = emitter.temp_alloc.alloc_arg(total_bytes).unwrap_or(0xF4);
+    let temp_base = emitter
+        .temp_alloc
+        .alloc_arg(total_bytes)
+        .unwrap_or(emitter.memory_layout.arg_temps);
     let mut temp_offset = 0u8;
     let mut arg_info = Vec::new();
 
@@ -52,10 +52,11 @@ pub(super) fn generate_type_cast(
     if source_is_enum {
         emitter.emit_comment("Dereference enum pointer to get discriminant");
         // A = low byte of pointer, X = high byte
-        emitter.emit_inst("STA", "$20");
-        emitter.emit_inst("STX", "$21");
+        let temp = emitter.memory_layout.temp_reg();
+        emitter.emit_inst("STA", &format!("${:02X}", temp));
+        emitter.emit_inst("STX", &format!("${:02X}", temp + 1));
         emitter.emit_inst("LDY", "#$00");
-        emitter.emit_inst("LDA", "($20),Y");
+        emitter.emit_inst("LDA", &format!("(${:02X}),Y", temp));
         // Now A contains the discriminant value
     }
 
@@ -297,6 +297,7 @@ pub(super) fn generate_variable(
                 }
                 Ok(())
             }
+            SymbolLocation::Long(_) => emitter.emit_lda_long_symbol(name),
             SymbolLocation::None => Err(CodegenError::UnsupportedOperation(format!(
                 "Variable '{}' has no storage location",
                 name
@@ -160,13 +160,14 @@ pub fn generate_expr(
                         generate_expr(object, emitter, info, string_collector)?;
 
                         // Store pointer to temp location ($F0-$F1)
-                        emitter.emit_inst("STA", "$F0");
-                        emitter.emit_inst("STX", "$F1");
+                        let string_ptr = emitter.memory_layout.operand_save;
+                        emitter.emit_inst("STA", &format!("${:02X}", string_ptr));
+                        emitter.emit_inst("STX", &format!("${:02X}", string_ptr + 1));
 
                         // Load length (single byte) via indirect indexed
                         // Result is u8 in A, zero-extended to u16 in Y:A
                         emitter.emit_inst("LDY", "#$00");
-                        emitter.emit_inst("LDA", "($F0),Y"); // Load length byte
+                        emitter.emit_inst("LDA", &format!("(${:02X}),Y", string_ptr)); // Load length byte
                         // Length is always <= 255, so high byte is 0
                         emitter.emit_inst("LDY", "#$00"); // High byte = 0
                         // Result: length in A (low byte), Y = 0 (high byte)
@@ -388,18 +389,24 @@ fn generate_match_expr(
     // Evaluate the matched expression
     generate_expr(match_expr, emitter, info, string_collector)?;
 
+    // Enum pointer at $20-$21 and tag at $22, or the plain value at $20
+    let temp = emitter.memory_layout.temp_reg();
+    let value = format!("${:02X}", temp);
+    let tag_addr = format!("${:02X}", temp + 2);
+    let payload = format!("(${:02X}),Y", temp);
+
     if is_enum_match {
         // For enum matching, expression returns a pointer in A:X
-        emitter.emit_inst("STA", "$20");
-        emitter.emit_inst("STX", "$21");
+        emitter.emit_inst("STA", &value);
+        emitter.emit_inst("STX", &format!("${:02X}", temp + 1));
 
         // Load the discriminant tag from the enum (first byte)
         emitter.emit_inst("LDY", "#$00");
-        emitter.emit_inst("LDA", "($20),Y");
-        emitter.emit_inst("STA", "$22"); // Store tag at $22
+        emitter.emit_inst("LDA", &payload);
+        emitter.emit_inst("STA", &tag_addr); // Store tag at $22
     } else {
         // For simple value matching, store value at $20
-        emitter.emit_inst("STA", "$20");
+        emitter.emit_inst("STA", &value);
     }
 
     // Generate code for each arm
@@ -420,7 +427,7 @@ fn generate_match_expr(
                         .position(|v| v.name == variant.node)
                 {
                     // Compare tag
-                    emitter.emit_inst("LDA", "$22");
+                    emitter.emit_inst("LDA", &tag_addr);
                     emitter.emit_inst("CMP", &format!("#${:02X}", tag));
                     emitter.emit_inst("BNE", &next_label);
 
@@ -428,7 +435,7 @@ fn generate_match_expr(
                     // This is a simplified version - assumes single u8 binding
                     if !bindings.is_empty() {
                         emitter.emit_inst("LDY", "#$01"); // Offset 1 = first payload byte
-                        emitter.emit_inst("LDA", "($20),Y");
+                        emitter.emit_inst("LDA", &payload);
                         // Value is now in A for the arm body to use
                     }
 
@@ -455,7 +462,7 @@ fn generate_match_expr(
             Pattern::Literal(lit_expr) => {
                 // Compare against literal
                 if let Expr::Literal(crate::ast::Literal::Integer(n)) = &lit_expr.node {
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &value);
                     emitter.emit_inst("CMP", &format!("#${:02X}", *n as u8));
                     emitter.emit_inst("BNE", &next_label);
                     generate_expr(&arm.body, emitter, info, string_collector)?;
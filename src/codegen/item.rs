@@ -3,9 +3,11 @@
 //! Handles generation of functions and other items.
 
 use crate::ast::{FnAttribute, Function, Item, PrimitiveType, Spanned, TypeExpr};
+use crate::codegen::peephole;
 use crate::codegen::section_allocator::{AllocationSource, SectionAllocator};
+use crate::codegen::stack_frame::{check_accesses, frame_locals};
 use crate::codegen::stmt::generate_stmt;
-use crate::codegen::{CodegenError, Emitter, StringCollector};
+use crate::codegen::{CodegenError, Emitter, StringCollector, measure_code};
 use crate::sema::ProgramInfo;
 
 /// Format a type for display in comments
@@ -45,9 +47,13 @@ pub fn generate_item(
     match &item.node {
         Item::Function(func) => {
             generate_function(func, emitter, info, section_alloc, string_collector)
+                .map_err(|error| error.at(func.name.span))
+        }
+        Item::Static(stat) => generate_static(stat, emitter, info, string_collector)
+            .map_err(|error| error.at(stat.name.span)),
+        Item::Address(addr) => {
+            generate_address(addr, emitter, info).map_err(|error| error.at(addr.name.span))
         }
-        Item::Static(stat) => generate_static(stat, emitter, info, string_collector),
-        Item::Address(addr) => generate_address(addr, emitter, info),
         _ => Ok(()),
     }
 }
@@ -68,104 +74,68 @@ fn generate_function(
         return Ok(());
     }
 
-    // Check if this is an interrupt handler (need to know for size calculation)
-    // Note: Reset is NOT an interrupt - it's the entry point, so no prologue/epilogue
-    let is_interrupt = func.attributes.iter().any(|attr| {
-        matches!(
-            attr,
-            FnAttribute::Interrupt | FnAttribute::Nmi | FnAttribute::Irq
-        )
-    });
+    // Tests only exist in the image built by `wraith test`
+    if !emitter.include_tests && func.attributes.contains(&FnAttribute::Test) {
+        return Ok(());
+    }
 
-    // First pass: Generate function into temporary emitter to measure size
-    let function_size = {
-        let mut temp_emitter = Emitter::new(emitter.verbosity);
-        // Copy register state and label counter to avoid label conflicts
-        temp_emitter.reg_state = emitter.reg_state.clone();
-        temp_emitter.label_counter = emitter.label_counter;
-        temp_emitter.match_counter = emitter.match_counter;
-        // Set current function for inline asm variable scoping
-        temp_emitter.set_current_function(name.clone());
-
-        // Include interrupt prologue size if needed (5 instructions = 10 bytes)
-        if is_interrupt {
-            temp_emitter.emit_inst("PHA", "");
-            temp_emitter.emit_inst("TXA", "");
-            temp_emitter.emit_inst("PHA", "");
-            temp_emitter.emit_inst("TYA", "");
-            temp_emitter.emit_inst("PHA", "");
-        }
+    // On the 65816 scalar locals live in a stack frame, except those an
+    // instruction without a stack-relative mode touches; a scratch
+    // generation finds them
+    let mut frame = if emitter.cpu.has_native_mode() {
+        frame_locals(func, info)
+    } else {
+        Vec::new()
+    };
+    if !frame.is_empty() {
+        let mut temp_emitter = scratch_emitter(emitter);
+        generate_function_body(func, &mut temp_emitter, info, string_collector, &frame)?;
+        let pinned = temp_emitter
+            .last_frame
+            .map(|f| f.pinned)
+            .unwrap_or_default();
+        frame.retain(|(addr, _)| !pinned.contains(addr));
+    }
 
-        // Generate function body to measure size
-        generate_stmt(&func.body, &mut temp_emitter, info, string_collector)?;
-
-        // Include epilogue size
-        if is_interrupt {
-            // 6 instructions for epilogue
-            temp_emitter.emit_inst("PLA", "");
-            temp_emitter.emit_inst("TAY", "");
-            temp_emitter.emit_inst("PLA", "");
-            temp_emitter.emit_inst("TAX", "");
-            temp_emitter.emit_inst("PLA", "");
-            temp_emitter.emit_inst("RTI", "");
-        } else if func.return_type.is_none() {
-            temp_emitter.emit_inst("RTS", "");
+    // First pass: generate the function into a scratch emitter and assemble
+    // it to get its exact size
+    let function_size = {
+        let mut temp_emitter = scratch_emitter(emitter);
+        generate_function_body(func, &mut temp_emitter, info, string_collector, &frame)?;
+        let code = temp_emitter.finish();
+        // The final code is optimized the same way as this copy
+        if !frame.is_empty() {
+            let optimized = peephole::optimize(
+                &peephole::parse_assembly(&code),
+                emitter.cpu,
+                &emitter.peephole,
+            );
+            check_accesses(name, &optimized)?;
         }
-
-        // Get the actual size + 10 bytes padding for safety
-        temp_emitter.byte_count() + 10
+        measure_code(&code, emitter.cpu, &emitter.peephole)?
     };
 
     // Determine function address
     // Priority: explicit org > section attribute > default section
-    let (_function_addr, _allocation_source) =
-        if let Some(metadata) = info.function_metadata.get(name) {
-            if let Some(org_addr) = metadata.org_address {
-                // Explicit org address takes precedence
-                emitter.emit_org(org_addr);
-                (org_addr, AllocationSource::ExplicitOrg)
-            } else if let Some(section_name) = &metadata.section {
-                // Allocate in specified section using actual measured size
-                let addr = section_alloc
-                    .allocate(section_name, function_size)
-                    .map_err(CodegenError::SectionError)?;
-                emitter.emit_org(addr);
-                (addr, AllocationSource::Section(section_name.clone()))
-            } else {
-                // Use default section (CODE)
-                let addr = section_alloc
-                    .allocate_default(function_size)
-                    .map_err(CodegenError::SectionError)?;
-                emitter.emit_org(addr);
-                (addr, AllocationSource::AutoAllocated)
-            }
-        } else {
-            // No metadata - use default section
-            let addr = section_alloc
-                .allocate_default(function_size)
-                .map_err(CodegenError::SectionError)?;
-            emitter.emit_org(addr);
-            (addr, AllocationSource::AutoAllocated)
-        };
     let (function_addr, allocation_source) =
         if let Some(metadata) = info.function_metadata.get(name) {
             if let Some(org_addr) = metadata.org_address {
                 // Explicit org address takes precedence
-                emitter.emit_org(org_addr);
+                emitter.emit_placement(org_addr, &org_segment_name(org_addr));
                 (org_addr, AllocationSource::ExplicitOrg)
             } else if let Some(section_name) = &metadata.section {
                 // Allocate in specified section using actual measured size
                 let addr = section_alloc
                     .allocate(section_name, function_size)
                     .map_err(CodegenError::SectionError)?;
-                emitter.emit_org(addr);
+                emitter.emit_placement(addr, section_name);
                 (addr, AllocationSource::Section(section_name.clone()))
             } else {
                 // Use default section (CODE)
                 let addr = section_alloc
                     .allocate_default(function_size)
                     .map_err(CodegenError::SectionError)?;
-                emitter.emit_org(addr);
+                emitter.emit_placement(addr, section_alloc.default_section_name());
                 (addr, AllocationSource::AutoAllocated)
             }
         } else {
@@ -173,7 +143,7 @@ fn generate_function(
             let addr = section_alloc
                 .allocate_default(function_size)
                 .map_err(CodegenError::SectionError)?;
-            emitter.emit_org(addr);
+            emitter.emit_placement(addr, section_alloc.default_section_name());
             (addr, AllocationSource::AutoAllocated)
         };
 
@@ -207,26 +177,28 @@ fn generate_function(
         emitter.emit_comment("  Returns: void");
     }
 
-    // Location
-    emitter.emit_comment(&format!("  Location: ${:04X}", function_addr));
+    // Location (only meaningful when we place code ourselves)
+    if !emitter.is_ca65() {
+        emitter.emit_comment(&format!("  Location: ${:04X}", function_addr));
+    }
 
     // Document zero-page usage in verbose mode
     if emitter.is_verbose() {
         emitter.emit_comment(&format!(
-            "  Temps: $20-${:02X}=available scratch",
-            emitter.memory_layout.param_base - 1
+            "  Temps: ${:02X}-${:02X}=available scratch",
+            emitter.memory_layout.temp_storage_start, emitter.memory_layout.pointer_ops_end
         ));
         emitter.emit_comment(&format!(
-            "  Params: ${:02X}-$81=parameter area",
-            emitter.memory_layout.param_base
+            "  Params: ${:02X}-${:02X}=parameter area",
+            emitter.memory_layout.param_base, emitter.memory_layout.param_end
         ));
         emitter.emit_comment(&format!(
-            "  Temps: $20-${:02X}=available scratch",
-            emitter.memory_layout.param_base - 1
+            "  Temps: ${:02X}-${:02X}=available scratch",
+            emitter.memory_layout.temp_storage_start, emitter.memory_layout.pointer_ops_end
         ));
         emitter.emit_comment(&format!(
-            "  Params: ${:02X}-$81=parameter area",
-            emitter.memory_layout.param_base
+            "  Params: ${:02X}-${:02X}=parameter area",
+            emitter.memory_layout.param_base, emitter.memory_layout.param_end
         ));
     }
 
@@ -244,6 +216,56 @@ fn generate_function(
         }
     }
 
+    // Public functions and vector handlers are visible to the linker
+    if emitter.is_ca65()
+        && (func.is_pub
+            || func.attributes.iter().any(|attr| {
+                matches!(
+                    attr,
+                    FnAttribute::Reset | FnAttribute::Nmi | FnAttribute::Irq | FnAttribute::Interrupt
+                )
+            }))
+    {
+        emitter.emit_raw(&format!(".export {}", name));
+    }
+
+    generate_function_body(func, emitter, info, string_collector, &frame)?;
+    emitter
+        .stack_locals
+        .extend(frame.iter().map(|&(addr, _)| (name.clone(), addr)));
+    Ok(())
+}
+
+/// Emitter for generating a function off to the side
+fn scratch_emitter(emitter: &Emitter) -> Emitter {
+    let mut temp_emitter = Emitter::new(emitter.verbosity);
+    temp_emitter.set_memory_layout(emitter.memory_layout.clone());
+    temp_emitter.cpu = emitter.cpu;
+    temp_emitter.peephole = emitter.peephole.clone();
+    // Copy register state and label counter to avoid label conflicts
+    temp_emitter.reg_state = emitter.reg_state.clone();
+    temp_emitter.label_counter = emitter.label_counter;
+    temp_emitter.match_counter = emitter.match_counter;
+    temp_emitter
+}
+
+/// Segment used for a function with an explicit `#[org]` in ca65 mode
+pub fn org_segment_name(address: u16) -> String {
+    format!("ORG_{:04X}", address)
+}
+
+/// Emit everything from the function's label through its epilogue
+fn generate_function_body(
+    func: &Function,
+    emitter: &mut Emitter,
+    info: &ProgramInfo,
+    string_collector: &mut StringCollector,
+    frame: &[(u8, u8)],
+) -> Result<(), CodegenError> {
+    let name = &func.name.node;
+
+    // Prologue and epilogue belong to the function's declaration
+    let previous_span = emitter.set_span(Some(func.name.span));
     emitter.emit_label(name);
 
     // Initialize software stack pointer for reset handler
@@ -255,16 +277,27 @@ fn generate_function(
         .attributes
         .iter()
         .any(|attr| matches!(attr, FnAttribute::Reset));
+    if is_reset && emitter.cpu.has_native_mode() {
+        emitter.emit_comment("Leave emulation mode; registers stay 8-bit");
+        emitter.emit_inst("CLC", "");
+        emitter.emit_inst("XCE", "");
+    }
     if is_reset {
         emitter.emit_comment("Initialize software stack pointer for parameter preservation");
+        let stack_pointer = format!("${:02X}", emitter.memory_layout.stack_pointer);
         emitter.emit_inst("LDA", "#$00");
-        emitter.emit_inst("STA", "$FF"); // Stack pointer at $FF, stack at $0200-$02FF
-        emitter.emit_inst("STA", "$FF"); // Stack pointer at $FF, stack at $0200-$02FF
+        emitter.emit_inst("STA", &stack_pointer); // Stack pointer at $FF, stack at $0200-$02FF
+        emitter.emit_inst("STA", &stack_pointer); // Stack pointer at $FF, stack at $0200-$02FF
     }
 
     // Set current function context for tail call detection and inline asm scoping
     emitter.set_current_function(name.clone());
 
+    // Stays in place across tail-recursive iterations
+    if !frame.is_empty() {
+        emitter.begin_frame(frame);
+    }
+
     // Check if function has tail recursion - if so, emit loop restart label
     let has_tail_recursion = info
         .function_metadata
@@ -333,11 +366,24 @@ fn generate_function(
         if emitter.is_verbose() {
             emitter.emit_comment("Stack: [return_lo, return_hi, P, A, X, Y] (6 bytes pushed)");
         }
-        emitter.emit_inst("PHA", "");
-        emitter.emit_inst("TXA", "");
-        emitter.emit_inst("PHA", "");
-        emitter.emit_inst("TYA", "");
-        emitter.emit_inst("PHA", "");
+        if emitter.cpu.has_native_mode() {
+            // The interrupted code may have had 16-bit registers
+            emitter.emit_inst("REP", "#$30");
+            emitter.emit_inst("PHA", "");
+            emitter.emit_inst("PHX", "");
+            emitter.emit_inst("PHY", "");
+            emitter.emit_inst("SEP", "#$30");
+        } else if emitter.cpu.has_cmos_instructions() {
+            emitter.emit_inst("PHA", "");
+            emitter.emit_inst("PHX", "");
+            emitter.emit_inst("PHY", "");
+        } else {
+            emitter.emit_inst("PHA", "");
+            emitter.emit_inst("TXA", "");
+            emitter.emit_inst("PHA", "");
+            emitter.emit_inst("TYA", "");
+            emitter.emit_inst("PHA", "");
+        }
     }
 
     // Initialize string pointer cache for hot parameters
@@ -399,21 +445,32 @@ fn generate_function(
         if emitter.is_verbose() {
             emitter.emit_comment("Restore Y, X, A in reverse order (LIFO)");
         }
-        emitter.emit_inst("PLA", "");
-        emitter.emit_inst("TAY", "");
-        emitter.emit_inst("PLA", "");
-        emitter.emit_inst("TAX", "");
+        if emitter.cpu.has_native_mode() {
+            emitter.emit_inst("REP", "#$30");
+        }
+        if emitter.cpu.has_cmos_instructions() {
+            emitter.emit_inst("PLY", "");
+            emitter.emit_inst("PLX", "");
+        } else {
+            emitter.emit_inst("PLA", "");
+            emitter.emit_inst("TAY", "");
+            emitter.emit_inst("PLA", "");
+            emitter.emit_inst("TAX", "");
+        }
         emitter.emit_inst("PLA", "");
         emitter.emit_inst("RTI", "");
     } else {
-        // Emit RTS for functions without explicit return (void functions)
+        // Emit RTS for functions without explicit return (void functions, or
+        // functions that leave their result in registers from inline asm)
         // Only emit if the last instruction wasn't already a terminal instruction (RTS, RTI, or JMP)
         // This avoids duplicate RTS when the function body ends with a return statement
-        if func.return_type.is_none() && !emitter.last_was_terminal() {
+        if !emitter.last_was_terminal() {
             emitter.emit_inst("RTS", "");
         }
     }
 
+    emitter.end_frame();
+    emitter.set_span(previous_span);
     Ok(())
 }
 
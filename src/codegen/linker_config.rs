@@ -0,0 +1,183 @@
+//! ld65 Linker Configuration
+//!
+//! Builds the `.cfg` file that ld65 needs to link ca65-mode output. Memory
+//! areas come from the `[[sections]]` of wraith.toml; `#[org]` placements
+//! become segments with a fixed start address. Every area is written to the
+//! output file with $FF fill, so the result is a flat ROM image.
+
+use crate::config::MemoryConfig;
+
+const ADDRESS_SPACE_END: u32 = 0x10000;
+
+/// A MEMORY area of the generated configuration
+struct Area {
+    name: String,
+    start: u32,
+    /// One past the last byte
+    end: u32,
+}
+
+/// A segment with a fixed start address (`#[org]`, const arrays)
+struct FixedSegment {
+    name: String,
+    start: u16,
+}
+
+/// Generate an ld65 configuration for the given memory layout
+///
+/// `placements` are the (address, segment) pairs the emitter produced;
+/// segments that are not sections are placed at their exact address.
+pub fn generate_linker_config(config: &MemoryConfig, placements: &[(u16, String)]) -> String {
+    // $FFFA, or $FFEA for the 65816's native-mode vectors
+    let vectors = placements
+        .iter()
+        .find(|(_, segment)| segment == "VECTORS")
+        .map(|&(address, _)| address as u32);
+
+    let mut fixed: Vec<FixedSegment> = Vec::new();
+    for (address, segment) in placements {
+        if segment == "VECTORS"
+            || config.get_section(segment).is_some()
+            || fixed.iter().any(|f| &f.name == segment)
+        {
+            continue;
+        }
+        fixed.push(FixedSegment {
+            name: segment.clone(),
+            start: *address,
+        });
+    }
+    fixed.sort_by_key(|f| f.start);
+
+    // One area per section, leaving room for the vector table
+    let mut areas: Vec<Area> = config
+        .sections
+        .iter()
+        .map(|section| {
+            let mut end = section.end as u32 + 1;
+            if let Some(vectors) = vectors
+                && (section.start as u32) < vectors
+            {
+                end = end.min(vectors);
+            }
+            Area {
+                name: section.name.clone(),
+                start: section.start as u32,
+                end,
+            }
+        })
+        .collect();
+    if let Some(vectors) = vectors {
+        areas.push(Area {
+            name: "VECTORS".to_string(),
+            start: vectors,
+            end: ADDRESS_SPACE_END,
+        });
+    }
+
+    // Fixed segments outside every section get an area of their own that
+    // extends to the next area
+    let containing_area = |areas: &[Area], address: u16| {
+        areas
+            .iter()
+            .position(|a| a.start <= address as u32 && (address as u32) < a.end)
+    };
+    for segment in &fixed {
+        if containing_area(&areas, segment.start).is_none() {
+            let start = segment.start as u32;
+            let end = areas
+                .iter()
+                .map(|a| a.start)
+                .filter(|&s| s > start)
+                .min()
+                .unwrap_or(ADDRESS_SPACE_END);
+            areas.push(Area {
+                name: segment.name.clone(),
+                start,
+                end,
+            });
+        }
+    }
+    areas.sort_by_key(|a| a.start);
+
+    // Pad gaps between areas so file offsets match addresses
+    let mut padded: Vec<Area> = Vec::new();
+    for area in areas {
+        if let Some(prev) = padded.last()
+            && prev.end < area.start
+        {
+            let gap = Area {
+                name: format!("PAD_{:04X}", prev.end),
+                start: prev.end,
+                end: area.start,
+            };
+            padded.push(gap);
+        }
+        padded.push(area);
+    }
+
+    let mut out = String::new();
+    out.push_str("# ld65 linker configuration generated by wraith from wraith.toml\n");
+    out.push_str("# Link with: ld65 -C <this file> -o <output>.bin <input>.o\n\n");
+
+    out.push_str("MEMORY {\n");
+    for area in &padded {
+        out.push_str(&format!(
+            "    {}: start = ${:04X}, size = ${:04X}, type = ro, file = %O, fill = yes, fillval = $FF;\n",
+            area.name,
+            area.start,
+            area.end - area.start
+        ));
+    }
+    out.push_str("}\n\n");
+
+    out.push_str("SEGMENTS {\n");
+    for area in &padded {
+        if config.get_section(&area.name).is_some() {
+            out.push_str(&format!(
+                "    {}: load = {}, type = ro, optional = yes;\n",
+                area.name, area.name
+            ));
+        }
+        for segment in &fixed {
+            let start = segment.start as u32;
+            if area.start <= start && start < area.end && !area.name.starts_with("PAD_") {
+                out.push_str(&format!(
+                    "    {}: load = {}, type = ro, start = ${:04X};\n",
+                    segment.name, area.name, segment.start
+                ));
+            }
+        }
+    }
+    if vectors.is_some() {
+        out.push_str("    VECTORS: load = VECTORS, type = ro;\n");
+    }
+    out.push_str("}\n");
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_layout_with_vectors_and_org() {
+        let placements = vec![
+            (0x8000, "CODE".to_string()),
+            (0x9000, "ORG_9000".to_string()),
+            (0xC000, "ORG_C000".to_string()),
+            (0xFFFA, "VECTORS".to_string()),
+        ];
+        let cfg = generate_linker_config(&MemoryConfig::default(), &placements);
+
+        assert!(cfg.contains("CODE: start = $8000, size = $4000"));
+        assert!(cfg.contains("ORG_C000: start = $C000, size = $1000"));
+        assert!(cfg.contains("DATA: start = $D000, size = $2000"));
+        assert!(cfg.contains("PAD_F000: start = $F000, size = $0FFA"));
+        assert!(cfg.contains("VECTORS: start = $FFFA, size = $0006"));
+        assert!(cfg.contains("ORG_9000: load = CODE, type = ro, start = $9000;"));
+        assert!(cfg.contains("ORG_C000: load = ORG_C000, type = ro, start = $C000;"));
+        assert!(cfg.contains("VECTORS: load = VECTORS, type = ro;"));
+    }
+}
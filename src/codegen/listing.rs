@@ -0,0 +1,126 @@
+//! Annotated Listing Output
+//!
+//! Combines the final assembly, the assembled bytes and the source spans
+//! recorded during code generation into a `.lst` file: address, encoded
+//! bytes, cycle cost and the Wraith source line behind each instruction.
+
+use super::CodegenOutput;
+use super::source_map::SourceIndex;
+use crate::assembler::cycles::{self, Cycles};
+use crate::assembler::parse::{self, Statement};
+use crate::assembler::{AssembledLine, Assembly, Cpu};
+use crate::ast::{FileId, SourceMap};
+use rustc_hash::FxHashMap as HashMap;
+
+/// Bytes shown per listing row; longer data continues on following rows
+const BYTES_PER_ROW: usize = 3;
+
+/// Render the listing for a program assembled from `output.asm`
+pub fn generate_listing(
+    output: &CodegenOutput,
+    assembly: &Assembly,
+    sources: &SourceMap,
+) -> String {
+    let mut line_index = SourceIndex::new(sources);
+    let assembled: HashMap<usize, &AssembledLine> =
+        assembly.lines.iter().map(|line| (line.line, line)).collect();
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "; Wraith listing for {}\n",
+        sources.name(FileId::ROOT)
+    ));
+    out.push_str("; CYC: \"4+\" is one cycle more when indexing crosses a page,\n");
+    out.push_str(";      \"2/3\" is a branch not taken/taken (\"2/4\" when the target is on another page)\n");
+    out.push('\n');
+    out.push_str(&format!("{:<6}{:<10}{:<6}SOURCE\n", "ADDR", "BYTES", "CYC"));
+
+    let mut last_source_line = None;
+    for (index, text) in output.asm.lines().enumerate() {
+        // Show the Wraith line before the first instruction generated from it
+        if let Some(Some(span)) = output.line_spans.get(index) {
+            let file = line_index.file(span.file);
+            let (line, _) = file.line_col(span.start);
+            if last_source_line != Some((span.file, line)) {
+                let code = file.line_text(line).trim();
+                let name = sources.name(span.file);
+                out.push_str(&format!("{:22}; {}:{}  {}\n", "", name, line, code));
+                last_source_line = Some((span.file, line));
+            }
+        }
+
+        let Some(line) = assembled.get(&(index + 1)) else {
+            // Labels get their address; everything else is shown as-is
+            let parsed = parse::parse_line(text).unwrap_or_default();
+            match parsed.labels.first().and_then(|label| assembly.symbol(label)) {
+                Some(address) if parsed.statement.is_none() => {
+                    out.push_str(&format!("{:04X}{:18}{}\n", address, "", text))
+                }
+                _ => out.push_str(&format!("{:22}{}\n", "", text)),
+            }
+            continue;
+        };
+
+        let is_instruction = matches!(
+            parse::parse_line(text).map(|p| p.statement),
+            Ok(Some(Statement::Instruction { .. }))
+        );
+        let timing = if is_instruction {
+            format_cycles(assembly.cpu, line)
+        } else {
+            String::new()
+        };
+
+        let mut rows = line.bytes.chunks(BYTES_PER_ROW);
+        let first = rows.next().unwrap_or_default();
+        out.push_str(&format!(
+            "{:04X}  {:<10}{:<6}{}\n",
+            line.address,
+            format_bytes(first),
+            timing,
+            text
+        ));
+        for (i, row) in rows.enumerate() {
+            let address = line.address.wrapping_add(((i + 1) * BYTES_PER_ROW) as u16);
+            out.push_str(&format!("{:04X}  {}\n", address, format_bytes(row)));
+        }
+    }
+
+    out.push('\n');
+    for stat in output.section_alloc.get_statistics() {
+        if stat.used > 0 {
+            out.push_str(&format!("; {}\n", stat.format()));
+        }
+    }
+    out.push_str(&format!("; Total: {} bytes\n", assembly.size()));
+
+    out
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Cycle column for an assembled instruction
+fn format_cycles(cpu: Cpu, line: &AssembledLine) -> String {
+    let Some(timing) = line.bytes.first().and_then(|&code| cycles::opcode_cycles(cpu, code)) else {
+        return String::new();
+    };
+    match timing {
+        Cycles { branch: true, .. } => {
+            let next = line.address.wrapping_add(2);
+            let offset = line.bytes.get(1).copied().unwrap_or(0) as i8;
+            let target = next.wrapping_add(offset as u16);
+            let taken = timing.base + 1 + cycles::crosses_page(next, target) as u8;
+            format!("{}/{}", timing.base, taken)
+        }
+        Cycles {
+            page_penalty: true, ..
+        } => format!("{}+", timing.base),
+        _ => timing.base.to_string(),
+    }
+}
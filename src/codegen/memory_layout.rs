@@ -21,13 +21,19 @@
 //! $F4-$FE (11 bytes): Function argument evaluation temp
 //! $FF:                Software stack pointer
 //! ```
+//!
+//! # Target Layouts
+//!
+//! A target's OS or hardware owns parts of the zero page instead of
+//! $00-$1F. The compiler's blocks that fall in those ranges move to the
+//! lowest free bytes (see [`MemoryLayout::for_reserved`]), and variables
+//! take whatever is left.
 
 /// Memory layout configuration for 6502 code generation
 #[derive(Debug, Clone)]
 pub struct MemoryLayout {
-    /// System reserved zero page (usually $00-$1F)
-    pub system_reserved_start: u8,
-    pub system_reserved_end: u8,
+    /// Zero page owned by the system (usually $00-$1F, or a target's ranges)
+    pub system_reserved: Vec<(u8, u8)>,
 
     /// Temporary storage for codegen operations (default $20-$2F)
     pub temp_storage_start: u8,
@@ -48,13 +54,24 @@ pub struct MemoryLayout {
 
     /// Parameter region end (default $BF) - gives 64 bytes for parameters
     pub param_end: u8,
+
+    /// Working storage of the stdlib math routines (default $D0-$D8)
+    pub math_scratch: u8,
+
+    /// Binary op left operand save (default $F0-$F3)
+    pub operand_save: u8,
+
+    /// Function argument evaluation temp (default $F4-$FE)
+    pub arg_temps: u8,
+
+    /// Software stack pointer for recursive functions' saved parameters (default $FF)
+    pub stack_pointer: u8,
 }
 
 impl Default for MemoryLayout {
     fn default() -> Self {
         Self {
-            system_reserved_start: 0x00,
-            system_reserved_end: 0x1F,
+            system_reserved: vec![(0x00, 0x1F)],
             temp_storage_start: 0x20,
             temp_storage_end: 0x2F,
             pointer_ops_start: 0x30,
@@ -63,16 +80,108 @@ impl Default for MemoryLayout {
             variable_alloc_end: 0x7F,
             param_base: 0x80,
             param_end: 0xBF,
+            math_scratch: 0xD0,
+            operand_save: TempAllocator::HIGH_BASE,
+            arg_temps: TempAllocator::ARG_BASE,
+            stack_pointer: 0xFF,
         }
     }
 }
 
 impl MemoryLayout {
+    /// Size of the stdlib math routines' working storage
+    pub const STDLIB_SCRATCH_SIZE: u8 = 9;
+
+    /// Fewest variable bytes a target layout settles for before it shrinks
+    /// the parameter region
+    pub const MIN_VARIABLE_SPACE: usize = 32;
+
     /// Create a new memory layout with default configuration
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Layout for a machine that owns the `reserved` zero-page ranges
+    ///
+    /// Blocks clear of the reserved ranges keep their default address; the
+    /// rest take the lowest free run, largest first. When that leaves no room
+    /// every block is packed from the bottom instead, and when variables
+    /// would still get fewer than [`Self::MIN_VARIABLE_SPACE`] bytes the
+    /// parameter region shrinks. Returns `None` when the blocks don't fit at
+    /// all.
+    pub fn for_reserved(reserved: &[(u8, u8)]) -> Option<Self> {
+        if reserved.is_empty() {
+            return Some(Self::default());
+        }
+
+        let mut fallback = None;
+        for param_size in [0x40, 0x20, 0x10] {
+            let Some(layout) = Self::place(reserved, param_size) else {
+                continue;
+            };
+            if layout.variable_space() >= Self::MIN_VARIABLE_SPACE {
+                return Some(layout);
+            }
+            fallback.get_or_insert(layout);
+        }
+        fallback
+    }
+
+    /// Place every block around `reserved` with a `param_size`-byte parameter region
+    fn place(reserved: &[(u8, u8)], param_size: u8) -> Option<Self> {
+        let mut layout = Self::default();
+
+        // (default start, size), largest first
+        let blocks = [
+            (layout.param_base, param_size),
+            (
+                layout.temp_storage_start,
+                layout.pointer_ops_end - layout.temp_storage_start + 1,
+            ),
+            (layout.arg_temps, TempAllocator::ARG_SIZE),
+            (layout.math_scratch, Self::STDLIB_SCRATCH_SIZE),
+            (layout.operand_save, TempAllocator::HIGH_SIZE),
+            (layout.stack_pointer, 1),
+        ];
+        let starts = place_blocks(reserved, &blocks, true)
+            .or_else(|| place_blocks(reserved, &blocks, false))?;
+
+        let [params, temps, args, scratch, save, stack_pointer] = starts;
+        layout.system_reserved = reserved.to_vec();
+        layout.param_base = params;
+        layout.param_end = params + (param_size - 1);
+        layout.temp_storage_start = temps;
+        layout.temp_storage_end = temps + 0x0F;
+        layout.pointer_ops_start = temps + 0x10;
+        layout.pointer_ops_end = temps + 0x1F;
+        layout.arg_temps = args;
+        layout.math_scratch = scratch;
+        layout.operand_save = save;
+        layout.stack_pointer = stack_pointer;
+
+        // Variables start at the lowest byte nothing else owns
+        let taken = layout.get_reserved_regions();
+        layout.variable_alloc_start = (0..=0xFF).find(|&addr| {
+            !taken
+                .iter()
+                .any(|&(start, end)| (start..=end).contains(&addr))
+        })?;
+        layout.variable_alloc_end = 0xFF;
+        Some(layout)
+    }
+
+    /// Get the total variable space available (in bytes)
+    pub fn variable_space(&self) -> usize {
+        let taken = self.get_reserved_regions();
+        (self.variable_alloc_start..=self.variable_alloc_end)
+            .filter(|&addr| {
+                !taken
+                    .iter()
+                    .any(|&(start, end)| (start..=end).contains(&addr))
+            })
+            .count()
+    }
+
     /// Get the temporary register address (first byte of temp storage)
     pub fn temp_reg(&self) -> u8 {
         self.temp_storage_start
@@ -95,19 +204,35 @@ impl MemoryLayout {
         self.pointer_ops_start // $30 by default
     }
 
+    /// Address `offset` bytes into the stdlib math scratch, as an operand
+    pub fn scratch(&self, offset: u8) -> String {
+        format!("${:02X}", self.math_scratch + offset)
+    }
+
+    /// Last byte of the stdlib math scratch
+    pub fn math_scratch_end(&self) -> u8 {
+        self.math_scratch + (Self::STDLIB_SCRATCH_SIZE - 1)
+    }
+
     /// Get reserved regions for zero page allocator
     pub fn get_reserved_regions(&self) -> Vec<(u8, u8)> {
-        vec![
-            (self.system_reserved_start, self.system_reserved_end),
+        let mut regions = self.system_reserved.clone();
+        regions.extend([
             (self.temp_storage_start, self.temp_storage_end),
             (self.pointer_ops_start, self.pointer_ops_end),
             (self.param_base, self.param_end),
-        ]
-    }
-
-    /// Get the total variable space available (in bytes)
-    pub fn variable_space(&self) -> u8 {
-        self.variable_alloc_end - self.variable_alloc_start + 1
+            (self.math_scratch, self.math_scratch_end()),
+            (
+                self.operand_save,
+                self.operand_save + (TempAllocator::HIGH_SIZE - 1),
+            ),
+            (
+                self.arg_temps,
+                self.arg_temps + (TempAllocator::ARG_SIZE - 1),
+            ),
+            (self.stack_pointer, self.stack_pointer),
+        ]);
+        regions
     }
 
     /// Get the total parameter space available (in bytes)
@@ -116,12 +241,53 @@ impl MemoryLayout {
     }
 }
 
+/// Start addresses for `blocks` (default start, size) that avoid `reserved`
+/// and each other; with `keep`, blocks already clear stay where they are
+fn place_blocks(reserved: &[(u8, u8)], blocks: &[(u8, u8); 6], keep: bool) -> Option<[u8; 6]> {
+    let mut taken: Vec<(usize, usize)> = reserved
+        .iter()
+        .map(|&(start, end)| (start as usize, end as usize))
+        .collect();
+    let is_free = |taken: &[(usize, usize)], start: usize, size: usize| {
+        start + size <= 0x100
+            && taken
+                .iter()
+                .all(|&(from, to)| start + size - 1 < from || start > to)
+    };
+
+    let mut starts = [None; 6];
+    if keep {
+        for (slot, &(start, size)) in starts.iter_mut().zip(blocks) {
+            let (start, size) = (start as usize, size as usize);
+            if is_free(&taken, start, size) {
+                taken.push((start, start + size - 1));
+                *slot = Some(start as u8);
+            }
+        }
+    }
+    for (slot, &(_, size)) in starts.iter_mut().zip(blocks) {
+        if slot.is_some() {
+            continue;
+        }
+        let size = size as usize;
+        let start = (0..0x100).find(|&start| is_free(&taken, start, size))?;
+        taken.push((start, start + size - 1));
+        *slot = Some(start as u8);
+    }
+
+    let mut placed = [0; 6];
+    for (address, start) in placed.iter_mut().zip(starts) {
+        *address = start?;
+    }
+    Some(placed)
+}
+
 /// Temporary storage allocator for codegen
 ///
 /// Manages allocation of temporary zero-page locations to prevent conflicts
 /// between different codegen phases (binary ops, function calls, etc.)
 ///
-/// # Regions Managed
+/// # Regions Managed (default layout)
 /// - Primary temp pool: $20-$3F (32 bytes)
 /// - High temp pool: $F0-$F3 (4 bytes) - for binary op saves
 /// - Arg temp pool: $F4-$FE (11 bytes) - for function arguments
@@ -133,6 +299,10 @@ pub struct TempAllocator {
     high_pool: u8,
     /// Bitmap for $F4-$FE (11 bytes)
     arg_pool: u16,
+    /// Where each pool starts in the layout
+    primary_base: u8,
+    high_base: u8,
+    arg_base: u8,
 }
 
 impl Default for TempAllocator {
@@ -142,7 +312,7 @@ impl Default for TempAllocator {
 }
 
 impl TempAllocator {
-    /// Base addresses for each pool
+    /// Base addresses for each pool in the default layout
     pub const PRIMARY_BASE: u8 = 0x20;
     pub const PRIMARY_SIZE: u8 = 32;
     pub const HIGH_BASE: u8 = 0xF0;
@@ -151,19 +321,27 @@ impl TempAllocator {
     pub const ARG_SIZE: u8 = 11;
 
     pub fn new() -> Self {
+        Self::for_layout(&MemoryLayout::default())
+    }
+
+    /// Allocator over the temp pools of `layout`
+    pub fn for_layout(layout: &MemoryLayout) -> Self {
         Self {
             primary_pool: 0,
             high_pool: 0,
             arg_pool: 0,
+            primary_base: layout.temp_storage_start,
+            high_base: layout.operand_save,
+            arg_base: layout.arg_temps,
         }
     }
 
-    /// Allocate `size` consecutive bytes from the primary temp pool ($20-$3F)
+    /// Allocate `size` consecutive bytes from the primary temp pool ($20-$3F by default)
     /// Returns the starting address, or None if no space available
     pub fn alloc_primary(&mut self, size: u8) -> Option<u8> {
         Self::alloc_from_pool(
             &mut self.primary_pool,
-            Self::PRIMARY_BASE,
+            self.primary_base,
             Self::PRIMARY_SIZE,
             size,
         )
@@ -171,13 +349,13 @@ impl TempAllocator {
 
     /// Free previously allocated bytes in the primary pool
     pub fn free_primary(&mut self, addr: u8, size: u8) {
-        Self::free_from_pool(&mut self.primary_pool, Self::PRIMARY_BASE, addr, size);
+        Self::free_from_pool(&mut self.primary_pool, self.primary_base, addr, size);
     }
 
     /// Allocate from high temp pool ($F0-$F3) - typically for binary op left operand
     pub fn alloc_high(&mut self, size: u8) -> Option<u8> {
         let mut pool = self.high_pool as u32;
-        let result = Self::alloc_from_pool(&mut pool, Self::HIGH_BASE, Self::HIGH_SIZE, size);
+        let result = Self::alloc_from_pool(&mut pool, self.high_base, Self::HIGH_SIZE, size);
         self.high_pool = pool as u8;
         result
     }
@@ -185,14 +363,14 @@ impl TempAllocator {
     /// Free previously allocated bytes in the high pool
     pub fn free_high(&mut self, addr: u8, size: u8) {
         let mut pool = self.high_pool as u32;
-        Self::free_from_pool(&mut pool, Self::HIGH_BASE, addr, size);
+        Self::free_from_pool(&mut pool, self.high_base, addr, size);
         self.high_pool = pool as u8;
     }
 
     /// Allocate from arg temp pool ($F4-$FE) - for function argument evaluation
     pub fn alloc_arg(&mut self, size: u8) -> Option<u8> {
         let mut pool = self.arg_pool as u32;
-        let result = Self::alloc_from_pool(&mut pool, Self::ARG_BASE, Self::ARG_SIZE, size);
+        let result = Self::alloc_from_pool(&mut pool, self.arg_base, Self::ARG_SIZE, size);
         self.arg_pool = pool as u16;
         result
     }
@@ -200,7 +378,7 @@ impl TempAllocator {
     /// Free previously allocated bytes in the arg pool
     pub fn free_arg(&mut self, addr: u8, size: u8) {
         let mut pool = self.arg_pool as u32;
-        Self::free_from_pool(&mut pool, Self::ARG_BASE, addr, size);
+        Self::free_from_pool(&mut pool, self.arg_base, addr, size);
         self.arg_pool = pool as u16;
     }
 
@@ -213,10 +391,12 @@ impl TempAllocator {
 
     /// Check if a specific address range is free in primary pool
     pub fn is_primary_free(&self, addr: u8, size: u8) -> bool {
-        if addr < Self::PRIMARY_BASE || addr + size > Self::PRIMARY_BASE + Self::PRIMARY_SIZE {
+        if addr < self.primary_base
+            || addr as u16 + size as u16 > self.primary_base as u16 + Self::PRIMARY_SIZE as u16
+        {
             return false;
         }
-        let offset = addr - Self::PRIMARY_BASE;
+        let offset = addr - self.primary_base;
         let mask = ((1u32 << size) - 1) << offset;
         (self.primary_pool & mask) == 0
     }
@@ -0,0 +1,463 @@
+//! Memory Map Output
+//!
+//! Lists where everything in an assembled program lives: functions, const
+//! arrays, string literals and mutable statics with their address and size,
+//! plus every zero-page byte the program or the compiler uses and the ranges
+//! the target reserves. Written as JSON for tools that track ROM and
+//! zero-page growth between builds, and as a `.map` text file for people.
+
+use super::CodegenOutput;
+use super::memory_layout::{MemoryLayout, TempAllocator};
+use crate::assembler::Assembly;
+use crate::assembler::parse::{Index, Operand, Statement, parse_line};
+use crate::debuginfo::variable_size;
+use crate::diagnostic::json_string;
+use crate::sema::ProgramInfo;
+use crate::sema::table::{SymbolKind, SymbolLocation};
+use crate::sema::types::Type;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+/// What a placed object is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    /// A function or stdlib routine
+    Function,
+    ConstArray,
+    /// String literal data (length prefix and bytes)
+    String,
+    /// A mutable static
+    Static,
+}
+
+impl ObjectKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectKind::Function => "function",
+            ObjectKind::ConstArray => "const_array",
+            ObjectKind::String => "string",
+            ObjectKind::Static => "static",
+        }
+    }
+}
+
+/// An object placed outside zero page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapObject {
+    pub name: String,
+    pub kind: ObjectKind,
+    pub address: u16,
+    pub size: u16,
+    /// Memory section holding the object, if any
+    pub section: Option<String>,
+}
+
+/// Who uses a range of zero page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeroPageKind {
+    /// Reserved for the system
+    Reserved,
+    /// Scratch space for generated code
+    Temp,
+    /// Working storage of the stdlib math routines
+    StdlibScratch,
+    /// The software stack pointer
+    StackPointer,
+    /// A variable
+    Variable,
+    /// A function parameter
+    Parameter,
+}
+
+impl ZeroPageKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ZeroPageKind::Reserved => "reserved",
+            ZeroPageKind::Temp => "temp",
+            ZeroPageKind::StdlibScratch => "stdlib",
+            ZeroPageKind::StackPointer => "stack_pointer",
+            ZeroPageKind::Variable => "variable",
+            ZeroPageKind::Parameter => "parameter",
+        }
+    }
+}
+
+/// A range of zero page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZeroPageEntry {
+    pub name: String,
+    pub kind: ZeroPageKind,
+    pub address: u8,
+    pub size: u16,
+    /// Function a variable or parameter belongs to
+    pub function: Option<String>,
+}
+
+/// Bytes used in one memory section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionUsage {
+    pub name: String,
+    pub start: u16,
+    pub end: u16,
+    /// Bytes taken by the objects placed in the section
+    pub used: u16,
+}
+
+/// The memory map of an assembled program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub sections: Vec<SectionUsage>,
+    /// Objects by address
+    pub objects: Vec<MapObject>,
+    /// Zero-page ranges by address
+    pub zero_page: Vec<ZeroPageEntry>,
+}
+
+/// Build the memory map of a program assembled from `output.asm`
+pub fn build_memory_map(
+    program: &ProgramInfo,
+    output: &CodegenOutput,
+    assembly: &Assembly,
+) -> MemoryMap {
+    let mut objects = Vec::new();
+
+    for allocation in &output.section_alloc.allocations {
+        objects.push((
+            allocation.name.clone(),
+            ObjectKind::Function,
+            allocation.start,
+            allocation.end - allocation.start + 1,
+        ));
+    }
+
+    for (label, size) in &output.strings {
+        if let Some(address) = assembly.symbol(label) {
+            objects.push((label.clone(), ObjectKind::String, address, *size));
+        }
+    }
+
+    let placed: HashSet<&str> = output
+        .section_alloc
+        .allocations
+        .iter()
+        .map(|allocation| allocation.name.as_str())
+        .collect();
+    let called: HashSet<&str> = program
+        .resolved_symbols
+        .values()
+        .filter(|info| info.kind == SymbolKind::Function)
+        .map(|info| info.name.as_str())
+        .collect();
+    let mut zero_page = Vec::new();
+    let mut seen: HashSet<(Option<String>, String, u16)> = HashSet::default();
+    let layout = &program.memory_layout;
+    for info in program.resolved_symbols.values() {
+        match (&info.kind, &info.location) {
+            // Const arrays and mutable statics are only placed by the assembler
+            (SymbolKind::Constant, _) if info.mutable || matches!(info.ty, Type::Array(..)) => {
+                let Some(address) = assembly.symbol(&info.name) else {
+                    continue;
+                };
+                if !seen.insert((None, info.name.clone(), address)) {
+                    continue;
+                }
+                let kind = if info.mutable {
+                    ObjectKind::Static
+                } else {
+                    ObjectKind::ConstArray
+                };
+                let size = u16::try_from(info.ty.size()).unwrap_or(u16::MAX);
+                objects.push((info.name.clone(), kind, address, size));
+            }
+            (SymbolKind::Variable, SymbolLocation::ZeroPage(address)) => {
+                let scope = info.containing_function.clone();
+                // Skip imported functions that were never generated or inlined
+                if let Some(function) = &scope
+                    && !placed.contains(function.as_str())
+                    && !called.contains(function.as_str())
+                {
+                    continue;
+                }
+                // 65816 locals in a stack frame don't use their address
+                if let Some(function) = &scope
+                    && output.stack_locals.contains(&(function.clone(), *address))
+                {
+                    continue;
+                }
+                // Every use of a symbol is resolved separately
+                if !seen.insert((scope.clone(), info.name.clone(), *address as u16)) {
+                    continue;
+                }
+                let kind = if (layout.param_base..=layout.param_end).contains(address) {
+                    ZeroPageKind::Parameter
+                } else {
+                    ZeroPageKind::Variable
+                };
+                zero_page.push(ZeroPageEntry {
+                    name: info.name.clone(),
+                    kind,
+                    address: *address,
+                    size: variable_size(&info.ty, program).unwrap_or(1),
+                    function: scope,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let reserved_name = program
+        .memory_config
+        .target
+        .map_or("system", |target| target.name());
+    zero_page.extend(compiler_zero_page(
+        layout,
+        reserved_name,
+        &zero_page_operands(&output.asm),
+    ));
+    zero_page
+        .sort_by(|a, b| (a.address, &a.function, &a.name).cmp(&(b.address, &b.function, &b.name)));
+
+    let config = output.section_alloc.config();
+    let section_of = |address: u16| {
+        config
+            .sections
+            .iter()
+            .find(|section| section.start <= address && address <= section.end)
+            .map(|section| section.name.clone())
+    };
+    let mut objects: Vec<_> = objects
+        .into_iter()
+        .map(|(name, kind, address, size)| MapObject {
+            section: section_of(address),
+            name,
+            kind,
+            address,
+            size,
+        })
+        .collect();
+    objects.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+
+    let sections = config
+        .sections
+        .iter()
+        .map(|section| SectionUsage {
+            name: section.name.clone(),
+            start: section.start,
+            end: section.end,
+            used: objects
+                .iter()
+                .filter(|object| object.section.as_ref() == Some(&section.name))
+                .map(|object| object.size)
+                .fold(0, u16::saturating_add),
+        })
+        .collect();
+
+    MemoryMap {
+        sections,
+        objects,
+        zero_page,
+    }
+}
+
+/// Zero page the system owns, and the compiler's regions that `used` touches
+fn compiler_zero_page(
+    layout: &MemoryLayout,
+    reserved_name: &str,
+    used: &HashSet<u8>,
+) -> Vec<ZeroPageEntry> {
+    let range = |name: &str, kind, start: u8, end: u8| ZeroPageEntry {
+        name: name.to_string(),
+        kind,
+        address: start,
+        size: end as u16 - start as u16 + 1,
+        function: None,
+    };
+    let mut entries: Vec<_> = layout
+        .system_reserved
+        .iter()
+        .map(|&(start, end)| range(reserved_name, ZeroPageKind::Reserved, start, end))
+        .collect();
+    let regions = [
+        range(
+            "temps",
+            ZeroPageKind::Temp,
+            layout.temp_storage_start,
+            layout.temp_storage_end,
+        ),
+        range(
+            "pointer_temps",
+            ZeroPageKind::Temp,
+            layout.pointer_ops_start,
+            layout.pointer_ops_end,
+        ),
+        range(
+            "math_scratch",
+            ZeroPageKind::StdlibScratch,
+            layout.math_scratch,
+            layout.math_scratch_end(),
+        ),
+        range(
+            "operand_save",
+            ZeroPageKind::Temp,
+            layout.operand_save,
+            layout.operand_save + (TempAllocator::HIGH_SIZE - 1),
+        ),
+        range(
+            "argument_temps",
+            ZeroPageKind::Temp,
+            layout.arg_temps,
+            layout.arg_temps + (TempAllocator::ARG_SIZE - 1),
+        ),
+        range(
+            "software_stack_pointer",
+            ZeroPageKind::StackPointer,
+            layout.stack_pointer,
+            layout.stack_pointer,
+        ),
+    ];
+    entries.extend(regions.into_iter().filter(|region| {
+        (region.address as u16..region.address as u16 + region.size)
+            .any(|address| used.contains(&(address as u8)))
+    }));
+    entries
+}
+
+/// Zero-page bytes read or written by the instructions in `asm`
+///
+/// Only numeric operands count: variables and parameters are listed from
+/// their symbols, so this finds the compiler's own temps and scratch.
+fn zero_page_operands(asm: &str) -> HashSet<u8> {
+    let mut used = HashSet::default();
+    for line in asm.lines() {
+        let Some(Statement::Instruction { operand, .. }) =
+            parse_line(line).ok().and_then(|parsed| parsed.statement)
+        else {
+            continue;
+        };
+        // Pointers take the byte after the operand as well
+        let (expr, pointer) = match &operand {
+            Operand::Direct(expr, Some(Index::X | Index::Y) | None)
+            | Operand::BitBranch(expr, _) => (expr, false),
+            Operand::Indirect(expr)
+            | Operand::IndexedIndirect(expr)
+            | Operand::IndirectIndexed(expr)
+            | Operand::IndirectLong(expr)
+            | Operand::IndirectLongIndexed(expr) => (expr, true),
+            _ => continue,
+        };
+        if expr.select.is_some() || !expr.is_zero_page() {
+            continue;
+        }
+        let Ok(address) = expr.eval(&HashMap::default(), 0) else {
+            continue;
+        };
+        used.insert(address as u8);
+        if pointer {
+            used.insert((address as u8).wrapping_add(1));
+        }
+    }
+    used
+}
+
+fn json_option(value: &Option<String>) -> String {
+    value.as_deref().map_or("null".to_string(), json_string)
+}
+
+/// Render the map as JSON, one entry per line so builds diff cleanly
+pub fn write_json(map: &MemoryMap) -> String {
+    let sections: Vec<_> = map
+        .sections
+        .iter()
+        .map(|section| {
+            format!(
+                "    {{\"name\": {}, \"start\": {}, \"end\": {}, \"used\": {}}}",
+                json_string(&section.name),
+                section.start,
+                section.end,
+                section.used
+            )
+        })
+        .collect();
+    let objects: Vec<_> = map
+        .objects
+        .iter()
+        .map(|object| {
+            format!(
+                "    {{\"name\": {}, \"kind\": \"{}\", \"address\": {}, \"size\": {}, \"section\": {}}}",
+                json_string(&object.name),
+                object.kind.name(),
+                object.address,
+                object.size,
+                json_option(&object.section)
+            )
+        })
+        .collect();
+    let zero_page: Vec<_> = map
+        .zero_page
+        .iter()
+        .map(|entry| {
+            format!(
+                "    {{\"name\": {}, \"kind\": \"{}\", \"address\": {}, \"size\": {}, \"function\": {}}}",
+                json_string(&entry.name),
+                entry.kind.name(),
+                entry.address,
+                entry.size,
+                json_option(&entry.function)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"sections\": [\n{}\n  ],\n  \"objects\": [\n{}\n  ],\n  \"zero_page\": [\n{}\n  ]\n}}\n",
+        sections.join(",\n"),
+        objects.join(",\n"),
+        zero_page.join(",\n")
+    )
+}
+
+/// Render the map as a text report
+pub fn write_text(map: &MemoryMap, file_name: &str) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("; Wraith memory map for {}\n\n", file_name));
+
+    out.push_str("SECTIONS\n");
+    for section in &map.sections {
+        let total = section.end as u32 - section.start as u32 + 1;
+        out.push_str(&format!(
+            "  {:<12} ${:04X}-${:04X}  {:>5}/{:<5} bytes\n",
+            section.name, section.start, section.end, section.used, total
+        ));
+    }
+
+    out.push_str("\nOBJECTS\n");
+    for object in &map.objects {
+        let end = object.address as u32 + object.size.max(1) as u32 - 1;
+        out.push_str(&format!(
+            "  ${:04X}-${:04X}  {:>5}  {:<12} {:<8} {}\n",
+            object.address,
+            end,
+            object.size,
+            object.kind.name(),
+            object.section.as_deref().unwrap_or("-"),
+            object.name
+        ));
+    }
+
+    out.push_str("\nZERO PAGE\n");
+    for entry in &map.zero_page {
+        let end = entry.address as u32 + entry.size.max(1) as u32 - 1;
+        let name = match &entry.function {
+            Some(function) => format!("{}::{}", function, entry.name),
+            None => entry.name.clone(),
+        };
+        out.push_str(&format!(
+            "  ${:02X}-${:02X}  {:>3}  {:<14} {}\n",
+            entry.address,
+            end,
+            entry.size,
+            entry.kind.name(),
+            name
+        ));
+    }
+
+    out
+}
@@ -3,13 +3,20 @@ pub mod comment_utils;
 pub mod emitter;
 pub mod expr;
 pub mod item;
+pub mod linker_config;
+pub mod listing;
 pub mod memory_layout;
+pub mod memory_map;
 pub mod peephole;
 pub mod regstate;
 pub mod section_allocator;
+pub mod source_map;
+pub mod stack_frame;
 pub mod stmt;
 
-use crate::ast::SourceFile;
+use crate::assembler::Cpu;
+use crate::ast::{SourceFile, Span};
+use crate::config::Startup;
 use crate::sema::ProgramInfo;
 use emitter::Emitter;
 use item::generate_item;
@@ -28,6 +35,53 @@ pub enum CommentVerbosity {
     Verbose,
 }
 
+/// How generated code and data are placed in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Absolute `.ORG` placement, assembled by the built-in assembler
+    #[default]
+    Absolute,
+    /// ca65 `.segment`/`.export`/`.import` output, placed by ld65
+    Ca65,
+}
+
+/// Options controlling code generation
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    pub verbosity: CommentVerbosity,
+    pub output_mode: OutputMode,
+    /// Generate `#[test]` functions, which are left out of normal builds
+    pub include_tests: bool,
+    /// Processor to generate code for; the 65C02 gets STZ, BRA, TSB/TRB,
+    /// PHX/PHY, `INC A` and `JMP (addr,X)`
+    pub cpu: Cpu,
+    /// Peephole passes to run (`-O`, `--disable-pass`)
+    pub peephole: peephole::PeepholeOptions,
+    /// Count what each peephole pass removed (`CodegenOutput::pass_stats`)
+    pub pass_stats: bool,
+}
+
+/// Everything produced by code generation
+pub struct CodegenOutput {
+    /// Final (peephole-optimized) assembly
+    pub asm: String,
+    /// Section placement of every function and data block
+    pub section_alloc: SectionAllocator,
+    /// ld65 linker configuration (ca65 mode only)
+    pub linker_config: Option<String>,
+    /// Source span of each line of `asm` (instructions from the main file only)
+    pub line_spans: Vec<Option<Span>>,
+    /// Maximum iterations of each loop, by the label of its first instruction
+    pub loop_bounds: Vec<(String, u32)>,
+    /// 65816 locals kept in stack frames, as (function, zero-page address
+    /// sema gave them); nothing uses those addresses
+    pub stack_locals: Vec<(String, u8)>,
+    /// String literal data blocks, as (label, size in bytes)
+    pub strings: Vec<(String, u16)>,
+    /// What each peephole pass removed from the final assembly, if requested
+    pub pass_stats: Vec<peephole::PassStats>,
+}
+
 #[derive(Debug, Clone)]
 pub enum CodegenError {
     Unknown,
@@ -35,6 +89,46 @@ pub enum CodegenError {
     SymbolNotFound(String),
     SectionError(String),
     AddressConflict(String),
+    AssemblyError(String),
+    /// An error in the code generated for a statement or item
+    Located {
+        error: Box<CodegenError>,
+        span: Span,
+    },
+}
+
+impl CodegenError {
+    /// Stable name of the kind of error, for machine-readable output
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodegenError::Located { error, .. } => error.code(),
+            CodegenError::Unknown => "codegen-error",
+            CodegenError::UnsupportedOperation(_) => "unsupported-operation",
+            CodegenError::SymbolNotFound(_) => "symbol-not-found",
+            CodegenError::SectionError(_) => "section-error",
+            CodegenError::AddressConflict(_) => "address-conflict",
+            CodegenError::AssemblyError(_) => "assembly-error",
+        }
+    }
+
+    /// Where the error is, if it came from generating a statement or item
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CodegenError::Located { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Locate the error at `span`, unless a narrower span is already known
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            CodegenError::Located { .. } => self,
+            error => CodegenError::Located {
+                error: Box::new(error),
+                span,
+            },
+        }
+    }
 }
 
 impl std::fmt::Display for CodegenError {
@@ -45,6 +139,8 @@ impl std::fmt::Display for CodegenError {
             CodegenError::SymbolNotFound(name) => write!(f, "undefined symbol '{}'", name),
             CodegenError::SectionError(msg) => write!(f, "section error: {}", msg),
             CodegenError::AddressConflict(msg) => write!(f, "{}", msg),
+            CodegenError::AssemblyError(msg) => write!(f, "assembly error: {}", msg),
+            CodegenError::Located { error, .. } => write!(f, "{}", error),
         }
     }
 }
@@ -113,6 +209,17 @@ impl StringCollector {
         label
     }
 
+    /// Label and size (length prefix included) of every collected string, by label
+    pub fn data_blocks(&self) -> Vec<(String, u16)> {
+        let mut blocks: Vec<_> = self
+            .strings
+            .iter()
+            .map(|(content, label)| (label.clone(), 1 + content.len() as u16))
+            .collect();
+        blocks.sort();
+        blocks
+    }
+
     /// Validate that all strings are within the 256-byte limit
     pub fn validate_strings(&self) -> Result<(), String> {
         for (content, label) in &self.strings {
@@ -155,7 +262,7 @@ impl StringCollector {
                 .allocate("DATA", data_size)
                 .map_err(CodegenError::SectionError)?;
 
-            emitter.emit_org(addr);
+            emitter.emit_placement(addr, "DATA");
             emitter.emit_label(label);
 
             // Emit length as u8 (single byte, max 255)
@@ -246,254 +353,340 @@ fn emit_stdlib_math_functions(
     emitter.emit_comment("============================================================");
 
     if emitter.needs_mul16 {
-        let org_addr = section_alloc
-            .allocate("CODE", 74)
-            .map_err(CodegenError::SectionError)?;
-        emitter.emit_org(org_addr);
-        emitter.emit_comment("Function: mul16");
-        emitter.emit_comment("  Params: a: u16 in $80-$81, b: u16 in $82-$83");
-        emitter.emit_comment("  Returns: u16 in A/Y (low/high)");
-        emitter.emit_comment(&format!("  Location: ${:04X}", org_addr));
-        emitter.emit_label("mul16");
-
-        // Emit mul16 implementation
-        // Memory layout: $D0-$D1 multiplicand, $D2-$D3 result,
-        //               $D4-$D5 multiplier, $D6 loop counter
-        emitter.emit_raw("    LDA #$00");
-        emitter.emit_raw("    STA $D2"); // result_low at $D2
-        emitter.emit_raw("    STA $D3"); // result_high at $D3
-        emitter.emit_raw("    LDA $80");
-        emitter.emit_raw("    STA $D0"); // param_a_low at $D0
-        emitter.emit_raw("    LDA $81");
-        emitter.emit_raw("    STA $D1"); // param_a_high at $D1
-        emitter.emit_raw("    LDA $82");
-        emitter.emit_raw("    STA $D4"); // param_b_low at $D4
-        emitter.emit_raw("    LDA $83");
-        emitter.emit_raw("    STA $D5"); // param_b_high at $D5
-        emitter.emit_raw("    LDX #$10");
-        emitter.emit_raw("    STX $D6"); // loop_counter at $D6
-        emitter.emit_raw("    mul16_loop:");
-        emitter.emit_raw("    LDA $D4");
-        emitter.emit_raw("    LSR A");
-        emitter.emit_raw("    BCC mul16_skip_add");
-        emitter.emit_raw("    CLC");
-        emitter.emit_raw("    LDA $D2");
-        emitter.emit_raw("    ADC $D0");
-        emitter.emit_raw("    STA $D2");
-        emitter.emit_raw("    LDA $D3");
-        emitter.emit_raw("    ADC $D1");
-        emitter.emit_raw("    STA $D3");
-        emitter.emit_raw("    mul16_skip_add:");
-        emitter.emit_raw("    LSR $D5");
-        emitter.emit_raw("    ROR $D4");
-        emitter.emit_raw("    ASL $D0");
-        emitter.emit_raw("    ROL $D1");
-        emitter.emit_raw("    DEC $D6");
-        emitter.emit_raw("    BNE mul16_loop");
-        emitter.emit_raw("    LDA $D2");
-        emitter.emit_raw("    LDY $D3");
-        emitter.emit_raw("    RTS");
+        emit_stdlib_routine(emitter, section_alloc, "mul16", "u16 in A/Y (low/high)", emit_mul16_body)?;
     }
 
     if emitter.needs_div16 {
-        let org_addr = section_alloc
-            .allocate("CODE", 110)
-            .map_err(CodegenError::SectionError)?;
-        emitter.emit_org(org_addr);
-        emitter.emit_comment("Function: div16");
-        emitter.emit_comment("  Params: a: u16 in $80-$81, b: u16 in $82-$83");
-        emitter.emit_comment("  Returns: u16 in A/Y (low/high)");
-        emitter.emit_comment(&format!("  Location: ${:04X}", org_addr));
-        emitter.emit_label("div16");
-
-        // Emit div16 implementation using proper remainder register
-        // Memory layout: $D0-$D1 dividend, $D2-$D3 divisor, $D4-$D5 quotient,
-        //               $D6-$D7 remainder, $D8 loop counter
-
-        // Zero check - return 0xFFFF for division by zero
-        emitter.emit_raw("    LDA $82");
-        emitter.emit_raw("    ORA $83");
-        emitter.emit_raw("    BNE div16_not_zero");
-        emitter.emit_raw("    LDA #$FF");
-        emitter.emit_raw("    TAY");
-        emitter.emit_raw("    JMP div16_done");
-
-        emitter.emit_raw("    div16_not_zero:");
-        // Initialize quotient and remainder to 0
-        emitter.emit_raw("    LDA #$00");
-        emitter.emit_raw("    STA $D4"); // quotient_low
-        emitter.emit_raw("    STA $D5"); // quotient_high
-        emitter.emit_raw("    STA $D6"); // remainder_low
-        emitter.emit_raw("    STA $D7"); // remainder_high
-
-        // Copy dividend to working storage
-        emitter.emit_raw("    LDA $80");
-        emitter.emit_raw("    STA $D0"); // dividend_low
-        emitter.emit_raw("    LDA $81");
-        emitter.emit_raw("    STA $D1"); // dividend_high
-
-        // Copy divisor to working storage
-        emitter.emit_raw("    LDA $82");
-        emitter.emit_raw("    STA $D2"); // divisor_low
-        emitter.emit_raw("    LDA $83");
-        emitter.emit_raw("    STA $D3"); // divisor_high
-
-        // Loop counter = 16
-        emitter.emit_raw("    LDA #$10");
-        emitter.emit_raw("    STA $D8");
-
-        emitter.emit_raw("    div16_loop:");
-        // Shift dividend left, high bit goes into remainder
-        emitter.emit_raw("    ASL $D0");
-        emitter.emit_raw("    ROL $D1");
-        emitter.emit_raw("    ROL $D6"); // Carry from dividend -> remainder
-        emitter.emit_raw("    ROL $D7");
-
-        // Shift quotient left to make room for next bit
-        emitter.emit_raw("    ASL $D4");
-        emitter.emit_raw("    ROL $D5");
-
-        // Compare remainder with divisor (16-bit)
-        emitter.emit_raw("    LDA $D7"); // remainder_high
-        emitter.emit_raw("    CMP $D3"); // divisor_high
-        emitter.emit_raw("    BCC div16_skip"); // remainder < divisor
-        emitter.emit_raw("    BNE div16_sub"); // remainder > divisor
-        // High bytes equal, compare low bytes
-        emitter.emit_raw("    LDA $D6"); // remainder_low
-        emitter.emit_raw("    CMP $D2"); // divisor_low
-        emitter.emit_raw("    BCC div16_skip"); // remainder < divisor
-
-        emitter.emit_raw("    div16_sub:");
-        // remainder -= divisor
-        emitter.emit_raw("    SEC");
-        emitter.emit_raw("    LDA $D6");
-        emitter.emit_raw("    SBC $D2");
-        emitter.emit_raw("    STA $D6");
-        emitter.emit_raw("    LDA $D7");
-        emitter.emit_raw("    SBC $D3");
-        emitter.emit_raw("    STA $D7");
-        // Set quotient bit 0
-        emitter.emit_raw("    INC $D4");
-
-        emitter.emit_raw("    div16_skip:");
-        emitter.emit_raw("    DEC $D8");
-        emitter.emit_raw("    BNE div16_loop");
-
-        // Return quotient in A/Y
-        emitter.emit_raw("    LDA $D4");
-        emitter.emit_raw("    LDY $D5");
-
-        emitter.emit_raw("    div16_done:");
-        emitter.emit_raw("    RTS");
+        emit_stdlib_routine(emitter, section_alloc, "div16", "u16 in A/Y (low/high)", emit_div16_body)?;
     }
 
     if emitter.needs_mod16 {
-        let org_addr = section_alloc
-            .allocate("CODE", 110)
-            .map_err(CodegenError::SectionError)?;
-        emitter.emit_org(org_addr);
-        emitter.emit_comment("Function: mod16");
-        emitter.emit_comment("  Params: a: u16 in $80-$81, b: u16 in $82-$83");
-        emitter.emit_comment("  Returns: u16 remainder in A/Y (low/high)");
-        emitter.emit_comment(&format!("  Location: ${:04X}", org_addr));
-        emitter.emit_label("mod16");
-
-        // Emit mod16 implementation - same as div16 but returns remainder
-        // Memory layout: $D0-$D1 dividend, $D2-$D3 divisor, $D4-$D5 quotient,
-        //               $D6-$D7 remainder, $D8 loop counter
-
-        // Zero check - return 0xFFFF for modulo by zero
-        emitter.emit_raw("    LDA $82");
-        emitter.emit_raw("    ORA $83");
-        emitter.emit_raw("    BNE mod16_not_zero");
-        emitter.emit_raw("    LDA #$FF");
-        emitter.emit_raw("    TAY");
-        emitter.emit_raw("    JMP mod16_done");
-
-        emitter.emit_raw("    mod16_not_zero:");
-        // Initialize quotient and remainder to 0
-        emitter.emit_raw("    LDA #$00");
-        emitter.emit_raw("    STA $D4"); // quotient_low
-        emitter.emit_raw("    STA $D5"); // quotient_high
-        emitter.emit_raw("    STA $D6"); // remainder_low
-        emitter.emit_raw("    STA $D7"); // remainder_high
-
-        // Copy dividend to working storage
-        emitter.emit_raw("    LDA $80");
-        emitter.emit_raw("    STA $D0"); // dividend_low
-        emitter.emit_raw("    LDA $81");
-        emitter.emit_raw("    STA $D1"); // dividend_high
-
-        // Copy divisor to working storage
-        emitter.emit_raw("    LDA $82");
-        emitter.emit_raw("    STA $D2"); // divisor_low
-        emitter.emit_raw("    LDA $83");
-        emitter.emit_raw("    STA $D3"); // divisor_high
-
-        // Loop counter = 16
-        emitter.emit_raw("    LDA #$10");
-        emitter.emit_raw("    STA $D8");
-
-        emitter.emit_raw("    mod16_loop:");
-        // Shift dividend left, high bit goes into remainder
-        emitter.emit_raw("    ASL $D0");
-        emitter.emit_raw("    ROL $D1");
-        emitter.emit_raw("    ROL $D6"); // Carry from dividend -> remainder
-        emitter.emit_raw("    ROL $D7");
-
-        // Shift quotient left to make room for next bit
-        emitter.emit_raw("    ASL $D4");
-        emitter.emit_raw("    ROL $D5");
-
-        // Compare remainder with divisor (16-bit)
-        emitter.emit_raw("    LDA $D7"); // remainder_high
-        emitter.emit_raw("    CMP $D3"); // divisor_high
-        emitter.emit_raw("    BCC mod16_skip"); // remainder < divisor
-        emitter.emit_raw("    BNE mod16_sub"); // remainder > divisor
-        // High bytes equal, compare low bytes
-        emitter.emit_raw("    LDA $D6"); // remainder_low
-        emitter.emit_raw("    CMP $D2"); // divisor_low
-        emitter.emit_raw("    BCC mod16_skip"); // remainder < divisor
-
-        emitter.emit_raw("    mod16_sub:");
-        // remainder -= divisor
-        emitter.emit_raw("    SEC");
-        emitter.emit_raw("    LDA $D6");
-        emitter.emit_raw("    SBC $D2");
-        emitter.emit_raw("    STA $D6");
-        emitter.emit_raw("    LDA $D7");
-        emitter.emit_raw("    SBC $D3");
-        emitter.emit_raw("    STA $D7");
-        // Set quotient bit 0
-        emitter.emit_raw("    INC $D4");
-
-        emitter.emit_raw("    mod16_skip:");
-        emitter.emit_raw("    DEC $D8");
-        emitter.emit_raw("    BNE mod16_loop");
-
-        // Return REMAINDER in A/Y (difference from div16)
-        emitter.emit_raw("    LDA $D6");
-        emitter.emit_raw("    LDY $D7");
-
-        emitter.emit_raw("    mod16_done:");
-        emitter.emit_raw("    RTS");
+        emit_stdlib_routine(
+            emitter,
+            section_alloc,
+            "mod16",
+            "u16 remainder in A/Y (low/high)",
+            emit_mod16_body,
+        )?;
     }
 
     Ok(())
 }
 
+/// Exact size of a code fragment after peephole optimization
+///
+/// Used to size functions before they are placed. The peephole passes only
+/// look at neighbouring lines within a function, so the fragment optimizes
+/// the same way here as it does in the final program.
+pub fn measure_code(
+    asm: &str,
+    cpu: Cpu,
+    options: &peephole::PeepholeOptions,
+) -> Result<u16, CodegenError> {
+    let optimized = peephole::optimize(&peephole::parse_assembly(asm), cpu, options);
+    crate::assembler::code_size(&peephole::lines_to_string(&optimized))
+        .map_err(|e| CodegenError::AssemblyError(e.message))
+}
+
+/// Emit one stdlib routine into CODE, sized exactly by assembling its body first
+fn emit_stdlib_routine(
+    emitter: &mut Emitter,
+    section_alloc: &mut SectionAllocator,
+    name: &str,
+    returns: &str,
+    body: fn(&mut Emitter),
+) -> Result<(), CodegenError> {
+    let mut temp_emitter = Emitter::new(emitter.verbosity);
+    temp_emitter.set_memory_layout(emitter.memory_layout.clone());
+    temp_emitter.cpu = emitter.cpu;
+    body(&mut temp_emitter);
+    let size = measure_code(&temp_emitter.finish(), emitter.cpu, &emitter.peephole)?;
+
+    let org_addr = section_alloc
+        .allocate("CODE", size)
+        .map_err(CodegenError::SectionError)?;
+    section_alloc.record_allocation(
+        name.to_string(),
+        org_addr,
+        size,
+        section_allocator::AllocationSource::Section("CODE".to_string()),
+    );
+    emitter.emit_placement(org_addr, "CODE");
+    emitter.emit_comment(&format!("Function: {}", name));
+    let params = emitter.memory_layout.param_base;
+    emitter.emit_comment(&format!(
+        "  Params: a: u16 in ${:02X}-${:02X}, b: u16 in ${:02X}-${:02X}",
+        params,
+        params + 1,
+        params + 2,
+        params + 3
+    ));
+    emitter.emit_comment(&format!("  Returns: {}", returns));
+    if !emitter.is_ca65() {
+        emitter.emit_comment(&format!("  Location: ${:04X}", org_addr));
+    }
+    emitter.emit_label(name);
+    body(emitter);
+    Ok(())
+}
+
+/// Body of the 16-bit multiply routine
+fn emit_mul16_body(emitter: &mut Emitter) {
+    // Emit mul16 implementation
+    // Scratch layout (default addresses): $D0-$D1 multiplicand, $D2-$D3 result,
+    //                                     $D4-$D5 multiplier, $D6 loop counter
+    let layout = emitter.memory_layout.clone();
+    let scratch = |offset| layout.scratch(offset);
+    let param = |offset: u8| format!("${:02X}", layout.param_base + offset);
+
+    emitter.emit_raw("    LDA #$00");
+    emitter.emit_raw(&format!("    STA {}", scratch(2))); // result_low at $D2
+    emitter.emit_raw(&format!("    STA {}", scratch(3))); // result_high at $D3
+    emitter.emit_raw(&format!("    LDA {}", param(0)));
+    emitter.emit_raw(&format!("    STA {}", scratch(0))); // param_a_low at $D0
+    emitter.emit_raw(&format!("    LDA {}", param(1)));
+    emitter.emit_raw(&format!("    STA {}", scratch(1))); // param_a_high at $D1
+    emitter.emit_raw(&format!("    LDA {}", param(2)));
+    emitter.emit_raw(&format!("    STA {}", scratch(4))); // param_b_low at $D4
+    emitter.emit_raw(&format!("    LDA {}", param(3)));
+    emitter.emit_raw(&format!("    STA {}", scratch(5))); // param_b_high at $D5
+    emitter.emit_raw("    LDX #$10");
+    emitter.emit_raw(&format!("    STX {}", scratch(6))); // loop_counter at $D6
+    emitter.emit_label("mul16_loop");
+    emitter.record_loop_bound("mul16_loop", 15);
+    emitter.emit_raw(&format!("    LDA {}", scratch(4)));
+    emitter.emit_raw("    LSR A");
+    emitter.emit_raw("    BCC mul16_skip_add");
+    emitter.emit_raw("    CLC");
+    emitter.emit_raw(&format!("    LDA {}", scratch(2)));
+    emitter.emit_raw(&format!("    ADC {}", scratch(0)));
+    emitter.emit_raw(&format!("    STA {}", scratch(2)));
+    emitter.emit_raw(&format!("    LDA {}", scratch(3)));
+    emitter.emit_raw(&format!("    ADC {}", scratch(1)));
+    emitter.emit_raw(&format!("    STA {}", scratch(3)));
+    emitter.emit_label("mul16_skip_add");
+    emitter.emit_raw(&format!("    LSR {}", scratch(5)));
+    emitter.emit_raw(&format!("    ROR {}", scratch(4)));
+    emitter.emit_raw(&format!("    ASL {}", scratch(0)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(1)));
+    emitter.emit_raw(&format!("    DEC {}", scratch(6)));
+    emitter.emit_raw("    BNE mul16_loop");
+    emitter.emit_raw(&format!("    LDA {}", scratch(2)));
+    emitter.emit_raw(&format!("    LDY {}", scratch(3)));
+    emitter.emit_raw("    RTS");
+}
+
+/// Body of the 16-bit division routine
+fn emit_div16_body(emitter: &mut Emitter) {
+    // Emit div16 implementation using proper remainder register
+    // Scratch layout (default addresses): $D0-$D1 dividend, $D2-$D3 divisor, $D4-$D5 quotient,
+    //                                     $D6-$D7 remainder, $D8 loop counter
+    let layout = emitter.memory_layout.clone();
+    let scratch = |offset| layout.scratch(offset);
+    let param = |offset: u8| format!("${:02X}", layout.param_base + offset);
+
+    // Zero check - return 0xFFFF for division by zero
+    emitter.emit_raw(&format!("    LDA {}", param(2)));
+    emitter.emit_raw(&format!("    ORA {}", param(3)));
+    emitter.emit_raw("    BNE div16_not_zero");
+    emitter.emit_raw("    LDA #$FF");
+    emitter.emit_raw("    TAY");
+    emitter.emit_raw("    JMP div16_done");
+
+    emitter.emit_label("div16_not_zero");
+    // Initialize quotient and remainder to 0
+    emitter.emit_raw("    LDA #$00");
+    emitter.emit_raw(&format!("    STA {}", scratch(4))); // quotient_low
+    emitter.emit_raw(&format!("    STA {}", scratch(5))); // quotient_high
+    emitter.emit_raw(&format!("    STA {}", scratch(6))); // remainder_low
+    emitter.emit_raw(&format!("    STA {}", scratch(7))); // remainder_high
+
+    // Copy dividend to working storage
+    emitter.emit_raw(&format!("    LDA {}", param(0)));
+    emitter.emit_raw(&format!("    STA {}", scratch(0))); // dividend_low
+    emitter.emit_raw(&format!("    LDA {}", param(1)));
+    emitter.emit_raw(&format!("    STA {}", scratch(1))); // dividend_high
+
+    // Copy divisor to working storage
+    emitter.emit_raw(&format!("    LDA {}", param(2)));
+    emitter.emit_raw(&format!("    STA {}", scratch(2))); // divisor_low
+    emitter.emit_raw(&format!("    LDA {}", param(3)));
+    emitter.emit_raw(&format!("    STA {}", scratch(3))); // divisor_high
+
+    // Loop counter = 16
+    emitter.emit_raw("    LDA #$10");
+    emitter.emit_raw(&format!("    STA {}", scratch(8)));
+
+    emitter.emit_label("div16_loop");
+    emitter.record_loop_bound("div16_loop", 15);
+    // Shift dividend left, high bit goes into remainder
+    emitter.emit_raw(&format!("    ASL {}", scratch(0)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(1)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(6))); // Carry from dividend -> remainder
+    emitter.emit_raw(&format!("    ROL {}", scratch(7)));
+
+    // Shift quotient left to make room for next bit
+    emitter.emit_raw(&format!("    ASL {}", scratch(4)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(5)));
+
+    // Compare remainder with divisor (16-bit)
+    emitter.emit_raw(&format!("    LDA {}", scratch(7))); // remainder_high
+    emitter.emit_raw(&format!("    CMP {}", scratch(3))); // divisor_high
+    emitter.emit_raw("    BCC div16_skip"); // remainder < divisor
+    emitter.emit_raw("    BNE div16_sub"); // remainder > divisor
+    // High bytes equal, compare low bytes
+    emitter.emit_raw(&format!("    LDA {}", scratch(6))); // remainder_low
+    emitter.emit_raw(&format!("    CMP {}", scratch(2))); // divisor_low
+    emitter.emit_raw("    BCC div16_skip"); // remainder < divisor
+
+    emitter.emit_label("div16_sub");
+    // remainder -= divisor
+    emitter.emit_raw("    SEC");
+    emitter.emit_raw(&format!("    LDA {}", scratch(6)));
+    emitter.emit_raw(&format!("    SBC {}", scratch(2)));
+    emitter.emit_raw(&format!("    STA {}", scratch(6)));
+    emitter.emit_raw(&format!("    LDA {}", scratch(7)));
+    emitter.emit_raw(&format!("    SBC {}", scratch(3)));
+    emitter.emit_raw(&format!("    STA {}", scratch(7)));
+    // Set quotient bit 0
+    emitter.emit_raw(&format!("    INC {}", scratch(4)));
+
+    emitter.emit_label("div16_skip");
+    emitter.emit_raw(&format!("    DEC {}", scratch(8)));
+    emitter.emit_raw("    BNE div16_loop");
+
+    // Return quotient in A/Y
+    emitter.emit_raw(&format!("    LDA {}", scratch(4)));
+    emitter.emit_raw(&format!("    LDY {}", scratch(5)));
+
+    emitter.emit_label("div16_done");
+    emitter.emit_raw("    RTS");
+}
+
+/// Body of the 16-bit modulo routine
+fn emit_mod16_body(emitter: &mut Emitter) {
+    // Emit mod16 implementation - same as div16 but returns remainder
+    // Scratch layout (default addresses): $D0-$D1 dividend, $D2-$D3 divisor, $D4-$D5 quotient,
+    //                                     $D6-$D7 remainder, $D8 loop counter
+    let layout = emitter.memory_layout.clone();
+    let scratch = |offset| layout.scratch(offset);
+    let param = |offset: u8| format!("${:02X}", layout.param_base + offset);
+
+    // Zero check - return 0xFFFF for modulo by zero
+    emitter.emit_raw(&format!("    LDA {}", param(2)));
+    emitter.emit_raw(&format!("    ORA {}", param(3)));
+    emitter.emit_raw("    BNE mod16_not_zero");
+    emitter.emit_raw("    LDA #$FF");
+    emitter.emit_raw("    TAY");
+    emitter.emit_raw("    JMP mod16_done");
+
+    emitter.emit_label("mod16_not_zero");
+    // Initialize quotient and remainder to 0
+    emitter.emit_raw("    LDA #$00");
+    emitter.emit_raw(&format!("    STA {}", scratch(4))); // quotient_low
+    emitter.emit_raw(&format!("    STA {}", scratch(5))); // quotient_high
+    emitter.emit_raw(&format!("    STA {}", scratch(6))); // remainder_low
+    emitter.emit_raw(&format!("    STA {}", scratch(7))); // remainder_high
+
+    // Copy dividend to working storage
+    emitter.emit_raw(&format!("    LDA {}", param(0)));
+    emitter.emit_raw(&format!("    STA {}", scratch(0))); // dividend_low
+    emitter.emit_raw(&format!("    LDA {}", param(1)));
+    emitter.emit_raw(&format!("    STA {}", scratch(1))); // dividend_high
+
+    // Copy divisor to working storage
+    emitter.emit_raw(&format!("    LDA {}", param(2)));
+    emitter.emit_raw(&format!("    STA {}", scratch(2))); // divisor_low
+    emitter.emit_raw(&format!("    LDA {}", param(3)));
+    emitter.emit_raw(&format!("    STA {}", scratch(3))); // divisor_high
+
+    // Loop counter = 16
+    emitter.emit_raw("    LDA #$10");
+    emitter.emit_raw(&format!("    STA {}", scratch(8)));
+
+    emitter.emit_label("mod16_loop");
+    emitter.record_loop_bound("mod16_loop", 15);
+    // Shift dividend left, high bit goes into remainder
+    emitter.emit_raw(&format!("    ASL {}", scratch(0)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(1)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(6))); // Carry from dividend -> remainder
+    emitter.emit_raw(&format!("    ROL {}", scratch(7)));
+
+    // Shift quotient left to make room for next bit
+    emitter.emit_raw(&format!("    ASL {}", scratch(4)));
+    emitter.emit_raw(&format!("    ROL {}", scratch(5)));
+
+    // Compare remainder with divisor (16-bit)
+    emitter.emit_raw(&format!("    LDA {}", scratch(7))); // remainder_high
+    emitter.emit_raw(&format!("    CMP {}", scratch(3))); // divisor_high
+    emitter.emit_raw("    BCC mod16_skip"); // remainder < divisor
+    emitter.emit_raw("    BNE mod16_sub"); // remainder > divisor
+    // High bytes equal, compare low bytes
+    emitter.emit_raw(&format!("    LDA {}", scratch(6))); // remainder_low
+    emitter.emit_raw(&format!("    CMP {}", scratch(2))); // divisor_low
+    emitter.emit_raw("    BCC mod16_skip"); // remainder < divisor
+
+    emitter.emit_label("mod16_sub");
+    // remainder -= divisor
+    emitter.emit_raw("    SEC");
+    emitter.emit_raw(&format!("    LDA {}", scratch(6)));
+    emitter.emit_raw(&format!("    SBC {}", scratch(2)));
+    emitter.emit_raw(&format!("    STA {}", scratch(6)));
+    emitter.emit_raw(&format!("    LDA {}", scratch(7)));
+    emitter.emit_raw(&format!("    SBC {}", scratch(3)));
+    emitter.emit_raw(&format!("    STA {}", scratch(7)));
+    // Set quotient bit 0
+    emitter.emit_raw(&format!("    INC {}", scratch(4)));
+
+    emitter.emit_label("mod16_skip");
+    emitter.emit_raw(&format!("    DEC {}", scratch(8)));
+    emitter.emit_raw("    BNE mod16_loop");
+
+    // Return REMAINDER in A/Y (difference from div16)
+    emitter.emit_raw(&format!("    LDA {}", scratch(6)));
+    emitter.emit_raw(&format!("    LDY {}", scratch(7)));
+
+    emitter.emit_label("mod16_done");
+    emitter.emit_raw("    RTS");
+}
+
 pub fn generate(
     ast: &SourceFile,
     program: &ProgramInfo,
     verbosity: CommentVerbosity,
 ) -> Result<(String, SectionAllocator), CodegenError> {
+    let options = CodegenOptions {
+        verbosity,
+        ..Default::default()
+    };
+    let output = generate_with_options(ast, program, &options)?;
+    Ok((output.asm, output.section_alloc))
+}
+
+/// Generate code with explicit options (output mode, verbosity)
+pub fn generate_with_options(
+    ast: &SourceFile,
+    program: &ProgramInfo,
+    options: &CodegenOptions,
+) -> Result<CodegenOutput, CodegenError> {
     use crate::sema::table::{SymbolKind, SymbolLocation};
 use rustc_hash::FxHashMap as HashMap;
 
-    let mut emitter = Emitter::new(verbosity);
-    let mut section_alloc = SectionAllocator::default();
+    let mut emitter = Emitter::new(options.verbosity);
+    emitter.set_memory_layout(program.memory_layout.clone());
+    emitter.output_mode = options.output_mode;
+    emitter.include_tests = options.include_tests;
+    emitter.cpu = options.cpu;
+    emitter.peephole = options.peephole.clone();
+    let mut section_alloc = SectionAllocator::new(program.memory_config.clone());
     let mut string_collector = StringCollector::new();
 
+    // ca65 has no name for the 2A03, whose instructions are the 6502's
+    let ca65_2a03 = options.cpu == Cpu::Ricoh2A03 && options.output_mode == OutputMode::Ca65;
+    if options.cpu != Cpu::Nmos6502 && !ca65_2a03 {
+        emitter.emit_raw(&format!(".SETCPU \"{}\"", options.cpu.name()));
+    }
+
     // Build a map of symbol names to their import source file
     let mut import_sources: HashMap<String, String> = HashMap::default();
     for item in &ast.items {
@@ -510,15 +703,17 @@ use rustc_hash::FxHashMap as HashMap;
 
     // Emit addresses from resolved_symbols (includes both local and imported addresses)
     for symbol in program.resolved_symbols.values() {
-        if symbol.kind == SymbolKind::Address
-            && let SymbolLocation::Absolute(addr) = symbol.location
-            && emitted_addresses.insert(symbol.name.clone())
-        {
+        let value = match symbol.location {
+            SymbolLocation::Absolute(addr) => format!("${:04X}", addr),
+            SymbolLocation::Long(addr) => format!("${:06X}", addr),
+            _ => continue,
+        };
+        if symbol.kind == SymbolKind::Address && emitted_addresses.insert(symbol.name.clone()) {
             // Emit comment if this address was imported
             if let Some(source) = import_sources.get(&symbol.name) {
                 emitter.emit_comment(&format!("Imported from {}", source));
             }
-            emitter.emit_raw(&format!("{} = ${:04X}", symbol.name, addr));
+            emitter.emit_raw(&format!("{} = {}", symbol.name, value));
         }
     }
 
@@ -540,9 +735,14 @@ use rustc_hash::FxHashMap as HashMap;
         emitter.emit_comment("Data Section (Const Arrays)");
         emitter.emit_comment("============================================================");
 
-        // Emit .ORG for DATA section (default location $C000)
-        // TODO: Make this configurable via wraith.toml
-        emitter.emit_data_org(0xC000);
+        // Emit .ORG for the RODATA section if there is one, else $C000
+        match section_alloc.get_section("RODATA") {
+            Some(rodata) => {
+                let start = rodata.start;
+                emitter.emit_placement(start, "RODATA");
+            }
+            None => emitter.emit_placement(0xC000, &item::org_segment_name(0xC000)),
+        }
         emitter.emit_raw("");
 
         // Emit const arrays from imported modules first
@@ -586,6 +786,9 @@ use rustc_hash::FxHashMap as HashMap;
         emitter.emit_raw("");
     }
 
+    // Without a vector table, the OS starts the program at its load address
+    generate_entry_stub(ast, &mut emitter, &mut section_alloc)?;
+
     // Generate code for imported items FIRST
     // This ensures that imported functions are defined before they're called
     // Only emit section header if there are actually imported items to generate
@@ -718,19 +921,132 @@ use rustc_hash::FxHashMap as HashMap;
     emit_stdlib_math_functions(&mut emitter, &mut section_alloc)?;
 
     // Generate interrupt vector table
-    generate_interrupt_vectors(ast, &mut emitter)?;
+    let startup = section_alloc.config().startup;
+    generate_interrupt_vectors(ast, &mut emitter, startup)?;
 
     // Apply peephole optimizations
-    let asm = emitter.finish();
-    let lines = peephole::parse_assembly(&asm);
-    let optimized = peephole::optimize(&lines);
-    let final_asm = peephole::lines_to_string(&optimized);
+    let emitter_placements = std::mem::take(&mut emitter.placements);
+    let loop_bounds = std::mem::take(&mut emitter.loop_bounds);
+    let stack_locals = std::mem::take(&mut emitter.stack_locals);
+    let (asm, spans) = emitter.finish_with_spans();
+    let lines = peephole::parse_assembly_with_spans(&asm, &spans);
+    let (optimized, pass_stats) = if options.pass_stats {
+        peephole::optimize_with_stats(&lines, options.cpu, &options.peephole)
+    } else {
+        (peephole::optimize(&lines, options.cpu, &options.peephole), Vec::new())
+    };
+    let mut final_asm = peephole::lines_to_string(&optimized);
+    let mut line_spans = peephole::line_spans(&optimized);
+
+    let linker_config = if options.output_mode == OutputMode::Ca65 {
+        // Anything referenced but not defined here must come from another object
+        let imports = crate::assembler::undefined_symbols(&final_asm)
+            .map_err(|e| CodegenError::AssemblyError(e.to_string()))?;
+        if !imports.is_empty() {
+            final_asm = format!(".import {}\n{}", imports.join(", "), final_asm);
+            line_spans.insert(0, None);
+        }
+        Some(linker_config::generate_linker_config(
+            section_alloc.config(),
+            &emitter_placements,
+        ))
+    } else {
+        None
+    };
+
+    Ok(CodegenOutput {
+        asm: final_asm,
+        section_alloc,
+        linker_config,
+        line_spans,
+        loop_bounds,
+        stack_locals,
+        strings: string_collector.data_blocks(),
+        pass_stats,
+    })
+}
+
+/// Jump to the `#[reset]` function from the start of the default section
+///
+/// Targets whose OS owns the vectors load the program and run it from its
+/// first byte; on Commodore machines a BASIC line `SYS <address>` comes first.
+fn generate_entry_stub(
+    ast: &SourceFile,
+    emitter: &mut Emitter,
+    section_alloc: &mut SectionAllocator,
+) -> Result<(), CodegenError> {
+    use crate::ast::{FnAttribute, Item};
 
-    Ok((final_asm, section_alloc))
+    let startup = section_alloc.config().startup;
+    if startup == Startup::Vectors {
+        return Ok(());
+    }
+    let Some(reset) = ast.items.iter().find_map(|item| match &item.node {
+        Item::Function(func) if func.attributes.contains(&FnAttribute::Reset) => {
+            Some(func.name.node.clone())
+        }
+        _ => None,
+    }) else {
+        return Ok(());
+    };
+
+    let start = section_alloc
+        .get_section(section_alloc.default_section_name())
+        .map(|section| section.start)
+        .ok_or_else(|| CodegenError::SectionError("no default section".to_string()))?;
+
+    // Link, line number 10, SYS token, address digits, end of line, end of program
+    let basic_line = (startup == Startup::BasicStub).then(|| {
+        let mut digits = 1;
+        while (start as usize + 8 + digits).to_string().len() != digits {
+            digits += 1;
+        }
+        let sys = (start as usize + 8 + digits).to_string();
+        let end_of_program = start + 6 + digits as u16;
+        let mut bytes = end_of_program.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[10, 0, 0x9E]);
+        bytes.extend_from_slice(sys.as_bytes());
+        bytes.extend_from_slice(&[0, 0, 0]);
+        (sys, bytes)
+    });
+
+    let size = basic_line.as_ref().map_or(0, |(_, bytes)| bytes.len() as u16) + 3;
+    let addr = section_alloc
+        .allocate_default(size)
+        .map_err(CodegenError::SectionError)?;
+    section_alloc.record_allocation(
+        "entry".to_string(),
+        addr,
+        size,
+        section_allocator::AllocationSource::AutoAllocated,
+    );
+
+    emitter.emit_comment("============================================================");
+    emitter.emit_comment("Entry point");
+    emitter.emit_comment("============================================================");
+    emitter.emit_placement(addr, section_alloc.default_section_name());
+    if let Some((sys, bytes)) = basic_line {
+        emitter.emit_comment(&format!("10 SYS {}", sys));
+        emitter.emit_bytes(&bytes);
+    }
+    emitter.emit_raw(&format!("    JMP {}", reset));
+    emitter.emit_raw("");
+    Ok(())
 }
 
 /// Generate the 6502 interrupt vector table at $FFFA-$FFFF
-fn generate_interrupt_vectors(ast: &SourceFile, emitter: &mut Emitter) -> Result<(), CodegenError> {
+///
+/// On the 65816 the table starts at $FFEA with the native-mode NMI and IRQ
+/// vectors, which are the ones taken once the reset handler has left
+/// emulation mode.
+///
+/// Targets whose OS owns the vectors get no table; `#[irq]` and `#[nmi]`
+/// functions are still compiled for the program to install itself.
+fn generate_interrupt_vectors(
+    ast: &SourceFile,
+    emitter: &mut Emitter,
+    startup: Startup,
+) -> Result<(), CodegenError> {
     use crate::ast::{FnAttribute, Item};
 
     // Find interrupt handlers
@@ -775,10 +1091,42 @@ fn generate_interrupt_vectors(ast: &SourceFile, emitter: &mut Emitter) -> Result
     }
 
     // Only generate vector table if at least one handler is defined
-    if nmi_handler.is_some() || reset_handler.is_some() || irq_handler.is_some() {
+    if startup == Startup::Vectors
+        && (nmi_handler.is_some() || reset_handler.is_some() || irq_handler.is_some())
+    {
         emitter.emit_comment("============================");
         emitter.emit_comment("Interrupt Vector Table");
-        emitter.emit_org(0xFFFA);
+        if emitter.cpu.has_native_mode() {
+            emitter.emit_placement(0xFFEA, "VECTORS");
+
+            // Native NMI vector at $FFEA
+            if let Some(handler) = &nmi_handler {
+                emitter.emit_comment(&format!("Native NMI vector -> {}", handler));
+                emitter.emit_word_label(handler);
+            } else {
+                emitter.emit_comment("Native NMI vector (not used)");
+                emitter.emit_word(0);
+            }
+            emitter.emit_comment("Reserved");
+            emitter.emit_word(0);
+
+            // Native IRQ vector at $FFEE
+            if let Some(handler) = &irq_handler {
+                emitter.emit_comment(&format!("Native IRQ vector -> {}", handler));
+                emitter.emit_word_label(handler);
+            } else {
+                emitter.emit_comment("Native IRQ vector (not used)");
+                emitter.emit_word(0);
+            }
+
+            // $FFF0-$FFF9: reserved, emulation COP and ABORT
+            emitter.emit_comment("Emulation COP/ABORT vectors (not used)");
+            for _ in 0..5 {
+                emitter.emit_word(0);
+            }
+        } else {
+            emitter.emit_placement(0xFFFA, "VECTORS");
+        }
 
         // NMI vector at $FFFA
         if let Some(handler) = nmi_handler {
@@ -3,7 +3,20 @@
 //! This module implements pattern-based peephole optimization to improve
 //! the quality of generated assembly code by eliminating redundant instructions,
 //! dead code, and other inefficiencies.
+//!
+//! When generating for the 65C02, extra passes rewrite NMOS sequences into
+//! its STZ, TSB/TRB, `INC A`/`DEC A` and BRA instructions; with the
+//! undocumented NMOS opcodes enabled, into LAX and DCP. Like every other
+//! pass they only look within one `.ORG` block, so a function optimizes the
+//! same way when it is measured on its own as in the final program.
+//!
+//! Every pass has a name and the lowest `OptLevel` it runs at, so passes can
+//! be switched off one at a time (`--disable-pass`) to find the one behind a
+//! miscompile, and `--pass-stats` reports what each of them removed.
 
+use crate::assembler::{self, Cpu};
+use crate::ast::Span;
+use rustc_hash::FxHashMap as HashMap;
 use std::fmt;
 
 /// A parsed assembly instruction
@@ -14,6 +27,8 @@ pub enum Line {
         mnemonic: String,
         operand: Option<String>,
         comment: Option<String>,
+        /// Wraith source the instruction was generated from
+        span: Option<Span>,
     },
     /// A label definition
     Label(String),
@@ -32,6 +47,7 @@ impl fmt::Display for Line {
                 mnemonic,
                 operand,
                 comment,
+                ..
             } => {
                 write!(f, "    {}", mnemonic)?;
                 if let Some(op) = operand {
@@ -52,8 +68,16 @@ impl fmt::Display for Line {
 
 /// Parse assembly output into structured lines
 pub fn parse_assembly(asm: &str) -> Vec<Line> {
+    parse_assembly_with_spans(asm, &[])
+}
+
+/// Parse assembly output, attaching a source span to each instruction
+///
+/// `spans` holds one entry per line of `asm` (see `Emitter::finish_with_spans`).
+pub fn parse_assembly_with_spans(asm: &str, spans: &[Option<Span>]) -> Vec<Line> {
     asm.lines()
-        .map(|line| {
+        .enumerate()
+        .map(|(index, line)| {
             let trimmed = line.trim();
 
             if trimmed.is_empty() {
@@ -101,6 +125,7 @@ pub fn parse_assembly(asm: &str) -> Vec<Line> {
                     mnemonic,
                     operand,
                     comment,
+                    span: spans.get(index).copied().flatten(),
                 };
             }
 
@@ -110,45 +135,251 @@ pub fn parse_assembly(asm: &str) -> Vec<Line> {
         .collect()
 }
 
+/// How much the peephole optimizer does (`-O0`, `-O1`, `-O2`, `-Os`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// No passes: the assembly is exactly what codegen emitted
+    O0,
+    /// Passes that delete an instruction made redundant by its neighbours
+    O1,
+    /// Also the passes that track register and flag values, rewrite
+    /// instruction sequences and use CPU-specific instructions
+    #[default]
+    O2,
+    /// Everything in `-O2`, plus inverting a branch over a JMP when the
+    /// target is in range (`branch-over-jump`), which is not on by default
+    /// yet
+    Os,
+}
+
+impl OptLevel {
+    /// Level for `-O<name>`
+    pub fn from_name(name: &str) -> Option<OptLevel> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            "s" => Some(OptLevel::Os),
+            _ => None,
+        }
+    }
+}
+
+/// Which peephole passes run
+#[derive(Debug, Clone, Default)]
+pub struct PeepholeOptions {
+    pub level: OptLevel,
+    /// Passes turned off by name (`--disable-pass`), whatever the level
+    pub disabled: Vec<String>,
+}
+
+impl PeepholeOptions {
+    fn runs(&self, pass: &Pass, cpu: Cpu) -> bool {
+        self.level >= pass.level
+            && (pass.available)(cpu)
+            && !self.disabled.iter().any(|name| name == pass.name)
+    }
+}
+
+/// One peephole pass
+struct Pass {
+    /// Name for `--disable-pass` and `--pass-stats`
+    name: &'static str,
+    /// Lowest level the pass runs at
+    level: OptLevel,
+    /// Whether the pass applies to a CPU
+    available: fn(Cpu) -> bool,
+    run: fn(&[Line]) -> Vec<Line>,
+}
+
+const fn pass(name: &'static str, level: OptLevel, run: fn(&[Line]) -> Vec<Line>) -> Pass {
+    Pass {
+        name,
+        level,
+        available: |_| true,
+        run,
+    }
+}
+
+/// Passes applied in order, repeatedly, until none removes a line
+const PASSES: &[Pass] = &[
+    pass("redundant-loads", OptLevel::O1, eliminate_redundant_loads),
+    pass("redundant-stores", OptLevel::O1, eliminate_redundant_stores),
+    pass("load-after-store", OptLevel::O1, eliminate_load_after_store),
+    pass("dead-stores", OptLevel::O2, eliminate_dead_stores),
+    pass("nop-operations", OptLevel::O1, eliminate_nop_operations),
+    pass(
+        "redundant-transfers",
+        OptLevel::O1,
+        eliminate_redundant_transfers,
+    ),
+    pass(
+        "unreachable",
+        OptLevel::O1,
+        eliminate_unreachable_after_terminator,
+    ),
+    pass(
+        "redundant-cmp-zero",
+        OptLevel::O1,
+        eliminate_redundant_cmp_zero,
+    ),
+    pass(
+        "redundant-ldy-zero",
+        OptLevel::O2,
+        eliminate_redundant_ldy_zero,
+    ),
+    pass("branch-over-jump", OptLevel::Os, eliminate_branch_over_jump),
+    pass(
+        "redundant-ldx-zero",
+        OptLevel::O2,
+        eliminate_redundant_ldx_zero,
+    ),
+    pass("clc-adc-zero", OptLevel::O1, eliminate_clc_adc_zero),
+    pass("sec-sbc-zero", OptLevel::O1, eliminate_sec_sbc_zero),
+    pass(
+        "redundant-flag-ops",
+        OptLevel::O1,
+        eliminate_redundant_flag_ops,
+    ),
+    pass(
+        "redundant-address-loads",
+        OptLevel::O2,
+        eliminate_redundant_address_loads,
+    ),
+    pass("strength-reduction", OptLevel::O2, apply_strength_reduction),
+    pass("tail-calls", OptLevel::O2, optimize_tail_calls),
+    Pass {
+        available: Cpu::has_cmos_instructions,
+        ..pass("stz", OptLevel::O2, use_stz)
+    },
+    Pass {
+        available: Cpu::has_cmos_instructions,
+        ..pass("inc-dec-accumulator", OptLevel::O2, use_inc_dec_accumulator)
+    },
+    Pass {
+        available: Cpu::has_cmos_instructions,
+        ..pass("test-and-set-bits", OptLevel::O2, use_test_and_set_bits)
+    },
+    Pass {
+        available: Cpu::has_native_mode,
+        ..pass("width-switches", OptLevel::O1, merge_width_switches)
+    },
+    Pass {
+        available: Cpu::has_undocumented_opcodes,
+        ..pass("lax", OptLevel::O2, use_lax)
+    },
+    Pass {
+        available: Cpu::has_undocumented_opcodes,
+        ..pass("dcp", OptLevel::O2, use_dcp)
+    },
+];
+
+/// Passes applied once at the end, when no other pass will move code any more
+const FINAL_PASSES: &[Pass] = &[Pass {
+    available: Cpu::has_cmos_instructions,
+    ..pass("bra", OptLevel::O2, use_bra)
+}];
+
+/// Names of every pass, in the order they run
+pub fn pass_names() -> impl Iterator<Item = &'static str> {
+    PASSES.iter().chain(FINAL_PASSES).map(|pass| pass.name)
+}
+
+/// What one pass removed over a whole optimization
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassStats {
+    pub name: &'static str,
+    pub instructions: i64,
+    pub bytes: i64,
+}
+
 /// Apply peephole optimizations to parsed assembly
-pub fn optimize(lines: &[Line]) -> Vec<Line> {
+pub fn optimize(lines: &[Line], cpu: Cpu, options: &PeepholeOptions) -> Vec<Line> {
+    run_passes(lines, cpu, options, None)
+}
+
+/// Apply peephole optimizations, counting what each pass that ran removed
+pub fn optimize_with_stats(
+    lines: &[Line],
+    cpu: Cpu,
+    options: &PeepholeOptions,
+) -> (Vec<Line>, Vec<PassStats>) {
+    let mut stats = Vec::new();
+    let result = run_passes(lines, cpu, options, Some(&mut stats));
+    (result, stats)
+}
+
+fn run_passes(
+    lines: &[Line],
+    cpu: Cpu,
+    options: &PeepholeOptions,
+    mut stats: Option<&mut Vec<PassStats>>,
+) -> Vec<Line> {
+    let passes: Vec<&Pass> = PASSES
+        .iter()
+        .filter(|pass| options.runs(pass, cpu))
+        .collect();
+    let final_passes: Vec<&Pass> = FINAL_PASSES
+        .iter()
+        .filter(|pass| options.runs(pass, cpu))
+        .collect();
+    if let Some(stats) = stats.as_deref_mut() {
+        stats.extend(passes.iter().chain(&final_passes).map(|pass| PassStats {
+            name: pass.name,
+            instructions: 0,
+            bytes: 0,
+        }));
+    }
+
     let mut result = lines.to_vec();
-    let mut changed = true;
+    let mut apply = |result: &mut Vec<Line>, index: usize, pass: &Pass| {
+        let before = stats.as_ref().map(|_| code_measure(result));
+        *result = (pass.run)(result);
+        if let (Some(stats), Some((instructions, bytes))) = (stats.as_deref_mut(), before) {
+            let (after_instructions, after_bytes) = code_measure(result);
+            stats[index].instructions += instructions - after_instructions;
+            stats[index].bytes += bytes - after_bytes;
+        }
+    };
 
     // Keep applying optimizations until no more changes
+    let mut changed = !passes.is_empty();
     while changed {
-        changed = false;
-
-        // Apply each optimization pass
         let before_len = result.len();
-        result = eliminate_redundant_loads(&result);
-        result = eliminate_redundant_stores(&result);
-        result = eliminate_load_after_store(&result);
-        result = eliminate_dead_stores(&result);
-        result = eliminate_nop_operations(&result);
-        result = eliminate_redundant_transfers(&result);
-        result = eliminate_unreachable_after_terminator(&result);
-        result = eliminate_redundant_cmp_zero(&result);
-        result = eliminate_redundant_ldy_zero(&result);
-        // DISABLED: eliminate_branch_over_jump breaks while loops with large bodies
-        // by inverting branches that exceed the 127-byte limit
-        // result = eliminate_branch_over_jump(&result);
-        result = eliminate_redundant_ldx_zero(&result);
-        result = eliminate_clc_adc_zero(&result);
-        result = eliminate_sec_sbc_zero(&result);
-        result = eliminate_redundant_flag_ops(&result);
-        result = eliminate_redundant_address_loads(&result);
-        result = apply_strength_reduction(&result);
-        result = optimize_tail_calls(&result);
-
-        if result.len() != before_len {
-            changed = true;
+        for (index, pass) in passes.iter().enumerate() {
+            apply(&mut result, index, pass);
         }
+        changed = result.len() != before_len;
+    }
+
+    for (index, pass) in final_passes.iter().enumerate() {
+        apply(&mut result, passes.len() + index, pass);
     }
 
     result
 }
 
+/// Number of instructions and their size in bytes
+///
+/// Instructions that cannot be sized on their own count as no bytes.
+fn code_measure(lines: &[Line]) -> (i64, i64) {
+    let mut widths = assembler::RegisterWidths::default();
+    let mut instructions = 0;
+    let mut bytes = 0;
+    for line in lines {
+        if let Line::Instruction {
+            mnemonic, operand, ..
+        } = line
+        {
+            let operand = operand.as_deref().unwrap_or("");
+            instructions += 1;
+            bytes += widths.instruction_size(mnemonic, operand).unwrap_or(0) as i64;
+            widths.follow(mnemonic, operand);
+        }
+    }
+    (instructions, bytes)
+}
+
 /// Eliminate redundant consecutive loads: LDA $40; LDA $40 → LDA $40
 fn eliminate_redundant_loads(lines: &[Line]) -> Vec<Line> {
     let mut result = Vec::new();
@@ -561,8 +792,31 @@ fn invert_branch(mnemonic: &str) -> Option<&'static str> {
 ///     BNE target_label
 /// skip_label:
 ///
-/// Saves 3 bytes (the JMP instruction).
+/// Saves 3 bytes (the JMP instruction). Only applies when `target_label` is
+/// in the same `.ORG` block and within branch range, measured before any
+/// JMP is removed; removing code can only bring targets closer.
 fn eliminate_branch_over_jump(lines: &[Line]) -> Vec<Line> {
+    let blocks = block_ids(lines);
+    let mut offsets = vec![None; lines.len()];
+    let mut labels = HashMap::default();
+    let mut start = 0;
+    while start < lines.len() {
+        let end = start
+            + blocks[start..]
+                .iter()
+                .take_while(|&&b| b == blocks[start])
+                .count();
+        if let Some(block_offsets) = block_offsets(&lines[start..end]) {
+            for (index, offset) in (start..end).zip(block_offsets) {
+                offsets[index] = Some(offset);
+                if let Line::Label(name) = &lines[index] {
+                    labels.insert((blocks[index], name.as_str()), offset);
+                }
+            }
+        }
+        start = end;
+    }
+
     let mut result = Vec::new();
     let mut i = 0;
 
@@ -574,6 +828,7 @@ fn eliminate_branch_over_jump(lines: &[Line]) -> Vec<Line> {
                     mnemonic: branch_m,
                     operand: Some(skip_label),
                     comment: branch_comment,
+                    span: branch_span,
                 },
                 Line::Instruction {
                     mnemonic: jmp_m,
@@ -587,12 +842,16 @@ fn eliminate_branch_over_jump(lines: &[Line]) -> Vec<Line> {
             if let Some(inverted) = invert_branch(branch_m)
                 && jmp_m == "JMP"
                 && skip_label == label
+                && let Some(offset) = offsets[i]
+                && let Some(&address) = labels.get(&(blocks[i], target_label.as_str()))
+                && (-128..=127).contains(&(address as i64 - (offset as i64 + 2)))
             {
                 // Replace with inverted branch to target
                 result.push(Line::Instruction {
                     mnemonic: inverted.to_string(),
                     operand: Some(target_label.clone()),
                     comment: branch_comment.clone(),
+                    span: *branch_span,
                 });
                 // Keep the label (might be used elsewhere)
                 result.push(lines[i + 2].clone());
@@ -632,6 +891,9 @@ fn eliminate_redundant_ldx_zero(lines: &[Line]) -> Vec<Line> {
                 // Track X register state
                 if mnemonic == "LDX" {
                     x_is_zero = operand.as_deref() == Some("#$00");
+                } else if mnemonic == "LAX" {
+                    // X = memory, unknown value
+                    x_is_zero = false;
                 } else if mnemonic == "INX" || mnemonic == "DEX" {
                     // X is modified, no longer known to be 0
                     x_is_zero = false;
@@ -829,9 +1091,7 @@ fn eliminate_redundant_address_loads(lines: &[Line]) -> Vec<Line> {
     for line in lines {
         match line {
             Line::Instruction {
-                mnemonic,
-                operand,
-                comment,
+                mnemonic, operand, ..
             } => {
                 // Check for redundant LDA #immediate
                 if mnemonic == "LDA"
@@ -879,14 +1139,21 @@ fn eliminate_redundant_address_loads(lines: &[Line]) -> Vec<Line> {
                         | "LSR"
                         | "ROL"
                         | "ROR"
-                ) {
+                        | "LAX"
+                        | "ISC"
+                        | "TSC"
+                        | "TDC"
+                        | "XBA"
+                ) || (matches!(mnemonic.as_str(), "INC" | "DEC")
+                    && operand.as_deref() == Some("A"))
+                {
                     a_value = None;
                 }
 
                 // Instructions that modify X
                 if matches!(
                     mnemonic.as_str(),
-                    "LDX" | "TAX" | "TSX" | "INX" | "DEX" | "PLX"
+                    "LDX" | "TAX" | "TSX" | "INX" | "DEX" | "PLX" | "LAX"
                 ) {
                     x_value = None;
                 }
@@ -897,11 +1164,7 @@ fn eliminate_redundant_address_loads(lines: &[Line]) -> Vec<Line> {
                     x_value = None;
                 }
 
-                result.push(Line::Instruction {
-                    mnemonic: mnemonic.clone(),
-                    operand: operand.clone(),
-                    comment: comment.clone(),
-                });
+                result.push(line.clone());
             }
             Line::Label(_) => {
                 // Labels are potential jump targets, reset tracking
@@ -957,6 +1220,7 @@ fn apply_strength_reduction(lines: &[Line]) -> Vec<Line> {
                     mnemonic: m3,
                     operand: Some(op3),
                     comment: c3,
+                    span: s3,
                 },
             ) = (&lines[i], &lines[i + 1], &lines[i + 2])
         {
@@ -968,6 +1232,7 @@ fn apply_strength_reduction(lines: &[Line]) -> Vec<Line> {
                     mnemonic: "ASL".to_string(),
                     operand: Some("A".to_string()),
                     comment: c3.clone(),
+                    span: *s3,
                 });
                 i += 3;
                 continue;
@@ -1008,6 +1273,7 @@ fn optimize_tail_calls(lines: &[Line]) -> Vec<Line> {
             mnemonic,
             operand: Some(target),
             comment,
+            span,
         } = &lines[i]
             && mnemonic == "JSR"
         {
@@ -1031,6 +1297,7 @@ fn optimize_tail_calls(lines: &[Line]) -> Vec<Line> {
                             mnemonic: "JMP".to_string(),
                             operand: Some(target.clone()),
                             comment: comment.clone(),
+                            span: *span,
                         });
                         // Skip the JSR, comments, and RTS
                         i = j + 1;
@@ -1057,7 +1324,604 @@ fn optimize_tail_calls(lines: &[Line]) -> Vec<Line> {
     result
 }
 
+/// The next instruction at or after `start`, skipping comments and blank lines
+///
+/// Stops at labels and directives, where control may come from elsewhere.
+fn next_instruction(lines: &[Line], start: usize) -> Option<(&str, Option<&str>)> {
+    for line in lines.get(start..)? {
+        match line {
+            Line::Comment(_) | Line::Empty => {}
+            Line::Instruction {
+                mnemonic, operand, ..
+            } => return Some((mnemonic, operand.as_deref())),
+            Line::Label(_) | Line::Directive { .. } => return None,
+        }
+    }
+    None
+}
+
+/// Whether an instruction replaces A and sets N and Z without reading A
+fn overwrites_accumulator(mnemonic: &str) -> bool {
+    matches!(mnemonic, "LDA" | "PLA" | "TXA" | "TYA")
+}
+
+/// Whether a directive starts a new block of code (`.ORG`, `.segment`)
+fn is_block_start(line: &Line) -> bool {
+    matches!(line, Line::Directive { name, .. }
+        if name.eq_ignore_ascii_case(".ORG") || name.eq_ignore_ascii_case(".SEGMENT"))
+}
+
+/// Index of the block each line belongs to
+fn block_ids(lines: &[Line]) -> Vec<usize> {
+    let mut block = 0;
+    lines
+        .iter()
+        .map(|line| {
+            if is_block_start(line) {
+                block += 1;
+            }
+            block
+        })
+        .collect()
+}
+
+/// Store zero with STZ instead of loading it into A (65C02)
+///
+/// Pattern:
+///     LDA #$00
+///     STA $40
+///     STA $0200,X
+///     LDA $41        ; A is replaced before it is read
+/// Becomes:
+///     STZ $40
+///     STZ $0200,X
+///     LDA $41
+fn use_stz(lines: &[Line]) -> Vec<Line> {
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if let Line::Instruction {
+            mnemonic,
+            operand: Some(value),
+            ..
+        } = &lines[i]
+            && mnemonic == "LDA"
+            && value == "#$00"
+        {
+            let stores = lines[i + 1..]
+                .iter()
+                .take_while(|line| {
+                    matches!(line, Line::Instruction { mnemonic, operand: Some(target), .. }
+                        if mnemonic == "STA" && stz_supports(target))
+                })
+                .count();
+            let after = i + 1 + stores;
+            if stores > 0
+                && next_instruction(lines, after).is_some_and(|(m, _)| overwrites_accumulator(m))
+            {
+                for store in &lines[i + 1..after] {
+                    if let Line::Instruction {
+                        operand,
+                        comment,
+                        span,
+                        ..
+                    } = store
+                    {
+                        result.push(Line::Instruction {
+                            mnemonic: "STZ".to_string(),
+                            operand: operand.clone(),
+                            comment: comment.clone(),
+                            span: *span,
+                        });
+                    }
+                }
+                i = after;
+                continue;
+            }
+        }
+
+        result.push(lines[i].clone());
+        i += 1;
+    }
+
+    result
+}
+
+/// STZ has zero page and absolute modes, optionally indexed by X
+fn stz_supports(operand: &str) -> bool {
+    let operand = operand.to_ascii_uppercase();
+    !operand.starts_with('(') && !operand.ends_with(",Y") && !operand.ends_with(",S")
+}
+
+/// Increment or decrement A in place (65C02)
+///
+/// Pattern:
+///     CLC            (or SEC)
+///     ADC #$01       (or SBC #$01)
+/// Becomes:
+///     INC A          (or DEC A)
+///
+/// Only when carry and overflow are not read afterwards, since INC A leaves
+/// them alone, and never in a block that uses decimal mode.
+fn use_inc_dec_accumulator(lines: &[Line]) -> Vec<Line> {
+    let blocks = block_ids(lines);
+    let mut decimal_blocks = Vec::new();
+    let mut overflow_blocks = Vec::new();
+    for (line, &block) in lines.iter().zip(&blocks) {
+        if let Line::Instruction { mnemonic, .. } = line {
+            match mnemonic.as_str() {
+                "SED" => decimal_blocks.push(block),
+                "BVC" | "BVS" | "PHP" => overflow_blocks.push(block),
+                _ => {}
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if i + 1 < lines.len()
+            && let (
+                Line::Instruction { mnemonic: m1, .. },
+                Line::Instruction {
+                    mnemonic: m2,
+                    operand: Some(value),
+                    comment,
+                    span,
+                },
+            ) = (&lines[i], &lines[i + 1])
+            && value == "#$01"
+            && !decimal_blocks.contains(&blocks[i])
+            && !overflow_blocks.contains(&blocks[i])
+            && carry_dead_after(lines, i + 2)
+        {
+            let replacement = match (m1.as_str(), m2.as_str()) {
+                ("CLC", "ADC") => Some("INC"),
+                ("SEC", "SBC") => Some("DEC"),
+                _ => None,
+            };
+            if let Some(mnemonic) = replacement {
+                result.push(Line::Instruction {
+                    mnemonic: mnemonic.to_string(),
+                    operand: Some("A".to_string()),
+                    comment: comment.clone(),
+                    span: *span,
+                });
+                i += 2;
+                continue;
+            }
+        }
+
+        result.push(lines[i].clone());
+        i += 1;
+    }
+
+    result
+}
+
+/// Whether the carry is set or cleared before anything reads it, looking
+/// forward from `start` to the end of the basic block
+fn carry_dead_after(lines: &[Line], start: usize) -> bool {
+    for line in &lines[start..] {
+        let Line::Instruction { mnemonic, .. } = line else {
+            if matches!(line, Line::Comment(_) | Line::Empty) {
+                continue;
+            }
+            return false;
+        };
+        match mnemonic.as_str() {
+            "ADC" | "SBC" | "ROL" | "ROR" | "BCC" | "BCS" | "PHP" => return false,
+            "CLC" | "SEC" | "CMP" | "CPX" | "CPY" | "ASL" | "LSR" | "PLP" => return true,
+            "BEQ" | "BNE" | "BMI" | "BPL" | "BVC" | "BVS" | "BRA" | "JMP" | "JSR" | "RTS"
+            | "RTI" | "BRK" => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Set or clear bits in memory with TSB/TRB (65C02)
+///
+/// Pattern:
+///     LDA $40
+///     ORA #$80       (or AND #$7F)
+///     STA $40
+///     LDA $41        ; A is replaced before it is read
+/// Becomes:
+///     LDA #$80       (or LDA #$80, the bits to clear)
+///     TSB $40        (or TRB $40)
+///     LDA $41
+fn use_test_and_set_bits(lines: &[Line]) -> Vec<Line> {
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if i + 2 < lines.len()
+            && let (
+                Line::Instruction {
+                    mnemonic: load,
+                    operand: Some(source),
+                    comment,
+                    span,
+                },
+                Line::Instruction {
+                    mnemonic: op,
+                    operand: Some(mask),
+                    ..
+                },
+                Line::Instruction {
+                    mnemonic: store,
+                    operand: Some(target),
+                    ..
+                },
+            ) = (&lines[i], &lines[i + 1], &lines[i + 2])
+            && load == "LDA"
+            && store == "STA"
+            && source == target
+            && !target.contains(',')
+            && !target.starts_with('(')
+            && !target.starts_with('#')
+            && next_instruction(lines, i + 3).is_some_and(|(m, _)| overwrites_accumulator(m))
+        {
+            let rewrite = match op.as_str() {
+                "ORA" if mask.starts_with('#') => Some(("TSB", mask.clone())),
+                "AND" => parse_immediate(mask).map(|bits| ("TRB", format!("#${:02X}", !bits))),
+                _ => None,
+            };
+            if let Some((mnemonic, bits)) = rewrite {
+                result.push(Line::Instruction {
+                    mnemonic: "LDA".to_string(),
+                    operand: Some(bits),
+                    comment: comment.clone(),
+                    span: *span,
+                });
+                result.push(Line::Instruction {
+                    mnemonic: mnemonic.to_string(),
+                    operand: Some(target.clone()),
+                    comment: None,
+                    span: *span,
+                });
+                i += 3;
+                continue;
+            }
+        }
+
+        result.push(lines[i].clone());
+        i += 1;
+    }
+
+    result
+}
+
+/// Keep the 65816 accumulator 16-bit between two wide sequences
+///
+/// Pattern:
+///     SEP #$20
+///     REP #$20
+/// Becomes nothing: the pair only drops and restores the accumulator width,
+/// and the hidden high byte survives both.
+fn merge_width_switches(lines: &[Line]) -> Vec<Line> {
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if let Line::Instruction {
+            mnemonic,
+            operand: Some(bits),
+            ..
+        } = &lines[i]
+            && mnemonic == "SEP"
+            && bits == "#$20"
+            && let Some(next) =
+                (i + 1..lines.len()).find(|&j| !matches!(lines[j], Line::Comment(_) | Line::Empty))
+            && matches!(&lines[next], Line::Instruction { mnemonic, operand: Some(bits), .. }
+                if mnemonic == "REP" && bits == "#$20")
+        {
+            result.extend(lines[i + 1..next].iter().cloned());
+            i = next + 1;
+            continue;
+        }
+
+        result.push(lines[i].clone());
+        i += 1;
+    }
+
+    result
+}
+
+/// Value of an immediate operand written as `#$XX` or `#N`
+fn parse_immediate(operand: &str) -> Option<u8> {
+    let value = operand.strip_prefix('#')?;
+    match value.strip_prefix('$') {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Turn jumps to nearby labels into BRA (65C02)
+///
+/// Pattern:
+///     JMP loop       ; `loop` within -128..=127 bytes
+/// Becomes:
+///     BRA loop
+///
+/// Saves a byte. Only labels in the same `.ORG` block are considered, and
+/// distances are measured before any jump shrinks, which can only bring
+/// targets closer.
+fn use_bra(lines: &[Line]) -> Vec<Line> {
+    let mut result = lines.to_vec();
+    let blocks = block_ids(lines);
+    let mut start = 0;
+
+    while start < lines.len() {
+        let end = start + blocks[start..].iter().take_while(|&&b| b == blocks[start]).count();
+        if let Some(offsets) = block_offsets(&lines[start..end]) {
+            let labels: Vec<(&str, u32)> = lines[start..end]
+                .iter()
+                .zip(&offsets)
+                .filter_map(|(line, &offset)| match line {
+                    Line::Label(name) => Some((name.as_str(), offset)),
+                    _ => None,
+                })
+                .collect();
+            for (index, &offset) in (start..end).zip(&offsets) {
+                if let Line::Instruction {
+                    mnemonic,
+                    operand: Some(target),
+                    ..
+                } = &lines[index]
+                    && mnemonic == "JMP"
+                    && let Some(&(_, address)) = labels.iter().find(|(name, _)| name == target)
+                    && (-128..=127).contains(&(address as i64 - (offset as i64 + 2)))
+                    && let Line::Instruction { mnemonic, .. } = &mut result[index]
+                {
+                    *mnemonic = "BRA".to_string();
+                }
+            }
+        }
+        start = end;
+    }
+
+    result
+}
+
+/// Offset of each line from the start of its block, if every line can be sized
+///
+/// 65816 immediates are sized at the register widths REP/SEP leave.
+fn block_offsets(lines: &[Line]) -> Option<Vec<u32>> {
+    let mut offset = 0;
+    let mut offsets = Vec::with_capacity(lines.len());
+    let mut widths = assembler::RegisterWidths::default();
+    for line in lines {
+        offsets.push(offset);
+        offset += match line {
+            Line::Instruction {
+                mnemonic, operand, ..
+            } => {
+                let operand = operand.as_deref().unwrap_or("");
+                let size = widths.instruction_size(mnemonic, operand).ok()?;
+                widths.follow(mnemonic, operand);
+                size as u32
+            }
+            Line::Directive { .. } => assembler::code_size(&line.to_string()).ok()? as u32,
+            Line::Label(_) | Line::Comment(_) | Line::Empty => 0,
+        };
+    }
+    Some(offsets)
+}
+
+/// Load A and X together with LAX (undocumented NMOS)
+///
+/// Pattern:
+///     LDA $40        (or LDX $40)
+///     TAX            (or TXA)
+/// Becomes:
+///     LAX $40
+fn use_lax(lines: &[Line]) -> Vec<Line> {
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if i + 1 < lines.len()
+            && let (
+                Line::Instruction {
+                    mnemonic: load,
+                    operand: Some(source),
+                    comment,
+                    span,
+                },
+                Line::Instruction {
+                    mnemonic: transfer,
+                    ..
+                },
+            ) = (&lines[i], &lines[i + 1])
+            && matches!(
+                (load.as_str(), transfer.as_str()),
+                ("LDA", "TAX") | ("LDX", "TXA")
+            )
+            && lax_supports(source)
+        {
+            result.push(Line::Instruction {
+                mnemonic: "LAX".to_string(),
+                operand: Some(source.clone()),
+                comment: comment.clone(),
+                span: *span,
+            });
+            i += 2;
+            continue;
+        }
+
+        result.push(lines[i].clone());
+        i += 1;
+    }
+
+    result
+}
+
+/// LAX has no immediate (it is unstable), `,X` or `(zp)` mode
+fn lax_supports(operand: &str) -> bool {
+    let operand = operand.to_ascii_uppercase();
+    let plain_indirect = operand.starts_with('(') && !operand.contains(',');
+    !operand.starts_with('#') && !operand.ends_with(",X") && !plain_indirect
+}
+
+/// Decrement and compare in one instruction with DCP (undocumented NMOS)
+///
+/// Pattern:
+///     DEC $40
+///     LDA #$00
+///     STA $20
+///     LDA $40
+///     CMP $20
+///     BEQ found      (or BNE)
+/// Becomes:
+///     LDA #$00
+///     STA $20
+///     DCP $40
+///     BEQ found
+///
+/// DCP compares the other way round, so only the zero flag matches: both
+/// sides of the branch must replace A, and set the carry before reading it.
+fn use_dcp(lines: &[Line]) -> Vec<Line> {
+    let blocks = block_ids(lines);
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if i + 5 < lines.len()
+            && let (
+                Line::Instruction {
+                    mnemonic: dec,
+                    operand: Some(counter),
+                    comment,
+                    span,
+                },
+                Line::Instruction {
+                    mnemonic: load_value,
+                    operand: Some(value),
+                    ..
+                },
+                Line::Instruction {
+                    mnemonic: store,
+                    operand: Some(temp),
+                    ..
+                },
+                Line::Instruction {
+                    mnemonic: load_counter,
+                    operand: Some(reloaded),
+                    ..
+                },
+                Line::Instruction {
+                    mnemonic: cmp,
+                    operand: Some(compared),
+                    ..
+                },
+                Line::Instruction {
+                    mnemonic: branch,
+                    operand: Some(target),
+                    ..
+                },
+            ) = (
+                &lines[i],
+                &lines[i + 1],
+                &lines[i + 2],
+                &lines[i + 3],
+                &lines[i + 4],
+                &lines[i + 5],
+            )
+            && dec == "DEC"
+            && load_value == "LDA"
+            && store == "STA"
+            && load_counter == "LDA"
+            && cmp == "CMP"
+            && matches!(branch.as_str(), "BEQ" | "BNE")
+            && value.starts_with('#')
+            && reloaded == counter
+            && compared == temp
+            && temp != counter
+            && let Some(taken) = label_index(lines, &blocks, blocks[i], target)
+            && next_instruction(lines, i + 6).is_some_and(|(m, _)| overwrites_accumulator(m))
+            && next_instruction(lines, taken + 1).is_some_and(|(m, _)| overwrites_accumulator(m))
+            && carry_dead_on_all_paths(lines, &blocks, i + 5, 4)
+        {
+            result.extend(lines[i + 1..i + 3].iter().cloned());
+            result.push(Line::Instruction {
+                mnemonic: "DCP".to_string(),
+                operand: Some(counter.clone()),
+                comment: comment.clone(),
+                span: *span,
+            });
+            result.push(lines[i + 5].clone());
+            i += 6;
+            continue;
+        }
+
+        result.push(lines[i].clone());
+        i += 1;
+    }
+
+    result
+}
+
+/// Index of a label defined in the given block
+fn label_index(lines: &[Line], blocks: &[usize], block: usize, name: &str) -> Option<usize> {
+    lines
+        .iter()
+        .zip(blocks)
+        .position(|(line, &b)| b == block && matches!(line, Line::Label(label) if label == name))
+}
+
+/// Whether every path from `start` sets or clears the carry before reading
+/// it, following branches and jumps (at most `hops` deep) within the block
+fn carry_dead_on_all_paths(lines: &[Line], blocks: &[usize], start: usize, hops: u8) -> bool {
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        let Line::Instruction {
+            mnemonic, operand, ..
+        } = line
+        else {
+            if matches!(line, Line::Directive { .. }) {
+                return false;
+            }
+            continue;
+        };
+        match mnemonic.as_str() {
+            "ADC" | "SBC" | "ROL" | "ROR" | "ISC" | "BCC" | "BCS" | "PHP" => return false,
+            "CLC" | "SEC" | "CMP" | "CPX" | "CPY" | "ASL" | "LSR" | "PLP" | "DCP" => return true,
+            "BEQ" | "BNE" | "BMI" | "BPL" | "BVC" | "BVS" | "BRA" | "JMP" => {
+                let Some(target) = operand
+                    .as_deref()
+                    .and_then(|name| label_index(lines, blocks, blocks[index], name))
+                else {
+                    return false;
+                };
+                if hops == 0 || !carry_dead_on_all_paths(lines, blocks, target, hops - 1) {
+                    return false;
+                }
+                if matches!(mnemonic.as_str(), "JMP" | "BRA") {
+                    return true;
+                }
+            }
+            "JSR" | "RTS" | "RTI" | "BRK" => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
 /// Convert optimized lines back to assembly string
+/// Source span of each line produced by `lines_to_string`
+pub fn line_spans(lines: &[Line]) -> Vec<Option<Span>> {
+    lines
+        .iter()
+        .map(|line| match line {
+            Line::Instruction { span, .. } => *span,
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn lines_to_string(lines: &[Line]) -> String {
     let mut result = lines
         .iter()
@@ -1204,31 +2068,31 @@ mod tests {
 
     #[test]
     fn test_branch_inversion_beq_jmp() {
-        let asm = "    BEQ skip\n    JMP target\nskip:\n    LDA #$00\n";
+        let asm = "target:\n    BEQ skip\n    JMP target\nskip:\n    LDA #$00\n";
         let lines = parse_assembly(asm);
         let optimized = eliminate_branch_over_jump(&lines);
         // BEQ skip; JMP target; skip: → BNE target; skip:
-        assert_eq!(optimized.len(), 3);
+        assert_eq!(optimized.len(), 4);
         assert!(
-            matches!(&optimized[0], Line::Instruction { mnemonic, operand, .. }
+            matches!(&optimized[1], Line::Instruction { mnemonic, operand, .. }
             if mnemonic == "BNE" && operand.as_deref() == Some("target"))
         );
-        assert!(matches!(&optimized[1], Line::Label(l) if l == "skip"));
+        assert!(matches!(&optimized[2], Line::Label(l) if l == "skip"));
     }
 
     #[test]
     fn test_branch_inversion_bne_jmp() {
-        let asm = "    BNE skip\n    JMP target\nskip:\n";
+        let asm = "    BNE skip\n    JMP target\nskip:\n    RTS\ntarget:\n";
         let lines = parse_assembly(asm);
         let optimized = eliminate_branch_over_jump(&lines);
         // BNE skip; JMP target; skip: → BEQ target; skip:
-        assert_eq!(optimized.len(), 2);
+        assert_eq!(optimized.len(), 4);
         assert!(matches!(&optimized[0], Line::Instruction { mnemonic, .. } if mnemonic == "BEQ"));
     }
 
     #[test]
     fn test_branch_inversion_bcs_jmp() {
-        let asm = "    BCS skip\n    JMP target\nskip:\n";
+        let asm = "    BCS skip\n    JMP target\nskip:\ntarget:\n";
         let lines = parse_assembly(asm);
         let optimized = eliminate_branch_over_jump(&lines);
         assert!(matches!(&optimized[0], Line::Instruction { mnemonic, .. } if mnemonic == "BCC"));
@@ -1237,15 +2101,33 @@ mod tests {
     #[test]
     fn test_branch_inversion_preserves_nonmatching() {
         // Label doesn't match branch target - should not optimize
-        let asm = "    BEQ other\n    JMP target\nskip:\n";
+        let asm = "    BEQ other\n    JMP target\nskip:\ntarget:\n";
         let lines = parse_assembly(asm);
         let optimized = eliminate_branch_over_jump(&lines);
-        // Should keep all 3 lines unchanged
-        assert_eq!(optimized.len(), 3);
+        // Should keep all 4 lines unchanged
+        assert_eq!(optimized.len(), 4);
         assert!(matches!(&optimized[0], Line::Instruction { mnemonic, .. } if mnemonic == "BEQ"));
         assert!(matches!(&optimized[1], Line::Instruction { mnemonic, .. } if mnemonic == "JMP"));
     }
 
+    #[test]
+    fn test_branch_inversion_needs_target_in_range() {
+        // 130 bytes of NOPs put `target` out of reach of a branch
+        let far = format!(
+            "    BEQ skip\n    JMP target\nskip:\n{}target:\n",
+            "    NOP\n".repeat(130)
+        );
+        let lines = parse_assembly(&far);
+        assert_eq!(eliminate_branch_over_jump(&lines), lines);
+
+        // Undefined or in another block
+        let lines = parse_assembly("    BEQ skip\n    JMP target\nskip:\n");
+        assert_eq!(eliminate_branch_over_jump(&lines), lines);
+        let lines =
+            parse_assembly("    BEQ skip\n    JMP target\nskip:\n    RTS\n.ORG $9000\ntarget:\n");
+        assert_eq!(eliminate_branch_over_jump(&lines), lines);
+    }
+
     // LDX #$00 tracking tests
 
     #[test]
@@ -1472,6 +2354,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rewrites_keep_source_spans() {
+        let span = Some(Span::new(10, 20));
+        let asm = "main:\n    JSR subroutine\n    RTS\n";
+        let lines = parse_assembly_with_spans(asm, &[None, span, None]);
+        let optimized = optimize(&lines, Cpu::Nmos6502, &PeepholeOptions::default());
+        assert_eq!(line_spans(&optimized), vec![None, span]);
+    }
+
     #[test]
     fn test_tail_call_with_code_between() {
         let asm = "    JSR subroutine\n    LDA #$00\n    RTS\n";
@@ -1516,4 +2407,196 @@ mod tests {
             if mnemonic == "JMP" && operand.as_deref() == Some("subroutine"))
         );
     }
+
+    fn mnemonics(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Instruction {
+                    mnemonic, operand, ..
+                } => Some(match operand {
+                    Some(op) => format!("{} {}", mnemonic, op),
+                    None => mnemonic.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stz_when_a_is_reloaded() {
+        let lines = parse_assembly("    LDA #$00\n    STA $40\n    STA $0200,X\n    LDA $41\n");
+        assert_eq!(
+            mnemonics(&use_stz(&lines)),
+            ["STZ $40", "STZ $0200,X", "LDA $41"]
+        );
+
+        // A is still needed, or the store is indexed by Y
+        let lines = parse_assembly("    LDA #$00\n    STA $40\n    TAX\n");
+        assert_eq!(use_stz(&lines), lines);
+        let lines = parse_assembly("    LDA #$00\n    STA $0200,Y\n    LDA $41\n");
+        assert_eq!(use_stz(&lines), lines);
+        let lines = parse_assembly("    LDA #$00\n    STA $01,S\n    LDA $41\n");
+        assert_eq!(use_stz(&lines), lines);
+    }
+
+    #[test]
+    fn test_merge_width_switches() {
+        let lines = parse_assembly(
+            "    REP #$20\n    INC $40\n    SEP #$20\n; next statement\n    REP #$20\n    STZ $42\n    SEP #$20\n",
+        );
+        assert_eq!(
+            mnemonics(&merge_width_switches(&lines)),
+            ["REP #$20", "INC $40", "STZ $42", "SEP #$20"]
+        );
+
+        // A label can be reached with the accumulator 8-bit
+        let lines = parse_assembly("    SEP #$20\nnext:\n    REP #$20\n");
+        assert_eq!(merge_width_switches(&lines), lines);
+    }
+
+    #[test]
+    fn test_inc_a_when_carry_is_dead() {
+        let lines = parse_assembly("    CLC\n    ADC #$01\n    STA $40\n    CMP #$0A\n    BCC loop\n");
+        assert_eq!(
+            mnemonics(&use_inc_dec_accumulator(&lines)),
+            ["INC A", "STA $40", "CMP #$0A", "BCC loop"]
+        );
+        let lines = parse_assembly("    SEC\n    SBC #$01\n    LDX $40\n    CPX #$00\n");
+        assert_eq!(mnemonics(&use_inc_dec_accumulator(&lines))[0], "DEC A");
+
+        // The carry out is used, or the block is in decimal mode
+        let lines = parse_assembly("    CLC\n    ADC #$01\n    STA $40\n    LDA #$00\n    ADC #$00\n");
+        assert_eq!(use_inc_dec_accumulator(&lines), lines);
+        let lines = parse_assembly("    SED\n    CLC\n    ADC #$01\n    CLD\n    CLC\n");
+        assert_eq!(use_inc_dec_accumulator(&lines), lines);
+    }
+
+    #[test]
+    fn test_tsb_and_trb() {
+        let lines = parse_assembly("    LDA $40\n    ORA #$80\n    STA $40\n    LDA $41\n");
+        assert_eq!(
+            mnemonics(&use_test_and_set_bits(&lines)),
+            ["LDA #$80", "TSB $40", "LDA $41"]
+        );
+        let lines = parse_assembly("    LDA $0300\n    AND #$FE\n    STA $0300\n    TYA\n");
+        assert_eq!(
+            mnemonics(&use_test_and_set_bits(&lines)),
+            ["LDA #$01", "TRB $0300", "TYA"]
+        );
+
+        // The result is still in A
+        let lines = parse_assembly("    LDA $40\n    ORA #$80\n    STA $40\n    STA $41\n");
+        assert_eq!(use_test_and_set_bits(&lines), lines);
+    }
+
+    #[test]
+    fn test_bra_within_range_and_block() {
+        let asm = ".ORG $8000\nloop:\n    INC $40\n    JMP loop\n    JMP far\n    .RES 200\nfar:\n    JMP other\n.ORG $9000\nother:\n    RTS\n";
+        let optimized = use_bra(&parse_assembly(asm));
+        assert_eq!(
+            mnemonics(&optimized),
+            ["INC $40", "BRA loop", "JMP far", "JMP other", "RTS"]
+        );
+    }
+
+    #[test]
+    fn test_lax() {
+        let lines = parse_assembly("    LDA $40\n    TAX\n    LDX $0300,Y\n    TXA\n");
+        assert_eq!(mnemonics(&use_lax(&lines)), ["LAX $40", "LAX $0300,Y"]);
+
+        // No immediate or X-indexed LAX
+        let lines = parse_assembly("    LDA #$05\n    TAX\n    LDA $40,X\n    TAX\n");
+        assert_eq!(use_lax(&lines), lines);
+    }
+
+    #[test]
+    fn test_dcp_before_materialized_comparison() {
+        let asm = "    DEC $40\n    LDA #$00\n    STA $20\n    LDA $40\n    CMP $20\n    BEQ et_1\n    LDA #$00\n    JMP ex_2\net_1:\n    LDA #$01\nex_2:\n    CMP #$00\n    BNE done\n";
+        let lines = parse_assembly(asm);
+        assert_eq!(
+            mnemonics(&use_dcp(&lines))[..4],
+            ["LDA #$00", "STA $20", "DCP $40", "BEQ et_1"]
+        );
+
+        // The carry from the comparison reaches an ADC
+        let asm = asm.replace("    CMP #$00\n", "    ADC #$00\n");
+        let lines = parse_assembly(&asm);
+        assert_eq!(use_dcp(&lines), lines);
+    }
+
+    #[test]
+    fn test_cmos_passes_only_run_for_65c02() {
+        let lines = parse_assembly("loop:\n    LDA #$00\n    STA $40\n    LDA $41\n    JMP loop\n");
+        assert_eq!(
+            optimize(&lines, Cpu::Nmos6502, &PeepholeOptions::default()),
+            lines
+        );
+        assert_eq!(
+            mnemonics(&optimize(
+                &lines,
+                Cpu::Cmos65C02,
+                &PeepholeOptions::default()
+            )),
+            ["STZ $40", "LDA $41", "BRA loop"]
+        );
+    }
+
+    #[test]
+    fn test_levels_and_disabled_passes() {
+        let lines = parse_assembly("    LDA $40\n    LDA $40\n    JSR helper\n    RTS\n");
+        let at = |level, disabled: &[&str]| {
+            let options = PeepholeOptions {
+                level,
+                disabled: disabled.iter().map(|name| name.to_string()).collect(),
+            };
+            mnemonics(&optimize(&lines, Cpu::Nmos6502, &options))
+        };
+        assert_eq!(
+            at(OptLevel::O0, &[]),
+            ["LDA $40", "LDA $40", "JSR helper", "RTS"]
+        );
+        assert_eq!(at(OptLevel::O1, &[]), ["LDA $40", "JSR helper", "RTS"]);
+        assert_eq!(at(OptLevel::O2, &[]), ["LDA $40", "JMP helper"]);
+        assert_eq!(at(OptLevel::Os, &[]), ["LDA $40", "JMP helper"]);
+        let branch = parse_assembly("target:\n    BEQ skip\n    JMP target\nskip:\n");
+        let options = |level| PeepholeOptions {
+            level,
+            disabled: Vec::new(),
+        };
+        assert_eq!(
+            optimize(&branch, Cpu::Nmos6502, &options(OptLevel::O2)),
+            branch
+        );
+        assert_eq!(
+            mnemonics(&optimize(&branch, Cpu::Nmos6502, &options(OptLevel::Os))),
+            ["BNE target"]
+        );
+        assert_eq!(
+            at(OptLevel::O2, &["redundant-loads"]),
+            ["LDA $40", "LDA $40", "JMP helper"]
+        );
+        assert!(pass_names().any(|name| name == "branch-over-jump"));
+    }
+
+    #[test]
+    fn test_pass_stats() {
+        let lines = parse_assembly("    LDA $40\n    LDA $40\n    JSR helper\n    RTS\n");
+        let (optimized, stats) =
+            optimize_with_stats(&lines, Cpu::Nmos6502, &PeepholeOptions::default());
+        assert_eq!(optimized.len(), 2);
+        let removed = |name| stats.iter().find(|stat| stat.name == name).unwrap();
+        assert_eq!(
+            removed("redundant-loads"),
+            &PassStats {
+                name: "redundant-loads",
+                instructions: 1,
+                bytes: 2,
+            }
+        );
+        assert_eq!(removed("tail-calls").instructions, 1);
+        assert_eq!(removed("tail-calls").bytes, 1);
+        // CMOS-only passes did not run
+        assert!(stats.iter().all(|stat| stat.name != "stz"));
+    }
 }
@@ -83,6 +83,16 @@ impl SectionAllocator {
         self.allocate(&default_section.name, size)
     }
 
+    /// Name of the section used for functions without a placement attribute
+    pub fn default_section_name(&self) -> &str {
+        &self.config.default_section_name
+    }
+
+    /// Memory configuration this allocator places into
+    pub fn config(&self) -> &MemoryConfig {
+        &self.config
+    }
+
     /// Get section info
     pub fn get_section(&self, name: &str) -> Option<&Section> {
         self.config.get_section(name)
@@ -0,0 +1,184 @@
+//! Source Maps
+//!
+//! Maps generated code back to Wraith source using the spans recorded by the
+//! emitter (and kept through peephole optimization). Produces a line table of
+//! address ranges for debuggers and can annotate the assembly with
+//! `; file:line` markers. Spans are resolved against the file they point
+//! into, so code from imported modules maps back to its own module.
+
+use super::CodegenOutput;
+use crate::assembler::Assembly;
+use crate::ast::{FileId, SourceMap, Span};
+use rustc_hash::FxHashMap as HashMap;
+
+/// Fast byte offset to line/column lookup for one source file
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// 1-based line and column of a byte offset
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let col = offset - self.line_starts[line - 1] + 1;
+        (line, col)
+    }
+
+    /// Text of a 1-based line, without the newline
+    pub fn line_text(&self, line: usize) -> &'a str {
+        let Some(&start) = self.line_starts.get(line.wrapping_sub(1)) else {
+            return "";
+        };
+        let end = self.line_starts.get(line).map_or(self.source.len(), |&next| next);
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+}
+
+/// Line indexes of the files in a [`SourceMap`], built on first use
+pub struct SourceIndex<'a> {
+    sources: &'a SourceMap,
+    indexes: HashMap<FileId, LineIndex<'a>>,
+}
+
+impl<'a> SourceIndex<'a> {
+    pub fn new(sources: &'a SourceMap) -> Self {
+        Self {
+            sources,
+            indexes: HashMap::default(),
+        }
+    }
+
+    /// Line index of the file a span points into
+    pub fn file(&mut self, file: FileId) -> &LineIndex<'a> {
+        let sources = self.sources;
+        self.indexes
+            .entry(file)
+            .or_insert_with(|| LineIndex::new(sources.source(file)))
+    }
+}
+
+/// A run of consecutive bytes generated from one source location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEntry {
+    /// First address of the run
+    pub start: u16,
+    /// Last address of the run (inclusive)
+    pub end: u16,
+    pub file: FileId,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Build the address → source line table of an assembled program
+///
+/// Adjacent instructions from the same statement are merged into one entry.
+pub fn line_table(
+    output: &CodegenOutput,
+    assembly: &Assembly,
+    sources: &SourceMap,
+) -> Vec<LineEntry> {
+    let mut index = SourceIndex::new(sources);
+    let mut entries: Vec<LineEntry> = Vec::new();
+    let mut previous_span: Option<Span> = None;
+
+    for line in &assembly.lines {
+        let Some(span) = output.line_spans.get(line.line - 1).copied().flatten() else {
+            previous_span = None;
+            continue;
+        };
+        let end = line.address.wrapping_add(line.bytes.len() as u16 - 1);
+        if let Some(last) = entries.last_mut()
+            && previous_span == Some(span)
+            && last.end.wrapping_add(1) == line.address
+        {
+            last.end = end;
+            continue;
+        }
+        let (line_no, col) = index.file(span.file).line_col(span.start);
+        entries.push(LineEntry {
+            start: line.address,
+            end,
+            file: span.file,
+            line: line_no,
+            col,
+        });
+        previous_span = Some(span);
+    }
+
+    entries.sort_by_key(|entry| entry.start);
+    entries
+}
+
+/// Render a line table as text, one `START END file:line:col` row per entry
+pub fn write_line_table(entries: &[LineEntry], sources: &SourceMap) -> String {
+    let mut out = String::new();
+    out.push_str("# Wraith line table: START END (inclusive, hex) FILE:LINE:COL\n");
+    for entry in entries {
+        out.push_str(&format!(
+            "{:04X} {:04X} {}:{}:{}\n",
+            entry.start,
+            entry.end,
+            sources.name(entry.file),
+            entry.line,
+            entry.col
+        ));
+    }
+    out
+}
+
+/// Insert a `; file:line` comment before the first instruction of each source line
+///
+/// `line_spans` is updated so it stays aligned with the new assembly.
+pub fn insert_markers(output: &mut CodegenOutput, sources: &SourceMap) {
+    let mut index = SourceIndex::new(sources);
+    let mut asm = String::with_capacity(output.asm.len() * 2);
+    let mut line_spans = Vec::with_capacity(output.line_spans.len());
+    let mut last_line = None;
+
+    for (text, span) in output.asm.lines().zip(output.line_spans.iter().copied()) {
+        if let Some(span) = span {
+            let file = index.file(span.file);
+            let (line, _) = file.line_col(span.start);
+            if last_line != Some((span.file, line)) {
+                asm.push_str(&format!(
+                    "; {}:{}  {}\n",
+                    sources.name(span.file),
+                    line,
+                    file.line_text(line).trim()
+                ));
+                line_spans.push(None);
+                last_line = Some((span.file, line));
+            }
+        }
+        asm.push_str(text);
+        asm.push('\n');
+        line_spans.push(span);
+    }
+
+    output.asm = asm;
+    output.line_spans = line_spans;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_index() {
+        let index = LineIndex::new("fn main() {\n    x = 1;\n}\n");
+        assert_eq!(index.line_col(0), (1, 1));
+        assert_eq!(index.line_col(16), (2, 5));
+        assert_eq!(index.line_text(2), "    x = 1;");
+    }
+}
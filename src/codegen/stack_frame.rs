@@ -0,0 +1,425 @@
+//! 65816 Stack Frames
+//!
+//! In native mode the 65816 can address memory relative to the stack
+//! pointer (`LDA $03,S`), so the scalar locals of a function live on the
+//! hardware stack instead of in zero page. Every activation gets its own
+//! copy, which keeps a recursive function's locals intact across the
+//! recursive call. The locals of inline functions expanded into the body
+//! run in the same activation and share its frame.
+//!
+//! Vector handlers keep their locals in zero page: the CPU enters them
+//! through a vector rather than a `JSR` and they end in `RTI` (or never
+//! return), so only one activation of each is live. So do functions with
+//! inline assembly, whose pushes and pulls would move the frame.
+//!
+//! Code generation still refers to a local by the zero-page address sema
+//! gave it; the emitter rewrites those operands to the frame as the
+//! instructions go out. Only the eight accumulator instructions have a
+//! stack-relative mode, so a local that any other instruction touches
+//! (`INC`, `LDY`, `STZ`, indexed or indirect modes) stays in zero page. A
+//! first pass over the function finds those locals, which fixes the layout
+//! before the function is emitted.
+//!
+//! # Frame Layout
+//!
+//! ```text
+//! Prologue:  REP #$20 / TSC / SEC / SBC #size / TCS / SEP #$20
+//! Locals:    $01,S .. size,S
+//! Epilogue:  TAX / REP #$20 / TSC / CLC / ADC #size / TCS / SEP #$20 / TXA
+//! ```
+//!
+//! Each local byte has a fixed offset. The compiler's own pushes (the low
+//! byte of a 16-bit add or subtract, `PHP` to read Z) are pulled again
+//! before any local is touched, so S is at the frame whenever one is;
+//! [`check_accesses`] holds the optimized code to that.
+//!
+//! The epilogue runs before every `RTS` and keeps the return value in A and
+//! Y; X is free at that point.
+
+use super::CodegenError;
+use super::peephole::Line;
+use crate::ast::{Expr, FnAttribute, Function, Literal, Spanned, Stmt, VariantData};
+use crate::sema::ProgramInfo;
+use crate::sema::table::{SymbolKind, SymbolLocation};
+use crate::sema::types::Type;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+/// Most bytes of locals moved to one frame, so offsets stay within a byte
+pub const MAX_FRAME_SIZE: u16 = 0x80;
+
+/// Instructions with a `d,S` addressing mode
+const STACK_RELATIVE: &[&str] = &["ORA", "AND", "EOR", "ADC", "STA", "LDA", "CMP", "SBC"];
+
+/// Scalar locals of `func` that can go in a stack frame, as (address, size)
+///
+/// Includes the locals of the inline functions it expands. Empty for inline
+/// functions, whose locals go in their callers' frames, for vector handlers
+/// and for functions that expand inline assembly.
+pub fn frame_locals(func: &Function, program: &ProgramInfo) -> Vec<(u8, u8)> {
+    let name = &func.name.node;
+    let is_inline = func.attributes.contains(&FnAttribute::Inline)
+        || program
+            .function_metadata
+            .get(name)
+            .is_some_and(|metadata| metadata.is_inline);
+    let is_entry = func.attributes.iter().any(|attr| {
+        matches!(
+            attr,
+            FnAttribute::Reset | FnAttribute::Nmi | FnAttribute::Irq | FnAttribute::Interrupt
+        )
+    });
+    if is_inline || is_entry {
+        return Vec::new();
+    }
+
+    // Follow inline calls through the bodies they expand
+    let mut owners = vec![name.as_str()];
+    let mut bodies = vec![&func.body];
+    let mut next = 0;
+    while let Some(body) = bodies.get(next) {
+        let mut calls = Vec::new();
+        stmt_calls(body, &mut calls);
+        for callee in calls {
+            if let Some(body) = program
+                .function_metadata
+                .get(callee)
+                .filter(|metadata| metadata.is_inline)
+                .and_then(|metadata| metadata.inline_body.as_ref())
+                && !owners.contains(&callee)
+            {
+                owners.push(callee);
+                bodies.push(body);
+            }
+        }
+        next += 1;
+    }
+    if bodies.iter().any(|body| contains_asm(body)) {
+        return Vec::new();
+    }
+
+    let layout = &program.memory_layout;
+    let mut locals: Vec<(u8, u8)> = program
+        .resolved_symbols
+        .values()
+        .filter(|info| {
+            info.kind == SymbolKind::Variable
+                && info
+                    .containing_function
+                    .as_deref()
+                    .is_some_and(|function| owners.contains(&function))
+        })
+        .filter_map(|info| match (&info.location, &info.ty) {
+            (SymbolLocation::ZeroPage(addr), Type::Primitive(primitive))
+                if !(layout.param_base..=layout.param_end).contains(addr) =>
+            {
+                let size = Type::Primitive(*primitive).size();
+                (1..=2).contains(&size).then_some((*addr, size as u8))
+            }
+            _ => None,
+        })
+        .collect();
+    locals.sort_unstable();
+    locals.dedup();
+
+    let mut total = 0u16;
+    locals.retain(|&(_, size)| {
+        total += size as u16;
+        total <= MAX_FRAME_SIZE
+    });
+    locals
+}
+
+/// Total bytes of a frame holding `locals`
+pub fn frame_size(locals: &[(u8, u8)]) -> u16 {
+    locals.iter().map(|&(_, size)| size as u16).sum()
+}
+
+fn contains_asm(stmt: &Spanned<Stmt>) -> bool {
+    match &stmt.node {
+        Stmt::Asm { .. } => true,
+        Stmt::Block(stmts) => stmts.iter().any(contains_asm),
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => contains_asm(then_branch) || else_branch.as_deref().is_some_and(contains_asm),
+        Stmt::While { body, .. }
+        | Stmt::Loop { body, .. }
+        | Stmt::For { body, .. }
+        | Stmt::ForEach { body, .. } => contains_asm(body),
+        Stmt::Match { arms, .. } => arms.iter().any(|arm| contains_asm(&arm.body)),
+        _ => false,
+    }
+}
+
+/// Names of the functions `stmt` calls
+fn stmt_calls<'a>(stmt: &'a Spanned<Stmt>, calls: &mut Vec<&'a str>) {
+    match &stmt.node {
+        Stmt::VarDecl { init, .. } => expr_calls(init, calls),
+        Stmt::Assign { target, value } => {
+            expr_calls(target, calls);
+            expr_calls(value, calls);
+        }
+        Stmt::Expr(expr) | Stmt::Return(Some(expr)) => expr_calls(expr, calls),
+        Stmt::Return(None) | Stmt::Break | Stmt::Continue | Stmt::Asm { .. } => {}
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            expr_calls(condition, calls);
+            stmt_calls(then_branch, calls);
+            if let Some(else_branch) = else_branch {
+                stmt_calls(else_branch, calls);
+            }
+        }
+        Stmt::While {
+            condition, body, ..
+        } => {
+            expr_calls(condition, calls);
+            stmt_calls(body, calls);
+        }
+        Stmt::Loop { body, .. } => stmt_calls(body, calls),
+        Stmt::For { range, body, .. } => {
+            expr_calls(&range.start, calls);
+            expr_calls(&range.end, calls);
+            stmt_calls(body, calls);
+        }
+        Stmt::ForEach { iterable, body, .. } => {
+            expr_calls(iterable, calls);
+            stmt_calls(body, calls);
+        }
+        Stmt::Match { expr, arms } => {
+            expr_calls(expr, calls);
+            for arm in arms {
+                stmt_calls(&arm.body, calls);
+            }
+        }
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                stmt_calls(stmt, calls);
+            }
+        }
+    }
+}
+
+fn expr_calls<'a>(expr: &'a Spanned<Expr>, calls: &mut Vec<&'a str>) {
+    match &expr.node {
+        Expr::Literal(Literal::Array(elements)) => {
+            for element in elements {
+                expr_calls(element, calls);
+            }
+        }
+        Expr::Literal(Literal::ArrayFill { value, .. }) => expr_calls(value, calls),
+        Expr::Literal(_)
+        | Expr::Variable(_)
+        | Expr::CpuFlagCarry
+        | Expr::CpuFlagZero
+        | Expr::CpuFlagOverflow
+        | Expr::CpuFlagNegative => {}
+        Expr::Binary { left, right, .. }
+        | Expr::Index {
+            object: left,
+            index: right,
+        } => {
+            expr_calls(left, calls);
+            expr_calls(right, calls);
+        }
+        Expr::Unary { operand: expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::Field { object: expr, .. }
+        | Expr::SliceLen(expr)
+        | Expr::U16Low(expr)
+        | Expr::U16High(expr)
+        | Expr::Paren(expr) => expr_calls(expr, calls),
+        Expr::Slice {
+            object, start, end, ..
+        } => {
+            expr_calls(object, calls);
+            expr_calls(start, calls);
+            expr_calls(end, calls);
+        }
+        Expr::Call { function, args } => {
+            calls.push(&function.node);
+            for arg in args {
+                expr_calls(arg, calls);
+            }
+        }
+        Expr::StructInit { fields, .. } | Expr::AnonStructInit { fields } => {
+            for field in fields {
+                expr_calls(&field.value, calls);
+            }
+        }
+        Expr::EnumVariant { data, .. } => match data {
+            VariantData::Unit => {}
+            VariantData::Tuple(values) => {
+                for value in values {
+                    expr_calls(value, calls);
+                }
+            }
+            VariantData::Struct(fields) => {
+                for field in fields {
+                    expr_calls(&field.value, calls);
+                }
+            }
+        },
+        Expr::Match { expr, arms } => {
+            expr_calls(expr, calls);
+            for arm in arms {
+                expr_calls(&arm.body, calls);
+            }
+        }
+    }
+}
+
+/// The frame of the function being generated
+#[derive(Debug, Clone, Default)]
+pub struct StackFrame {
+    /// Frame byte of each zero-page byte moved to the stack, with the
+    /// address of the local it belongs to
+    slots: HashMap<u8, (u8, u8)>,
+    size: u16,
+    /// Whether the accumulator is 16-bit
+    wide: bool,
+    /// Locals an instruction touched without a stack-relative mode
+    pub pinned: HashSet<u8>,
+}
+
+impl StackFrame {
+    pub fn new(locals: &[(u8, u8)]) -> Self {
+        let mut slots = HashMap::default();
+        let mut offset = 0u8;
+        for &(addr, size) in locals {
+            for byte in 0..size {
+                slots.insert(addr + byte, (offset, addr));
+                offset += 1;
+            }
+        }
+        Self {
+            slots,
+            size: frame_size(locals),
+            wide: false,
+            pinned: HashSet::default(),
+        }
+    }
+
+    /// Bytes reserved on the stack
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Instructions to emit for `mnemonic operand` when it touches the frame
+    ///
+    /// Returns `None` when the instruction goes out unchanged. Index
+    /// register loads and stores have no `d,S` mode and go through A, with A
+    /// kept in the hidden B byte and the flags left as the original
+    /// instruction leaves them. A local any other instruction touches is
+    /// pinned to zero page instead.
+    pub fn rewrite<'a>(
+        &mut self,
+        mnemonic: &'a str,
+        operand: &str,
+    ) -> Option<Vec<(&'a str, String)>> {
+        let addr = zero_page_address(operand)?;
+        let &(offset, local) = self.slots.get(&addr)?;
+        // `extra` counts bytes the rewritten instructions push themselves
+        let slot = |extra: u8| format!("${:02X},S", offset + 1 + extra);
+        let direct = operand == format!("${:02X}", addr);
+        let rewritten = match (mnemonic, direct && !self.wide) {
+            _ if !direct => None,
+            (mnemonic, _) if STACK_RELATIVE.contains(&mnemonic) => Some(vec![(mnemonic, slot(0))]),
+            ("LDX" | "LDY", true) => {
+                let (transfer, increment, decrement) = if mnemonic == "LDX" {
+                    ("TAX", "INX", "DEX")
+                } else {
+                    ("TAY", "INY", "DEY")
+                };
+                Some(vec![
+                    ("XBA", String::new()),
+                    ("LDA", slot(0)),
+                    (transfer, String::new()),
+                    ("XBA", String::new()),
+                    // Set N and Z from the index register, leaving C and V
+                    (increment, String::new()),
+                    (decrement, String::new()),
+                ])
+            }
+            ("STX" | "STY", true) => {
+                let transfer = if mnemonic == "STX" { "TXA" } else { "TYA" };
+                Some(vec![
+                    ("PHP", String::new()),
+                    ("XBA", String::new()),
+                    (transfer, String::new()),
+                    ("STA", slot(1)),
+                    ("XBA", String::new()),
+                    ("PLP", String::new()),
+                ])
+            }
+            _ => None,
+        };
+        if rewritten.is_none() {
+            self.pinned.insert(local);
+        }
+        rewritten
+    }
+
+    /// Follow the accumulator width `mnemonic` sets
+    pub fn track(&mut self, mnemonic: &str, operand: &str) {
+        let width_bit = || {
+            zero_page_address(operand.trim_start_matches('#')).is_some_and(|bits| bits & 0x20 != 0)
+        };
+        match mnemonic {
+            "REP" if width_bit() => self.wide = true,
+            "SEP" if width_bit() => self.wide = false,
+            _ => {}
+        }
+    }
+}
+
+/// Check that S is at the frame at every frame access in `lines`, the
+/// optimized code of `function`
+///
+/// The only push allowed to be outstanding is the `PHP` that saves the
+/// flags around an index register store, whose `STA` allows for it.
+pub fn check_accesses(function: &str, lines: &[Line]) -> Result<(), CodegenError> {
+    let mut pushed: Vec<&str> = Vec::new();
+    for line in lines {
+        let Line::Instruction {
+            mnemonic, operand, ..
+        } = line
+        else {
+            continue;
+        };
+        let operand = operand.as_deref().unwrap_or("");
+        if operand.ends_with(",S") && !(pushed.is_empty() || pushed == ["PHP"] && mnemonic == "STA")
+        {
+            return Err(CodegenError::UnsupportedOperation(format!(
+                "'{}' reaches its stack frame ({} {}) with {} pushed",
+                function,
+                mnemonic,
+                operand,
+                pushed.join(", ")
+            )));
+        }
+        match mnemonic.as_str() {
+            "PHA" | "PHP" | "PHX" | "PHY" => pushed.push(mnemonic),
+            "PLA" | "PLP" | "PLX" | "PLY" if pushed.pop().is_none() => {
+                return Err(CodegenError::UnsupportedOperation(format!(
+                    "'{}' pulls its stack frame with {}",
+                    function, mnemonic
+                )));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Zero-page address an operand refers to, if it's a numeric memory operand
+fn zero_page_address(operand: &str) -> Option<u8> {
+    let digits = operand.trim_start_matches(['(', '[']).strip_prefix('$')?;
+    let end = digits
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(digits.len());
+    u8::try_from(u16::from_str_radix(&digits[..end], 16).ok()?).ok()
+}
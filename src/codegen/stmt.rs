@@ -101,6 +101,22 @@ pub fn generate_stmt(
         return Ok(());
     }
 
+    // Blocks only group statements; everything else owns its instructions
+    if matches!(stmt.node, Stmt::Block(_)) {
+        return generate_stmt_node(stmt, emitter, info, string_collector);
+    }
+    let previous_span = emitter.set_span(Some(stmt.span));
+    let result = generate_stmt_node(stmt, emitter, info, string_collector);
+    emitter.set_span(previous_span);
+    result.map_err(|error| error.at(stmt.span))
+}
+
+fn generate_stmt_node(
+    stmt: &Spanned<Stmt>,
+    emitter: &mut Emitter,
+    info: &ProgramInfo,
+    string_collector: &mut StringCollector,
+) -> Result<(), CodegenError> {
     match &stmt.node {
         Stmt::Block(stmts) => {
             for s in stmts {
@@ -156,6 +172,14 @@ pub fn generate_stmt(
                     }
                 }
 
+                if emitter.cpu.has_native_mode()
+                    && let Some(target) = word_symbol_operand(sym)
+                    && let Some(instructions) = wide_assignment(target, init, info)
+                {
+                    emit_wide(emitter, &instructions);
+                    return Ok(());
+                }
+
                 // Check for shorthand array syntax: [value] expanding to [value, value, ...]
                 // If init is a single-element array and target is a larger array, synthesize an ArrayFill
                 let modified_init;
@@ -255,6 +279,9 @@ pub fn generate_stmt(
                             emitter.emit_inst(hi_inst, &format!("${:02X}", addr + 1));
                         }
                     }
+                    crate::sema::table::SymbolLocation::Long(_) => {
+                        emitter.emit_sta_long_symbol(&name.node)?;
+                    }
                     crate::sema::table::SymbolLocation::None => {
                         return Err(CodegenError::UnsupportedOperation(format!(
                             "VarDecl '{}' has no storage location",
@@ -334,6 +361,16 @@ pub fn generate_stmt(
             Ok(())
         }
         Stmt::Assign { target, value } => {
+            // With the 65816's 16-bit accumulator, word arithmetic on
+            // variables and constants needs no byte pairs
+            if emitter.cpu.has_native_mode()
+                && let Some(target) = word_variable_operand(target, info)
+                && let Some(instructions) = wide_assignment(target, value, info)
+            {
+                emit_wide(emitter, &instructions);
+                return Ok(());
+            }
+
             // Optimization: detect x = x + 1 and x = x - 1 patterns
             // Use INC/DEC instead of LDA/ADC/STA or LDA/SBC/STA
             if let crate::ast::Expr::Variable(target_name) = &target.node
@@ -400,6 +437,34 @@ pub fn generate_stmt(
                 }
             }
 
+            // With the bit instructions, setting or clearing one bit of a
+            // zero-page byte is a single SMBn/RMBn
+            if emitter.cpu.has_bit_instructions()
+                && let Some((mnemonic, addr)) = bit_update(target, value, info)
+            {
+                emitter.emit_inst(&mnemonic, &format!("${:02X}", addr));
+                emitter.reg_state.invalidate_zero_page(addr);
+                return Ok(());
+            }
+
+            // With the undocumented opcodes, x = a & b is LDA a; LDX b; SAX x
+            if emitter.cpu.has_undocumented_opcodes()
+                && let crate::ast::Expr::Binary {
+                    left,
+                    op: crate::ast::BinaryOp::BitAnd,
+                    right,
+                } = &value.node
+                && let Some(target_operand) = byte_variable_operand(target, info)
+                && let Some(left_operand) = byte_operand(left, info)
+                && let Some(right_operand) = byte_operand(right, info)
+            {
+                emitter.emit_inst("LDA", &left_operand);
+                emitter.emit_inst("LDX", &right_operand);
+                emitter.emit_inst("SAX", &target_operand);
+                emitter.reg_state.invalidate_all();
+                return Ok(());
+            }
+
             // 1. Generate code for value (result in A)
             generate_expr(value, emitter, info, string_collector)?;
 
@@ -459,6 +524,9 @@ pub fn generate_stmt(
                                     emitter.emit_inst(hi_inst, &format!("${:02X}", addr + 1));
                                 }
                             }
+                            crate::sema::table::SymbolLocation::Long(_) => {
+                                emitter.emit_sta_long_symbol(name)?;
+                            }
                             crate::sema::table::SymbolLocation::None => {
                                 return Err(CodegenError::UnsupportedOperation(format!(
                                     "Variable '{}' has no storage location",
@@ -528,9 +596,6 @@ pub fn generate_stmt(
             let else_label = emitter.next_label("else");
             let end_label = emitter.next_label("end");
 
-            // Condition
-            generate_expr(condition, emitter, info, string_collector)?;
-
             // For large if statements, we need to avoid forward branches
             // that might exceed 127 bytes. Use this structure:
             //   condition
@@ -543,11 +608,20 @@ pub fn generate_stmt(
             //   else_body
             // end:
             
+            // One bit of a zero-page byte is tested in place: BBSn/BBRn $xx,then
+            let bit_test = bit_test(condition, info).filter(|_| emitter.cpu.has_bit_instructions());
+            if bit_test.is_none() {
+                generate_expr(condition, emitter, info, string_collector)?;
+            }
             if !emitter.is_minimal() {
                 emitter.emit_comment("Branch to then if condition is true");
             }
-            emitter.emit_inst("CMP", "#$00");
-            emitter.emit_inst("BNE", &then_label);
+            if let Some((mnemonic, addr)) = bit_test {
+                emitter.emit_inst(&mnemonic, &format!("${:02X},{}", addr, then_label));
+            } else {
+                emitter.emit_inst("CMP", "#$00");
+                emitter.emit_inst("BNE", &then_label);
+            }
             emitter.emit_inst("JMP", &else_label);
 
             // Then
@@ -568,10 +642,17 @@ pub fn generate_stmt(
             emitter.reg_state.invalidate_all();
             Ok(())
         }
-        Stmt::While { condition, body } => {
+        Stmt::While {
+            condition,
+            body,
+            bound,
+        } => {
             let body_label = emitter.next_label("wb");
             let check_label = emitter.next_label("wc");
             let end_label = emitter.next_label("we");
+            if let Some(bound) = bound {
+                emitter.record_loop_bound(&check_label, *bound);
+            }
 
             // Structure that avoids long branches:
             // check:
@@ -588,15 +669,22 @@ pub fn generate_stmt(
             
             // Condition check
             emitter.emit_label(&check_label);
-            generate_expr(condition, emitter, info, string_collector)?;
+            let bit_test = bit_test(condition, info).filter(|_| emitter.cpu.has_bit_instructions());
+            if bit_test.is_none() {
+                generate_expr(condition, emitter, info, string_collector)?;
+            }
 
             if !emitter.is_minimal() {
                 emitter.emit_comment("Continue to body if condition is true");
             }
-            emitter.emit_inst("CMP", "#$00");
-            // BNE jumps only 3 bytes forward (size of JMP instruction)
-            // This is always within the 127-byte branch limit
-            emitter.emit_inst("BNE", &body_label);
+            if let Some((mnemonic, addr)) = bit_test {
+                emitter.emit_inst(&mnemonic, &format!("${:02X},{}", addr, body_label));
+            } else {
+                emitter.emit_inst("CMP", "#$00");
+                // BNE jumps only 3 bytes forward (size of JMP instruction)
+                // This is always within the 127-byte branch limit
+                emitter.emit_inst("BNE", &body_label);
+            }
             emitter.emit_inst("JMP", &end_label);
 
             emitter.emit_label(&body_label);
@@ -618,9 +706,12 @@ pub fn generate_stmt(
             emitter.reg_state.invalidate_all();
             Ok(())
         }
-        Stmt::Loop { body } => {
+        Stmt::Loop { body, bound } => {
             let loop_label = emitter.next_label("lp");
             let end_label = emitter.next_label("lx");
+            if let Some(bound) = bound {
+                emitter.record_loop_bound(&loop_label, *bound);
+            }
 
             emitter.emit_label(&loop_label);
 
@@ -641,6 +732,7 @@ pub fn generate_stmt(
             var_type: _,
             range,
             body,
+            bound,
         } => {
             // Check if loop can be unrolled (constant bounds, small count)
             let start_const = info.folded_constants.get(&range.start.span);
@@ -669,9 +761,14 @@ pub fn generate_stmt(
                         if count == 1 { "" } else { "s" }
                     ));
 
-                    // Use first variable slot for loop variable (same as normal loops)
-                    // This matches the allocation strategy in semantic analysis
-                    let loop_var_addr = emitter.memory_layout.variable_alloc_start;
+                    // Use the loop variable's slot from semantic analysis
+                    let loop_var_addr = match info.resolved_symbols.get(&var_name.span) {
+                        Some(crate::sema::table::SymbolInfo {
+                            location: crate::sema::table::SymbolLocation::ZeroPage(addr),
+                            ..
+                        }) => *addr,
+                        _ => emitter.memory_layout.variable_alloc_start,
+                    };
 
                     // Create end label for break statements
                     let end_label = emitter.next_label("ux");
@@ -709,8 +806,24 @@ pub fn generate_stmt(
                 }
             }
 
-            // NORMAL LOOP: Generate standard loop code
-            generate_normal_loop(var_name, range, body, emitter, info, string_collector)
+            // NORMAL LOOP: Generate standard loop code, bounded by the
+            // constant range unless the user gave a bound
+            let range_bound = match (start_const, end_const) {
+                (
+                    Some(crate::sema::const_eval::ConstValue::Integer(start)),
+                    Some(crate::sema::const_eval::ConstValue::Integer(end)),
+                ) => Some((end - start + range.inclusive as i64).clamp(0, u32::MAX as i64) as u32),
+                _ => None,
+            };
+            generate_normal_loop(
+                var_name,
+                range,
+                body,
+                bound.or(range_bound),
+                emitter,
+                info,
+                string_collector,
+            )
         }
         Stmt::ForEach {
             var_name,
@@ -718,6 +831,7 @@ pub fn generate_stmt(
             iterable,
             body,
             index_var,
+            bound,
         } => {
             // ForEach loop: for item in iterable { ... } or for (index, item) in iterable { ... }
             // Supports arrays and strings
@@ -779,19 +893,21 @@ pub fn generate_stmt(
 
             // For strings, we need to load the length first
             // For arrays, the size is known at compile time
+            // Strings are read through the operand save area ($F0-$F2)
+            let string_ptr = emitter.memory_layout.operand_save;
             let array_size = if is_string {
                 // For strings, get length at runtime
                 emitter.emit_comment("String iteration - load length");
                 // Load string pointer to temp location
                 emitter.emit_inst("LDA", &format!("${:02X}", iterable_base));
-                emitter.emit_inst("STA", "$F0");
+                emitter.emit_inst("STA", &format!("${:02X}", string_ptr));
                 emitter.emit_inst("LDA", &format!("${:02X}", iterable_base + 1));
-                emitter.emit_inst("STA", "$F1");
+                emitter.emit_inst("STA", &format!("${:02X}", string_ptr + 1));
                 // Load length (first byte)
                 emitter.emit_inst("LDY", "#$00");
-                emitter.emit_inst("LDA", "($F0),Y");
+                emitter.emit_inst("LDA", &format!("(${:02X}),Y", string_ptr));
                 // Store length in temp location for comparison
-                emitter.emit_inst("STA", "$F2");
+                emitter.emit_inst("STA", &format!("${:02X}", string_ptr + 2));
                 None // Will compare against $F2
             } else {
                 // For arrays, size is known at compile time
@@ -805,13 +921,17 @@ pub fn generate_stmt(
                 }
             };
 
+            // Strings are at most 255 characters long
+            let iterations = bound.unwrap_or(array_size.map_or(255, |size| size as u32));
+            emitter.record_loop_bound(&loop_label, iterations);
+
             // Loop start
             emitter.emit_label(&loop_label);
 
             // Check if counter (X) >= length
             if is_string {
                 // Compare X against string length in $F2
-                emitter.emit_inst("CPX", "$F2");
+                emitter.emit_inst("CPX", &format!("${:02X}", string_ptr + 2));
             } else if let Some(size) = array_size {
                 // Compare X against known array size
                 emitter.emit_inst("CPX", &format!("#${:02X}", size));
@@ -845,7 +965,7 @@ pub fn generate_stmt(
             if is_string {
                 // For strings, add 1 to skip length byte
                 emitter.emit_inst("INY", "");
-                emitter.emit_inst("LDA", "($F0),Y");
+                emitter.emit_inst("LDA", &format!("(${:02X}),Y", string_ptr));
             } else {
                 // For arrays, direct indexed access
                 emitter.emit_inst("LDA", &format!("(${:02X}),Y", iterable_base));
@@ -927,19 +1047,35 @@ pub fn generate_stmt(
                     substituted
                 };
 
-                let parts: Vec<&str> = final_line.split_whitespace().collect();
-                if parts.is_empty() {
-                    continue;
-                }
+                // A single string may hold several newline-separated instructions
+                for asm_line in final_line.lines() {
+                    let mut parts: Vec<&str> = asm_line.split_whitespace().collect();
 
-                let mnemonic = parts[0];
-                let operand = if parts.len() > 1 {
-                    parts[1..].join(" ")
-                } else {
-                    String::new()
-                };
+                    // Leading labels ("loop:" or "loop: DEX") are emitted as real labels
+                    // so the peephole optimizer treats them as branch targets
+                    while let Some(label) = parts.first().and_then(|p| p.strip_suffix(':')) {
+                        emitter.emit_label(label);
+                        parts.remove(0);
+                    }
+                    if parts.is_empty() {
+                        continue;
+                    }
+
+                    let mnemonic = parts[0];
+                    if is_undocumented_opcode(mnemonic) && !emitter.cpu.has_undocumented_opcodes() {
+                        return Err(CodegenError::UnsupportedOperation(format!(
+                            "inline asm uses undocumented opcode {}; enable it with --undocumented",
+                            mnemonic.to_ascii_uppercase()
+                        )));
+                    }
+                    let operand = if parts.len() > 1 {
+                        parts[1..].join(" ")
+                    } else {
+                        String::new()
+                    };
 
-                emitter.emit_inst(mnemonic, &operand);
+                    emitter.emit_inst(mnemonic, &operand);
+                }
             }
             // Invalidate register state after inline assembly
             // (we don't know what the assembly does to registers)
@@ -990,11 +1126,13 @@ fn generate_index_assignment(
     emitter.emit_comment("Evaluate value to assign");
     generate_expr(value, emitter, info, string_collector)?;
 
+    let temp = format!("${:02X}", emitter.memory_layout.temp_reg());
+    let temp_high = format!("${:02X}", emitter.memory_layout.temp_reg() + 1);
     // Step 3: Save value to temp storage
     emitter.emit_comment("Save value to temp");
-    emitter.emit_inst("STA", "$20"); // Save low byte
+    emitter.emit_inst("STA", &temp); // Save low byte
     if is_multibyte {
-        emitter.emit_inst("STY", "$21"); // Save high byte for u16
+        emitter.emit_inst("STY", &temp_high); // Save high byte for u16
     }
 
     // Step 4: Evaluate index expression
@@ -1018,7 +1156,7 @@ fn generate_index_assignment(
                 // For u8 arrays: direct indexed addressing
                 if !is_multibyte {
                     // Restore value
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &temp);
                     // Store to array[index]
                     emitter.emit_inst("STA", &format!("(${:02X}),Y", addr));
                 } else {
@@ -1029,12 +1167,12 @@ fn generate_index_assignment(
                     emitter.emit_inst("TAY", ""); // Back to Y
 
                     // Restore and store low byte
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &temp);
                     emitter.emit_inst("STA", &format!("(${:02X}),Y", addr));
 
                     // Store high byte at next position
                     emitter.emit_inst("INY", "");
-                    emitter.emit_inst("LDA", "$21");
+                    emitter.emit_inst("LDA", &temp_high);
                     emitter.emit_inst("STA", &format!("(${:02X}),Y", addr));
                 }
             }
@@ -1043,7 +1181,7 @@ fn generate_index_assignment(
                 // For u8 arrays: direct indexed addressing
                 if !is_multibyte {
                     // Restore value
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &temp);
                     // Store to array[index]
                     emitter.emit_inst("STA", &format!("(${:02X}),Y", addr_u8));
                 } else {
@@ -1054,12 +1192,12 @@ fn generate_index_assignment(
                     emitter.emit_inst("TAY", ""); // Back to Y
 
                     // Restore and store low byte
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &temp);
                     emitter.emit_inst("STA", &format!("(${:02X}),Y", addr_u8));
 
                     // Store high byte at next position
                     emitter.emit_inst("INY", "");
-                    emitter.emit_inst("LDA", "$21");
+                    emitter.emit_inst("LDA", &temp_high);
                     emitter.emit_inst("STA", &format!("(${:02X}),Y", addr_u8));
                 }
             }
@@ -1277,21 +1415,23 @@ fn generate_field_assignment(
             // Need to save A first since we'll need Y for the offset
             let offset = field_info.offset;
 
+            let temp = format!("${:02X}", emitter.memory_layout.temp_reg());
+            let temp_high = format!("${:02X}", emitter.memory_layout.temp_reg() + 1);
             // Save value to temp
-            emitter.emit_inst("STA", "$20"); // Save low byte
+            emitter.emit_inst("STA", &temp); // Save low byte
             if is_multibyte {
-                emitter.emit_inst("STY", "$21"); // Save high byte
+                emitter.emit_inst("STY", &temp_high); // Save high byte
             }
 
             // Set Y to field offset and store via indirect
             emitter.emit_inst("LDY", &format!("#${:02X}", offset));
-            emitter.emit_inst("LDA", "$20"); // Restore value
+            emitter.emit_inst("LDA", &temp); // Restore value
             emitter.emit_inst("STA", &format!("(${:02X}),Y", ptr_addr));
 
             if is_multibyte {
                 // Store high byte at next offset
                 emitter.emit_inst("INY", "");
-                emitter.emit_inst("LDA", "$21");
+                emitter.emit_inst("LDA", &temp_high);
                 emitter.emit_inst("STA", &format!("(${:02X}),Y", ptr_addr));
             }
         } else {
@@ -1376,6 +1516,7 @@ fn generate_match_sequential(
     // Use pointer ops area for indirect addressing to avoid conflict with temp storage
     let ptr_base = emitter.memory_layout.pointer_ops_start; // $30 by default
 
+    let temp = format!("${:02X}", emitter.memory_layout.temp_reg());
     if is_enum_match {
         // For enum matching, expression returns a pointer in A:X
         // Store pointer at pointer ops area (not $20 which is used by temp storage)
@@ -1388,7 +1529,7 @@ fn generate_match_sequential(
         emitter.emit_inst("STA", &format!("${:02X}", ptr_base + 2)); // Store tag
     } else {
         // For simple value matching, store value at $20
-        emitter.emit_inst("STA", "$20");
+        emitter.emit_inst("STA", &temp);
     }
 
     // Generate code for each arm
@@ -1401,7 +1542,7 @@ fn generate_match_sequential(
                 {
                     // For enum matching, we already have the tag in $22, but this is for literal patterns
                     // which shouldn't mix with enum patterns in the same match
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &temp);
                     emitter.emit_inst("CMP", &format!("#${:02X}", val));
                     emitter.emit_inst("BEQ", &format!("match_{}_arm_{}", match_id, i));
                 }
@@ -1417,7 +1558,7 @@ fn generate_match_sequential(
                     crate::ast::Expr::Literal(crate::ast::Literal::Integer(end_val)),
                 ) = (&start.node, &end.node)
                 {
-                    emitter.emit_inst("LDA", "$20");
+                    emitter.emit_inst("LDA", &temp);
 
                     // Check if value < start, skip this arm
                     emitter.emit_inst("CMP", &format!("#${:02X}", start_val));
@@ -1529,14 +1670,18 @@ fn generate_match_jump_table(
     // Jump table dispatch:
     // 1. Double the tag (addresses are 2 bytes)
     // 2. Use as index into jump table
-    // 3. Load address and JMP indirect
+    // 3. Load address and JMP indirect (the 65C02 indexes the JMP itself)
     emitter.emit_inst("ASL", ""); // tag * 2
     emitter.emit_inst("TAX", ""); // Transfer to X for indexing
-    emitter.emit_inst("LDA", &format!("match_{}_jt,X", match_id));
-    emitter.emit_inst("STA", &format!("${:02X}", jump_ptr));
-    emitter.emit_inst("LDA", &format!("match_{}_jt+1,X", match_id));
-    emitter.emit_inst("STA", &format!("${:02X}", jump_ptr + 1));
-    emitter.emit_inst("JMP", &format!("(${:02X})", jump_ptr));
+    if emitter.cpu.has_cmos_instructions() {
+        emitter.emit_inst("JMP", &format!("(match_{}_jt,X)", match_id));
+    } else {
+        emitter.emit_inst("LDA", &format!("match_{}_jt,X", match_id));
+        emitter.emit_inst("STA", &format!("${:02X}", jump_ptr));
+        emitter.emit_inst("LDA", &format!("match_{}_jt+1,X", match_id));
+        emitter.emit_inst("STA", &format!("${:02X}", jump_ptr + 1));
+        emitter.emit_inst("JMP", &format!("(${:02X})", jump_ptr));
+    }
 
     // Emit jump table
     emit_jump_table(emitter, arms, info, match_id, max_tag, wildcard_arm_index)?;
@@ -1722,6 +1867,13 @@ fn generate_match_arm_bodies(
     Ok(())
 }
 
+/// Whether an inline asm mnemonic is one of the stable undocumented NMOS opcodes
+fn is_undocumented_opcode(mnemonic: &str) -> bool {
+    crate::assembler::opcodes::UNDOCUMENTED_OPCODES
+        .iter()
+        .any(|op| op.mnemonic.eq_ignore_ascii_case(mnemonic))
+}
+
 /// Substitute {variable} patterns in inline assembly with actual addresses
 fn substitute_asm_vars(
     instruction: &str,
@@ -1760,6 +1912,7 @@ fn substitute_asm_vars(
             let address = match symbol.location {
                 crate::sema::table::SymbolLocation::ZeroPage(addr) => format!("${:02X}", addr),
                 crate::sema::table::SymbolLocation::Absolute(addr) => format!("${:04X}", addr),
+                crate::sema::table::SymbolLocation::Long(addr) => format!("${:06X}", addr),
                 crate::sema::table::SymbolLocation::None => {
                     return Err(CodegenError::SymbolNotFound(format!(
                         "{} has no memory location",
@@ -1837,11 +1990,273 @@ fn uniquify_asm_labels(line: &str, suffix: usize) -> String {
     }
 }
 
+/// Location of a one-byte variable in memory
+fn byte_variable_location<'a>(
+    expr: &Spanned<crate::ast::Expr>,
+    info: &'a ProgramInfo,
+) -> Option<&'a crate::sema::table::SymbolLocation> {
+    use crate::ast::PrimitiveType;
+    use crate::sema::table::SymbolKind;
+    use crate::sema::types::Type;
+
+    let crate::ast::Expr::Variable(name) = &expr.node else {
+        return None;
+    };
+    let sym = info
+        .resolved_symbols
+        .get(&expr.span)
+        .or_else(|| info.table.lookup(name))?;
+    if sym.kind != SymbolKind::Variable
+        || !matches!(
+            sym.ty,
+            Type::Primitive(PrimitiveType::U8 | PrimitiveType::I8)
+        )
+    {
+        return None;
+    }
+    Some(&sym.location)
+}
+
+/// Operand of a one-byte variable in memory, for instructions that address it directly
+fn byte_variable_operand(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<String> {
+    use crate::sema::table::SymbolLocation;
+
+    match byte_variable_location(expr, info)? {
+        SymbolLocation::ZeroPage(addr) => Some(format!("${:02X}", addr)),
+        SymbolLocation::Absolute(addr) => Some(format!("${:04X}", addr)),
+        _ => None,
+    }
+}
+
+/// Operand of a u16 or i16 variable in memory
+fn word_variable_operand(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<String> {
+    let crate::ast::Expr::Variable(name) = &expr.node else {
+        return None;
+    };
+    let sym = info
+        .resolved_symbols
+        .get(&expr.span)
+        .or_else(|| info.table.lookup(name))?;
+    word_symbol_operand(sym)
+}
+
+fn word_symbol_operand(sym: &crate::sema::table::SymbolInfo) -> Option<String> {
+    use crate::ast::PrimitiveType;
+    use crate::sema::table::{SymbolKind, SymbolLocation};
+    use crate::sema::types::Type;
+
+    if sym.kind != SymbolKind::Variable
+        || !matches!(
+            sym.ty,
+            Type::Primitive(PrimitiveType::U16 | PrimitiveType::I16)
+        )
+    {
+        return None;
+    }
+    match sym.location {
+        SymbolLocation::ZeroPage(addr) => Some(format!("${:02X}", addr)),
+        SymbolLocation::Absolute(addr) => Some(format!("${:04X}", addr)),
+        _ => None,
+    }
+}
+
+/// Word value of a constant expression that fits in a u16 or an i16
+fn constant_word(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<u16> {
+    use crate::sema::const_eval::ConstValue;
+
+    let value = match (&expr.node, info.folded_constants.get(&expr.span)) {
+        (_, Some(ConstValue::Integer(value)))
+        | (crate::ast::Expr::Literal(crate::ast::Literal::Integer(value)), _) => *value,
+        _ => return None,
+    };
+    (-32768..=65535).contains(&value).then_some(value as u16)
+}
+
+/// Operand of a 16-bit variable or a `#$XXXX` constant
+pub(crate) fn word_operand(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<String> {
+    let expr = unparen(expr);
+    match constant_word(expr, info) {
+        Some(value) => Some(format!("#${:04X}", value)),
+        None => word_variable_operand(expr, info),
+    }
+}
+
+/// `x = y`, `x = y op z` or `x = x ± 1` on 16-bit variables and constants,
+/// as the instructions to run with a 16-bit accumulator
+fn wide_assignment(
+    target: String,
+    value: &Spanned<crate::ast::Expr>,
+    info: &ProgramInfo,
+) -> Option<Vec<(&'static str, String)>> {
+    use crate::ast::{BinaryOp, Expr};
+
+    let value = unparen(value);
+    if let Some(source) = word_operand(value, info) {
+        if source == "#$0000" {
+            return Some(vec![("STZ", target)]);
+        }
+        return Some(vec![("LDA", source), ("STA", target)]);
+    }
+
+    let Expr::Binary { left, op, right } = &value.node else {
+        return None;
+    };
+    let left = word_operand(left, info)?;
+    let right = word_operand(right, info)?;
+    let (carry, mnemonic) = match op {
+        BinaryOp::Add if left == target && right == "#$0001" => {
+            return Some(vec![("INC", target)]);
+        }
+        BinaryOp::Sub if left == target && right == "#$0001" => {
+            return Some(vec![("DEC", target)]);
+        }
+        BinaryOp::Add => (Some("CLC"), "ADC"),
+        BinaryOp::Sub => (Some("SEC"), "SBC"),
+        BinaryOp::BitAnd => (None, "AND"),
+        BinaryOp::BitOr => (None, "ORA"),
+        BinaryOp::BitXor => (None, "EOR"),
+        _ => return None,
+    };
+    let mut instructions = vec![("LDA", left)];
+    instructions.extend(carry.map(|flag| (flag, String::new())));
+    instructions.extend([(mnemonic, right), ("STA", target)]);
+    Some(instructions)
+}
+
+/// Run instructions with the 65816 accumulator switched to 16 bits
+fn emit_wide(emitter: &mut Emitter, instructions: &[(&str, String)]) {
+    emitter.emit_inst("REP", "#$20");
+    for (mnemonic, operand) in instructions {
+        emitter.emit_inst(mnemonic, operand);
+    }
+    emitter.emit_inst("SEP", "#$20");
+    emitter.reg_state.invalidate_all();
+}
+
+/// Address of a one-byte zero-page variable, the only operand SMB/RMB/BBR/BBS take
+fn zero_page_byte_variable(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<u8> {
+    match byte_variable_location(unparen(expr), info)? {
+        crate::sema::table::SymbolLocation::ZeroPage(addr) => Some(*addr),
+        _ => None,
+    }
+}
+
+/// Byte value of a constant expression that fits in a u8 or an i8, such as `~8`
+fn constant_byte(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<u8> {
+    use crate::sema::const_eval::ConstValue;
+
+    let value = match (&expr.node, info.folded_constants.get(&expr.span)) {
+        (_, Some(ConstValue::Integer(value)))
+        | (crate::ast::Expr::Literal(crate::ast::Literal::Integer(value)), _) => *value,
+        _ => return None,
+    };
+    (-128..=255).contains(&value).then_some(value as u8)
+}
+
+/// Number of the only bit set in `mask`
+fn single_bit(mask: u8) -> Option<u8> {
+    mask.is_power_of_two().then(|| mask.trailing_zeros() as u8)
+}
+
+fn unparen(expr: &Spanned<crate::ast::Expr>) -> &Spanned<crate::ast::Expr> {
+    match &expr.node {
+        crate::ast::Expr::Paren(inner) => unparen(inner),
+        _ => expr,
+    }
+}
+
+/// `x = x | 8` or `x = x & ~8` on a zero-page byte, as SMB3/RMB3 and the address
+fn bit_update(
+    target: &Spanned<crate::ast::Expr>,
+    value: &Spanned<crate::ast::Expr>,
+    info: &ProgramInfo,
+) -> Option<(String, u8)> {
+    use crate::ast::{BinaryOp, Expr};
+
+    let Expr::Variable(target_name) = &target.node else {
+        return None;
+    };
+    let Expr::Binary { left, op, right } = &unparen(value).node else {
+        return None;
+    };
+    let addr = zero_page_byte_variable(target, info)?;
+    let same = |expr: &Spanned<Expr>| match &unparen(expr).node {
+        Expr::Variable(name) => name == target_name,
+        _ => false,
+    };
+    let mask = if same(left) {
+        constant_byte(right, info)?
+    } else if same(right) {
+        constant_byte(left, info)?
+    } else {
+        return None;
+    };
+    match op {
+        BinaryOp::BitOr => Some((format!("SMB{}", single_bit(mask)?), addr)),
+        BinaryOp::BitAnd => Some((format!("RMB{}", single_bit(!mask)?), addr)),
+        _ => None,
+    }
+}
+
+/// A condition on one bit of a zero-page byte, `(x & 8) != 0` or `(x & 8) == 0`,
+/// as the BBS3/BBR3 that branches when it holds and the address
+fn bit_test(condition: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<(String, u8)> {
+    use crate::ast::{BinaryOp, Expr};
+
+    let Expr::Binary { left, op, right } = &unparen(condition).node else {
+        return None;
+    };
+    if !matches!(op, BinaryOp::Eq | BinaryOp::Ne) {
+        return None;
+    }
+    let (masked, compared) = match (constant_byte(left, info), constant_byte(right, info)) {
+        (None, Some(value)) => (left, value),
+        (Some(value), None) => (right, value),
+        _ => return None,
+    };
+    let Expr::Binary {
+        left: operand,
+        op: BinaryOp::BitAnd,
+        right: mask,
+    } = &unparen(masked).node
+    else {
+        return None;
+    };
+    let (addr, mask) = match (
+        zero_page_byte_variable(operand, info),
+        zero_page_byte_variable(mask, info),
+    ) {
+        (Some(addr), None) => (addr, constant_byte(mask, info)?),
+        (None, Some(addr)) => (addr, constant_byte(operand, info)?),
+        _ => return None,
+    };
+    let bit = single_bit(mask)?;
+    // Comparing with the mask itself tests for a set bit, like != 0
+    let set = match compared {
+        0 => *op == BinaryOp::Ne,
+        value if value == mask => *op == BinaryOp::Eq,
+        _ => return None,
+    };
+    let mnemonic = if set { "BBS" } else { "BBR" };
+    Some((format!("{}{}", mnemonic, bit), addr))
+}
+
+/// Operand of a one-byte variable or a literal that fits in a byte
+fn byte_operand(expr: &Spanned<crate::ast::Expr>, info: &ProgramInfo) -> Option<String> {
+    match &expr.node {
+        crate::ast::Expr::Literal(crate::ast::Literal::Integer(value)) => u8::try_from(*value)
+            .ok()
+            .map(|byte| format!("#${:02X}", byte)),
+        _ => byte_variable_operand(expr, info),
+    }
+}
+
 /// Generate a normal (non-unrolled) for loop
 fn generate_normal_loop(
     var_name: &Spanned<String>,
     range: &crate::ast::Range,
     body: &Spanned<Stmt>,
+    bound: Option<u32>,
     emitter: &mut Emitter,
     info: &ProgramInfo,
     string_collector: &mut StringCollector,
@@ -1852,6 +2267,9 @@ fn generate_normal_loop(
     let loop_end_temp = emitter.memory_layout.loop_end_temp();
     let loop_label = emitter.next_label("fl");
     let end_label = emitter.next_label("fx");
+    if let Some(bound) = bound {
+        emitter.record_loop_bound(&loop_label, bound);
+    }
 
     // Initialize loop counter with range start
     generate_expr(&range.start, emitter, info, string_collector)?;
@@ -1861,21 +2279,16 @@ fn generate_normal_loop(
     generate_expr(&range.end, emitter, info, string_collector)?;
     emitter.emit_inst("STA", &format!("${:02X}", loop_end_temp));
 
+    // Loop variable location; X is copied there so the body can read it
+    let var_operand = match info.resolved_symbols.get(&var_name.span).map(|sym| &sym.location) {
+        Some(crate::sema::table::SymbolLocation::ZeroPage(addr)) => Some(format!("${:02X}", addr)),
+        Some(crate::sema::table::SymbolLocation::Absolute(addr)) => Some(format!("${:04X}", addr)),
+        _ => None,
+    };
+
     // Store X (loop counter) to the loop variable location
-    if let Some(sym) = info
-        .resolved_symbols
-        .get(&body.span)
-        .or_else(|| info.table.lookup(&var_name.node))
-    {
-        match sym.location {
-            crate::sema::table::SymbolLocation::ZeroPage(addr) => {
-                emitter.emit_inst("STX", &format!("${:02X}", addr));
-            }
-            crate::sema::table::SymbolLocation::Absolute(addr) => {
-                emitter.emit_inst("STX", &format!("${:04X}", addr));
-            }
-            _ => {}
-        }
+    if let Some(operand) = &var_operand {
+        emitter.emit_inst("STX", operand);
     }
 
     // Loop start
@@ -1895,8 +2308,9 @@ fn generate_normal_loop(
         emitter.emit_inst("BCS", &end_label);
     }
 
-    // Push loop context for break/continue
-    emitter.push_loop(loop_label.clone(), end_label.clone());
+    // Push loop context for break/continue (continue must still increment)
+    let next_label = emitter.next_label("fi");
+    emitter.push_loop(next_label.clone(), end_label.clone());
 
     // Execute body
     emitter.reg_state.invalidate_all(); // Body might use registers
@@ -1905,24 +2319,17 @@ fn generate_normal_loop(
     // Pop loop context
     emitter.pop_loop();
 
-    // Increment counter
+    // Increment counter, reloading it first since nested loops and calls
+    // in the body are free to use X
+    emitter.emit_label(&next_label);
+    if let Some(operand) = &var_operand {
+        emitter.emit_inst("LDX", operand);
+    }
     emitter.emit_inst("INX", "");
 
     // Update loop variable with new counter value
-    if let Some(sym) = info
-        .resolved_symbols
-        .get(&body.span)
-        .or_else(|| info.table.lookup(&var_name.node))
-    {
-        match sym.location {
-            crate::sema::table::SymbolLocation::ZeroPage(addr) => {
-                emitter.emit_inst("STX", &format!("${:02X}", addr));
-            }
-            crate::sema::table::SymbolLocation::Absolute(addr) => {
-                emitter.emit_inst("STX", &format!("${:04X}", addr));
-            }
-            _ => {}
-        }
+    if let Some(operand) = &var_operand {
+        emitter.emit_inst("STX", operand);
     }
 
     emitter.emit_inst("JMP", &loop_label);
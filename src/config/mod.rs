@@ -1,8 +1,12 @@
 //! Configuration and memory layout
 
+pub mod target;
+
+use crate::codegen::memory_layout::MemoryLayout;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+pub use target::{Startup, Target};
 
 /// Memory section definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +40,11 @@ impl Section {
 /// Configuration file structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Machine profile supplying sections, reserved zero page and I/O addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>,
+    /// Sections, replacing the target's sections of the same name
+    #[serde(default)]
     pub sections: Vec<Section>,
     #[serde(default = "default_section_name")]
     pub default_section: String,
@@ -51,7 +60,37 @@ impl Config {
         let content = fs::read_to_string(path.as_ref())
             .map_err(|e| format!("Failed to read config file: {}", e))?;
 
-        toml::from_str(&content).map_err(|e| format!("Failed to parse config file: {}", e))
+        let config: Self = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file: {}", e))?;
+        if config.target.is_none() && config.sections.is_empty() {
+            return Err("Config file needs a target or at least one [[sections]] entry".to_string());
+        }
+        Ok(config)
+    }
+
+    /// Configuration for a target profile
+    pub fn for_target(target: Target) -> Self {
+        Self {
+            target: Some(target),
+            sections: Vec::new(),
+            default_section: default_section_name(),
+        }
+    }
+
+    /// The target's sections overridden by the configured ones, in address order
+    pub fn resolved_sections(&self) -> Vec<Section> {
+        let Some(target) = self.target else {
+            return self.sections.clone();
+        };
+        let mut sections = target.sections();
+        for section in &self.sections {
+            match sections.iter_mut().find(|s| s.name == section.name) {
+                Some(existing) => *existing = section.clone(),
+                None => sections.push(section.clone()),
+            }
+        }
+        sections.sort_by_key(|section| section.start);
+        sections
     }
 
     /// Try to load from wraith.toml in current directory, fall back to defaults
@@ -68,6 +107,7 @@ impl Config {
     /// Create default configuration for 6502
     pub fn default_6502() -> Self {
         Self {
+            target: None,
             sections: vec![
                 Section::new("CODE", 0x8000, 0xBFFF), // 16KB for user code
                 Section::new("DATA", 0xD000, 0xEFFF), // 8KB for constants/data
@@ -88,17 +128,43 @@ impl Default for Config {
 pub struct MemoryConfig {
     pub sections: Vec<Section>,
     pub default_section_name: String,
+    pub target: Option<Target>,
+    /// Zero page the compiler must leave alone
+    pub reserved_zero_page: Vec<(u8, u8)>,
+    pub startup: Startup,
 }
 
 impl MemoryConfig {
     /// Create from a Config
     pub fn from_config(config: Config) -> Self {
+        let target = config.target;
         Self {
-            sections: config.sections,
+            sections: config.resolved_sections(),
             default_section_name: config.default_section,
+            target,
+            reserved_zero_page: target
+                .map(|t| t.reserved_zero_page().to_vec())
+                .unwrap_or_default(),
+            startup: target.map(Target::startup).unwrap_or_default(),
         }
     }
 
+    /// Memory layout for a target profile
+    pub fn for_target(target: Target) -> Self {
+        Self::from_config(Config::for_target(target))
+    }
+
+    /// Zero page for the compiler's temps, parameters and scratch, moved
+    /// out of the reserved ranges; `None` when they don't fit around them
+    pub fn zero_page_layout(&self) -> Option<MemoryLayout> {
+        MemoryLayout::for_reserved(&self.reserved_zero_page)
+    }
+
+    /// I/O addresses predeclared by the target
+    pub fn io_addresses(&self) -> &'static [(&'static str, u16)] {
+        self.target.map(Target::io_addresses).unwrap_or_default()
+    }
+
     /// Load from wraith.toml or use defaults
     pub fn load_or_default() -> Self {
         Self::from_config(Config::load_or_default())
@@ -159,6 +225,39 @@ mod tests {
         assert!(!section.contains(0x9000));
     }
 
+    #[test]
+    fn test_target_sections_can_be_overridden() {
+        let config: Config = toml::from_str(
+            r#"
+target = "c64"
+
+[[sections]]
+name = "DATA"
+start = 0xC000
+end = 0xCFFF
+
+[[sections]]
+name = "HIGH"
+start = 0xE000
+end = 0xEFFF
+"#,
+        )
+        .unwrap();
+        let memory = MemoryConfig::from_config(config);
+        let names: Vec<_> = memory.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["CODE", "RODATA", "DATA", "HIGH"]);
+        assert_eq!(memory.default_section().start, 0x0801);
+        assert_eq!(memory.get_section("DATA").unwrap().start, 0xC000);
+        assert_eq!(memory.startup, Startup::BasicStub);
+        assert!(memory.reserved_zero_page.contains(&(0x90, 0xFF)));
+    }
+
+    #[test]
+    fn test_unknown_target_is_rejected() {
+        let result: Result<Config, _> = toml::from_str("target = \"vic20\"\n");
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_memory_config_default_section() {
         let config = MemoryConfig::default();
@@ -0,0 +1,242 @@
+//! Target profiles
+//!
+//! A target names a machine and supplies everything a project would otherwise
+//! hand-tune in `wraith.toml`: the memory sections, the zero page the
+//! machine's OS or hardware owns, how the program is started, `addr`
+//! constants for its I/O registers, and the processor.
+
+use super::Section;
+use crate::assembler::Cpu;
+use serde::{Deserialize, Serialize};
+
+/// Machines with a predefined memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Target {
+    /// Commodore 64: a `.prg` at $0801 started by a BASIC `SYS` line
+    #[serde(rename = "c64")]
+    C64,
+    /// NES with an NROM-256 cartridge: 32KB of PRG ROM at $8000
+    #[serde(rename = "nes-nrom")]
+    NesNrom,
+    /// Apple II: a binary `BRUN` at $0803
+    #[serde(rename = "apple2")]
+    Apple2,
+    /// Atari 400/800/XL: a binary loaded and run at $2000
+    #[serde(rename = "atari800")]
+    Atari800,
+    /// BBC Micro: a binary `*RUN` at $1900
+    #[serde(rename = "bbc")]
+    Bbc,
+    /// Ben Eater's breadboard computer: 32KB ROM at $8000, 6522 VIA at $6000
+    #[serde(rename = "ben-eater")]
+    BenEater,
+}
+
+/// How control reaches the `#[reset]` function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Startup {
+    /// The program supplies the interrupt vector table at $FFFA-$FFFF
+    #[default]
+    Vectors,
+    /// The OS keeps the vectors and jumps to the start of the default section
+    LoadAddress,
+    /// A BASIC line `SYS <address>` at the start of the default section
+    BasicStub,
+}
+
+impl Target {
+    pub const ALL: [Target; 6] = [
+        Target::C64,
+        Target::NesNrom,
+        Target::Apple2,
+        Target::Atari800,
+        Target::Bbc,
+        Target::BenEater,
+    ];
+
+    /// Name as written in `wraith.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::C64 => "c64",
+            Target::NesNrom => "nes-nrom",
+            Target::Apple2 => "apple2",
+            Target::Atari800 => "atari800",
+            Target::Bbc => "bbc",
+            Target::BenEater => "ben-eater",
+        }
+    }
+
+    /// Look up a target by its `wraith.toml` name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|target| target.name() == name)
+    }
+
+    /// Memory sections; const arrays go in RODATA, strings in DATA
+    pub fn sections(self) -> Vec<Section> {
+        match self {
+            Target::C64 => vec![
+                section("CODE", 0x0801, 0x7FFF, "BASIC RAM, after the SYS line"),
+                section("DATA", 0x8000, 0x8FFF, "strings"),
+                section("RODATA", 0x9000, 0x9FFF, "const arrays"),
+            ],
+            Target::NesNrom | Target::BenEater => vec![
+                section("CODE", 0x8000, 0xBFFF, "ROM"),
+                section("RODATA", 0xC000, 0xDFFF, "const arrays"),
+                section("DATA", 0xE000, 0xFFF9, "strings, up to the vectors"),
+            ],
+            Target::Apple2 => vec![
+                section("CODE", 0x0803, 0x17FF, "below hi-res page 1"),
+                section("RODATA", 0x1800, 0x1BFF, "const arrays"),
+                section("DATA", 0x1C00, 0x1FFF, "strings"),
+            ],
+            Target::Atari800 => vec![
+                section("CODE", 0x2000, 0x7FFF, "above DOS"),
+                section("RODATA", 0x8000, 0x8FFF, "const arrays"),
+                section("DATA", 0x9000, 0x97FF, "strings, below the display list"),
+            ],
+            Target::Bbc => vec![
+                section("CODE", 0x1900, 0x5FFF, "above the filing system workspace"),
+                section("RODATA", 0x6000, 0x6FFF, "const arrays"),
+                section(
+                    "DATA",
+                    0x7000,
+                    0x7BFF,
+                    "strings, below MODE 7 screen memory",
+                ),
+            ],
+        }
+    }
+
+    /// Zero-page ranges the machine's OS or hardware uses while the program runs
+    pub fn reserved_zero_page(self) -> &'static [(u8, u8)] {
+        match self {
+            // 6510 I/O port, and the KERNAL's variables
+            Target::C64 => &[(0x00, 0x01), (0x90, 0xFF)],
+            // Monitor and DOS 3.3
+            Target::Apple2 => &[(0x20, 0x4F)],
+            // OS variables
+            Target::Atari800 => &[(0x00, 0x7F)],
+            // Econet, filing system and MOS variables
+            Target::Bbc => &[(0x90, 0xFF)],
+            Target::NesNrom | Target::BenEater => &[],
+        }
+    }
+
+    /// Processor the machine has, used unless the build names another
+    pub fn cpu(self) -> Cpu {
+        match self {
+            Target::NesNrom => Cpu::Ricoh2A03,
+            _ => Cpu::Nmos6502,
+        }
+    }
+
+    pub fn startup(self) -> Startup {
+        match self {
+            Target::C64 => Startup::BasicStub,
+            Target::Apple2 | Target::Atari800 | Target::Bbc => Startup::LoadAddress,
+            Target::NesNrom | Target::BenEater => Startup::Vectors,
+        }
+    }
+
+    /// I/O registers, declared as if by `const NAME: addr = ...`
+    pub fn io_addresses(self) -> &'static [(&'static str, u16)] {
+        match self {
+            Target::C64 => &[
+                ("SCREEN", 0x0400),
+                ("VIC_CTRL1", 0xD011),
+                ("VIC_RASTER", 0xD012),
+                ("VIC_MEMORY", 0xD018),
+                ("VIC_IRQ", 0xD019),
+                ("VIC_IRQ_MASK", 0xD01A),
+                ("VIC_BORDER", 0xD020),
+                ("VIC_BACKGROUND", 0xD021),
+                ("SID_VOLUME", 0xD418),
+                ("COLOR_RAM", 0xD800),
+                ("CIA1_PRA", 0xDC00),
+                ("CIA1_PRB", 0xDC01),
+                ("CIA1_ICR", 0xDC0D),
+                ("CIA2_PRA", 0xDD00),
+            ],
+            Target::NesNrom => &[
+                ("PPUCTRL", 0x2000),
+                ("PPUMASK", 0x2001),
+                ("PPUSTATUS", 0x2002),
+                ("OAMADDR", 0x2003),
+                ("OAMDATA", 0x2004),
+                ("PPUSCROLL", 0x2005),
+                ("PPUADDR", 0x2006),
+                ("PPUDATA", 0x2007),
+                ("DMC_FREQ", 0x4010),
+                ("OAMDMA", 0x4014),
+                ("SND_CHN", 0x4015),
+                ("JOY1", 0x4016),
+                ("JOY2", 0x4017),
+            ],
+            Target::Apple2 => &[
+                ("KBD", 0xC000),
+                ("KBDSTRB", 0xC010),
+                ("SPKR", 0xC030),
+                ("TXTCLR", 0xC050),
+                ("TXTSET", 0xC051),
+                ("MIXCLR", 0xC052),
+                ("MIXSET", 0xC053),
+                ("LOWSCR", 0xC054),
+                ("HISCR", 0xC055),
+                ("LORES", 0xC056),
+                ("HIRES", 0xC057),
+                ("BUTN0", 0xC061),
+                ("BUTN1", 0xC062),
+            ],
+            Target::Atari800 => &[
+                ("COLPF0", 0xD016),
+                ("COLPF1", 0xD017),
+                ("COLPF2", 0xD018),
+                ("COLPF3", 0xD019),
+                ("COLBK", 0xD01A),
+                ("CONSOL", 0xD01F),
+                ("AUDF1", 0xD200),
+                ("AUDC1", 0xD201),
+                ("AUDCTL", 0xD208),
+                ("RANDOM", 0xD20A),
+                ("PORTA", 0xD300),
+                ("DMACTL", 0xD400),
+                ("WSYNC", 0xD40A),
+                ("VCOUNT", 0xD40B),
+                ("NMIEN", 0xD40E),
+            ],
+            Target::Bbc => &[
+                ("CRTC_ADDR", 0xFE00),
+                ("CRTC_DATA", 0xFE01),
+                ("VIDEO_ULA_CTRL", 0xFE20),
+                ("VIDEO_ULA_PALETTE", 0xFE21),
+                ("SYS_VIA_ORB", 0xFE40),
+                ("SYS_VIA_ORA", 0xFE41),
+                ("SYS_VIA_IFR", 0xFE4D),
+                ("SYS_VIA_IER", 0xFE4E),
+                ("USER_VIA_ORB", 0xFE60),
+                ("USER_VIA_DDRB", 0xFE62),
+                ("USER_VIA_IFR", 0xFE6D),
+                ("USER_VIA_IER", 0xFE6E),
+            ],
+            Target::BenEater => &[
+                ("PORTB", 0x6000),
+                ("PORTA", 0x6001),
+                ("DDRB", 0x6002),
+                ("DDRA", 0x6003),
+                ("T1CL", 0x6004),
+                ("T1CH", 0x6005),
+                ("ACR", 0x600B),
+                ("PCR", 0x600C),
+                ("IFR", 0x600D),
+                ("IER", 0x600E),
+            ],
+        }
+    }
+}
+
+fn section(name: &str, start: u16, end: u16, description: &str) -> Section {
+    Section {
+        description: Some(description.to_string()),
+        ..Section::new(name, start, end)
+    }
+}
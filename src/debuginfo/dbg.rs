@@ -0,0 +1,118 @@
+//! ld65 Debug Info
+//!
+//! Writes the text format ld65 produces with `--dbgfile`, which Mesen and
+//! other cc65-aware debuggers load. Functions become labels in the global
+//! scope; each function with zero-page locals gets a child scope holding
+//! them as equates.
+
+use super::{DebugSymbol, DebugSymbolKind};
+use crate::codegen::CodegenOutput;
+
+/// Render an ld65-style `.dbg` file
+pub fn write_debug_info(symbols: &[DebugSymbol], output: &CodegenOutput, file_name: &str) -> String {
+    let module = file_name.trim_end_matches(".wr");
+
+    // Sections that received code or data
+    let segments: Vec<(String, u16, u16)> = output
+        .section_alloc
+        .get_statistics()
+        .into_iter()
+        .filter(|stat| stat.used > 0)
+        .filter_map(|stat| {
+            let section = output.section_alloc.get_section(&stat.name)?;
+            Some((stat.name, section.start, stat.used))
+        })
+        .collect();
+    let segment_of = |address: u16| {
+        segments
+            .iter()
+            .position(|(_, start, size)| address >= *start && (address as u32) < *start as u32 + *size as u32)
+    };
+
+    // Child scopes for functions with locals, in order of first appearance
+    let mut scopes: Vec<&str> = Vec::new();
+    for symbol in symbols {
+        if let Some(function) = symbol.scope.as_deref()
+            && !scopes.contains(&function)
+        {
+            scopes.push(function);
+        }
+    }
+    let scope_id = |function: Option<&str>| {
+        function
+            .and_then(|f| scopes.iter().position(|s| *s == f))
+            .map_or(0, |i| i + 1)
+    };
+
+    let mut out = String::new();
+    out.push_str("version\tmajor=2,minor=0\n");
+    out.push_str(&format!(
+        "info\tcsym=0,file=1,lib=0,line=0,mod=1,scope={},seg={},span=0,sym={},type=0\n",
+        scopes.len() + 1,
+        segments.len(),
+        symbols.len()
+    ));
+    out.push_str(&format!(
+        "file\tid=0,name=\"{}\",size=0,mtime=0x00000000,mod=0\n",
+        file_name
+    ));
+    out.push_str(&format!("mod\tid=0,name=\"{}.o\",file=0\n", module));
+
+    for (id, (name, start, size)) in segments.iter().enumerate() {
+        out.push_str(&format!(
+            "seg\tid={},name=\"{}\",start=0x{:06X},size=0x{:04X},addrsize=absolute,type=ro\n",
+            id, name, start, size
+        ));
+    }
+
+    out.push_str("scope\tid=0,name=\"\",mod=0\n");
+    for (i, function) in scopes.iter().enumerate() {
+        let label = symbols
+            .iter()
+            .position(|s| s.kind == DebugSymbolKind::Function && s.name == *function);
+        let mut line = format!(
+            "scope\tid={},name=\"{}\",mod=0,type=scope,parent=0",
+            i + 1,
+            function
+        );
+        if let Some(label) = label {
+            if let Some(size) = symbols[label].size {
+                line.push_str(&format!(",size={}", size));
+            }
+            line.push_str(&format!(",sym={}", label));
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    for (id, symbol) in symbols.iter().enumerate() {
+        let zero_page = symbol.address < 0x100 && symbol.kind == DebugSymbolKind::Variable;
+        let mut line = format!(
+            "sym\tid={},name=\"{}\",addrsize={}",
+            id,
+            symbol.name,
+            if zero_page { "zeropage" } else { "absolute" }
+        );
+        if let Some(size) = symbol.size {
+            line.push_str(&format!(",size={}", size));
+        }
+        line.push_str(&format!(
+            ",scope={},val=0x{:X}",
+            scope_id(symbol.scope.as_deref()),
+            symbol.address
+        ));
+        match symbol.kind {
+            DebugSymbolKind::Function | DebugSymbolKind::Data => {
+                if let Some(seg) = segment_of(symbol.address) {
+                    line.push_str(&format!(",seg={}", seg));
+                }
+                line.push_str(",type=lab");
+            }
+            DebugSymbolKind::Address | DebugSymbolKind::Variable => line.push_str(",type=equ"),
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    out
+}
@@ -0,0 +1,63 @@
+//! Mesen Label Files
+//!
+//! Each line is `TYPE:OFFSET[-END]:name`, where the type selects the memory
+//! the offset is relative to. The mapping assumes an NROM-style NES layout:
+//! internal RAM at $0000-$07FF, save RAM at $6000-$7FFF and PRG ROM from $8000.
+
+use super::{DebugSymbol, DebugSymbolKind};
+
+/// Mesen memory type and offset for a CPU address
+fn memory_offset(address: u16) -> (char, u16) {
+    match address {
+        0x0000..=0x07FF => ('R', address),
+        0x6000..=0x7FFF => ('S', address - 0x6000),
+        0x8000..=0xFFFF => ('P', address - 0x8000),
+        _ => ('G', address),
+    }
+}
+
+/// Render a Mesen `.mlb` label file
+pub fn write_labels(symbols: &[DebugSymbol]) -> String {
+    let mut out = String::new();
+    for symbol in symbols {
+        let (memory, offset) = memory_offset(symbol.address);
+        out.push_str(&format!("{}:{:04X}", memory, offset));
+        // Code labels mark an entry point; data labels cover every byte
+        if symbol.kind != DebugSymbolKind::Function
+            && let Some(size) = symbol.size.filter(|&size| size > 1)
+        {
+            out.push_str(&format!("-{:04X}", offset.saturating_add(size - 1)));
+        }
+        out.push_str(&format!(":{}\n", symbol.qualified_name()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, address: u16, size: u16, kind: DebugSymbolKind) -> DebugSymbol {
+        DebugSymbol {
+            name: name.to_string(),
+            address,
+            size: Some(size),
+            kind,
+            scope: None,
+        }
+    }
+
+    #[test]
+    fn test_mesen_labels() {
+        let symbols = vec![
+            symbol("total", 0x40, 2, DebugSymbolKind::Variable),
+            symbol("SRAM", 0x6000, 1, DebugSymbolKind::Address),
+            symbol("main", 0x8010, 12, DebugSymbolKind::Function),
+            symbol("PPUCTRL", 0x2000, 1, DebugSymbolKind::Address),
+        ];
+        assert_eq!(
+            write_labels(&symbols),
+            "R:0040-0041:total\nS:0000:SRAM\nP:0010:main\nG:2000:PPUCTRL\n"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Debug Symbol Export
+//!
+//! Collects the addresses of functions, const data, `addr` declarations,
+//! mutable statics and zero-page variables, and writes them in formats emulators and debuggers
+//! can load: VICE monitor labels, Mesen `.mlb` and the ld65 `.dbg` format.
+
+pub mod dbg;
+pub mod mesen;
+pub mod vice;
+
+use crate::assembler::Assembly;
+use crate::codegen::CodegenOutput;
+use crate::sema::ProgramInfo;
+use crate::sema::table::{SymbolKind, SymbolLocation};
+use crate::sema::types::Type;
+use rustc_hash::FxHashSet as HashSet;
+
+/// What a debug symbol refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugSymbolKind {
+    /// A function (or stdlib routine) in ROM
+    Function,
+    /// Const array data in ROM
+    Data,
+    /// A memory-mapped `addr` declaration
+    Address,
+    /// A variable, parameter or mutable static in RAM
+    Variable,
+}
+
+/// A named address for the debugger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugSymbol {
+    pub name: String,
+    pub address: u16,
+    /// Size in bytes, when known
+    pub size: Option<u16>,
+    pub kind: DebugSymbolKind,
+    /// Function a local variable belongs to
+    pub scope: Option<String>,
+}
+
+impl DebugSymbol {
+    /// Flat label name; locals are prefixed with their function (`main__count`)
+    pub fn qualified_name(&self) -> String {
+        match &self.scope {
+            Some(function) => format!("{}__{}", function, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Supported symbol file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolFormat {
+    /// VICE monitor label file (`al C:xxxx .name`)
+    Vice,
+    /// Mesen label file
+    Mesen,
+    /// ld65 debug info
+    Dbg,
+}
+
+impl SymbolFormat {
+    /// Parse a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vice" => Some(SymbolFormat::Vice),
+            "mesen" | "mlb" => Some(SymbolFormat::Mesen),
+            "dbg" | "ca65" => Some(SymbolFormat::Dbg),
+            _ => None,
+        }
+    }
+
+    /// File extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            SymbolFormat::Vice => "lbl",
+            SymbolFormat::Mesen => "mlb",
+            SymbolFormat::Dbg => "dbg",
+        }
+    }
+
+    /// Render symbols in this format
+    pub fn write(self, symbols: &[DebugSymbol], output: &CodegenOutput, file_name: &str) -> String {
+        match self {
+            SymbolFormat::Vice => vice::write_labels(symbols),
+            SymbolFormat::Mesen => mesen::write_labels(symbols),
+            SymbolFormat::Dbg => dbg::write_debug_info(symbols, output, file_name),
+        }
+    }
+}
+
+/// Collect every debug symbol of a compiled and assembled program
+///
+/// Functions come from the section placements, everything else from the
+/// resolved symbol locations. Locals of functions that were never placed
+/// (uncalled imports) are left out, since their addresses alias live ones,
+/// and so are 65816 locals kept in stack frames.
+/// Const arrays and mutable statics are only placed by the assembler, so
+/// their addresses are read from `assembly`.
+pub fn collect_symbols(
+    program: &ProgramInfo,
+    output: &CodegenOutput,
+    assembly: &Assembly,
+) -> Vec<DebugSymbol> {
+    let mut symbols = Vec::new();
+
+    for allocation in &output.section_alloc.allocations {
+        symbols.push(DebugSymbol {
+            name: allocation.name.clone(),
+            address: allocation.start,
+            size: Some(allocation.end - allocation.start + 1),
+            kind: DebugSymbolKind::Function,
+            scope: None,
+        });
+    }
+
+    let placed: HashSet<&str> = output
+        .section_alloc
+        .allocations
+        .iter()
+        .map(|allocation| allocation.name.as_str())
+        .collect();
+    let mut seen: HashSet<(Option<String>, String, u16)> = HashSet::default();
+    for info in program.resolved_symbols.values() {
+        if let Some(function) = &info.containing_function
+            && !placed.contains(function.as_str())
+        {
+            continue;
+        }
+        let symbol = match (&info.kind, &info.location) {
+            (SymbolKind::Address, SymbolLocation::Absolute(address)) => DebugSymbol {
+                name: info.name.clone(),
+                address: *address,
+                size: None,
+                kind: DebugSymbolKind::Address,
+                scope: None,
+            },
+            // 65816 locals in a stack frame have no fixed address
+            (SymbolKind::Variable, SymbolLocation::ZeroPage(address))
+                if info.containing_function.as_ref().is_some_and(|function| {
+                    output.stack_locals.contains(&(function.clone(), *address))
+                }) =>
+            {
+                continue;
+            }
+            (SymbolKind::Variable, SymbolLocation::ZeroPage(address)) => DebugSymbol {
+                name: info.name.clone(),
+                address: *address as u16,
+                size: variable_size(&info.ty, program),
+                kind: DebugSymbolKind::Variable,
+                scope: info.containing_function.clone(),
+            },
+            (SymbolKind::Variable, SymbolLocation::Absolute(address)) => DebugSymbol {
+                name: info.name.clone(),
+                address: *address,
+                size: variable_size(&info.ty, program),
+                kind: DebugSymbolKind::Variable,
+                scope: info.containing_function.clone(),
+            },
+            (SymbolKind::Constant, _) if info.mutable || matches!(info.ty, Type::Array(..)) => {
+                let Some(address) = assembly.symbol(&info.name) else {
+                    continue;
+                };
+                DebugSymbol {
+                    name: info.name.clone(),
+                    address,
+                    size: u16::try_from(info.ty.size()).ok(),
+                    kind: if info.mutable {
+                        DebugSymbolKind::Variable
+                    } else {
+                        DebugSymbolKind::Data
+                    },
+                    scope: None,
+                }
+            }
+            _ => continue,
+        };
+        // Every use of a symbol is resolved separately
+        if seen.insert((symbol.scope.clone(), symbol.name.clone(), symbol.address)) {
+            symbols.push(symbol);
+        }
+    }
+
+    symbols.sort_by(|a, b| {
+        (a.address, a.qualified_name()).cmp(&(b.address, b.qualified_name()))
+    });
+    symbols
+}
+
+/// Zero-page footprint of a variable (arrays and enums are held by pointer)
+pub(crate) fn variable_size(ty: &Type, program: &ProgramInfo) -> Option<u16> {
+    let size = match ty {
+        Type::Array(..) => 2,
+        Type::Named(name) => match program.type_registry.get_struct(name) {
+            Some(def) => def.total_size,
+            None => 2,
+        },
+        _ => ty.size(),
+    };
+    u16::try_from(size).ok().filter(|&size| size > 0)
+}
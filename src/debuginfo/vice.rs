@@ -0,0 +1,32 @@
+//! VICE Monitor Labels
+//!
+//! One `al C:xxxx .name` command per symbol, loadable with
+//! `x64sc -moncommands program.lbl` or the monitor's `ll` command.
+
+use super::DebugSymbol;
+
+/// Render a VICE label file
+pub fn write_labels(symbols: &[DebugSymbol]) -> String {
+    symbols
+        .iter()
+        .map(|symbol| format!("al C:{:04X} .{}\n", symbol.address, symbol.qualified_name()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debuginfo::DebugSymbolKind;
+
+    #[test]
+    fn test_vice_labels() {
+        let symbols = vec![DebugSymbol {
+            name: "count".to_string(),
+            address: 0x40,
+            size: Some(1),
+            kind: DebugSymbolKind::Variable,
+            scope: Some("main".to_string()),
+        }];
+        assert_eq!(write_labels(&symbols), "al C:0040 .main__count\n");
+    }
+}
@@ -0,0 +1,249 @@
+//! Machine-readable diagnostics
+//!
+//! Errors and warnings from every phase of the compiler in one shape, written
+//! as one JSON object per line for `--message-format=json`:
+//!
+//! ```text
+//! {"severity":"error","code":"type-mismatch","message":"type mismatch",
+//!  "span":{"file":"main.wr","line":3,"column":19,"end_line":3,"end_column":20,
+//!  "label":"expected bool, found u8"},"secondary":[],"notes":[]}
+//! ```
+//!
+//! `code` names the kind of diagnostic and doesn't change between releases.
+//! Lines and columns start at 1; the end column is exclusive.
+
+use crate::ast::{SourceMap, Span};
+use crate::codegen::CodegenError;
+use crate::lexer::LexError;
+use crate::parser::{ParseError, ParseErrorKind};
+use crate::sema::{SemaError, Warning};
+use crate::stack::StackError;
+use crate::wcet::WcetError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A span with the text shown under it
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// Where the problem is; `None` for errors without a source location
+    pub primary: Option<Label>,
+    /// Other places involved, such as an earlier definition
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    fn error(code: &'static str, message: String, primary: Option<Label>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message,
+            primary,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn from_lex_error(error: &LexError) -> Self {
+        let span = Span::new(error.span.start, error.span.end);
+        Self::error(
+            "invalid-token",
+            error.message.clone(),
+            Some(Label {
+                span,
+                message: error.message.clone(),
+            }),
+        )
+    }
+
+    /// One diagnostic per error; the parser may report several at once
+    pub fn from_parse_error(error: &ParseError) -> Vec<Self> {
+        match &error.kind {
+            ParseErrorKind::Multiple(errors) => {
+                errors.iter().flat_map(Self::from_parse_error).collect()
+            }
+            kind => {
+                let message = error.message();
+                let mut diagnostic = Self::error(
+                    error.code(),
+                    message.clone(),
+                    Some(Label {
+                        span: error.span,
+                        message,
+                    }),
+                );
+                if let ParseErrorKind::CustomDetailed { prefix, suffix, .. } = kind {
+                    diagnostic
+                        .notes
+                        .extend(prefix.iter().chain(suffix).cloned());
+                }
+                vec![diagnostic]
+            }
+        }
+    }
+
+    pub fn from_sema_error(error: &SemaError, source: &str) -> Self {
+        let (title, label) = error.describe(source);
+        let mut diagnostic = Self::error(
+            error.code(),
+            title,
+            error.span().map(|span| Label {
+                span,
+                message: label,
+            }),
+        );
+        match error {
+            // The earlier definition is a span of its own rather than part
+            // of the message
+            SemaError::DuplicateSymbol {
+                name,
+                span,
+                previous_span: Some(previous),
+            } => {
+                diagnostic.message = format!("duplicate symbol '{}'", name);
+                diagnostic.primary = Some(Label {
+                    span: *span,
+                    message: diagnostic.message.clone(),
+                });
+                diagnostic.secondary.push(Label {
+                    span: *previous,
+                    message: "previously defined here".to_string(),
+                });
+            }
+            SemaError::CircularImport { chain, path } => {
+                diagnostic
+                    .notes
+                    .push(format!("import chain: {} -> {}", chain.join(" -> "), path))
+            }
+            _ => {}
+        }
+        diagnostic
+    }
+
+    pub fn from_warning(warning: &Warning) -> Self {
+        let (message, span) = warning.describe();
+        Self {
+            severity: Severity::Warning,
+            code: warning.code(),
+            message: message.clone(),
+            primary: Some(Label { span, message }),
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn from_codegen_error(error: &CodegenError) -> Self {
+        Self::located(error.code(), error.to_string(), error.span())
+    }
+
+    pub fn from_stack_error(error: &StackError) -> Self {
+        Self::located(error.code(), error.to_string(), error.span())
+    }
+
+    pub fn from_wcet_error(error: &WcetError) -> Self {
+        Self::located(error.code(), error.to_string(), error.span())
+    }
+
+    /// An error whose message is also the label of its span
+    fn located(code: &'static str, message: String, span: Option<Span>) -> Self {
+        let primary = span.map(|span| Label {
+            span,
+            message: message.clone(),
+        });
+        Self::error(code, message, primary)
+    }
+
+    /// A warning without a source location
+    pub fn warning(code: &'static str, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, None)
+        }
+    }
+
+    /// One line of JSON, with spans resolved to lines and columns of `source`
+    pub fn to_json(&self, source: &str, file: &str) -> String {
+        self.to_json_with_sources(&SourceMap::new(file, source))
+    }
+
+    /// One line of JSON, with each span resolved against the file it is in;
+    /// a span in an imported module gets a note for each import that led to it
+    pub fn to_json_with_sources(&self, sources: &SourceMap) -> String {
+        let label_json = |label: &Label| {
+            let source = sources.source(label.span.file);
+            let start = label.span.to_line_col(source);
+            let end = Span::new(label.span.end, label.span.end).to_line_col(source);
+            format!(
+                "{{\"file\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{},\"label\":{}}}",
+                json_string(sources.name(label.span.file)),
+                start.line,
+                start.col,
+                end.line,
+                end.col,
+                json_string(&label.message)
+            )
+        };
+        let secondary: Vec<String> = self.secondary.iter().map(label_json).collect();
+        let imports = self.primary.iter().flat_map(|primary| {
+            sources
+                .import_chain(primary.span.file)
+                .into_iter()
+                .map(|import| format!("imported from {}", sources.format_location(import)))
+        });
+        let notes: Vec<String> = self
+            .notes
+            .iter()
+            .cloned()
+            .chain(imports)
+            .map(|note| json_string(&note))
+            .collect();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"span\":{},\"secondary\":[{}],\"notes\":[{}]}}",
+            self.severity.name(),
+            self.code,
+            json_string(&self.message),
+            self.primary.as_ref().map_or("null".to_string(), label_json),
+            secondary.join(","),
+            notes.join(",")
+        )
+    }
+}
+
+/// Quote a string for JSON
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
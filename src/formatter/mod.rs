@@ -0,0 +1,979 @@
+//! Source formatter
+//!
+//! Prints a parsed file back out in one layout: four-space indents, one
+//! statement, field, variant and match arm per line, and single spaces
+//! around binary operators. Comments are trivia the parser never sees, so
+//! they are placed by position: before the construct that follows them on
+//! their own line, or after it when they share its last line. Blank lines
+//! between statements are kept, at most one in a row, and items that span
+//! several lines are always separated by one.
+//!
+//! The AST drops some spelling, such as hex integer literals or the
+//! delimiters of an enum pattern's bindings; those are taken from the tokens
+//! instead, so formatting never changes what a file means.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::ast::{
+    AccessMode, BinaryOp, Expr, FieldInit, Item, Literal, Pattern, PrimitiveType, SourceFile, Span,
+    Spanned, Stmt, StructField, TypeExpr, UnaryOp, VariantData,
+};
+use crate::ast::{EnumVariant, ExprMatchArm};
+use crate::lexer::{LexError, SpannedToken, Token, lex_with_comments};
+use crate::parser::{ParseError, Parser};
+
+const INDENT: &str = "    ";
+
+/// Why a file could not be formatted
+#[derive(Debug)]
+pub enum FormatError {
+    Lex(LexError),
+    Parse(ParseError),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Lex(e) => write!(f, "{}", e.message),
+            FormatError::Parse(e) => write!(f, "{}", e.message()),
+        }
+    }
+}
+
+/// Format a whole source file
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let (tokens, comments) = lex_with_comments(source).map_err(FormatError::Lex)?;
+    let ast = Parser::parse(&tokens).map_err(FormatError::Parse)?;
+    let mut formatter = Formatter {
+        source,
+        tokens: &tokens,
+        comments,
+        next_comment: 0,
+        out: String::with_capacity(source.len()),
+        indent: 0,
+        last_end: 0,
+        force_blank_line: false,
+    };
+    formatter.source_file(&ast);
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    tokens: &'a [SpannedToken],
+    comments: Vec<Range<usize>>,
+    /// The first comment not yet written
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// End of the last source construct or comment written
+    last_end: usize,
+    /// Separate the next line from the previous one by a blank line
+    force_blank_line: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn source_file(&mut self, ast: &SourceFile) {
+        let mut previous: Option<&Item> = None;
+        for item in &ast.items {
+            let multiline =
+                |item: &Item| matches!(item, Item::Function(_) | Item::Struct(_) | Item::Enum(_));
+            self.force_blank_line = previous.is_some_and(multiline) || multiline(&item.node);
+            self.item(item);
+            previous = Some(&item.node);
+        }
+        self.comments_before(self.source.len());
+    }
+
+    // === Lines and comments ===
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Write the comments that start before `offset`, each on its own line
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment).cloned()
+            && comment.start < offset
+        {
+            self.next_comment += 1;
+            self.blank_line_before(comment.start);
+            self.write_indent();
+            self.write(self.source[comment.clone()].trim_end());
+            self.out.push('\n');
+            self.last_end = self.last_end.max(comment.end);
+        }
+    }
+
+    /// Keep one blank line where the source has any before `start`
+    fn blank_line_before(&mut self, start: usize) {
+        let source_blank = self
+            .source
+            .get(self.last_end..start)
+            .is_some_and(|gap| gap.matches('\n').count() > 1);
+        let at_open = self.out.is_empty()
+            || self.out.ends_with("{\n")
+            || self.out.ends_with("[\n")
+            || self.out.ends_with("(\n");
+        if (source_blank || self.force_blank_line) && !at_open {
+            self.out.push('\n');
+        }
+        self.force_blank_line = false;
+    }
+
+    /// Start the line of the construct at `start`, after the comments before it
+    fn begin_line(&mut self, start: usize) {
+        self.comments_before(start);
+        self.blank_line_before(start);
+        self.write_indent();
+    }
+
+    /// End the line of a construct ending at `end`, with the comments inside
+    /// it and those after it on the same line
+    fn end_line(&mut self, end: usize) {
+        let mut after_line_comment = false;
+        while let Some(comment) = self.comments.get(self.next_comment).cloned()
+            && (comment.start < end || !self.source[end..comment.start].contains('\n'))
+        {
+            self.next_comment += 1;
+            if after_line_comment {
+                self.out.push('\n');
+                self.write_indent();
+            } else {
+                self.out.push(' ');
+            }
+            let text = self.source[comment.clone()].trim_end();
+            self.write(text);
+            after_line_comment = text.starts_with("//");
+            self.last_end = self.last_end.max(comment.end);
+        }
+        self.out.push('\n');
+        self.last_end = self.last_end.max(end);
+    }
+
+    /// Close a braced body whose `}` is at `close`
+    fn close(&mut self, close: usize, delimiter: &str) {
+        self.comments_before(close);
+        self.indent -= 1;
+        self.write_indent();
+        self.write(delimiter);
+        self.last_end = close + 1;
+    }
+
+    fn has_comment_before(&self, offset: usize) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.start < offset)
+    }
+
+    // === Tokens ===
+
+    fn text(&self, span: Span) -> &'a str {
+        &self.source[span.start..span.end]
+    }
+
+    /// Index of the first token starting at or after `offset`
+    fn token_index(&self, offset: usize) -> usize {
+        self.tokens
+            .partition_point(|token| token.span.start < offset)
+    }
+
+    /// The first token starting at or after `offset`
+    fn token_at(&self, offset: usize) -> Option<&SpannedToken> {
+        self.tokens.get(self.token_index(offset))
+    }
+
+    /// Source text of the first integer after `offset`, which the AST keeps
+    /// only as a value
+    fn integer_after(&self, offset: usize) -> String {
+        self.tokens[self.token_index(offset)..]
+            .iter()
+            .find(|token| matches!(token.token, Token::Integer(_)))
+            .map_or_else(String::new, |token| {
+                self.source[token.span.clone()].to_string()
+            })
+    }
+
+    /// The `#[...]` attributes starting at `offset`, with the spaces inside
+    /// them removed
+    fn attributes(&self, offset: usize) -> Vec<String> {
+        let mut i = self.token_index(offset);
+        let mut attributes = Vec::new();
+        while self
+            .tokens
+            .get(i)
+            .is_some_and(|token| token.token == Token::Hash)
+        {
+            let mut attribute = String::new();
+            while let Some(token) = self.tokens.get(i) {
+                attribute.push_str(&self.source[token.span.clone()]);
+                i += 1;
+                if token.token == Token::RBracket {
+                    break;
+                }
+            }
+            attributes.push(attribute);
+        }
+        attributes
+    }
+
+    /// Write attributes at `offset` one per line, leaving the line after
+    /// them started
+    fn write_attributes(&mut self, offset: usize) {
+        for attribute in self.attributes(offset) {
+            self.write(&attribute);
+            self.out.push('\n');
+            self.write_indent();
+        }
+    }
+
+    // === Items ===
+
+    fn item(&mut self, item: &Spanned<Item>) {
+        self.begin_line(item.span.start);
+        self.write_attributes(item.span.start);
+        let is_pub = match &item.node {
+            Item::Function(function) => function.is_pub,
+            Item::Struct(s) => s.is_pub,
+            Item::Enum(e) => e.is_pub,
+            Item::Static(stat) => stat.is_pub,
+            Item::Address(addr) => addr.is_pub,
+            Item::Import(_) => false,
+        };
+        if is_pub {
+            self.write("pub ");
+        }
+
+        match &item.node {
+            Item::Function(function) => {
+                self.write("fn ");
+                self.write(&function.name.node);
+                self.write("(");
+                for (i, param) in function.params.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.write(&param.name.node);
+                    self.write(": ");
+                    self.ty(&param.ty);
+                }
+                self.write(")");
+                if let Some(return_type) = &function.return_type {
+                    self.write(" -> ");
+                    self.ty(return_type);
+                }
+                self.write(" ");
+                self.block(&function.body);
+            }
+            Item::Struct(s) => {
+                self.write("struct ");
+                self.write(&s.name.node);
+                self.write(" ");
+                self.fields(&s.fields, s.name.span.end, item.span.end - 1);
+            }
+            Item::Enum(e) => {
+                self.write("enum ");
+                self.write(&e.name.node);
+                let close = item.span.end - 1;
+                if e.variants.is_empty() && !self.has_comment_before(close) {
+                    self.write(" {}");
+                } else {
+                    self.write(" {");
+                    self.end_line(e.name.span.end);
+                    self.indent += 1;
+                    for variant in &e.variants {
+                        self.variant(variant);
+                    }
+                    self.close(close, "}");
+                }
+            }
+            Item::Static(stat) => {
+                self.write("const ");
+                self.write(&stat.name.node);
+                self.write(": ");
+                self.ty(&stat.ty);
+                self.write(" = ");
+                self.expr(&stat.init);
+                self.write(";");
+            }
+            Item::Address(addr) => {
+                self.write("const ");
+                self.write(&addr.name.node);
+                self.write(match addr.access {
+                    AccessMode::Read => ": read addr = ",
+                    AccessMode::Write => ": write addr = ",
+                    AccessMode::ReadWrite => ": addr = ",
+                });
+                self.expr(&addr.address);
+                self.write(";");
+            }
+            Item::Import(import) => {
+                self.write("import { ");
+                let symbols: Vec<&str> = import
+                    .symbols
+                    .iter()
+                    .map(|symbol| symbol.node.as_str())
+                    .collect();
+                self.write(&symbols.join(", "));
+                self.write(" } from ");
+                self.write(self.text(import.path.span));
+                self.write(";");
+            }
+        }
+        self.end_line(item.span.end);
+    }
+
+    /// Struct fields one per line, from the `{` after `open` to the `}` at
+    /// `close`
+    fn fields(&mut self, fields: &[StructField], open: usize, close: usize) {
+        if fields.is_empty() && !self.has_comment_before(close) {
+            self.write("{}");
+            return;
+        }
+        self.write("{");
+        self.end_line(open);
+        self.indent += 1;
+        for field in fields {
+            self.begin_line(field.name.span.start);
+            self.write(&field.name.node);
+            self.write(": ");
+            self.ty(&field.ty);
+            self.write(",");
+            self.end_line(field.ty.span.end);
+        }
+        self.close(close, "}");
+    }
+
+    fn variant(&mut self, variant: &EnumVariant) {
+        match variant {
+            EnumVariant::Unit { name, value } => {
+                self.begin_line(name.span.start);
+                self.write(&name.node);
+                let mut end = name.span.end;
+                if value.is_some() {
+                    self.write(" = ");
+                    let index = self.token_index(name.span.end) + 1;
+                    if let Some(token) = self.tokens.get(index) {
+                        end = token.span.end;
+                    }
+                    self.write(&self.integer_after(name.span.end));
+                }
+                self.write(",");
+                self.end_line(end);
+            }
+            EnumVariant::Tuple { name, fields } => {
+                self.begin_line(name.span.start);
+                self.write(&name.node);
+                self.write("(");
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.ty(field);
+                }
+                self.write("),");
+                self.end_line(fields.last().map_or(name.span.end, |field| field.span.end));
+            }
+            EnumVariant::Struct { name, fields } => {
+                self.begin_line(name.span.start);
+                self.write(&name.node);
+                if fields.is_empty() {
+                    self.write(" {},");
+                } else {
+                    self.write(" { ");
+                    for (i, field) in fields.iter().enumerate() {
+                        if i > 0 {
+                            self.write(", ");
+                        }
+                        self.write(&field.name.node);
+                        self.write(": ");
+                        self.ty(&field.ty);
+                    }
+                    self.write(" },");
+                }
+                self.end_line(
+                    fields
+                        .last()
+                        .map_or(name.span.end, |field| field.ty.span.end),
+                );
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Spanned<TypeExpr>) {
+        match &ty.node {
+            TypeExpr::Primitive(primitive) => self.write(match primitive {
+                PrimitiveType::U8 => "u8",
+                PrimitiveType::I8 => "i8",
+                PrimitiveType::U16 => "u16",
+                PrimitiveType::I16 => "i16",
+                PrimitiveType::Bool => "bool",
+                PrimitiveType::B8 => "b8",
+                PrimitiveType::B16 => "b16",
+                PrimitiveType::Addr => "addr",
+            }),
+            TypeExpr::Named(name) => self.write(name),
+            TypeExpr::Array { element, .. } => {
+                self.write("[");
+                self.ty(element);
+                self.write("; ");
+                self.write(&self.integer_after(element.span.end));
+                self.write("]");
+            }
+            TypeExpr::Slice { element, .. } => {
+                self.write("&[");
+                self.ty(element);
+                self.write("]");
+            }
+        }
+    }
+
+    // === Statements ===
+
+    /// A `{ ... }` block, from its opening brace to its closing one
+    fn block(&mut self, block: &Spanned<Stmt>) {
+        let stmts = match &block.node {
+            Stmt::Block(stmts) => stmts.as_slice(),
+            _ => std::slice::from_ref(block),
+        };
+        let close = block.span.end - 1;
+        if stmts.is_empty() && !self.has_comment_before(close) {
+            self.write("{}");
+            self.last_end = block.span.end;
+            return;
+        }
+        self.write("{");
+        self.end_line(block.span.start + 1);
+        self.indent += 1;
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.close(close, "}");
+    }
+
+    fn stmt(&mut self, stmt: &Spanned<Stmt>) {
+        self.begin_line(stmt.span.start);
+        match &stmt.node {
+            Stmt::VarDecl { name, ty, init, .. } => {
+                self.write("let ");
+                self.write(&name.node);
+                self.write(": ");
+                self.ty(ty);
+                self.write(" = ");
+                self.expr(init);
+                self.write(";");
+            }
+            Stmt::Assign { target, value } => {
+                self.expr(target);
+                match &value.node {
+                    // `x += y` is parsed as `x = x + y` spanning the statement
+                    Expr::Binary { left, op, right }
+                        if value.span == stmt.span && **left == *target =>
+                    {
+                        self.write(" ");
+                        self.write(binary_op(*op));
+                        self.write("= ");
+                        self.expr(right);
+                    }
+                    _ => {
+                        self.write(" = ");
+                        self.expr(value);
+                    }
+                }
+                self.write(";");
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+                self.write(";");
+            }
+            Stmt::Return(value) => {
+                self.write("return");
+                if let Some(value) = value {
+                    self.write(" ");
+                    self.expr(value);
+                }
+                self.write(";");
+            }
+            Stmt::If { .. } => self.if_stmt(stmt),
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.write_attributes(stmt.span.start);
+                self.write("while ");
+                self.expr(condition);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::Loop { body, .. } => {
+                self.write_attributes(stmt.span.start);
+                self.write("loop ");
+                self.block(body);
+            }
+            Stmt::For {
+                var_name,
+                var_type,
+                range,
+                body,
+                ..
+            } => {
+                self.write_attributes(stmt.span.start);
+                self.write("for ");
+                self.write(&var_name.node);
+                if let Some(var_type) = var_type {
+                    self.write(": ");
+                    self.ty(var_type);
+                }
+                self.write(" in ");
+                self.expr(&range.start);
+                self.write(if range.inclusive { "..=" } else { ".." });
+                self.expr(&range.end);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::ForEach {
+                var_name,
+                var_type,
+                iterable,
+                body,
+                index_var,
+                ..
+            } => {
+                self.write_attributes(stmt.span.start);
+                self.write("for ");
+                match index_var {
+                    Some(index_var) => {
+                        self.write("(");
+                        self.write(&index_var.node);
+                        self.write(", ");
+                        self.write(&var_name.node);
+                        self.write(")");
+                    }
+                    None => self.write(&var_name.node),
+                }
+                if let Some(var_type) = var_type {
+                    self.write(": ");
+                    self.ty(var_type);
+                }
+                self.write(" in ");
+                self.expr(iterable);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::Match { expr, arms } => {
+                self.write("match ");
+                self.expr(expr);
+                self.write(" {");
+                self.end_line(expr.span.end);
+                self.indent += 1;
+                for arm in arms {
+                    self.begin_line(arm.pattern.span.start);
+                    self.pattern(&arm.pattern);
+                    self.write(" => ");
+                    match expression_arm(&arm.body) {
+                        Some(expr) => {
+                            self.expr(expr);
+                            self.write(",");
+                        }
+                        None => self.block(&arm.body),
+                    }
+                    self.end_line(arm.body.span.end);
+                }
+                self.close(stmt.span.end - 1, "}");
+            }
+            Stmt::Break => self.write("break;"),
+            Stmt::Continue => self.write("continue;"),
+            Stmt::Block(_) => self.block(stmt),
+            Stmt::Asm { .. } => self.asm(stmt),
+        }
+        self.end_line(stmt.span.end);
+    }
+
+    fn if_stmt(&mut self, stmt: &Spanned<Stmt>) {
+        let Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } = &stmt.node
+        else {
+            return;
+        };
+        self.write("if ");
+        self.expr(condition);
+        self.write(" ");
+        self.block(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.write(" else ");
+            match else_branch.node {
+                Stmt::If { .. } => self.if_stmt(else_branch),
+                _ => self.block(else_branch),
+            }
+        }
+    }
+
+    /// `asm { "...", }`, one line per string; the strings are copied from the
+    /// source since the AST keeps them unescaped
+    fn asm(&mut self, stmt: &Spanned<Stmt>) {
+        let first = self.token_index(stmt.span.start);
+        let last = self.token_index(stmt.span.end);
+        let lines: Vec<Span> = self.tokens[first..last]
+            .iter()
+            .filter(|token| matches!(token.token, Token::String(_)))
+            .map(|token| Span::new(token.span.start, token.span.end))
+            .collect();
+        let close = stmt.span.end - 1;
+        if lines.is_empty() && !self.has_comment_before(close) {
+            self.write("asm {}");
+            return;
+        }
+        self.write("asm {");
+        let open = self
+            .tokens
+            .get(first + 1)
+            .map_or(stmt.span.start, |t| t.span.end);
+        self.end_line(open);
+        self.indent += 1;
+        for line in lines {
+            self.begin_line(line.start);
+            self.write(self.text(line));
+            self.write(",");
+            self.end_line(line.end);
+        }
+        self.close(close, "}");
+    }
+
+    fn pattern(&mut self, pattern: &Spanned<Pattern>) {
+        match &pattern.node {
+            Pattern::Literal(expr) => self.expr(expr),
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                self.expr(start);
+                self.write(if *inclusive { "..=" } else { ".." });
+                self.expr(end);
+            }
+            Pattern::Wildcard => self.write("_"),
+            Pattern::EnumVariant {
+                enum_name,
+                variant,
+                bindings,
+            } => {
+                self.write(&enum_name.node);
+                self.write("::");
+                self.write(&variant.node);
+                let names: Vec<&str> = bindings
+                    .iter()
+                    .map(|binding| binding.name.node.as_str())
+                    .collect();
+                let delimiter = self
+                    .token_at(variant.span.end)
+                    .filter(|token| token.span.start < pattern.span.end)
+                    .map(|token| token.token.clone());
+                match delimiter {
+                    Some(Token::LParen) => {
+                        self.write("(");
+                        self.write(&names.join(", "));
+                        self.write(")");
+                    }
+                    Some(Token::LBrace) if names.is_empty() => self.write(" {}"),
+                    Some(Token::LBrace) => {
+                        self.write(" { ");
+                        self.write(&names.join(", "));
+                        self.write(" }");
+                    }
+                    _ => {}
+                }
+            }
+            Pattern::Variable(name) => self.write(name),
+        }
+    }
+
+    // === Expressions ===
+
+    fn expr(&mut self, expr: &Spanned<Expr>) {
+        match &expr.node {
+            Expr::Literal(Literal::Integer(_) | Literal::String(_)) => {
+                self.write(self.text(expr.span))
+            }
+            Expr::Literal(Literal::Bool(value)) => {
+                self.write(if *value { "true" } else { "false" })
+            }
+            Expr::Literal(Literal::Array(elements)) => {
+                self.list("[", "]", expr.span, elements, |e| e.span, Self::expr)
+            }
+            Expr::Literal(Literal::ArrayFill { value, .. }) => {
+                self.write("[");
+                self.expr(value);
+                self.write("; ");
+                self.write(&self.integer_after(value.span.end));
+                self.write("]");
+            }
+            Expr::Variable(name) => self.write(name),
+            Expr::Binary { left, op, right } => {
+                self.expr(left);
+                self.write(" ");
+                self.write(binary_op(*op));
+                self.write(" ");
+                self.expr(right);
+            }
+            Expr::Unary { op, operand } => {
+                self.write(match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::BitNot => "~",
+                    UnaryOp::Not => "!",
+                });
+                self.expr(operand);
+            }
+            Expr::Cast { expr, target_type } => {
+                self.expr(expr);
+                self.write(" as ");
+                self.ty(target_type);
+            }
+            Expr::Field { object, field } => {
+                self.expr(object);
+                self.write(".");
+                self.write(&field.node);
+            }
+            Expr::Index { object, index } => {
+                self.expr(object);
+                self.write("[");
+                self.expr(index);
+                self.write("]");
+            }
+            Expr::Slice {
+                object,
+                start,
+                end,
+                inclusive,
+            } => {
+                self.expr(object);
+                self.write("[");
+                self.expr(start);
+                self.write(if *inclusive { "..=" } else { ".." });
+                self.expr(end);
+                self.write("]");
+            }
+            Expr::Call { function, args } => {
+                self.write(&function.node);
+                self.list("(", ")", expr.span, args, |e| e.span, Self::expr);
+            }
+            Expr::StructInit { name, fields } => {
+                self.write(&name.node);
+                self.write(" ");
+                self.list(
+                    "{",
+                    "}",
+                    expr.span,
+                    fields,
+                    field_init_span,
+                    Self::field_init,
+                );
+            }
+            Expr::AnonStructInit { fields } => self.list(
+                "{",
+                "}",
+                expr.span,
+                fields,
+                field_init_span,
+                Self::field_init,
+            ),
+            Expr::EnumVariant {
+                enum_name,
+                variant,
+                data,
+            } => {
+                self.write(&enum_name.node);
+                self.write("::");
+                self.write(&variant.node);
+                match data {
+                    VariantData::Unit => {}
+                    VariantData::Tuple(args) => {
+                        self.list("(", ")", expr.span, args, |e| e.span, Self::expr)
+                    }
+                    VariantData::Struct(fields) => {
+                        self.write(" ");
+                        self.list(
+                            "{",
+                            "}",
+                            expr.span,
+                            fields,
+                            field_init_span,
+                            Self::field_init,
+                        );
+                    }
+                }
+            }
+            Expr::SliceLen(object) => {
+                self.expr(object);
+                self.write(".len");
+            }
+            Expr::U16Low(object) => {
+                self.expr(object);
+                self.write(".low");
+            }
+            Expr::U16High(object) => {
+                self.expr(object);
+                self.write(".high");
+            }
+            Expr::CpuFlagCarry => self.write("carry"),
+            Expr::CpuFlagZero => self.write("zero"),
+            Expr::CpuFlagOverflow => self.write("overflow"),
+            Expr::CpuFlagNegative => self.write("negative"),
+            Expr::Paren(inner) => {
+                // A match arm's `{ expr }` body is parsed as a parenthesized
+                // expression
+                if self.source.as_bytes()[expr.span.start] == b'{' {
+                    self.write("{ ");
+                    self.expr(inner);
+                    self.write(" }");
+                } else {
+                    self.write("(");
+                    self.expr(inner);
+                    self.write(")");
+                }
+            }
+            Expr::Match {
+                expr: scrutinee,
+                arms,
+            } => {
+                self.write("match ");
+                self.expr(scrutinee);
+                self.write(" {");
+                self.end_line(scrutinee.span.end);
+                self.indent += 1;
+                for arm in arms {
+                    self.expr_arm(arm);
+                }
+                self.close(expr.span.end - 1, "}");
+            }
+        }
+    }
+
+    fn expr_arm(&mut self, arm: &ExprMatchArm) {
+        self.begin_line(arm.pattern.span.start);
+        self.pattern(&arm.pattern);
+        self.write(" => ");
+        self.expr(&arm.body);
+        self.write(",");
+        self.end_line(arm.body.span.end);
+    }
+
+    fn field_init(&mut self, field: &FieldInit) {
+        self.write(&field.name.node);
+        self.write(": ");
+        self.expr(&field.value);
+    }
+
+    /// A comma-separated list spanning `span`, which ends at `close`
+    ///
+    /// The list stays on one line unless it spans several in the source; then
+    /// each source line of items becomes one line, so tables keep their
+    /// rows and the comments beside them.
+    fn list<T>(
+        &mut self,
+        open: &str,
+        close: &str,
+        span: Span,
+        items: &[T],
+        item_span: fn(&T) -> Span,
+        write_item: fn(&mut Self, &T),
+    ) {
+        let braces = open == "{";
+        let Some(first) = items.first() else {
+            self.write(open);
+            self.write(close);
+            return;
+        };
+        if !self.text(span).contains('\n') {
+            self.write(open);
+            if braces {
+                self.write(" ");
+            }
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    self.write(", ");
+                }
+                write_item(self, item);
+            }
+            if braces {
+                self.write(" ");
+            }
+            self.write(close);
+            return;
+        }
+
+        self.write(open);
+        let open_token = self.token_index(item_span(first).start).saturating_sub(1);
+        self.end_line(self.tokens[open_token].span.end);
+        self.indent += 1;
+        let mut previous_end: Option<usize> = None;
+        for item in items {
+            let item_span = item_span(item);
+            match previous_end {
+                Some(end) if !self.source[end..item_span.start].contains('\n') => {
+                    self.write(" ");
+                }
+                Some(end) => {
+                    self.end_line(end);
+                    self.begin_line(item_span.start);
+                }
+                None => self.begin_line(item_span.start),
+            }
+            write_item(self, item);
+            self.write(",");
+            previous_end = Some(item_span.end);
+        }
+        if let Some(end) = previous_end {
+            self.end_line(end);
+        }
+        self.close(span.end - 1, close);
+    }
+}
+
+fn field_init_span(field: &FieldInit) -> Span {
+    field.name.span.merge(field.value.span)
+}
+
+/// The expression of a match arm written without braces
+fn expression_arm(body: &Spanned<Stmt>) -> Option<&Spanned<Expr>> {
+    match &body.node {
+        Stmt::Block(stmts) if stmts.len() == 1 && stmts[0].span == body.span => {
+            match &stmts[0].node {
+                Stmt::Expr(expr) => Some(expr),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn binary_op(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "^",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::Le => "<=",
+        BinaryOp::Ge => ">=",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+    }
+}
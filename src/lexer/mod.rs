@@ -7,6 +7,7 @@ use logos::Logos;
 /// Tokens for the Wraith language
 #[derive(Logos, Debug, Clone, PartialEq)]
 #[logos(skip r"[ \t\r\n\f]+")]
+#[logos(extras = Vec<std::ops::Range<usize>>)]
 pub enum Token {
     // === Keywords ===
     #[token("fn")]
@@ -213,12 +214,17 @@ pub enum Token {
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
     Ident(String),
 
-    // === Comments (skipped) ===
-    #[regex(r"//[^\n]*?", logos::skip)]
-    #[regex(r"/\*([^*]|\*[^/])*\*/", logos::skip)]
+    // === Comments (skipped, with their spans kept as trivia) ===
+    #[regex(r"//[^\n]*?", comment)]
+    #[regex(r"/\*([^*]|\*[^/])*\*/", comment)]
     Comment,
 }
 
+fn comment(lex: &mut logos::Lexer<Token>) -> logos::Skip {
+    lex.extras.push(lex.span());
+    logos::Skip
+}
+
 fn parse_hex(s: &str) -> Option<i64> {
     i64::from_str_radix(&s[2..], 16).ok()
 }
@@ -264,6 +270,14 @@ pub struct SpannedToken {
 
 /// Lex source code into tokens
 pub fn lex(source: &str) -> Result<Vec<SpannedToken>, LexError> {
+    lex_with_comments(source).map(|(tokens, _)| tokens)
+}
+
+/// Lex source code into tokens and the spans of the comments between them,
+/// including `///` doc comments, in source order
+pub fn lex_with_comments(
+    source: &str,
+) -> Result<(Vec<SpannedToken>, Vec<std::ops::Range<usize>>), LexError> {
     let mut lexer = Token::lexer(source);
     // Pre-allocate tokens vector: assume avg token length of 4 chars
     let mut tokens = Vec::with_capacity(source.len() / 4);
@@ -285,7 +299,7 @@ pub fn lex(source: &str) -> Result<Vec<SpannedToken>, LexError> {
         }
     }
 
-    Ok(tokens)
+    Ok((tokens, lexer.extras))
 }
 
 /// An error that occurred during lexing
@@ -375,6 +389,15 @@ mod tests {
         assert_eq!(tokens[2].token, Token::Ident("baz".to_string()));
     }
 
+    #[test]
+    fn test_comments_kept_as_trivia() {
+        let source = "/// doc\nfoo // line\nbar /* block */ baz";
+        let (tokens, comments) = lex_with_comments(source).unwrap();
+        assert_eq!(tokens.len(), 3);
+        let comments: Vec<&str> = comments.into_iter().map(|span| &source[span]).collect();
+        assert_eq!(comments, ["/// doc", "// line", "/* block */"]);
+    }
+
     #[test]
     fn test_function_signature() {
         let tokens = lex("fn add(a: u8, b: u8) -> u8").unwrap();
@@ -5,12 +5,21 @@
 
 #![warn(clippy::all)]
 
+pub mod assembler;
 pub mod ast;
 pub mod codegen;
 pub mod config;
+pub mod debuginfo;
+pub mod diagnostic;
+pub mod formatter;
 pub mod lexer;
 pub mod parser;
+pub mod profile;
 pub mod sema;
+pub mod sim;
+pub mod stack;
+pub mod testing;
+pub mod wcet;
 
 // Re-export commonly used types
 pub use ast::{SourceFile, Span, Spanned};
@@ -1,8 +1,10 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use wraith::{Parser, codegen, lex};
+use wraith::ast::{FileId, SourceMap, Span};
+use wraith::diagnostic::Diagnostic;
+use wraith::{Parser, SourceFile, codegen, lex};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -12,14 +14,156 @@ const RED: &str = "\x1b[91m";
 const YELLOW: &str = "\x1b[93m";
 const RESET: &str = "\x1b[0m";
 
+/// Exit status for errors: bad arguments, a failed compile, a failed run
+const EXIT_ERROR: i32 = 1;
+/// Exit status when `--deny-warnings` fails a compile that only had warnings
+const EXIT_WARNINGS: i32 = 2;
+/// Exit status when a `#[test]` function fails
+const EXIT_TEST_FAILED: i32 = 101;
+
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
 
-    // Parse arguments
+    match args.get(1).map(String::as_str) {
+        Some("build") => build(&args, 2),
+        Some("check") => check(&args),
+        Some("test") => run_tests(&args),
+        Some("run") => run_program(&args),
+        Some("profile") => run_profile(&args),
+        Some("fmt") => format_files(&args),
+        // `wraith <input.wr>` is `wraith build <input.wr>`
+        _ => build(&args, 1),
+    }
+}
+
+/// Options every subcommand accepts
+#[derive(Default)]
+struct CommonOptions {
+    /// The `.wr` file to compile
+    input: Option<String>,
+    /// `--config FILE`, read instead of `wraith.toml` in the working directory
+    config: Option<String>,
+    /// `-D NAME=VALUE` constants
+    defines: Vec<(String, i64)>,
+    cpu: Option<wraith::assembler::Cpu>,
+    peephole: codegen::peephole::PeepholeOptions,
+    /// Fail with `EXIT_WARNINGS` instead of compiling a program with warnings
+    deny_warnings: bool,
+    /// `--message-format=json`: diagnostics as one JSON object per line
+    json: bool,
+}
+
+impl CommonOptions {
+    /// Take the option at `args[*i]` if it is one of these, moving past it
+    fn parse(&mut self, args: &[String], i: &mut usize) -> bool {
+        match args[*i].as_str() {
+            "--cmos" => self.cpu = Some(wraith::assembler::Cpu::Cmos65C02),
+            "--undocumented" => self.cpu = Some(wraith::assembler::Cpu::Nmos6502Undocumented),
+            "--cpu" => {
+                self.cpu = Some(parse_cpu(option_value(args, *i)));
+                *i += 1;
+            }
+            "--disable-pass" => {
+                self.peephole
+                    .disabled
+                    .push(parse_pass(option_value(args, *i)));
+                *i += 1;
+            }
+            "--config" => {
+                self.config = Some(option_value(args, *i).to_string());
+                *i += 1;
+            }
+            "-D" => {
+                self.defines.push(parse_define(option_value(args, *i)));
+                *i += 1;
+            }
+            "--deny-warnings" => self.deny_warnings = true,
+            "--message-format" => {
+                self.json = parse_message_format(option_value(args, *i));
+                *i += 1;
+            }
+            arg if arg.starts_with("--message-format=") => {
+                self.json = parse_message_format(&arg["--message-format=".len()..]);
+            }
+            arg if arg.starts_with("-D") => self.defines.push(parse_define(&arg[2..])),
+            arg if arg.starts_with("-O") => self.peephole.level = parse_opt_level(arg),
+            arg if !arg.starts_with('-') => {
+                if self.input.is_some() {
+                    eprintln!("{}Error:{} multiple input files not supported", RED, RESET);
+                    std::process::exit(EXIT_ERROR);
+                }
+                self.input = Some(arg.to_string());
+            }
+            _ => return false,
+        }
+        *i += 1;
+        true
+    }
+
+    /// Print a compiler diagnostic on stderr, as `text` or as JSON
+    fn report(
+        &self,
+        diagnostic: wraith::diagnostic::Diagnostic,
+        text: String,
+        sources: &SourceMap,
+    ) {
+        if self.json {
+            eprintln!("{}", diagnostic.to_json_with_sources(sources));
+        } else {
+            eprintln!("{}", text);
+        }
+    }
+
+    /// The processor to compile for: `--cpu`, or else the configured target's
+    fn cpu(&self, program_info: &wraith::sema::ProgramInfo) -> wraith::assembler::Cpu {
+        self.cpu.unwrap_or_else(|| target_cpu(program_info))
+    }
+}
+
+/// `--message-format human|json`, true for JSON
+fn parse_message_format(format: &str) -> bool {
+    match format {
+        "human" => false,
+        "json" => true,
+        _ => {
+            eprintln!(
+                "{}Error:{} unknown message format: {} (expected human or json)",
+                RED, RESET, format
+            );
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// The argument after the option at `args[i]`, or exit if there is none
+fn option_value(args: &[String], i: usize) -> &str {
+    match args.get(i + 1) {
+        Some(value) => value,
+        None => {
+            eprintln!("{}Error:{} {} requires an argument", RED, RESET, args[i]);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// `wraith build <input.wr>`: compile to assembly, plus any requested images,
+/// listings, maps and symbols
+fn build(args: &[String], first: usize) {
+    let mut common = CommonOptions::default();
     let mut verbosity = codegen::CommentVerbosity::Normal;
-    let mut input_file: Option<String> = None;
+    let mut output_file: Option<String> = None;
+    let mut image_format: Option<wraith::assembler::image::ImageFormat> = None;
+    let mut write_listing = false;
+    let mut write_source_map = false;
+    let mut source_markers = false;
+    let mut write_timing = false;
+    let mut write_stack = false;
+    let mut map_file: Option<String> = None;
+    let mut symbol_formats: Vec<wraith::debuginfo::SymbolFormat> = Vec::new();
+    let mut output_mode = codegen::OutputMode::Absolute;
+    let mut pass_stats = false;
 
-    let mut i = 1;
+    let mut i = first;
     while i < args.len() {
         match args[i].as_str() {
             "--version" | "-v" => {
@@ -30,6 +174,84 @@ fn main() {
                 print_usage(&args[0]);
                 return;
             }
+            "-o" => {
+                output_file = Some(option_value(args, i).to_string());
+                i += 2;
+            }
+            "--bin" | "-b" => {
+                image_format = Some(wraith::assembler::image::ImageFormat::Raw);
+                i += 1;
+            }
+            "--listing" | "-l" => {
+                write_listing = true;
+                i += 1;
+            }
+            "--source-map" => {
+                write_source_map = true;
+                i += 1;
+            }
+            "--source-markers" => {
+                source_markers = true;
+                i += 1;
+            }
+            "--timing" => {
+                write_timing = true;
+                i += 1;
+            }
+            "--stack" => {
+                write_stack = true;
+                i += 1;
+            }
+            "--map" => {
+                if i + 1 < args.len() {
+                    map_file = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("{}Error:{} --map requires an output file", RED, RESET);
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+            "--format" | "-f" => {
+                if i + 1 < args.len() {
+                    match wraith::assembler::image::ImageFormat::from_name(&args[i + 1]) {
+                        Some(format) => image_format = Some(format),
+                        None => {
+                            eprintln!(
+                                "{}Error:{} unknown output format: {}",
+                                RED, RESET, args[i + 1]
+                            );
+                            eprintln!("       valid options: raw, ihex, srec, prg");
+                            std::process::exit(EXIT_ERROR);
+                        }
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("{}Error:{} --format requires an argument", RED, RESET);
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+            "--symbols" | "-s" => {
+                if i + 1 < args.len() {
+                    for name in args[i + 1].split(',') {
+                        match wraith::debuginfo::SymbolFormat::from_name(name) {
+                            Some(format) => symbol_formats.push(format),
+                            None => {
+                                eprintln!("{}Error:{} unknown symbol format: {}", RED, RESET, name);
+                                eprintln!("       valid options: vice, mesen, dbg");
+                                std::process::exit(EXIT_ERROR);
+                            }
+                        }
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("{}Error:{} --symbols requires an argument", RED, RESET);
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+            "--ca65" => {
+                output_mode = codegen::OutputMode::Ca65;
+                i += 1;
+            }
             "--comments" | "-c" => {
                 if i + 1 < args.len() {
                     verbosity = match args[i + 1].as_str() {
@@ -39,57 +261,778 @@ fn main() {
                         other => {
                             eprintln!("{}Error:{} unknown verbosity level: {}", RED, RESET, other);
                             eprintln!("       valid options: minimal, normal, verbose");
-                            std::process::exit(1);
+                            std::process::exit(EXIT_ERROR);
                         }
                     };
                     i += 2;
                 } else {
                     eprintln!("{}Error:{} --comments requires an argument", RED, RESET);
-                    std::process::exit(1);
+                    std::process::exit(EXIT_ERROR);
                 }
             }
-            arg if !arg.starts_with('-') => {
-                if input_file.is_some() {
-                    eprintln!("{}Error:{} multiple input files not supported", RED, RESET);
-                    std::process::exit(1);
-                }
-                input_file = Some(arg.to_string());
+            "--pass-stats" => {
+                pass_stats = true;
                 i += 1;
             }
+            _ if common.parse(args, &mut i) => {}
             unknown => {
                 eprintln!("{}Error:{} unknown option: {}", RED, RESET, unknown);
                 print_usage(&args[0]);
-                std::process::exit(1);
+                std::process::exit(EXIT_ERROR);
             }
         }
     }
 
-    let file = match input_file {
-        Some(f) => f,
-        None => {
-            print_usage(&args[0]);
-            std::process::exit(1);
+    let Some(file) = common.input.clone() else {
+        print_usage(&args[0]);
+        std::process::exit(EXIT_ERROR);
+    };
+    let needs_assembly = image_format.is_some()
+        || write_listing
+        || write_source_map
+        || write_timing
+        || map_file.is_some()
+        || !symbol_formats.is_empty();
+    if needs_assembly && output_mode == codegen::OutputMode::Ca65 {
+        let flag = if image_format.is_some() {
+            "--bin/--format"
+        } else if write_listing {
+            "--listing"
+        } else if write_source_map {
+            "--source-map"
+        } else if write_timing {
+            "--timing"
+        } else if map_file.is_some() {
+            "--map"
+        } else {
+            "--symbols"
+        };
+        eprintln!(
+            "{}Error:{} {} cannot be combined with --ca65 (addresses are assigned by ld65)",
+            RED, RESET, flag
+        );
+        std::process::exit(EXIT_ERROR);
+    }
+    let out_file = output_file
+        .clone()
+        .unwrap_or_else(|| artifact_path(&file, "asm"));
+    // The text map would overwrite a JSON map given the same name
+    if let Some(json_file) = &map_file
+        && same_path(json_file, &artifact_path(&out_file, "map"))
+    {
+        eprintln!(
+            "{}Error:{} --map {} is where the text memory map goes; name the JSON map differently (e.g. {})",
+            RED,
+            RESET,
+            json_file,
+            artifact_path(json_file, "json")
+        );
+        std::process::exit(EXIT_ERROR);
+    }
+    let start_time = Instant::now();
+
+    let (sources, ast, program_info) = analyze_file(&file, &common);
+    let cpu = common.cpu(&program_info);
+
+    // Unbounded recursion or a stack overflow fails the build
+    let stack = analyze_stack(&ast, &program_info, cpu, &common, &sources);
+
+    // Code generation
+    let options = codegen::CodegenOptions {
+        verbosity,
+        output_mode,
+        cpu,
+        peephole: common.peephole.clone(),
+        pass_stats,
+        ..Default::default()
+    };
+    let mut output = generate(&ast, &program_info, &options, &common, &sources);
+    if source_markers {
+        codegen::source_map::insert_markers(&mut output, &sources);
+    }
+
+    // #[max_cycles] budgets are checked on the assembled code
+    let timed_functions = wraith::wcet::timed_functions(&ast, &program_info);
+    let has_budgets = timed_functions.iter().any(|func| func.budget.is_some());
+    if has_budgets && output_mode == codegen::OutputMode::Ca65 {
+        let message = "#[max_cycles] budgets are not checked with --ca65".to_string();
+        let text = format!("{}Warning:{} {}", YELLOW, RESET, message);
+        common.report(
+            Diagnostic::warning("unchecked-budget", message),
+            text,
+            &sources,
+        );
+        if common.deny_warnings {
+            std::process::exit(EXIT_WARNINGS);
+        }
+    }
+
+    // Write output; everything else is named after the assembly file
+    let artifact = |extension: &str| artifact_path(&out_file, extension);
+    if let Some(parent) = std::path::Path::new(&out_file).parent()
+        && let Err(e) = fs::create_dir_all(parent)
+    {
+        eprintln!("error: could not create {}: {}", parent.display(), e);
+        std::process::exit(EXIT_ERROR);
+    }
+    if let Err(e) = fs::write(&out_file, &output.asm) {
+        eprintln!("error: could not write to {}: {}", out_file, e);
+        std::process::exit(EXIT_ERROR);
+    }
+
+    // Write the ld65 configuration for segment output
+    let cfg_file = output.linker_config.as_ref().map(|cfg| {
+        let cfg_file = artifact("cfg");
+        if let Err(e) = fs::write(&cfg_file, cfg) {
+            eprintln!("error: could not write to {}: {}", cfg_file, e);
+            std::process::exit(EXIT_ERROR);
+        }
+        cfg_file
+    });
+
+    // Assemble with the built-in assembler (for the ROM image, listing and symbols)
+    let assembly = if needs_assembly || (has_budgets && output_mode == codegen::OutputMode::Absolute) {
+        match wraith::assembler::assemble(&output.asm) {
+            Ok(assembly) => Some(assembly),
+            Err(e) => {
+                eprintln!("{}Error:{} {}: {}", RED, RESET, out_file, e);
+                std::process::exit(EXIT_ERROR);
+            }
+        }
+    } else {
+        None
+    };
+
+    // Worst-case cycle counts; a function over its budget fails the build
+    let mut timing = Vec::new();
+    if let Some(assembly) = &assembly
+        && (write_timing || has_budgets)
+    {
+        let mut analyzer = wraith::wcet::WcetAnalyzer::new(&output, assembly, &sources);
+        let failures = analyzer.check_budgets(&timed_functions);
+        if !failures.is_empty() {
+            for failure in &failures {
+                let text = error_text(failure, failure.span(), &sources);
+                common.report(Diagnostic::from_wcet_error(failure), text, &sources);
+            }
+            std::process::exit(EXIT_ERROR);
+        }
+        if write_timing {
+            for func in &timed_functions {
+                timing.push((func, analyzer.function(&func.name)));
+            }
+        }
+    }
+
+    // Write the ROM image
+    let binary = match (&assembly, image_format) {
+        (Some(assembly), Some(format)) => {
+            let bin_file = artifact(format.extension());
+            let sections = &output.section_alloc.config().sections;
+            let name = std::path::Path::new(&file)
+                .file_stem()
+                .map_or(file.clone(), |stem| stem.to_string_lossy().into_owned());
+            if let Err(e) = fs::write(&bin_file, format.write(assembly, sections, &name)) {
+                eprintln!("error: could not write to {}: {}", bin_file, e);
+                std::process::exit(EXIT_ERROR);
+            }
+            Some((bin_file, assembly))
+        }
+        _ => None,
+    };
+
+    // Write the annotated listing
+    let lst_file = match &assembly {
+        Some(assembly) if write_listing => {
+            let lst_file = artifact("lst");
+            let listing = codegen::listing::generate_listing(&output, assembly, &sources);
+            if let Err(e) = fs::write(&lst_file, listing) {
+                eprintln!("error: could not write to {}: {}", lst_file, e);
+                std::process::exit(EXIT_ERROR);
+            }
+            Some(lst_file)
+        }
+        _ => None,
+    };
+
+    // Write the source line table
+    let srcmap_file = match &assembly {
+        Some(assembly) if write_source_map => {
+            let srcmap_file = artifact("srcmap");
+            let table = codegen::source_map::line_table(&output, assembly, &sources);
+            let contents = codegen::source_map::write_line_table(&table, &sources);
+            if let Err(e) = fs::write(&srcmap_file, contents) {
+                eprintln!("error: could not write to {}: {}", srcmap_file, e);
+                std::process::exit(EXIT_ERROR);
+            }
+            Some(srcmap_file)
+        }
+        _ => None,
+    };
+
+    // Write the memory map as JSON, plus a text version next to the input
+    let map_files = match (&assembly, &map_file) {
+        (Some(assembly), Some(json_file)) => {
+            let map = codegen::memory_map::build_memory_map(&program_info, &output, assembly);
+            let text_file = artifact("map");
+            for (path, contents) in [
+                (json_file, codegen::memory_map::write_json(&map)),
+                (&text_file, codegen::memory_map::write_text(&map, &file)),
+            ] {
+                if let Err(e) = fs::write(path, contents) {
+                    eprintln!("error: could not write to {}: {}", path, e);
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+            Some((json_file.clone(), text_file))
+        }
+        _ => None,
+    };
+
+    // Write debug symbol files
+    let mut symbol_files = Vec::new();
+    if let Some(assembly) = &assembly
+        && !symbol_formats.is_empty()
+    {
+        let symbols = wraith::debuginfo::collect_symbols(&program_info, &output, assembly);
+        for format in &symbol_formats {
+            let sym_file = artifact(format.extension());
+            if let Err(e) = fs::write(&sym_file, format.write(&symbols, &output, &file)) {
+                eprintln!("error: could not write to {}: {}", sym_file, e);
+                std::process::exit(EXIT_ERROR);
+            }
+            symbol_files.push(sym_file);
+        }
+    }
+
+    let elapsed = start_time.elapsed();
+    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+
+    println!(
+        "{}{:>12}{} {} in {:.2}ms",
+        GREEN, "Finished", RESET, out_file, elapsed_ms
+    );
+
+    if let Some(cfg_file) = &cfg_file {
+        println!("{}{:>12}{} {}", GREEN, "Linker cfg", RESET, cfg_file);
+    }
+
+    if let Some(lst_file) = &lst_file {
+        println!("{}{:>12}{} {}", GREEN, "Listing", RESET, lst_file);
+    }
+
+    if let Some(srcmap_file) = &srcmap_file {
+        println!("{}{:>12}{} {}", GREEN, "Source map", RESET, srcmap_file);
+    }
+
+    if let Some((json_file, text_file)) = &map_files {
+        println!("{}{:>12}{} {}, {}", GREEN, "Memory map", RESET, json_file, text_file);
+    }
+
+    for sym_file in &symbol_files {
+        println!("{}{:>12}{} {}", GREEN, "Symbols", RESET, sym_file);
+    }
+
+    if let Some((bin_file, assembly)) = &binary
+        && let (Some(start), Some(end)) = (assembly.start_address(), assembly.end_address())
+    {
+        println!(
+            "{}{:>12}{} {} (${:04X}-${:04X}, {} bytes)",
+            GREEN,
+            "Assembled",
+            RESET,
+            bin_file,
+            start,
+            end,
+            end as usize - start as usize + 1
+        );
+    }
+
+    for (func, result) in &timing {
+        let report = match (result, func.budget) {
+            (Ok(cycles), Some(budget)) => format!("{} cycles (budget {})", cycles, budget),
+            (Ok(cycles), None) => format!("{} cycles", cycles),
+            (Err(e), _) => format!("unknown: {}", e),
+        };
+        println!("{}{:>12}{} {}: {}", YELLOW, "Timing", RESET, func.name, report);
+    }
+
+    if write_stack {
+        for usage in &stack.entries {
+            println!(
+                "{}{:>12}{} {} {}: {} bytes ({})",
+                YELLOW,
+                "Stack",
+                RESET,
+                usage.entry.name(),
+                usage.function,
+                usage.depth,
+                usage.path.join(" -> ")
+            );
+        }
+        println!(
+            "{}{:>12}{} worst case {}/{} bytes",
+            YELLOW,
+            "Stack",
+            RESET,
+            stack.worst_case(),
+            wraith::stack::STACK_SIZE
+        );
+    }
+
+    if pass_stats {
+        for stat in &output.pass_stats {
+            println!(
+                "{}{:>12}{} {}: {} instructions, {} bytes",
+                YELLOW, "Peephole", RESET, stat.name, stat.instructions, stat.bytes
+            );
+        }
+        let instructions: i64 = output.pass_stats.iter().map(|stat| stat.instructions).sum();
+        let bytes: i64 = output.pass_stats.iter().map(|stat| stat.bytes).sum();
+        println!(
+            "{}{:>12}{} total: {} instructions, {} bytes",
+            YELLOW, "Peephole", RESET, instructions, bytes
+        );
+    }
+
+    // Print section statistics
+    let stats = output.section_alloc.get_statistics();
+    for stat in stats {
+        if stat.used > 0 {
+            println!(
+                "{}{:>12}{} {}",
+                YELLOW,
+                stat.name,
+                RESET,
+                stat.format_compact()
+            );
+        }
+    }
+}
+
+/// `wraith fmt [--check] <input.wr|dir>...`: rewrite source files in the
+/// standard layout
+///
+/// Directories are searched for `.wr` files. With `--check` nothing is
+/// written; unformatted files are listed and the exit status is 1.
+fn format_files(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: {} fmt [--check] <input.wr|dir>...", args[0]);
+        std::process::exit(EXIT_ERROR);
+    };
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "--check" => check = true,
+            arg if !arg.starts_with('-') => collect_sources(Path::new(arg), &mut files),
+            _ => usage(),
+        }
+    }
+    if files.is_empty() {
+        usage();
+    }
+
+    let mut failed = false;
+    for file in &files {
+        let name = file.display().to_string();
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{}Error:{} {}: {}", RED, RESET, name, e);
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match wraith::formatter::format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(wraith::formatter::FormatError::Lex(e)) => {
+                eprintln!(
+                    "{}Error:{} {}: Lexical analysis failed\n{:?}",
+                    RED, RESET, name, e
+                );
+                failed = true;
+                continue;
+            }
+            Err(wraith::formatter::FormatError::Parse(e)) => {
+                eprintln!("{}", e.format_with_source_and_file(&source, Some(&name)));
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            let line = source
+                .lines()
+                .zip(formatted.lines())
+                .take_while(|(old, new)| old == new)
+                .count()
+                + 1;
+            eprintln!(
+                "{}Error:{} {} is not formatted (from line {})",
+                RED, RESET, name, line
+            );
+            failed = true;
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("{}Error:{} {}: {}", RED, RESET, name, e);
+            failed = true;
+        } else {
+            println!("{}{:>12}{} {}", GREEN, "Formatted", RESET, name);
         }
+    }
+    if failed {
+        std::process::exit(EXIT_ERROR);
+    }
+}
+
+/// `path` if it is a file, or the `.wr` files under it if it is a directory
+fn collect_sources(path: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(path) else {
+        files.push(path.to_path_buf());
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_sources(&entry, files);
+        } else if entry.extension().is_some_and(|extension| extension == "wr") {
+            files.push(entry);
+        }
+    }
+}
+
+/// `wraith test <input.wr>`: build a test image and run every `#[test]` function
+fn run_tests(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: {} test <input.wr> [COMMON OPTIONS]", args[0]);
+        std::process::exit(EXIT_ERROR);
+    };
+    let mut common = CommonOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        if !common.parse(args, &mut i) {
+            usage();
+        }
+    }
+    let Some(file) = common.input.as_deref() else {
+        usage()
     };
     let start_time = Instant::now();
+    let (sources, ast, program_info) = analyze_file(file, &common);
+
+    let options = codegen::CodegenOptions {
+        include_tests: true,
+        cpu: common.cpu(&program_info),
+        peephole: common.peephole.clone(),
+        ..Default::default()
+    };
+    let output = generate(&ast, &program_info, &options, &common, &sources);
+    let assembly = match wraith::assembler::assemble(&output.asm) {
+        Ok(assembly) => assembly,
+        Err(e) => {
+            eprintln!("{}Error:{} test image: {}", RED, RESET, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+
+    let tests = wraith::testing::discover_tests(&ast, &program_info);
+    let runner = wraith::testing::TestRunner::new(&output, &assembly, &sources);
+    println!(
+        "{}{:>12}{} test image in {:.2}ms",
+        GREEN,
+        "Finished",
+        RESET,
+        start_time.elapsed().as_secs_f64() * 1000.0
+    );
 
+    println!();
+    println!(
+        "running {} test{}",
+        tests.len(),
+        if tests.len() == 1 { "" } else { "s" }
+    );
+    let run_start = Instant::now();
+    let mut results = Vec::with_capacity(tests.len());
+    for test in &tests {
+        let result = runner.run(test);
+        let status = if result.passed() {
+            format!("{}ok{}", GREEN, RESET)
+        } else {
+            format!("{}FAILED{}", RED, RESET)
+        };
+        let origin = if test.imported { " (imported)" } else { "" };
+        println!(
+            "test {}{} ... {} ({} cycles)",
+            result.name, origin, status, result.cycles
+        );
+        results.push(result);
+    }
+
+    let failures: Vec<_> = results
+        .iter()
+        .filter_map(|result| match &result.outcome {
+            wraith::testing::TestOutcome::Failed(reason) => Some((&result.name, reason)),
+            wraith::testing::TestOutcome::Passed => None,
+        })
+        .collect();
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (name, reason) in &failures {
+            println!("    {}: {}", name, reason);
+        }
+    }
+
+    let passed = results.len() - failures.len();
+    let total_cycles: u64 = results.iter().map(|result| result.cycles).sum();
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed; {} cycles; finished in {:.2}s",
+        if failures.is_empty() {
+            format!("{}ok{}", GREEN, RESET)
+        } else {
+            format!("{}FAILED{}", RED, RESET)
+        },
+        passed,
+        failures.len(),
+        total_cycles,
+        run_start.elapsed().as_secs_f64()
+    );
+
+    if !failures.is_empty() {
+        std::process::exit(EXIT_TEST_FAILED);
+    }
+}
+
+fn run_profile(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!(
+            "Usage: {} profile <input.wr> [--function NAME] [--cycles N] [--lines N] [COMMON OPTIONS]",
+            args[0]
+        );
+        std::process::exit(EXIT_ERROR);
+    };
+    let mut common = CommonOptions::default();
+    let mut entry = wraith::profile::ProfileEntry::Reset;
+    let mut max_cycles = wraith::sim::DEFAULT_CYCLE_LIMIT;
+    let mut max_lines = 10;
+
+    let mut i = 2;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1)) {
+            ("--function", Some(name)) => {
+                entry = wraith::profile::ProfileEntry::Function(name.clone());
+                i += 2;
+            }
+            ("--cycles", Some(n)) => {
+                max_cycles = n.parse().unwrap_or_else(|_| usage());
+                i += 2;
+            }
+            ("--lines", Some(n)) => {
+                max_lines = n.parse().unwrap_or_else(|_| usage());
+                i += 2;
+            }
+            _ if common.parse(args, &mut i) => {}
+            _ => usage(),
+        }
+    }
+    let Some(file) = common.input.as_deref() else {
+        usage()
+    };
+
+    let start_time = Instant::now();
+    let (sources, ast, program_info) = analyze_file(file, &common);
+    let options = codegen::CodegenOptions {
+        cpu: common.cpu(&program_info),
+        peephole: common.peephole.clone(),
+        ..Default::default()
+    };
+    let output = generate(&ast, &program_info, &options, &common, &sources);
+    let assembly = match wraith::assembler::assemble(&output.asm) {
+        Ok(assembly) => assembly,
+        Err(e) => {
+            eprintln!("{}Error:{} {}", RED, RESET, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    println!(
+        "{}{:>12}{} in {:.2}ms",
+        GREEN,
+        "Finished",
+        RESET,
+        start_time.elapsed().as_secs_f64() * 1000.0
+    );
+
+    let mut profiler = wraith::profile::Profiler::new(&output, &assembly, &sources);
+    profiler.max_cycles = max_cycles;
+    let profile = match profiler.run(&entry) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("{}Error:{} {}", RED, RESET, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    println!("{}{:>12}{} {}", YELLOW, "Profiling", RESET, file);
+    println!();
+    print!(
+        "{}",
+        wraith::profile::format_report(&profile, &sources, max_lines)
+    );
+}
+
+/// `wraith check <input.wr>`: analyze and generate code without writing anything
+fn check(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: {} check <input.wr> [COMMON OPTIONS]", args[0]);
+        std::process::exit(EXIT_ERROR);
+    };
+    let mut common = CommonOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        if !common.parse(args, &mut i) {
+            usage();
+        }
+    }
+    let Some(file) = common.input.as_deref() else {
+        usage()
+    };
+
+    let start_time = Instant::now();
+    let (sources, ast, program_info) = analyze_file(file, &common);
+    let cpu = common.cpu(&program_info);
+    analyze_stack(&ast, &program_info, cpu, &common, &sources);
+    let options = codegen::CodegenOptions {
+        cpu,
+        peephole: common.peephole.clone(),
+        ..Default::default()
+    };
+    generate(&ast, &program_info, &options, &common, &sources);
+    println!(
+        "{}{:>12}{} checking {} in {:.2}ms",
+        GREEN,
+        "Finished",
+        RESET,
+        file,
+        start_time.elapsed().as_secs_f64() * 1000.0
+    );
+}
+
+/// `wraith run <input.wr>`: build the program and run it in the simulator
+///
+/// Runs from reset, or calls `--function NAME`, until the program returns or
+/// halts in a `loop {}`. A `BRK` or running out of cycles is an error.
+fn run_program(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!(
+            "Usage: {} run <input.wr> [--function NAME] [--cycles N] [COMMON OPTIONS]",
+            args[0]
+        );
+        std::process::exit(EXIT_ERROR);
+    };
+    let mut common = CommonOptions::default();
+    let mut entry = wraith::profile::ProfileEntry::Reset;
+    let mut max_cycles = wraith::sim::DEFAULT_CYCLE_LIMIT;
+
+    let mut i = 2;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1)) {
+            ("--function", Some(name)) => {
+                entry = wraith::profile::ProfileEntry::Function(name.clone());
+                i += 2;
+            }
+            ("--cycles", Some(n)) => {
+                max_cycles = n.parse().unwrap_or_else(|_| usage());
+                i += 2;
+            }
+            _ if common.parse(args, &mut i) => {}
+            _ => usage(),
+        }
+    }
+    let Some(file) = common.input.as_deref() else {
+        usage()
+    };
+
+    let (sources, ast, program_info) = analyze_file(file, &common);
+    let options = codegen::CodegenOptions {
+        cpu: common.cpu(&program_info),
+        peephole: common.peephole.clone(),
+        ..Default::default()
+    };
+    let output = generate(&ast, &program_info, &options, &common, &sources);
+    let assembly = match wraith::assembler::assemble(&output.asm) {
+        Ok(assembly) => assembly,
+        Err(e) => {
+            eprintln!("{}Error:{} {}", RED, RESET, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+
+    let mut sim = wraith::sim::Simulator::with_assembly(&assembly);
+    let result = match &entry {
+        wraith::profile::ProfileEntry::Reset => sim.run_from_reset(max_cycles),
+        wraith::profile::ProfileEntry::Function(name) => sim.call(name, max_cycles),
+    };
+    let (stopped, failed) = match result {
+        Ok(wraith::sim::StopReason::Returned) => ("returned".to_string(), false),
+        Ok(wraith::sim::StopReason::Halted { pc }) => (format!("halted at ${:04X}", pc), false),
+        Ok(wraith::sim::StopReason::Break { pc }) => (format!("BRK at ${:04X}", pc), true),
+        Ok(wraith::sim::StopReason::CycleLimit) => {
+            (format!("ran out of cycles after {}", max_cycles), true)
+        }
+        Err(e) => {
+            eprintln!("{}Error:{} {}", RED, RESET, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    let registers = &sim.registers;
+    println!(
+        "{}{:>12}{} {} {} after {} cycles (A=${:02X} X=${:02X} Y=${:02X} SP=${:02X})",
+        if failed { RED } else { GREEN },
+        "Stopped",
+        RESET,
+        file,
+        stopped,
+        sim.cycles,
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.sp
+    );
+    if failed {
+        std::process::exit(EXIT_ERROR);
+    }
+}
+
+/// Read, parse and analyze a source file, printing progress and diagnostics
+///
+/// Exits the process on any error, and with `--deny-warnings` if there are
+/// any warnings.
+fn analyze_file(
+    file: &str,
+    common: &CommonOptions,
+) -> (SourceMap, SourceFile, wraith::sema::ProgramInfo) {
     // Read source file
     println!("{}{:>12}{} {}", YELLOW, "Compiling", RESET, file);
-    let source = match fs::read_to_string(&file) {
+    let source = match fs::read_to_string(file) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("{}Error:{} {}: {}", RED, RESET, file, e);
-            std::process::exit(1);
+            std::process::exit(EXIT_ERROR);
         }
     };
+    let mut sources = SourceMap::new(file, source.as_str());
 
     // Lex
     let tokens = match lex(&source) {
         Ok(tokens) => tokens,
         Err(e) => {
-            eprintln!("{}Error:{} Lexical analysis failed", RED, RESET);
-            eprintln!("{:?}", e);
-            std::process::exit(1);
+            let text = format!("{}Error:{} Lexical analysis failed\n{:?}", RED, RESET, e);
+            common.report(Diagnostic::from_lex_error(&e), text, &sources);
+            std::process::exit(EXIT_ERROR);
         }
     };
 
@@ -97,8 +1040,14 @@ fn main() {
     let ast = match Parser::parse(&tokens) {
         Ok(ast) => ast,
         Err(e) => {
-            eprintln!("{}", e.format_with_source_and_file(&source, Some(&file)));
-            std::process::exit(1);
+            if common.json {
+                for diagnostic in Diagnostic::from_parse_error(&e) {
+                    eprintln!("{}", diagnostic.to_json_with_sources(&sources));
+                }
+            } else {
+                eprintln!("{}", e.format_with_source_and_file(&source, Some(file)));
+            }
+            std::process::exit(EXIT_ERROR);
         }
     };
 
@@ -112,70 +1061,292 @@ fn main() {
         }
     }
 
+    // `--config`, or else wraith.toml in the working directory if there is one
+    let config_file = common
+        .config
+        .as_deref()
+        .or(std::path::Path::new("wraith.toml")
+            .exists()
+            .then_some("wraith.toml"));
+    let config = match config_file.map(wraith::config::Config::from_file) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            eprintln!(
+                "{}Error:{} {}: {}",
+                RED,
+                RESET,
+                config_file.unwrap_or_default(),
+                e
+            );
+            std::process::exit(EXIT_ERROR);
+        }
+        None => wraith::config::Config::default(),
+    };
+
     // Semantic analysis
-    let file_path = PathBuf::from(&file);
-    let program_info = match wraith::sema::analyze_with_path(&ast, file_path) {
+    let file_path = PathBuf::from(file);
+    let program_info = match wraith::sema::analyze_program(
+        &ast,
+        file_path,
+        wraith::config::MemoryConfig::from_config(config),
+        &common.defines,
+        common.cpu,
+        &mut sources,
+    ) {
         Ok(info) => info,
-        Err(e) => {
-            eprintln!("{}", e.format_with_source_and_file(&source, Some(&file)));
-            std::process::exit(1);
+        Err(diagnostics) => {
+            print_warnings(&diagnostics.warnings, &sources, common);
+            for error in &diagnostics.errors {
+                let text = format!("{}\n", error.format_with_sources(&sources));
+                let file = error.span().map_or(FileId::ROOT, |span| span.file);
+                common.report(
+                    Diagnostic::from_sema_error(error, sources.source(file)),
+                    text,
+                    &sources,
+                );
+            }
+            if !common.json {
+                eprintln!(
+                    "{}Error:{} could not compile {} due to {} error(s)",
+                    RED,
+                    RESET,
+                    file,
+                    diagnostics.errors.len()
+                );
+            }
+            std::process::exit(EXIT_ERROR);
         }
     };
 
-    // Display warnings
-    for warning in &program_info.warnings {
+    print_warnings(&program_info.warnings, &sources, common);
+    if common.deny_warnings && !program_info.warnings.is_empty() {
+        if common.json {
+            std::process::exit(EXIT_WARNINGS);
+        }
         eprintln!(
-            "{}",
-            warning.format_with_source_and_file(&source, Some(&file))
+            "{}Error:{} {} warning(s) denied by --deny-warnings",
+            RED,
+            RESET,
+            program_info.warnings.len()
         );
-        eprintln!(); // Add blank line between warnings
+        std::process::exit(EXIT_WARNINGS);
     }
 
-    // Code generation
-    let (code, section_alloc) = match codegen::generate(&ast, &program_info, verbosity) {
-        Ok(result) => result,
+    (sources, ast, program_info)
+}
+
+/// Print warnings on stderr, each followed by a blank line in text form
+fn print_warnings(warnings: &[wraith::sema::Warning], sources: &SourceMap, common: &CommonOptions) {
+    for warning in warnings {
+        let text = format!("{}\n", warning.format_with_sources(sources));
+        common.report(Diagnostic::from_warning(warning), text, sources);
+    }
+}
+
+/// Worst-case stack use, or report unbounded recursion or an overflow and exit
+fn analyze_stack(
+    ast: &SourceFile,
+    program_info: &wraith::sema::ProgramInfo,
+    cpu: wraith::assembler::Cpu,
+    common: &CommonOptions,
+    sources: &SourceMap,
+) -> wraith::stack::StackReport {
+    match wraith::stack::analyze(ast, program_info, cpu) {
+        Ok(report) => report,
         Err(e) => {
-            eprintln!("{}Error:{} {}", RED, RESET, e);
-            std::process::exit(1);
+            let text = error_text(&e, e.span(), sources);
+            common.report(Diagnostic::from_stack_error(&e), text, sources);
+            std::process::exit(EXIT_ERROR);
         }
-    };
+    }
+}
 
-    // Write output
-    let out_file = file.replace(".wr", ".asm");
-    if let Err(e) = fs::write(&out_file, &code) {
-        eprintln!("error: could not write to {}: {}", out_file, e);
-        std::process::exit(1);
+/// Generate code, or report the error and exit
+fn generate(
+    ast: &SourceFile,
+    program_info: &wraith::sema::ProgramInfo,
+    options: &codegen::CodegenOptions,
+    common: &CommonOptions,
+    sources: &SourceMap,
+) -> codegen::CodegenOutput {
+    match codegen::generate_with_options(ast, program_info, options) {
+        Ok(output) => output,
+        Err(e) => {
+            let text = error_text(&e, e.span(), sources);
+            common.report(Diagnostic::from_codegen_error(&e), text, sources);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// `Error: message` for text output, after the location if there is one
+fn error_text(error: &impl std::fmt::Display, span: Option<Span>, sources: &SourceMap) -> String {
+    match span {
+        Some(span) => format!(
+            "{}Error:{} {}: {}",
+            RED,
+            RESET,
+            sources.format_location(span),
+            error
+        ),
+        None => format!("{}Error:{} {}", RED, RESET, error),
     }
+}
 
-    let elapsed = start_time.elapsed();
-    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+/// `path` with its extension replaced
+fn artifact_path(path: &str, extension: &str) -> String {
+    std::path::Path::new(path)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
 
-    println!(
-        "{}{:>12}{} {} in {:.2}ms",
-        GREEN, "Finished", RESET, out_file, elapsed_ms
-    );
+/// Whether two paths name the same file, before either exists
+fn same_path(a: &str, b: &str) -> bool {
+    match (std::path::absolute(a), std::path::absolute(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
 
-    // Print section statistics
-    let stats = section_alloc.get_statistics();
-    for stat in stats {
-        if stat.used > 0 {
-            println!(
-                "{}{:>12}{} {}",
-                YELLOW,
-                stat.name,
-                RESET,
-                stat.format_compact()
+/// CPU of the `wraith.toml` target, for builds that don't name one
+fn target_cpu(program_info: &wraith::sema::ProgramInfo) -> wraith::assembler::Cpu {
+    program_info
+        .memory_config
+        .target
+        .map_or(wraith::assembler::Cpu::Nmos6502, |target| target.cpu())
+}
+
+/// Peephole level for `-O0`, `-O1`, `-O2` or `-Os`, or exit with the valid ones
+fn parse_opt_level(arg: &str) -> codegen::peephole::OptLevel {
+    match codegen::peephole::OptLevel::from_name(&arg[2..]) {
+        Some(level) => level,
+        None => {
+            eprintln!("{}Error:{} unknown optimization level: {}", RED, RESET, arg);
+            eprintln!("       valid options: -O0, -O1, -O2, -Os");
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// Pass name for `--disable-pass NAME`, or exit with the valid names
+fn parse_pass(name: &str) -> String {
+    if codegen::peephole::pass_names().any(|pass| pass == name) {
+        return name.to_string();
+    }
+    eprintln!("{}Error:{} unknown peephole pass: {}", RED, RESET, name);
+    let names: Vec<&str> = codegen::peephole::pass_names().collect();
+    eprintln!("       valid options: {}", names.join(", "));
+    std::process::exit(EXIT_ERROR);
+}
+
+/// Constant for `-D NAME=VALUE`, or exit
+///
+/// `-D NAME` alone defines NAME as 1. Values are decimal, `0x` hex or `0b`
+/// binary integers from -32768 to 65535, or `true`/`false`.
+fn parse_define(define: &str) -> (String, i64) {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        eprintln!("{}Error:{} invalid define name: {}", RED, RESET, name);
+        std::process::exit(EXIT_ERROR);
+    }
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let magnitude = match digits {
+        "true" if !negative => Ok(1),
+        "false" if !negative => Ok(0),
+        _ => {
+            if let Some(hex) = digits.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(binary) = digits.strip_prefix("0b") {
+                i64::from_str_radix(binary, 2)
+            } else {
+                digits.parse()
+            }
+        }
+    };
+    match magnitude.map(|n| if negative { -n } else { n }) {
+        Ok(value) if (-32768..=65535).contains(&value) => (name.to_string(), value),
+        _ => {
+            eprintln!(
+                "{}Error:{} invalid value for {}: {} (expected an integer from -32768 to 65535)",
+                RED, RESET, name, value
             );
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// CPU for `--cpu NAME`, or exit with the valid names
+fn parse_cpu(name: &str) -> wraith::assembler::Cpu {
+    match wraith::assembler::Cpu::from_name(name) {
+        Some(cpu) => cpu,
+        None => {
+            eprintln!("{}Error:{} unknown CPU: {}", RED, RESET, name);
+            let names: Vec<&str> = wraith::assembler::Cpu::ALL
+                .iter()
+                .map(|cpu| cpu.name())
+                .collect();
+            eprintln!("       valid options: {}", names.join(", "));
+            std::process::exit(EXIT_ERROR);
         }
     }
 }
 
 fn print_usage(program: &str) {
-    eprintln!("Usage: {} [OPTIONS] <input.wr>", program);
+    eprintln!("Usage: {} [build] [BUILD OPTIONS] [COMMON OPTIONS] <input.wr>", program);
+    eprintln!("                             Compile to assembly (<input>.asm) and other outputs");
+    eprintln!("       {} check <input.wr> [COMMON OPTIONS]", program);
+    eprintln!("                             Analyze and generate code without writing any files");
+    eprintln!("       {} test <input.wr> [COMMON OPTIONS]", program);
+    eprintln!("                             Run the #[test] functions");
+    eprintln!(
+        "       {} run <input.wr> [--function NAME] [--cycles N] [COMMON OPTIONS]",
+        program
+    );
+    eprintln!("                             Run in the simulator until it returns or halts");
+    eprintln!(
+        "       {} profile <input.wr> [--function NAME] [--cycles N] [--lines N] [COMMON OPTIONS]",
+        program
+    );
+    eprintln!("                             Run in the simulator and report cycles per function and line");
+    eprintln!("       {} fmt [--check] <input.wr|dir>...", program);
+    eprintln!("                             Format source files in place, or list unformatted ones");
     eprintln!();
-    eprintln!("Options:");
+    eprintln!("Common options:");
+    eprintln!("      --config FILE       Read the memory layout from FILE instead of ./wraith.toml");
+    eprintln!("  -D NAME[=VALUE]         Define the constant NAME (default 1); a `const NAME` takes this value");
+    eprintln!("      --cpu NAME          Generate code for NAME: 6502, 65C02, W65C02 (or R65C02), 6502X, 2A03, 65816");
+    eprintln!("      --cmos              Generate code for the 65C02 (same as --cpu 65C02)");
+    eprintln!("      --undocumented      Also use the stable undocumented NMOS opcodes (LAX, SAX, DCP, ISC)");
+    eprintln!("  -O0, -O1, -O2, -Os      Peephole passes: none, local cleanups, all (default), all + branch-over-jump");
+    eprintln!("      --disable-pass NAME Skip one peephole pass (repeatable)");
+    eprintln!("      --deny-warnings     Fail with exit status 2 if there are any warnings");
+    eprintln!("      --message-format F  Print errors and warnings as human (default) or json lines");
+    eprintln!();
+    eprintln!("Build options:");
     eprintln!("  -h, --help              Print this help message");
     eprintln!("  -v, --version           Print version information");
+    eprintln!("  -o FILE                 Write the assembly to FILE; other outputs are named after it");
+    eprintln!("  -b, --bin               Also assemble a raw binary image (<input>.bin)");
+    eprintln!("  -f, --format FORMAT     Also assemble an image: raw (.bin), ihex (.hex), srec (.srec), prg (.prg)");
+    eprintln!("  -l, --listing           Also write an annotated listing (<input>.lst)");
+    eprintln!("      --source-map        Write a PC to source line table (<input>.srcmap)");
+    eprintln!("      --source-markers    Mark each source line with '; file:line' in the assembly");
+    eprintln!("      --timing            Print the worst-case cycles of every function");
+    eprintln!("      --stack             Print the worst-case hardware stack use per entry point");
+    eprintln!("      --map FILE          Write a JSON memory map to FILE and a text map (<input>.map)");
+    eprintln!("  -s, --symbols FORMATS   Write debug symbols (<input>.lbl/.mlb/.dbg)");
+    eprintln!("                          FORMATS: comma-separated vice, mesen, dbg");
+    eprintln!("      --ca65              Emit ca65 segments and an ld65 config (<input>.cfg)");
+    eprintln!("      --pass-stats        Print the instructions and bytes each peephole pass removed");
     eprintln!("  -c, --comments LEVEL    Set comment verbosity in generated assembly");
     eprintln!("                          LEVEL: minimal, normal (default), verbose");
+    eprintln!();
+    eprintln!("Exit status: 0 on success, 1 on errors, 2 for warnings denied by --deny-warnings,");
+    eprintln!("             101 when a test fails");
 }
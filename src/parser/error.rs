@@ -130,38 +130,53 @@ impl ParseError {
             }
             _ => {
                 // Standard error formatting for other variants
-                let (error_type, message) = match &self.kind {
-                    ParseErrorKind::UnexpectedToken { expected, found } => {
-                        let found_str = match found {
-                            Some(tok) => format_token(tok),
-                            None => "end of file".to_string(),
-                        };
-                        (
-                            "error",
-                            format!("expected {}, found {}", expected, found_str),
-                        )
-                    }
-                    ParseErrorKind::UnexpectedEof { expected } => (
-                        "error",
-                        format!("unexpected end of file, expected {}", expected),
-                    ),
-                    ParseErrorKind::InvalidInteger(s) => {
-                        ("error", format!("invalid integer: {}", s))
-                    }
-                    ParseErrorKind::InvalidType(s) => ("error", format!("invalid type: {}", s)),
-                    ParseErrorKind::Custom(msg) => ("error", msg.clone()),
-                    _ => unreachable!(),
-                };
-
+                let message = self.message();
                 format!(
-                    "{}: {}\n{}",
-                    error_type,
+                    "error: {}\n{}",
                     message,
                     self.span.format_error_context(source, filename, &message)
                 )
             }
         }
     }
+
+    /// The error message, without source context
+    pub fn message(&self) -> String {
+        match &self.kind {
+            ParseErrorKind::UnexpectedToken { expected, found } => {
+                let found_str = match found {
+                    Some(tok) => format_token(tok),
+                    None => "end of file".to_string(),
+                };
+                format!("expected {}, found {}", expected, found_str)
+            }
+            ParseErrorKind::UnexpectedEof { expected } => {
+                format!("unexpected end of file, expected {}", expected)
+            }
+            ParseErrorKind::InvalidInteger(s) => format!("invalid integer: {}", s),
+            ParseErrorKind::InvalidType(s) => format!("invalid type: {}", s),
+            ParseErrorKind::Custom(msg) => msg.clone(),
+            ParseErrorKind::CustomDetailed { message, .. } => message.clone(),
+            ParseErrorKind::Multiple(errors) => errors
+                .iter()
+                .map(ParseError::message)
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    }
+
+    /// Stable name of the kind of error, for machine-readable output
+    pub fn code(&self) -> &'static str {
+        match &self.kind {
+            ParseErrorKind::UnexpectedToken { .. } => "unexpected-token",
+            ParseErrorKind::UnexpectedEof { .. } => "unexpected-eof",
+            ParseErrorKind::InvalidInteger(_) => "invalid-integer",
+            ParseErrorKind::InvalidType(_) => "invalid-type",
+            ParseErrorKind::Custom(_)
+            | ParseErrorKind::CustomDetailed { .. }
+            | ParseErrorKind::Multiple(_) => "syntax-error",
+        }
+    }
 }
 
 /// Format a token for display in error messages
@@ -232,6 +232,7 @@ impl Parser<'_> {
                     "nmi" => FnAttribute::Nmi,
                     "irq" => FnAttribute::Irq,
                     "reset" => FnAttribute::Reset,
+                    "test" => FnAttribute::Test,
                     "org" => {
                         self.expect(&Token::LParen)?;
                         let addr = match self.peek().cloned() {
@@ -250,6 +251,42 @@ impl Parser<'_> {
                         self.expect(&Token::RParen)?;
                         FnAttribute::Org(addr)
                     }
+                    "max_cycles" => {
+                        self.expect(&Token::LParen)?;
+                        let cycles = match self.peek().cloned() {
+                            Some(Token::Integer(n)) if (1..=u32::MAX as i64).contains(&n) => {
+                                self.advance();
+                                n as u32
+                            }
+                            tok => {
+                                return Err(ParseError::unexpected_token(
+                                    self.current_span(),
+                                    "cycle count",
+                                    tok,
+                                ));
+                            }
+                        };
+                        self.expect(&Token::RParen)?;
+                        FnAttribute::MaxCycles(cycles)
+                    }
+                    "recursion" => {
+                        self.expect(&Token::LParen)?;
+                        let depth = match self.peek().cloned() {
+                            Some(Token::Integer(n)) if (1..=u32::MAX as i64).contains(&n) => {
+                                self.advance();
+                                n as u32
+                            }
+                            tok => {
+                                return Err(ParseError::unexpected_token(
+                                    self.current_span(),
+                                    "recursion depth",
+                                    tok,
+                                ));
+                            }
+                        };
+                        self.expect(&Token::RParen)?;
+                        FnAttribute::Recursion(depth)
+                    }
                     "section" => {
                         self.expect(&Token::LParen)?;
                         let section_name = match self.peek().cloned() {
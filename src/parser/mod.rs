@@ -9,7 +9,7 @@ mod stmt;
 
 pub use error::{ParseError, ParseErrorKind, ParseResult};
 
-use crate::ast::{SourceFile, Span, Spanned};
+use crate::ast::{FileId, SourceFile, Span, Spanned};
 use crate::lexer::{SpannedToken, Token};
 
 /// The Wraith parser
@@ -18,6 +18,8 @@ pub struct Parser<'a> {
     pos: usize,
     /// Collected parse errors for multi-error reporting
     errors: Vec<ParseError>,
+    /// File every span is in
+    file: FileId,
 }
 
 impl<'a> Parser<'a> {
@@ -27,6 +29,7 @@ impl<'a> Parser<'a> {
             tokens,
             pos: 0,
             errors: Vec::with_capacity(tokens.len() / 20),
+            file: FileId::ROOT,
         }
     }
 
@@ -36,6 +39,13 @@ impl<'a> Parser<'a> {
         parser.parse_source_file()
     }
 
+    /// Parse the tokens of an imported module, with spans in `file`
+    pub fn parse_file(tokens: &'a [SpannedToken], file: FileId) -> ParseResult<SourceFile> {
+        let mut parser = Parser::new(tokens);
+        parser.file = file;
+        parser.parse_source_file()
+    }
+
     // === Token navigation ===
 
     /// Peek at the current token
@@ -101,13 +111,14 @@ impl<'a> Parser<'a> {
     fn current_span(&self) -> Span {
         self.tokens
             .get(self.pos)
-            .map(|t| Span::new(t.span.start, t.span.end))
+            .map(|t| Span::new(t.span.start, t.span.end).in_file(self.file))
             .unwrap_or_else(|| {
                 // EOF span - use end of last token or 0
                 self.tokens
                     .last()
                     .map(|t| Span::new(t.span.end, t.span.end))
                     .unwrap_or_default()
+                    .in_file(self.file)
             })
     }
 
@@ -115,9 +126,9 @@ impl<'a> Parser<'a> {
     fn previous_span(&self) -> Span {
         if self.pos > 0 {
             let t = &self.tokens[self.pos - 1];
-            Span::new(t.span.start, t.span.end)
+            Span::new(t.span.start, t.span.end).in_file(self.file)
         } else {
-            Span::default()
+            Span::default().in_file(self.file)
         }
     }
 
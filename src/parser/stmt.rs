@@ -21,6 +21,7 @@ impl Parser<'_> {
             Some(Token::Loop) => self.parse_loop_stmt(),
             Some(Token::For) => self.parse_for_stmt(),
             Some(Token::Match) => self.parse_match_stmt(),
+            Some(Token::Hash) => self.parse_bounded_loop(),
 
             // Jump statements
             Some(Token::Return) => self.parse_return_stmt(),
@@ -119,7 +120,14 @@ impl Parser<'_> {
 
         let span = start.merge(self.previous_span());
 
-        Ok(Spanned::new(Stmt::While { condition, body }, span))
+        Ok(Spanned::new(
+            Stmt::While {
+                condition,
+                body,
+                bound: None,
+            },
+            span,
+        ))
     }
 
     /// Parse loop statement
@@ -130,7 +138,60 @@ impl Parser<'_> {
         let body = Box::new(self.parse_block()?);
         let span = start.merge(self.previous_span());
 
-        Ok(Spanned::new(Stmt::Loop { body }, span))
+        Ok(Spanned::new(Stmt::Loop { body, bound: None }, span))
+    }
+
+    /// Parse a loop with an iteration bound: #[bound(N)] while ... { }
+    fn parse_bounded_loop(&mut self) -> ParseResult<Spanned<Stmt>> {
+        let start = self.current_span();
+        self.expect(&Token::Hash)?;
+        self.expect(&Token::LBracket)?;
+        let name_span = self.current_span();
+        let name = self.expect_ident()?;
+        if name.node != "bound" {
+            return Err(ParseError::custom(
+                name_span,
+                format!("unknown statement attribute: {}", name.node),
+            ));
+        }
+        self.expect(&Token::LParen)?;
+        let limit = match self.peek().cloned() {
+            Some(Token::Integer(n)) if (0..=u32::MAX as i64).contains(&n) => {
+                self.advance();
+                n as u32
+            }
+            tok => {
+                return Err(ParseError::unexpected_token(
+                    self.current_span(),
+                    "iteration count",
+                    tok,
+                ));
+            }
+        };
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::RBracket)?;
+
+        let mut stmt = match self.peek() {
+            Some(Token::While) => self.parse_while_stmt()?,
+            Some(Token::Loop) => self.parse_loop_stmt()?,
+            Some(Token::For) => self.parse_for_stmt()?,
+            tok => {
+                return Err(ParseError::unexpected_token(
+                    self.current_span(),
+                    "loop after #[bound]",
+                    tok.cloned(),
+                ));
+            }
+        };
+        match &mut stmt.node {
+            Stmt::While { bound, .. }
+            | Stmt::Loop { bound, .. }
+            | Stmt::For { bound, .. }
+            | Stmt::ForEach { bound, .. } => *bound = Some(limit),
+            _ => unreachable!("parsed a loop"),
+        }
+        stmt.span = start.merge(stmt.span);
+        Ok(stmt)
     }
 
     /// Parse for statement
@@ -198,6 +259,7 @@ impl Parser<'_> {
                         inclusive,
                     },
                     body,
+                    bound: None,
                 },
                 span,
             ))
@@ -213,6 +275,7 @@ impl Parser<'_> {
                     iterable: first_expr,
                     body,
                     index_var,
+                    bound: None,
                 },
                 span,
             ))
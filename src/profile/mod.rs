@@ -0,0 +1,334 @@
+//! Cycle Profiler
+//!
+//! Runs a compiled program in the simulator and attributes every cycle to
+//! the Wraith function and source line that spent it. Function ranges come
+//! from the placements recorded by the section allocator; a shadow call
+//! stack follows `JSR`/`RTS` to give inclusive times and call counts.
+
+use crate::assembler::Assembly;
+use crate::ast::{FileId, SourceMap};
+use crate::codegen::CodegenOutput;
+use crate::codegen::source_map::{LineEntry, SourceIndex, line_table};
+use crate::sim::{DEFAULT_CYCLE_LIMIT, SimError, Simulator, Step, StopReason};
+use rustc_hash::FxHashMap as HashMap;
+
+/// Where the profiled run starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileEntry {
+    /// Reset the CPU and run the `#[reset]` handler
+    Reset,
+    /// Call a function as if by `JSR` and run until it returns
+    Function(String),
+}
+
+/// Cycles spent in one function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    /// Times the function was entered by `JSR` (or a tail `JMP`)
+    pub calls: u64,
+    /// Cycles from entry to return, including callees
+    pub inclusive: u64,
+    /// Cycles spent executing the function's own instructions
+    pub exclusive: u64,
+}
+
+/// Cycles spent in the code generated for one source line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineProfile {
+    pub file: FileId,
+    pub line: usize,
+    pub cycles: u64,
+    /// Instructions executed
+    pub instructions: u64,
+}
+
+/// The result of a profiled run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Cycles executed by instructions during the run
+    pub total_cycles: u64,
+    pub stop: StopReason,
+    /// Functions that executed, most inclusive cycles first
+    pub functions: Vec<FunctionProfile>,
+    /// Source lines that executed, most cycles first
+    pub lines: Vec<LineProfile>,
+}
+
+impl Profile {
+    pub fn function(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+/// A function on the shadow call stack
+struct Frame {
+    function: usize,
+    /// Stack pointer just after the call; popping above it means we returned
+    sp: u8,
+    /// Cycle count when the function was entered
+    start: u64,
+}
+
+/// Bookkeeping for one run
+struct Recorder<'a> {
+    ranges: &'a [(u16, u16, usize)],
+    functions: Vec<FunctionProfile>,
+    /// Frames per function currently on the stack, so recursion isn't counted twice
+    active: Vec<u32>,
+    stack: Vec<Frame>,
+    pcs: HashMap<u16, (u64, u64)>,
+}
+
+impl Recorder<'_> {
+    /// Index of the function containing `pc`
+    fn function_at(&self, pc: u16) -> Option<usize> {
+        let i = self.ranges.partition_point(|&(start, _, _)| start <= pc);
+        let &(_, end, function) = self.ranges.get(i.checked_sub(1)?)?;
+        (pc <= end).then_some(function)
+    }
+
+    /// Index of the function starting exactly at `pc`
+    fn function_entry(&self, pc: u16) -> Option<usize> {
+        self.ranges
+            .binary_search_by_key(&pc, |&(start, _, _)| start)
+            .ok()
+            .map(|i| self.ranges[i].2)
+    }
+
+    fn enter(&mut self, function: usize, sp: u8, now: u64) {
+        self.functions[function].calls += 1;
+        self.active[function] += 1;
+        self.stack.push(Frame {
+            function,
+            sp,
+            start: now,
+        });
+    }
+
+    fn leave(&mut self, now: u64) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        self.active[frame.function] -= 1;
+        if self.active[frame.function] == 0 {
+            self.functions[frame.function].inclusive += now - frame.start;
+        }
+    }
+
+    fn observe(&mut self, sim: &Simulator, step: &Step) {
+        let cycles = step.cycles as u64;
+        let entry = self.pcs.entry(step.pc).or_default();
+        entry.0 += cycles;
+        entry.1 += 1;
+        if let Some(function) = self.function_at(step.pc) {
+            self.functions[function].exclusive += cycles;
+        }
+
+        // Anything that moves SP above a frame (RTS, RTI, or dropping the
+        // return address with PLA) leaves that function
+        let sp = sim.registers.sp;
+        while self.stack.last().is_some_and(|frame| sp > frame.sp) {
+            self.leave(sim.cycles);
+        }
+
+        let pc = sim.registers.pc;
+        match step.mnemonic {
+            "JSR" => {
+                if let Some(callee) = self.function_at(pc) {
+                    self.enter(callee, sp, sim.cycles);
+                }
+            }
+            "JMP" => {
+                // A jump to another function's entry is a tail call
+                let current = self.stack.last().map(|frame| frame.function);
+                if let Some(callee) = self.function_entry(pc)
+                    && current.is_some_and(|current| current != callee)
+                {
+                    let sp = self.stack.last().map_or(sp, |frame| frame.sp);
+                    self.leave(sim.cycles);
+                    self.enter(callee, sp, sim.cycles);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Profiles runs of an assembled program
+pub struct Profiler<'a> {
+    assembly: &'a Assembly,
+    names: Vec<String>,
+    /// Function address ranges (start, end inclusive, index into `names`), sorted
+    ranges: Vec<(u16, u16, usize)>,
+    lines: Vec<LineEntry>,
+    /// Cycle budget for the run
+    pub max_cycles: u64,
+}
+
+impl<'a> Profiler<'a> {
+    pub fn new(output: &CodegenOutput, assembly: &'a Assembly, sources: &SourceMap) -> Self {
+        let mut names = Vec::new();
+        let mut ranges = Vec::new();
+        for allocation in &output.section_alloc.allocations {
+            // Only code placements have a label the assembler knows about
+            if assembly.symbol(&allocation.name) == Some(allocation.start) {
+                ranges.push((allocation.start, allocation.end, names.len()));
+                names.push(allocation.name.clone());
+            }
+        }
+        ranges.sort_by_key(|&(start, _, _)| start);
+
+        Self {
+            assembly,
+            names,
+            ranges,
+            lines: line_table(output, assembly, sources),
+            max_cycles: DEFAULT_CYCLE_LIMIT,
+        }
+    }
+
+    /// Run the program from `entry` and collect a profile
+    pub fn run(&self, entry: &ProfileEntry) -> Result<Profile, SimError> {
+        let mut sim = Simulator::with_assembly(self.assembly);
+        let mut recorder = Recorder {
+            ranges: &self.ranges,
+            functions: self
+                .names
+                .iter()
+                .map(|name| FunctionProfile {
+                    name: name.clone(),
+                    calls: 0,
+                    inclusive: 0,
+                    exclusive: 0,
+                })
+                .collect(),
+            active: vec![0; self.names.len()],
+            stack: Vec::new(),
+            pcs: HashMap::default(),
+        };
+
+        // The entry frame sits at the top of the stack: a reset handler
+        // reinitializes SP, so nothing may pop it
+        let root_sp = match entry {
+            ProfileEntry::Reset => {
+                sim.reset();
+                u8::MAX
+            }
+            ProfileEntry::Function(name) => {
+                sim.begin_call(sim.symbol(name)?);
+                sim.registers.sp
+            }
+        };
+        let start = sim.cycles;
+        if let Some(function) = recorder.function_at(sim.registers.pc) {
+            recorder.enter(function, root_sp, start);
+        }
+
+        let stop = sim.run_with(self.max_cycles, |sim, step| recorder.observe(sim, step))?;
+        while !recorder.stack.is_empty() {
+            recorder.leave(sim.cycles);
+        }
+
+        let mut functions: Vec<_> = recorder
+            .functions
+            .into_iter()
+            .filter(|f| f.exclusive > 0)
+            .collect();
+        functions.sort_by(|a, b| {
+            b.inclusive
+                .cmp(&a.inclusive)
+                .then(b.exclusive.cmp(&a.exclusive))
+        });
+
+        Ok(Profile {
+            total_cycles: recorder.pcs.values().map(|&(cycles, _)| cycles).sum(),
+            stop,
+            functions,
+            lines: self.line_profile(&recorder.pcs),
+        })
+    }
+
+    /// Group per-address counts by source line
+    fn line_profile(&self, pcs: &HashMap<u16, (u64, u64)>) -> Vec<LineProfile> {
+        let mut by_line: HashMap<(FileId, usize), LineProfile> = HashMap::default();
+        for (&pc, &(cycles, instructions)) in pcs {
+            let i = self.lines.partition_point(|entry| entry.start <= pc);
+            let Some(entry) = i.checked_sub(1).map(|i| &self.lines[i]) else {
+                continue;
+            };
+            if pc > entry.end {
+                continue;
+            }
+            let line = by_line
+                .entry((entry.file, entry.line))
+                .or_insert(LineProfile {
+                    file: entry.file,
+                    line: entry.line,
+                    cycles: 0,
+                    instructions: 0,
+                });
+            line.cycles += cycles;
+            line.instructions += instructions;
+        }
+
+        let mut lines: Vec<_> = by_line.into_values().collect();
+        lines.sort_by(|a, b| {
+            b.cycles
+                .cmp(&a.cycles)
+                .then((a.file, a.line).cmp(&(b.file, b.line)))
+        });
+        lines
+    }
+}
+
+/// Render a profile as a text report with at most `max_lines` hot lines
+pub fn format_report(profile: &Profile, sources: &SourceMap, max_lines: usize) -> String {
+    let percent = |cycles: u64| {
+        if profile.total_cycles == 0 {
+            0.0
+        } else {
+            cycles as f64 * 100.0 / profile.total_cycles as f64
+        }
+    };
+    let mut out = String::new();
+
+    let stop = match profile.stop {
+        StopReason::Returned => "returned".to_string(),
+        StopReason::Halted { pc } => format!("halted at ${:04X}", pc),
+        StopReason::Break { pc } => format!("BRK at ${:04X}", pc),
+        StopReason::CycleLimit => "stopped at the cycle limit".to_string(),
+    };
+    out.push_str(&format!("{} cycles, {}\n\n", profile.total_cycles, stop));
+
+    out.push_str("  inclusive      %   exclusive      %     calls  function\n");
+    for function in &profile.functions {
+        out.push_str(&format!(
+            "{:>11} {:>5.1}% {:>11} {:>5.1}% {:>9}  {}\n",
+            function.inclusive,
+            percent(function.inclusive),
+            function.exclusive,
+            percent(function.exclusive),
+            function.calls,
+            function.name
+        ));
+    }
+
+    if !profile.lines.is_empty() && max_lines > 0 {
+        let mut index = SourceIndex::new(sources);
+        out.push_str("\n     cycles      %    instrs  line\n");
+        for line in profile.lines.iter().take(max_lines) {
+            out.push_str(&format!(
+                "{:>11} {:>5.1}% {:>9}  {}:{}  {}\n",
+                line.cycles,
+                percent(line.cycles),
+                line.instructions,
+                sources.name(line.file),
+                line.line,
+                index.file(line.file).line_text(line.line).trim()
+            ));
+        }
+    }
+
+    out
+}
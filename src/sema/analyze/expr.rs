@@ -377,7 +377,17 @@ impl SemanticAnalyzer {
 
             // Only allow Add, Sub, comparisons on BCD
             match op {
-                BinaryOp::Add | BinaryOp::Sub => {} // Hardware supported
+                // Hardware supported, given SED
+                BinaryOp::Add | BinaryOp::Sub if !self.cpu().has_decimal_mode() => {
+                    return Err(SemaError::Custom {
+                        message: format!(
+                            "b8/b16 arithmetic needs decimal mode, which the {} does not have; use u8/u16 instead",
+                            self.cpu().name()
+                        ),
+                        span,
+                    });
+                }
+                BinaryOp::Add | BinaryOp::Sub => {}
                 BinaryOp::Eq
                 | BinaryOp::Ne
                 | BinaryOp::Lt
@@ -9,15 +9,16 @@ mod tail_call;
 mod unused;
 mod zp_alloc;
 
+use crate::assembler::Cpu;
 use crate::ast::{Function, Item, PrimitiveType, SourceFile, Spanned, TypeExpr};
 use crate::codegen::memory_layout::MemoryLayout;
 use crate::sema::const_eval::ConstEnv;
 use crate::sema::table::{SymbolInfo, SymbolKind, SymbolLocation, SymbolTable};
 use crate::sema::type_defs::TypeRegistry;
 use crate::sema::types::Type;
-use crate::sema::{FunctionMetadata, ProgramInfo, SemaError, Warning};
+use crate::sema::{Diagnostics, FunctionMetadata, ProgramInfo, SemaError, Warning};
 
-use crate::ast::Span;
+use crate::ast::{FileId, SourceMap, Span};
 use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use std::path::PathBuf;
 
@@ -72,6 +73,18 @@ pub struct SemanticAnalyzer {
     pub(super) string_access_counts: HashMap<String, HashMap<String, usize>>,
     /// Track which strings have been cached already (to avoid double-counting)
     pub(super) cached_strings: HashSet<String>,
+    /// Target I/O addresses not yet redeclared by the program
+    pub(super) target_addresses: HashSet<String>,
+    /// Constants defined on the command line (`-D NAME=VALUE`)
+    pub(super) defines: Vec<(String, i64)>,
+    /// Defines not yet redeclared by a `const` in the program
+    pub(super) pending_defines: HashSet<String>,
+    /// Names whose declaration failed; errors that only repeat their absence are dropped
+    pub(super) poisoned: HashSet<String>,
+    /// Text of the root file and of every module imported so far
+    pub sources: SourceMap,
+    /// Processor given with `--cpu`, overriding the target's
+    pub(super) cpu: Option<Cpu>,
 }
 
 impl Default for SemanticAnalyzer {
@@ -82,6 +95,8 @@ impl Default for SemanticAnalyzer {
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
+        let memory_config = crate::config::MemoryConfig::load_or_default();
+        let memory_layout = memory_config.zero_page_layout().unwrap_or_default();
         Self {
             table: SymbolTable::new(),
             errors: Vec::with_capacity(16),
@@ -95,7 +110,7 @@ impl SemanticAnalyzer {
             imported_items: Vec::with_capacity(8),
             base_path: None,
             imported_files: HashSet::default(),
-            zp_allocator: ZeroPageAllocator::new(),
+            zp_allocator: ZeroPageAllocator::new(&memory_layout),
             const_env: ConstEnv::default(),
             loop_depth: 0,
             used_variables: HashSet::default(),
@@ -106,18 +121,26 @@ impl SemanticAnalyzer {
             declared_functions: Vec::with_capacity(16),
             called_functions: HashSet::default(),
             unreachable_stmts: HashSet::default(),
-            memory_layout: MemoryLayout::new(),
+            memory_layout,
             checking_assignment_target: false,
             expected_type: None,
             resolved_struct_names: HashMap::default(),
-            memory_config: crate::config::MemoryConfig::load_or_default(),
+            memory_config,
             current_function: None,
             string_access_counts: HashMap::default(),
             cached_strings: HashSet::default(),
+            target_addresses: HashSet::default(),
+            defines: Vec::new(),
+            pending_defines: HashSet::default(),
+            poisoned: HashSet::default(),
+            sources: SourceMap::default(),
+            cpu: None,
         }
     }
 
     pub fn with_base_path(base_path: PathBuf) -> Self {
+        let memory_config = crate::config::MemoryConfig::load_or_default();
+        let memory_layout = memory_config.zero_page_layout().unwrap_or_default();
         Self {
             table: SymbolTable::new(),
             errors: Vec::with_capacity(16),
@@ -131,7 +154,7 @@ impl SemanticAnalyzer {
             imported_items: Vec::with_capacity(8),
             base_path: Some(base_path),
             imported_files: HashSet::default(),
-            zp_allocator: ZeroPageAllocator::new(),
+            zp_allocator: ZeroPageAllocator::new(&memory_layout),
             const_env: ConstEnv::default(),
             loop_depth: 0,
             used_variables: HashSet::default(),
@@ -142,17 +165,53 @@ impl SemanticAnalyzer {
             declared_functions: Vec::with_capacity(16),
             called_functions: HashSet::default(),
             unreachable_stmts: HashSet::default(),
-            memory_layout: MemoryLayout::new(),
+            memory_layout,
             checking_assignment_target: false,
             expected_type: None,
             resolved_struct_names: HashMap::default(),
-            memory_config: crate::config::MemoryConfig::load_or_default(),
+            memory_config,
             current_function: None,
             string_access_counts: HashMap::default(),
             cached_strings: HashSet::default(),
+            target_addresses: HashSet::default(),
+            defines: Vec::new(),
+            pending_defines: HashSet::default(),
+            poisoned: HashSet::default(),
+            sources: SourceMap::default(),
+            cpu: None,
         }
     }
 
+    /// Analyze against `config` instead of the `wraith.toml` in the working directory
+    pub fn with_memory_config(mut self, config: crate::config::MemoryConfig) -> Self {
+        self.memory_layout = config.zero_page_layout().unwrap_or_default();
+        self.zp_allocator = ZeroPageAllocator::new(&self.memory_layout);
+        self.memory_config = config;
+        self
+    }
+
+    /// Analyze for `cpu` instead of the configured target's processor
+    pub fn with_cpu(mut self, cpu: Option<Cpu>) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// The processor the program is compiled for
+    pub(super) fn cpu(&self) -> Cpu {
+        self.cpu.unwrap_or_else(|| {
+            self.memory_config
+                .target
+                .map_or(Cpu::Nmos6502, |target| target.cpu())
+        })
+    }
+
+    /// Predeclare `-D NAME=VALUE` constants; a `const` of the same name takes
+    /// the defined value instead of its initializer
+    pub fn with_defines(mut self, defines: &[(String, i64)]) -> Self {
+        self.defines = defines.to_vec();
+        self
+    }
+
     /// Get the standard library path
     /// Checks WRAITH_STD_PATH environment variable, falls back to ./std
     pub(super) fn get_std_lib_path() -> PathBuf {
@@ -185,19 +244,75 @@ impl SemanticAnalyzer {
         }
     }
 
+    /// Analyze a program, stopping at the first error
     pub fn analyze(&mut self, source: &SourceFile) -> Result<ProgramInfo, SemaError> {
+        self.analyze_all(source)
+            .map_err(|diagnostics| diagnostics.errors[0].clone())
+    }
+
+    /// Analyze a program, recovering after each failed item and statement so
+    /// that every error is reported
+    pub fn analyze_all(&mut self, source: &SourceFile) -> Result<ProgramInfo, Diagnostics> {
+        // The compiler's temps and parameters have to fit around the zero
+        // page the target reserves
+        if self.memory_config.zero_page_layout().is_none() {
+            self.report(SemaError::Custom {
+                message: "the target's reserved zero page leaves no room for the compiler's \
+                          temps, parameters and scratch"
+                    .to_string(),
+                span: Span::new(0, 0),
+            });
+        }
+
+        // The target's I/O registers, unless the program declares its own
+        self.register_target_addresses();
+        self.register_defines();
+
         // First pass: Register all global items (functions, statics, structs)
-        for item in &source.items {
-            self.register_item(item)?;
+        let mut failed = HashSet::default();
+        for (index, item) in source.items.iter().enumerate() {
+            if let Err(error) = self.register_item(item) {
+                self.poison_item(item);
+                self.report(error);
+                failed.insert(index);
+            }
         }
 
         // Second pass: Analyze function bodies
-        for item in &source.items {
-            self.analyze_item(item)?;
+        let poisoned_globals = self.poisoned.clone();
+        for (index, item) in source.items.iter().enumerate() {
+            if failed.contains(&index) {
+                continue;
+            }
+            let depth = self.table.depth();
+            if let Err(error) = self.analyze_item(item) {
+                self.recover(depth);
+                self.current_function = None;
+                self.current_return_type = None;
+                self.report(error);
+            }
+            // Locals poisoned in one function don't hide errors in the next
+            self.poisoned.clone_from(&poisoned_globals);
         }
 
         if !self.errors.is_empty() {
-            return Err(self.errors[0].clone());
+            let mut errors = std::mem::take(&mut self.errors);
+            // Errors in imported modules sort at the import that loaded them,
+            // in their order within the module
+            errors.sort_by_key(|error| {
+                error.span().map_or((0, FileId::ROOT, 0), |span| {
+                    let import = self
+                        .sources
+                        .import_chain(span.file)
+                        .last()
+                        .map_or(span.start, |import| import.start);
+                    (import, span.file, span.start)
+                })
+            });
+            return Err(Diagnostics {
+                errors,
+                warnings: self.warnings.clone(),
+            });
         }
 
         // Check for unused imports and functions after all analysis is complete
@@ -208,6 +323,15 @@ impl SemanticAnalyzer {
         let tail_call_info = self.analyze_tail_calls(source);
 
         Ok(ProgramInfo {
+            tail_call_info,
+            ..self.program_info()
+        })
+    }
+
+    /// Everything resolved so far; after a failed `analyze_all` this covers
+    /// the items and statements that checked, for editor tooling
+    pub fn program_info(&self) -> ProgramInfo {
+        ProgramInfo {
             table: self.table.clone(),
             resolved_symbols: self.resolved_symbols.clone(),
             function_metadata: self.function_metadata.clone(),
@@ -217,10 +341,62 @@ impl SemanticAnalyzer {
             imported_items: self.imported_items.clone(),
             warnings: self.warnings.clone(),
             unreachable_stmts: self.unreachable_stmts.clone(),
-            tail_call_info,
+            tail_call_info: HashMap::default(),
             resolved_struct_names: self.resolved_struct_names.clone(),
             string_pool: HashMap::default(), // Will be populated during codegen
-        })
+            memory_config: self.memory_config.clone(),
+            memory_layout: self.memory_layout.clone(),
+        }
+    }
+
+    /// Record an error unless it only follows from an earlier one
+    pub(super) fn report(&mut self, error: SemaError) {
+        let cascade = match &error {
+            SemaError::UndefinedSymbol { name, .. } => self.poisoned.contains(name),
+            SemaError::OutOfZeroPage { .. } => self
+                .errors
+                .iter()
+                .any(|e| matches!(e, SemaError::OutOfZeroPage { .. })),
+            _ => false,
+        };
+        if !cascade {
+            self.errors.push(error);
+        }
+    }
+
+    /// Close the scopes a failed item or statement left open and clear the
+    /// state it was analyzed in
+    pub(super) fn recover(&mut self, depth: usize) {
+        while self.table.depth() > depth {
+            self.table.exit_scope();
+        }
+        self.checking_assignment_target = false;
+        self.expected_type = None;
+    }
+
+    /// Names declared by an item that failed to register
+    fn poison_item(&mut self, item: &Spanned<Item>) {
+        match &item.node {
+            Item::Function(func) => {
+                self.poisoned.insert(func.name.node.clone());
+            }
+            Item::Static(stat) => {
+                self.poisoned.insert(stat.name.node.clone());
+            }
+            Item::Address(addr) => {
+                self.poisoned.insert(addr.name.node.clone());
+            }
+            Item::Struct(def) => {
+                self.poisoned.insert(def.name.node.clone());
+            }
+            Item::Enum(def) => {
+                self.poisoned.insert(def.name.node.clone());
+            }
+            Item::Import(import) => {
+                self.poisoned
+                    .extend(import.symbols.iter().map(|symbol| symbol.node.clone()));
+            }
+        }
     }
 
     fn analyze_item(&mut self, item: &Spanned<Item>) -> Result<(), SemaError> {
@@ -266,7 +442,7 @@ impl SemanticAnalyzer {
             // Register parameters
             // Parameters are passed via the param region ($80+), not regular variable space
             // Each parameter gets sequential bytes (16-bit params take 2 bytes)
-            let layout = MemoryLayout::new();
+            let layout = self.memory_layout.clone();
             let mut byte_offset = 0u8;
             let mut struct_param_locals: HashMap<String, u8> =
                 HashMap::default();
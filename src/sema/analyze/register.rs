@@ -6,7 +6,8 @@
 use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use std::path::PathBuf;
 
-use crate::ast::{EnumVariant, Import, Item, PrimitiveType, Spanned};
+use crate::ast::{EnumVariant, Import, Item, PrimitiveType, Span, Spanned};
+use crate::parser::ParseErrorKind;
 use crate::sema::const_eval::{eval_const_expr_with_env, ConstValue};
 use crate::sema::table::{SymbolInfo, SymbolKind, SymbolLocation};
 use crate::sema::type_defs::{EnumDef, FieldInfo, StructDef, VariantData, VariantInfo};
@@ -71,14 +72,47 @@ impl SemanticAnalyzer {
             return Err(SemaError::DuplicateSymbol {
                 name: name.clone(),
                 span: func.name.span,
-                previous_span: None, // Could track this if we store spans
+                previous_span: self
+                    .declared_functions
+                    .iter()
+                    .find(|(declared, _)| *declared == name)
+                    .map(|&(_, span)| span),
+            });
+        }
+
+        let ty = self.resolve_function_type(func)?;
+
+        // Tests are called with no arguments and may only report pass/fail
+        if func.attributes.contains(&crate::ast::FnAttribute::Test)
+            && let Type::Function(params, ret) = &ty
+            && (!params.is_empty() || !matches!(**ret, Type::Void | Type::Primitive(PrimitiveType::Bool)))
+        {
+            return Err(SemaError::Custom {
+                message: format!(
+                    "test function '{}' must take no parameters and return nothing or bool",
+                    name
+                ),
+                span: func.name.span,
+            });
+        }
+
+        // Inline functions have no code of their own to time
+        if is_inline
+            && func
+                .attributes
+                .iter()
+                .any(|attr| matches!(attr, crate::ast::FnAttribute::MaxCycles(_)))
+        {
+            return Err(SemaError::Custom {
+                message: format!("inline function '{}' cannot have a cycle budget", name),
+                span: func.name.span,
             });
         }
 
         let info = SymbolInfo {
             name: name.clone(),
             kind: SymbolKind::Function,
-            ty: self.resolve_function_type(func)?,
+            ty,
             location: SymbolLocation::Absolute(0),
             mutable: false,
             access_mode: None,
@@ -138,10 +172,10 @@ impl SemanticAnalyzer {
         // Track string parameter names for cache eligibility
         let mut param_names: HashSet<String> = HashSet::default();
         for param in &func.params {
-            if let Ok(ty) = self.resolve_type(&param.ty.node) {
-                if matches!(ty, Type::String) {
-                    param_names.insert(param.name.node.clone());
-                }
+            if let Ok(ty) = self.resolve_type(&param.ty.node)
+                && matches!(ty, Type::String)
+            {
+                param_names.insert(param.name.node.clone());
             }
         }
 
@@ -168,6 +202,7 @@ impl SemanticAnalyzer {
         // - irq (interrupt handler)
         // - nmi (NMI handler)
         // - inline (may be called from other modules)
+        // - test (called by the test runner)
         let is_special = func.attributes.iter().any(|attr| {
             matches!(
                 attr,
@@ -175,6 +210,7 @@ impl SemanticAnalyzer {
                     | crate::ast::FnAttribute::Irq
                     | crate::ast::FnAttribute::Nmi
                     | crate::ast::FnAttribute::Inline
+                    | crate::ast::FnAttribute::Test
             )
         });
 
@@ -196,8 +232,13 @@ impl SemanticAnalyzer {
             });
         }
 
-        // Check for duplicate static definition
-        if self.table.defined_in_current_scope(&name) {
+        // Check for duplicate static definition; a constant defined on the
+        // command line may be declared once, giving its type
+        let define = match self.defines.iter().find(|(defined, _)| *defined == name) {
+            Some(&(_, value)) if !stat.mutable && self.pending_defines.remove(&name) => Some(value),
+            _ => None,
+        };
+        if self.table.defined_in_current_scope(&name) && define.is_none() {
             return Err(SemaError::DuplicateSymbol {
                 name: name.clone(),
                 span: stat.name.span,
@@ -218,7 +259,20 @@ impl SemanticAnalyzer {
 
         // If it's a non-mutable static (const), evaluate it and add to const_env
         if !stat.mutable {
-            match eval_const_expr_with_env(&stat.init, &self.const_env) {
+            let value = match define {
+                Some(value) if declared_ty == Type::Primitive(PrimitiveType::Bool) => {
+                    if !(0..=1).contains(&value) {
+                        return Err(SemaError::Custom {
+                            message: format!("-D {}={} does not fit in type bool", name, value),
+                            span: stat.name.span,
+                        });
+                    }
+                    Ok(ConstValue::Bool(value == 1))
+                }
+                Some(value) => Ok(ConstValue::Integer(value)),
+                None => eval_const_expr_with_env(&stat.init, &self.const_env),
+            };
+            match value {
                 Ok(val) => {
                     // Check that the constant value fits within the declared type
                     if let Some(int_val) = val.as_integer() {
@@ -237,7 +291,11 @@ impl SemanticAnalyzer {
                             return Err(SemaError::ConstantOverflow {
                                 value: int_val,
                                 ty: declared_ty.display_name(),
-                                span: stat.init.span,
+                                span: if define.is_some() {
+                                    stat.name.span
+                                } else {
+                                    stat.init.span
+                                },
                             });
                         }
                     }
@@ -275,8 +333,9 @@ impl SemanticAnalyzer {
             });
         }
 
-        // Check for duplicate address definition
-        if self.table.defined_in_current_scope(&name) {
+        // Check for duplicate address definition; a target's I/O address
+        // may be redeclared once
+        if self.table.defined_in_current_scope(&name) && !self.target_addresses.remove(&name) {
             return Err(SemaError::DuplicateSymbol {
                 name: name.clone(),
                 span: addr.name.span,
@@ -287,13 +346,22 @@ impl SemanticAnalyzer {
         // Evaluate the address expression as a constant, using the const environment
         let address = match eval_const_expr_with_env(&addr.address, &self.const_env) {
             Ok(ConstValue::Integer(val)) => {
-                if !(0..=0xFFFF).contains(&val) {
+                // Only the 65816 reaches past $FFFF, with long addressing
+                let highest = if self.cpu().has_native_mode() {
+                    0xFF_FFFF
+                } else {
+                    0xFFFF
+                };
+                if !(0..=highest).contains(&val) {
                     return Err(SemaError::Custom {
-                        message: format!("address value {} out of range (must be 0-65535)", val),
+                        message: format!(
+                            "address value {} out of range (must be 0-{})",
+                            val, highest
+                        ),
                         span: addr.address.span,
                     });
                 }
-                val as u16
+                val as u32
             }
             Ok(_) => {
                 return Err(SemaError::Custom {
@@ -309,26 +377,34 @@ impl SemanticAnalyzer {
         self.const_env
             .insert(name.clone(), ConstValue::Integer(address as i64));
 
-        // Check for overlap with compiler-managed memory sections
-        for section in &self.memory_config.sections {
-            if section.contains(address) {
-                self.warnings.push(Warning::AddressOverlap {
-                    name: name.clone(),
-                    address,
-                    section_name: section.name.clone(),
-                    section_start: section.start,
-                    section_end: section.end,
-                    span: addr.address.span,
-                });
-                break; // Only warn once per address
+        // Addresses above $FFFF need the 65816's long addressing and lie
+        // outside every section
+        let location = match u16::try_from(address) {
+            Ok(address) => {
+                // Check for overlap with compiler-managed memory sections
+                for section in &self.memory_config.sections {
+                    if section.contains(address) {
+                        self.warnings.push(Warning::AddressOverlap {
+                            name: name.clone(),
+                            address,
+                            section_name: section.name.clone(),
+                            section_start: section.start,
+                            section_end: section.end,
+                            span: addr.address.span,
+                        });
+                        break; // Only warn once per address
+                    }
+                }
+                SymbolLocation::Absolute(address)
             }
-        }
+            Err(_) => SymbolLocation::Long(address),
+        };
 
         let info = SymbolInfo {
             name: name.clone(),
             kind: SymbolKind::Address,
             ty: Type::Primitive(PrimitiveType::U8),
-            location: SymbolLocation::Absolute(address),
+            location,
             // Write and ReadWrite can be written to; Read cannot
             mutable: matches!(
                 addr.access,
@@ -343,10 +419,59 @@ impl SemanticAnalyzer {
         Ok(())
     }
 
-    pub(super) fn process_import(&mut self, import: &Import) -> Result<(), SemaError> {
-        // Resolve the import path
-        let import_str = &import.path.node;
-        let import_path = if import_str.starts_with("./") || import_str.starts_with("../") {
+    /// Declare the target's I/O registers as read-write addresses
+    pub(super) fn register_target_addresses(&mut self) {
+        for &(name, address) in self.memory_config.io_addresses() {
+            self.const_env
+                .insert(name.to_string(), ConstValue::Integer(address as i64));
+            self.table.insert(
+                name.to_string(),
+                SymbolInfo {
+                    name: name.to_string(),
+                    kind: SymbolKind::Address,
+                    ty: Type::Primitive(PrimitiveType::U8),
+                    location: SymbolLocation::Absolute(address),
+                    mutable: true,
+                    access_mode: Some(crate::ast::AccessMode::ReadWrite),
+                    is_pub: false,
+                    containing_function: None,
+                },
+            );
+            self.target_addresses.insert(name.to_string());
+        }
+    }
+
+    /// Constants defined on the command line, typed by their value
+    pub(super) fn register_defines(&mut self) {
+        for (name, value) in &self.defines {
+            let ty = match value {
+                0..=255 => PrimitiveType::U8,
+                -128..=-1 => PrimitiveType::I8,
+                256..=65535 => PrimitiveType::U16,
+                _ => PrimitiveType::I16,
+            };
+            self.const_env
+                .insert(name.clone(), ConstValue::Integer(*value));
+            self.table.insert(
+                name.clone(),
+                SymbolInfo {
+                    name: name.clone(),
+                    kind: SymbolKind::Constant,
+                    ty: Type::Primitive(ty),
+                    location: SymbolLocation::Absolute(0),
+                    mutable: false,
+                    access_mode: None,
+                    is_pub: false,
+                    containing_function: None,
+                },
+            );
+            self.pending_defines.insert(name.clone());
+        }
+    }
+
+    /// The file an `import ... from "path"` refers to
+    pub fn resolve_import_path(&self, import_str: &str) -> PathBuf {
+        if import_str.starts_with("./") || import_str.starts_with("../") {
             // Relative import - resolve relative to the current file's directory
             if let Some(base) = &self.base_path {
                 base.parent().unwrap_or(base).join(import_str)
@@ -366,7 +491,11 @@ impl SemanticAnalyzer {
                     PathBuf::from(import_str)
                 }
             }
-        };
+        }
+    }
+
+    pub(super) fn process_import(&mut self, import: &Import) -> Result<(), SemaError> {
+        let import_path = self.resolve_import_path(&import.path.node);
 
         // Check if we've already imported this file to avoid circular imports
         if self.imported_files.contains(&import_path) {
@@ -381,22 +510,58 @@ impl SemanticAnalyzer {
             span: import.path.span,
         })?;
 
+        let file = self.sources.add_import(
+            // Drop the `./` of relative imports from the displayed name
+            import_path
+                .components()
+                .collect::<PathBuf>()
+                .display()
+                .to_string(),
+            source.as_str(),
+            import.path.span,
+        );
+
         let tokens = crate::lex(&source).map_err(|e| SemaError::ImportError {
             path: import.path.node.clone(),
-            reason: format!("lexer error: {:?}", e),
-            span: import.path.span,
+            reason: e.message.clone(),
+            span: Span::new(e.span.start, e.span.end).in_file(file),
         })?;
 
-        let ast = crate::Parser::parse(&tokens).map_err(|e| SemaError::ImportError {
-            path: import.path.node.clone(),
-            reason: format!("parser error: {:?}", e),
-            span: import.path.span,
-        })?;
+        let ast = match crate::Parser::parse_file(&tokens, file) {
+            Ok(ast) => ast,
+            Err(e) => {
+                let errors = match e.kind {
+                    ParseErrorKind::Multiple(errors) => errors,
+                    _ => vec![e],
+                };
+                let errors = errors
+                    .iter()
+                    .map(|error| SemaError::ImportError {
+                        path: import.path.node.clone(),
+                        reason: error.message(),
+                        span: error.span,
+                    })
+                    .collect();
+                return Err(self.report_all_but_first(errors));
+            }
+        };
 
-        // Analyze the imported file
-        let mut imported_analyzer = SemanticAnalyzer::with_base_path(import_path.clone());
+        // Analyze the imported file; it records its own imports in the same source map
+        let mut imported_analyzer = SemanticAnalyzer::with_base_path(import_path.clone())
+            .with_memory_config(self.memory_config.clone())
+            .with_cpu(self.cpu)
+            .with_defines(&self.defines);
         imported_analyzer.imported_files = self.imported_files.clone();
-        let imported_info = imported_analyzer.analyze(&ast)?;
+        imported_analyzer.sources = std::mem::take(&mut self.sources);
+        let imported_info = imported_analyzer.analyze_all(&ast);
+        self.sources = std::mem::take(&mut imported_analyzer.sources);
+        let imported_info = match imported_info {
+            Ok(info) => info,
+            Err(diagnostics) => {
+                self.warnings.extend(diagnostics.warnings);
+                return Err(self.report_all_but_first(diagnostics.errors));
+            }
+        };
 
         // Collect all items from the imported file for codegen
         // We collect ALL items, not just the imported symbols, because functions
@@ -487,6 +652,17 @@ impl SemanticAnalyzer {
         Ok(())
     }
 
+    /// Report every error of a failed import but the first, which is
+    /// returned for the import item itself
+    fn report_all_but_first(&mut self, errors: Vec<SemaError>) -> SemaError {
+        let mut errors = errors.into_iter();
+        let first = errors.next().expect("a failed import has an error");
+        for error in errors {
+            self.report(error);
+        }
+        first
+    }
+
     pub(super) fn register_struct(
         &mut self,
         struct_def: &crate::ast::Struct,
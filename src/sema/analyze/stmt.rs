@@ -28,7 +28,18 @@ impl SemanticAnalyzer {
                         // Continue analyzing (don't skip) to find other errors/warnings
                     }
 
-                    self.analyze_stmt(s)?;
+                    // Report the error and carry on with the next statement;
+                    // a failed `let` poisons its name
+                    let depth = self.table.depth();
+                    let loop_depth = self.loop_depth;
+                    if let Err(error) = self.analyze_stmt(s) {
+                        self.recover(depth);
+                        self.loop_depth = loop_depth;
+                        if let Stmt::VarDecl { name, .. } = &s.node {
+                            self.poisoned.insert(name.node.clone());
+                        }
+                        self.report(error);
+                    }
 
                     // Check if this statement terminates control flow
                     if matches!(s.node, Stmt::Return(_) | Stmt::Break | Stmt::Continue) {
@@ -84,7 +95,7 @@ impl SemanticAnalyzer {
                     self.analyze_stmt(else_b)?;
                 }
             }
-            Stmt::While { condition, body } => {
+            Stmt::While { condition, body, .. } => {
                 let cond_ty = self.check_expr(condition)?;
                 if cond_ty != Type::Primitive(PrimitiveType::Bool) {
                     return Err(SemaError::TypeMismatch {
@@ -102,6 +113,7 @@ impl SemanticAnalyzer {
                 var_type,
                 range,
                 body,
+                ..
             } => {
                 self.analyze_for_loop(var_name, var_type, range, body)?;
             }
@@ -111,10 +123,11 @@ impl SemanticAnalyzer {
                 iterable,
                 body,
                 index_var,
+                ..
             } => {
                 self.analyze_foreach_loop(var_name, var_type, iterable, body, index_var.as_ref())?;
             }
-            Stmt::Loop { body } => {
+            Stmt::Loop { body, .. } => {
                 self.loop_depth += 1;
                 self.analyze_stmt(body)?;
                 self.loop_depth -= 1;
@@ -160,6 +173,15 @@ impl SemanticAnalyzer {
                 // Variables are referenced as {var_name} or {struct.field}
                 for line in lines {
                     self.extract_asm_variables(&line.instruction);
+                    if !self.cpu().has_decimal_mode() && asm_uses_sed(&line.instruction) {
+                        return Err(SemaError::Custom {
+                            message: format!(
+                                "inline asm uses SED, but the {} has no decimal mode",
+                                self.cpu().name()
+                            ),
+                            span: stmt.span,
+                        });
+                    }
                 }
             }
         }
@@ -203,7 +225,12 @@ impl SemanticAnalyzer {
             return Err(SemaError::DuplicateSymbol {
                 name: name.node.clone(),
                 span: name.span,
-                previous_span: None,
+                previous_span: self
+                    .declared_variables
+                    .iter()
+                    .rev()
+                    .find(|(declared, _)| *declared == name.node)
+                    .map(|&(_, span)| span),
             });
         }
 
@@ -387,7 +414,9 @@ impl SemanticAnalyzer {
             is_pub: false, // Local variables are never public
             containing_function: self.current_function.clone(),
         };
-        self.table.insert(var_name.node.clone(), info);
+        self.table.insert(var_name.node.clone(), info.clone());
+        // Add to resolved_symbols so codegen can find the loop variable
+        self.resolved_symbols.insert(var_name.span, info);
 
         // Check range bounds if not already checked
         if var_type.is_some() {
@@ -625,3 +654,13 @@ impl SemanticAnalyzer {
         Ok(())
     }
 }
+
+/// Whether any instruction of an inline asm line (which may hold several,
+/// one per line, after optional `label:` prefixes) is SED
+fn asm_uses_sed(instruction: &str) -> bool {
+    instruction.lines().any(|line| {
+        line.split_whitespace()
+            .find(|part| !part.ends_with(':'))
+            .is_some_and(|mnemonic| mnemonic.eq_ignore_ascii_case("SED"))
+    })
+}
@@ -90,7 +90,7 @@ impl SemanticAnalyzer {
             }
 
             // Recurse into loop
-            Stmt::Loop { body } => {
+            Stmt::Loop { body, .. } => {
                 self.find_tail_recursive_returns(func_name, body, tail_recursive_returns);
             }
 
@@ -14,41 +14,46 @@ pub(super) struct ZeroPageAllocator {
     next_addr: u8,
     /// Reserved ranges (start, end) that cannot be allocated
     reserved: Vec<(u8, u8)>,
+    /// Where allocation starts over after a reset
+    start_addr: u8,
 }
 
 impl ZeroPageAllocator {
-    pub fn new() -> Self {
-        let layout = MemoryLayout::new();
+    /// Allocator for the bytes `layout` leaves to variables
+    pub fn new(layout: &MemoryLayout) -> Self {
         Self {
             next_addr: layout.variable_alloc_start,
             reserved: layout.get_reserved_regions(),
+            start_addr: layout.variable_alloc_start,
         }
     }
 
+    fn is_reserved(&self, addr: u8) -> bool {
+        addr == 0xFF
+            || self
+                .reserved
+                .iter()
+                .any(|(start, end)| addr >= *start && addr <= *end)
+    }
+
     /// Allocate a single byte in zero page
     pub fn allocate(&mut self) -> Result<u8, SemaError> {
         // Find next available address
         loop {
             let addr = self.next_addr;
 
-            // Check if this address is reserved
-            let is_reserved = self
-                .reserved
-                .iter()
-                .any(|(start, end)| addr >= *start && addr <= *end);
-
-            if !is_reserved && addr != 0xFF {
+            if !self.is_reserved(addr) {
                 self.next_addr = addr + 1;
                 return Ok(addr);
             }
 
             // Try next address
-            self.next_addr += 1;
+            self.next_addr = self.next_addr.wrapping_add(1);
 
             if self.next_addr == 0 {
                 // Wrapped around - out of zero page
                 return Err(SemaError::OutOfZeroPage {
-                    span: Span { start: 0, end: 0 }, // No span context in allocator
+                    span: Span::new(0, 0), // No span context in allocator
                 });
             }
         }
@@ -57,27 +62,26 @@ impl ZeroPageAllocator {
     /// Allocate multiple consecutive bytes
     #[allow(dead_code)]
     pub fn allocate_range(&mut self, count: u8) -> Result<u8, SemaError> {
-        let start = self.next_addr;
-
-        // Check if we have enough space
-        if start as usize + count as usize > 0x100 {
-            return Err(SemaError::OutOfZeroPage {
-                span: Span { start: 0, end: 0 }, // No span context in allocator
-            });
-        }
-
-        // Allocate each byte
-        for _ in 0..count {
-            self.allocate()?;
+        // First run of `count` bytes that doesn't straddle a reserved range
+        let mut start = self.next_addr as usize;
+        while start + count as usize <= 0x100 {
+            match (start..start + count as usize).find(|&addr| self.is_reserved(addr as u8)) {
+                Some(reserved) => start = reserved + 1,
+                None => {
+                    self.next_addr = (start + count as usize) as u8;
+                    return Ok(start as u8);
+                }
+            }
         }
 
-        Ok(start)
+        Err(SemaError::OutOfZeroPage {
+            span: Span::new(0, 0), // No span context in allocator
+        })
     }
 
     /// Reset allocator (for new scope/function)
     #[allow(dead_code)]
     pub fn reset(&mut self) {
-        let layout = MemoryLayout::new();
-        self.next_addr = layout.variable_alloc_start;
+        self.next_addr = self.start_addr;
     }
 }
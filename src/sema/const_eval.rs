@@ -168,7 +168,7 @@ fn eval_literal(lit: &Literal) -> Result<ConstValue, SemaError> {
         Literal::String(s) => Ok(ConstValue::String(s.clone())),
         _ => Err(SemaError::Custom {
             message: "literal cannot be evaluated as constant".to_string(),
-            span: crate::ast::Span { start: 0, end: 0 },
+            span: crate::ast::Span::new(0, 0),
         }),
     }
 }
@@ -392,27 +392,23 @@ pub fn validate_bcd_cast(
 
     if let Some(n) = value.as_integer() {
         match target_prim {
-            PrimitiveType::B8 => {
-                if decimal_to_bcd(n, 2).is_none() {
-                    return Err(SemaError::Custom {
-                        message: format!(
-                            "value {} is out of range for BCD type b8 (valid range: 0-99)",
-                            n
-                        ),
-                        span,
-                    });
-                }
+            PrimitiveType::B8 if decimal_to_bcd(n, 2).is_none() => {
+                return Err(SemaError::Custom {
+                    message: format!(
+                        "value {} is out of range for BCD type b8 (valid range: 0-99)",
+                        n
+                    ),
+                    span,
+                });
             }
-            PrimitiveType::B16 => {
-                if decimal_to_bcd(n, 4).is_none() {
-                    return Err(SemaError::Custom {
-                        message: format!(
-                            "value {} is out of range for BCD type b16 (valid range: 0-9999)",
-                            n
-                        ),
-                        span,
-                    });
-                }
+            PrimitiveType::B16 if decimal_to_bcd(n, 4).is_none() => {
+                return Err(SemaError::Custom {
+                    message: format!(
+                        "value {} is out of range for BCD type b16 (valid range: 0-9999)",
+                        n
+                    ),
+                    span,
+                });
             }
             _ => {}
         }
@@ -549,7 +545,7 @@ mod tests {
     fn make_int(n: i64) -> Spanned<Expr> {
         Spanned {
             node: Expr::Literal(Literal::Integer(n)),
-            span: Span { start: 0, end: 0 },
+            span: Span::new(0, 0),
         }
     }
 
@@ -560,7 +556,7 @@ mod tests {
                 op,
                 right: Box::new(right),
             },
-            span: Span { start: 0, end: 0 },
+            span: Span::new(0, 0),
         }
     }
 
@@ -12,7 +12,8 @@ pub mod table;
 pub mod type_defs;
 pub mod types;
 
-use crate::ast::SourceFile;
+use crate::assembler::Cpu;
+use crate::ast::{FileId, SourceFile, SourceMap};
 use analyze::SemanticAnalyzer;
 use std::path::PathBuf;
 
@@ -120,6 +121,33 @@ pub enum SemaError {
 }
 
 impl SemaError {
+    /// Where the error is, if it points into the source
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            SemaError::UndefinedSymbol { span, .. }
+            | SemaError::TypeMismatch { span, .. }
+            | SemaError::InvalidBinaryOp { span, .. }
+            | SemaError::InvalidUnaryOp { span, .. }
+            | SemaError::ArityMismatch { span, .. }
+            | SemaError::ImmutableAssignment { span, .. }
+            | SemaError::ReturnTypeMismatch { span, .. }
+            | SemaError::ReturnOutsideFunction { span }
+            | SemaError::BreakOutsideLoop { span }
+            | SemaError::DuplicateSymbol { span, .. }
+            | SemaError::FieldNotFound { span, .. }
+            | SemaError::ImportError { span, .. }
+            | SemaError::OutOfZeroPage { span }
+            | SemaError::InstructionConflict { span, .. }
+            | SemaError::Custom { span, .. }
+            | SemaError::ConstantOverflow { span, .. }
+            | SemaError::WriteOnlyRead { span, .. }
+            | SemaError::ReadOnlyWrite { span, .. }
+            | SemaError::InvalidAddrUsage { span, .. }
+            | SemaError::ArrayIndexOutOfBounds { span, .. } => Some(*span),
+            SemaError::CircularImport { .. } => None,
+        }
+    }
+
     /// Format error with source code context showing the actual line and error marker
     pub fn format_with_source(&self, source: &str) -> String {
         self.format_with_source_and_file(source, None)
@@ -127,216 +155,169 @@ impl SemaError {
 
     /// Format error with source code context and filename
     pub fn format_with_source_and_file(&self, source: &str, filename: Option<&str>) -> String {
+        let (title, label) = self.describe(source);
+        match self.span() {
+            Some(span) => format!(
+                "error: {}\n{}",
+                title,
+                span.format_error_context(source, filename, &label)
+            ),
+            None => format!("error: {}", title),
+        }
+    }
+
+    /// Format error with context from the file its span is in
+    pub fn format_with_sources(&self, sources: &SourceMap) -> String {
+        let file = self.span().map_or(FileId::ROOT, |span| span.file);
+        let (title, label) = self.describe(sources.source(file));
+        match self.span() {
+            Some(span) => format!(
+                "error: {}\n{}",
+                title,
+                sources.format_error_context(span, &label)
+            ),
+            None => format!("error: {}", title),
+        }
+    }
+
+    /// Stable name of the kind of error, for machine-readable output
+    pub fn code(&self) -> &'static str {
         match self {
-            SemaError::UndefinedSymbol { name, span } => {
-                let msg = format!("undefined symbol '{}'", name);
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
+            SemaError::UndefinedSymbol { .. } => "undefined-symbol",
+            SemaError::TypeMismatch { .. } => "type-mismatch",
+            SemaError::InvalidBinaryOp { .. } => "invalid-binary-op",
+            SemaError::InvalidUnaryOp { .. } => "invalid-unary-op",
+            SemaError::ArityMismatch { .. } => "arity-mismatch",
+            SemaError::ImmutableAssignment { .. } => "immutable-assignment",
+            SemaError::CircularImport { .. } => "circular-import",
+            SemaError::ReturnTypeMismatch { .. } => "return-type-mismatch",
+            SemaError::ReturnOutsideFunction { .. } => "return-outside-function",
+            SemaError::BreakOutsideLoop { .. } => "break-outside-loop",
+            SemaError::DuplicateSymbol { .. } => "duplicate-symbol",
+            SemaError::FieldNotFound { .. } => "field-not-found",
+            SemaError::ImportError { .. } => "import-error",
+            SemaError::OutOfZeroPage { .. } => "out-of-zero-page",
+            SemaError::InstructionConflict { .. } => "instruction-conflict",
+            SemaError::Custom { .. } => "semantic-error",
+            SemaError::ConstantOverflow { .. } => "constant-overflow",
+            SemaError::WriteOnlyRead { .. } => "write-only-read",
+            SemaError::ReadOnlyWrite { .. } => "read-only-write",
+            SemaError::InvalidAddrUsage { .. } => "invalid-addr-usage",
+            SemaError::ArrayIndexOutOfBounds { .. } => "array-index-out-of-bounds",
+        }
+    }
+
+    /// The error's headline and the label under its span
+    pub fn describe(&self, source: &str) -> (String, String) {
+        let same = |msg: String| (msg.clone(), msg);
+        match self {
+            SemaError::UndefinedSymbol { name, .. } => same(format!("undefined symbol '{}'", name)),
             SemaError::TypeMismatch {
-                expected,
-                found,
-                span,
-            } => {
-                let msg = format!("expected {}, found {}", expected, found);
-                format!(
-                    "error: type mismatch\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
+                expected, found, ..
+            } => (
+                "type mismatch".to_string(),
+                format!("expected {}, found {}", expected, found),
+            ),
             SemaError::InvalidBinaryOp {
                 op,
                 left_ty,
                 right_ty,
-                span,
-            } => {
-                let msg = format!(
+                ..
+            } => (
+                "invalid binary operation".to_string(),
+                format!(
                     "cannot apply '{}' to types {} and {}",
                     op, left_ty, right_ty
-                );
-                format!(
-                    "error: invalid binary operation\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::InvalidUnaryOp {
-                op,
-                operand_ty,
-                span,
-            } => {
-                let msg = format!("cannot apply '{}' to type {}", op, operand_ty);
-                format!(
-                    "error: invalid unary operation\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
+                ),
+            ),
+            SemaError::InvalidUnaryOp { op, operand_ty, .. } => (
+                "invalid unary operation".to_string(),
+                format!("cannot apply '{}' to type {}", op, operand_ty),
+            ),
             SemaError::ArityMismatch {
-                expected,
-                found,
-                span,
-            } => {
-                let msg = format!("expected {} argument(s), found {}", expected, found);
-                format!(
-                    "error: function call\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::ImmutableAssignment { symbol, span } => {
-                let msg = format!("cannot assign to immutable variable '{}'", symbol);
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::CircularImport { path, chain } => {
-                format!(
-                    "error: circular import detected: {} -> {}",
-                    chain.join(" -> "),
-                    path
-                )
-            }
+                expected, found, ..
+            } => (
+                "function call".to_string(),
+                format!("expected {} argument(s), found {}", expected, found),
+            ),
+            SemaError::ImmutableAssignment { symbol, .. } => {
+                same(format!("cannot assign to immutable variable '{}'", symbol))
+            }
+            SemaError::CircularImport { path, chain } => same(format!(
+                "circular import detected: {} -> {}",
+                chain.join(" -> "),
+                path
+            )),
             SemaError::ReturnTypeMismatch {
-                expected,
-                found,
-                span,
-            } => {
-                let msg = format!("expected {}, found {}", expected, found);
-                format!(
-                    "error: return type mismatch\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::ReturnOutsideFunction { span } => {
-                let msg = "return statement outside function".to_string();
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::BreakOutsideLoop { span } => {
-                let msg = "break/continue outside loop".to_string();
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
+                expected, found, ..
+            } => (
+                "return type mismatch".to_string(),
+                format!("expected {}, found {}", expected, found),
+            ),
+            SemaError::ReturnOutsideFunction { .. } => {
+                same("return statement outside function".to_string())
             }
+            SemaError::BreakOutsideLoop { .. } => same("break/continue outside loop".to_string()),
             SemaError::DuplicateSymbol {
                 name,
-                span,
                 previous_span,
-            } => {
-                let msg = if let Some(prev) = previous_span {
-                    format!(
-                        "duplicate symbol '{}' (previously defined at {})",
-                        name,
-                        prev.format_position(source)
-                    )
-                } else {
-                    format!("duplicate symbol '{}'", name)
-                };
+                ..
+            } => same(if let Some(prev) = previous_span {
                 format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
+                    "duplicate symbol '{}' (previously defined at {})",
+                    name,
+                    prev.format_position(source)
                 )
-            }
+            } else {
+                format!("duplicate symbol '{}'", name)
+            }),
             SemaError::FieldNotFound {
                 struct_name,
                 field_name,
-                span,
-            } => {
-                let msg = format!(
-                    "field '{}' not found in struct '{}'",
-                    field_name, struct_name
-                );
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::ImportError { path, reason, span } => {
-                let msg = format!("failed to import '{}': {}", path, reason);
-                format!(
-                    "error: import error\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::OutOfZeroPage { span } => {
-                let msg = "no more zero page addresses available".to_string();
-                format!(
-                    "error: out of zero page memory\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::InstructionConflict { name, span } => {
-                let msg = format!("identifier '{}' conflicts with instruction mnemonic", name);
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::Custom { message, span } => {
-                format!(
-                    "error: {}\n{}",
-                    message,
-                    span.format_error_context(source, filename, message)
-                )
-            }
-            SemaError::ConstantOverflow { value, ty, span } => {
-                let msg = format!("constant value {} does not fit in type {}", value, ty);
-                format!(
-                    "error: constant overflow\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
+                ..
+            } => same(format!(
+                "field '{}' not found in struct '{}'",
+                field_name, struct_name
+            )),
+            SemaError::ImportError { path, reason, .. } => (
+                "import error".to_string(),
+                format!("failed to import '{}': {}", path, reason),
+            ),
+            SemaError::OutOfZeroPage { .. } => (
+                "out of zero page memory".to_string(),
+                "no more zero page addresses available".to_string(),
+            ),
+            SemaError::InstructionConflict { name, .. } => same(format!(
+                "identifier '{}' conflicts with instruction mnemonic",
+                name
+            )),
+            SemaError::Custom { message, .. } => same(message.clone()),
+            SemaError::ConstantOverflow { value, ty, .. } => (
+                "constant overflow".to_string(),
+                format!("constant value {} does not fit in type {}", value, ty),
+            ),
+            SemaError::WriteOnlyRead { name, .. } => {
+                same(format!("cannot read from write-only address '{}'", name))
             }
-            SemaError::WriteOnlyRead { name, span } => {
-                let msg = format!("cannot read from write-only address '{}'", name);
-                format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
+            SemaError::ReadOnlyWrite { name, .. } => {
+                same(format!("cannot write to read-only address '{}'", name))
             }
-            SemaError::ReadOnlyWrite { name, span } => {
-                let msg = format!("cannot write to read-only address '{}'", name);
+            SemaError::InvalidAddrUsage { context, .. } => (
+                "invalid addr usage".to_string(),
                 format!(
-                    "error: {}\n{}",
-                    msg,
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
-            SemaError::InvalidAddrUsage { context, span } => {
-                let msg = format!(
                     "addr type can only be used in const declarations, not {}",
                     context
-                );
-                format!(
-                    "error: invalid addr usage\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
+                ),
+            ),
             SemaError::ArrayIndexOutOfBounds {
-                index,
-                array_size,
-                span,
-            } => {
-                let msg = format!(
+                index, array_size, ..
+            } => (
+                "array index out of bounds".to_string(),
+                format!(
                     "array index {} is out of bounds for array of length {}",
                     index, array_size
-                );
-                format!(
-                    "error: array index out of bounds\n{}",
-                    span.format_error_context(source, filename, &msg)
-                )
-            }
+                ),
+            ),
         }
     }
 }
@@ -535,6 +516,15 @@ impl std::fmt::Display for SemaError {
 
 impl std::error::Error for SemaError {}
 
+/// Every error found by an analysis that failed, with the warnings
+/// collected up to that point
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// In source order
+    pub errors: Vec<SemaError>,
+    pub warnings: Vec<Warning>,
+}
+
 /// Compiler warnings (non-fatal diagnostics)
 #[derive(Debug, Clone)]
 pub enum Warning {
@@ -584,6 +574,41 @@ pub enum Warning {
 impl Warning {
     /// Format warning with source context (similar to error formatting)
     pub fn format_with_source_and_file(&self, source: &str, filename: Option<&str>) -> String {
+        let (message, span) = self.describe();
+        format!(
+            "warning: {}\n{}",
+            message,
+            span.format_error_context(source, filename, &message)
+        )
+    }
+
+    /// Format warning with context from the file its span is in
+    pub fn format_with_sources(&self, sources: &SourceMap) -> String {
+        let (message, span) = self.describe();
+        format!(
+            "warning: {}\n{}",
+            message,
+            sources.format_error_context(span, &message)
+        )
+    }
+
+    /// Stable name of the kind of warning, for machine-readable output
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable { .. } => "unused-variable",
+            Warning::UnusedImport { .. } => "unused-import",
+            Warning::UnreachableCode { .. } => "unreachable-code",
+            Warning::UnusedParameter { .. } => "unused-parameter",
+            Warning::UnusedFunction { .. } => "unused-function",
+            Warning::NonExhaustiveMatch { .. } => "non-exhaustive-match",
+            Warning::NonUppercaseConstant { .. } => "non-uppercase-constant",
+            Warning::ParameterOverflow { .. } => "parameter-overflow",
+            Warning::AddressOverlap { .. } => "address-overlap",
+        }
+    }
+
+    /// The warning's message and where it points
+    pub fn describe(&self) -> (String, Span) {
         let (message, span) = match self {
             Warning::UnusedVariable { name, span } => {
                 (format!("unused variable: `{}`", name), span)
@@ -637,12 +662,7 @@ impl Warning {
                 span,
             ),
         };
-
-        format!(
-            "warning: {}\n{}",
-            message,
-            span.format_error_context(source, filename, &message)
-        )
+        (message, *span)
     }
 }
 
@@ -708,6 +728,10 @@ pub struct ProgramInfo {
     /// Global string pool for cross-module string deduplication
     /// Maps string content to a unique label (e.g., "Hello" -> "str_0")
     pub string_pool: HashMap<String, String>,
+    /// Sections and target the program was analyzed against
+    pub memory_config: crate::config::MemoryConfig,
+    /// Zero page the compiler uses, clear of the target's reserved ranges
+    pub memory_layout: crate::codegen::memory_layout::MemoryLayout,
 }
 
 /// 6502 and 65C02 instruction mnemonics
@@ -741,3 +765,41 @@ pub fn analyze_with_path(ast: &SourceFile, file_path: PathBuf) -> Result<Program
     let mut analyzer = SemanticAnalyzer::with_base_path(file_path);
     analyzer.analyze(ast)
 }
+
+/// Analyze the main file of a build against an explicit memory configuration,
+/// with constants defined on the command line and the `--cpu` processor,
+/// reporting every error
+///
+/// `sources` holds the root file and gains every module the program imports.
+pub fn analyze_program(
+    ast: &SourceFile,
+    file_path: PathBuf,
+    config: crate::config::MemoryConfig,
+    defines: &[(String, i64)],
+    cpu: Option<Cpu>,
+    sources: &mut SourceMap,
+) -> Result<ProgramInfo, Diagnostics> {
+    let mut analyzer = SemanticAnalyzer::with_base_path(file_path)
+        .with_memory_config(config)
+        .with_cpu(cpu)
+        .with_defines(defines);
+    analyzer.sources = std::mem::take(sources);
+    let result = analyzer.analyze_all(ast);
+    *sources = std::mem::take(&mut analyzer.sources);
+    result
+}
+
+/// Analyze for a processor other than the configured target's
+pub fn analyze_for_cpu(ast: &SourceFile, cpu: Cpu) -> Result<ProgramInfo, SemaError> {
+    let mut analyzer = SemanticAnalyzer::new().with_cpu(Some(cpu));
+    analyzer.analyze(ast)
+}
+
+/// Analyze against an explicit memory configuration (a target profile, for instance)
+pub fn analyze_with_config(
+    ast: &SourceFile,
+    config: crate::config::MemoryConfig,
+) -> Result<ProgramInfo, SemaError> {
+    let mut analyzer = SemanticAnalyzer::new().with_memory_config(config);
+    analyzer.analyze(ast)
+}
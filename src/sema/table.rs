@@ -20,6 +20,8 @@ pub enum SymbolKind {
 pub enum SymbolLocation {
     ZeroPage(u8),
     Absolute(u16),
+    /// Above $FFFF, for `addr` declarations reached with 65816 long addressing
+    Long(u32),
     None, // For types or compile-time constants
 }
 
@@ -60,6 +62,11 @@ impl SymbolTable {
         }
     }
 
+    /// Number of open scopes, including the global one
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
     pub fn insert(&mut self, name: String, info: SymbolInfo) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(name, info);
@@ -0,0 +1,915 @@
+//! Instruction Execution
+//!
+//! Registers and the fetch/decode/execute step. Instructions are decoded
+//! through the assembler's opcode table and timed with its cycle table, with
+//! page-crossing and taken-branch penalties added from the actual addresses.
+//!
+//! The 65816 runs in emulation mode until `XCE` switches it to native mode,
+//! where REP/SEP make the accumulator and index registers 16-bit. Code always
+//! runs from bank 0: the program bank is pushed as zero and long jumps keep
+//! only the low 16 bits of their target.
+
+use super::{SimError, Simulator};
+use crate::assembler::AddressingMode::{self, *};
+use crate::assembler::RegisterWidths;
+use crate::assembler::cycles::{self, crosses_page};
+use crate::assembler::opcodes::{Cpu, Opcode};
+use std::sync::OnceLock;
+
+/// CPU registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    /// Processor status (NV-BDIZC; NVMXDIZC in 65816 native mode)
+    pub status: u8,
+    /// High byte of the 65816's 16-bit accumulator
+    pub b: u8,
+    /// High bytes of X and Y, zero while they are 8-bit (65816)
+    pub xh: u8,
+    pub yh: u8,
+    /// High byte of the stack pointer, $01 outside 65816 native mode
+    pub sph: u8,
+    /// Direct page register (65816)
+    pub d: u16,
+    /// Data bank register (65816)
+    pub dbr: u8,
+    /// Emulation mode; only a 65816 leaves it
+    pub emulation: bool,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0xFD,
+            pc: 0,
+            status: Self::UNUSED | Self::INTERRUPT,
+            b: 0,
+            xh: 0,
+            yh: 0,
+            sph: 0x01,
+            d: 0,
+            dbr: 0,
+            emulation: true,
+        }
+    }
+}
+
+impl Registers {
+    pub const CARRY: u8 = 0x01;
+    pub const ZERO: u8 = 0x02;
+    pub const INTERRUPT: u8 = 0x04;
+    pub const DECIMAL: u8 = 0x08;
+    pub const BREAK: u8 = 0x10;
+    pub const UNUSED: u8 = 0x20;
+    pub const OVERFLOW: u8 = 0x40;
+    pub const NEGATIVE: u8 = 0x80;
+    /// 8-bit X and Y in 65816 native mode (the B bit in emulation mode)
+    pub const INDEX_8: u8 = 0x10;
+    /// 8-bit accumulator in 65816 native mode (the unused bit in emulation mode)
+    pub const ACCUMULATOR_8: u8 = 0x20;
+
+    pub fn flag(&self, flag: u8) -> bool {
+        self.status & flag != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.status |= flag;
+        } else {
+            self.status &= !flag;
+        }
+    }
+
+    /// Whether the accumulator is 16-bit (65816 native mode with M clear)
+    pub fn wide_accumulator(&self) -> bool {
+        !self.emulation && !self.flag(Self::ACCUMULATOR_8)
+    }
+
+    /// Whether X and Y are 16-bit (65816 native mode with X clear)
+    pub fn wide_index(&self) -> bool {
+        !self.emulation && !self.flag(Self::INDEX_8)
+    }
+
+    /// The full 16-bit accumulator, B:A
+    pub fn c(&self) -> u16 {
+        u16::from_le_bytes([self.a, self.b])
+    }
+
+    fn set_nz(&mut self, value: u8) {
+        self.set_flag(Self::ZERO, value == 0);
+        self.set_flag(Self::NEGATIVE, value & 0x80 != 0);
+    }
+
+    fn set_nz_wide(&mut self, value: u16, wide: bool) {
+        if wide {
+            self.set_flag(Self::ZERO, value == 0);
+            self.set_flag(Self::NEGATIVE, value & 0x8000 != 0);
+        } else {
+            self.set_nz(value as u8);
+        }
+    }
+}
+
+/// One executed instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// Address of the opcode
+    pub pc: u16,
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// Cycles taken, including page-crossing and branch penalties
+    pub cycles: u8,
+}
+
+type DecodeTable = [Option<&'static Opcode>; 256];
+
+/// Opcode table of a CPU, indexed by opcode byte
+fn decode_table(cpu: Cpu) -> &'static DecodeTable {
+    static NMOS: OnceLock<DecodeTable> = OnceLock::new();
+    static NMOS_UNDOCUMENTED: OnceLock<DecodeTable> = OnceLock::new();
+    static CMOS: OnceLock<DecodeTable> = OnceLock::new();
+    static W65C02: OnceLock<DecodeTable> = OnceLock::new();
+    static W65C816: OnceLock<DecodeTable> = OnceLock::new();
+    let table = match cpu {
+        // The 2A03 decodes like the NMOS part; only ADC and SBC differ
+        Cpu::Nmos6502 | Cpu::Ricoh2A03 => &NMOS,
+        Cpu::Nmos6502Undocumented => &NMOS_UNDOCUMENTED,
+        Cpu::Cmos65C02 => &CMOS,
+        Cpu::W65C02 => &W65C02,
+        Cpu::W65C816 => &W65C816,
+    };
+    table.get_or_init(|| {
+        let mut table = [None; 256];
+        for op in cpu.opcodes() {
+            table[op.code as usize] = Some(op);
+        }
+        table
+    })
+}
+
+/// Where an instruction's operand comes from
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(u16),
+    /// A 24-bit address; only the 65816 goes beyond bank 0
+    Memory(u32),
+    /// Zero-page byte tested by BBR/BBS, and the branch target
+    BitBranch(u16, u16),
+    /// Source and destination bank of MVN/MVP
+    BlockMove(u8, u8),
+}
+
+impl Simulator {
+    /// Execute a single instruction
+    pub fn step(&mut self) -> Result<Step, SimError> {
+        let pc = self.registers.pc;
+        let opcode = self.read(pc);
+        let Some(op) = decode_table(self.cpu)[opcode as usize] else {
+            return Err(SimError::InvalidOpcode { pc, opcode });
+        };
+        let timing = cycles::opcode_cycles(self.cpu, opcode).expect("every opcode has a timing");
+        let widths = RegisterWidths {
+            accumulator: self.registers.wide_accumulator(),
+            index: self.registers.wide_index(),
+        };
+        let wide_immediate = op.mode == Immediate && widths.wide_immediate(op.mnemonic);
+        let next = pc.wrapping_add(op.mode.instruction_size() + wide_immediate as u16);
+        self.registers.pc = next;
+
+        let (operand, page_crossed) = self.operand(op.mode, pc, wide_immediate);
+        let mut cycles = timing.base;
+        if page_crossed && timing.page_penalty {
+            cycles += 1;
+        }
+        cycles += cycles::wide_penalty(op.mnemonic, op.mode, widths.accumulator, widths.index);
+        cycles += self.execute(op.mnemonic, operand, next);
+
+        self.cycles += cycles as u64;
+        Ok(Step {
+            pc,
+            mnemonic: op.mnemonic,
+            mode: op.mode,
+            cycles,
+        })
+    }
+
+    /// Resolve the operand, and whether indexing crossed a page
+    fn operand(&self, mode: AddressingMode, pc: u16, wide_immediate: bool) -> (Operand, bool) {
+        let byte = self.read(pc.wrapping_add(1));
+        let word = self.read_u16(pc.wrapping_add(1));
+        let long = u32::from_le_bytes([
+            byte,
+            self.read(pc.wrapping_add(2)),
+            self.read(pc.wrapping_add(3)),
+            0,
+        ]);
+        let (x, y) = (self.index_x(), self.index_y());
+        let data = |addr: u16| (self.registers.dbr as u32) << 16 | addr as u32;
+        let indexed = |base: u32, index: u16| {
+            let addr = (base + index as u32) & self.address_mask();
+            (
+                Operand::Memory(addr),
+                crosses_page(base as u16, addr as u16),
+            )
+        };
+        let long_pointer = |addr: u32| {
+            u32::from_le_bytes([
+                self.read_long(addr),
+                self.read_long(addr + 1),
+                self.read_long(addr + 2),
+                0,
+            ])
+        };
+        match mode {
+            Implied => (Operand::None, false),
+            Accumulator => (Operand::Accumulator, false),
+            Immediate if wide_immediate => (Operand::Immediate(word), false),
+            Immediate => (Operand::Immediate(byte as u16), false),
+            ZeroPage => (Operand::Memory(self.direct(byte, 0)), false),
+            ZeroPageX => (Operand::Memory(self.direct(byte, x)), false),
+            ZeroPageY => (Operand::Memory(self.direct(byte, y)), false),
+            Absolute => (Operand::Memory(data(word)), false),
+            AbsoluteX => indexed(data(word), x),
+            AbsoluteY => indexed(data(word), y),
+            // The NMOS 6502 never carries into the high byte of the pointer
+            Indirect => {
+                let hi_addr = if self.cpu.has_cmos_instructions() {
+                    word.wrapping_add(1)
+                } else {
+                    (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF)
+                };
+                let target = u16::from_le_bytes([self.read(word), self.read(hi_addr)]);
+                (Operand::Memory(target as u32), false)
+            }
+            IndexedIndirect => (Operand::Memory(data(self.direct_word(byte, x))), false),
+            IndirectIndexed => indexed(data(self.direct_word(byte, 0)), y),
+            ZeroPageIndirect => (Operand::Memory(data(self.direct_word(byte, 0))), false),
+            AbsoluteIndexedIndirect => {
+                let ptr = word.wrapping_add(x);
+                (Operand::Memory(self.read_u16(ptr) as u32), false)
+            }
+            Relative => {
+                let offset = byte as i8 as i16 as u16;
+                let target = pc.wrapping_add(2).wrapping_add(offset);
+                (Operand::Memory(target as u32), false)
+            }
+            ZeroPageRelative => {
+                let offset = self.read(pc.wrapping_add(2)) as i8 as i16 as u16;
+                let target = pc.wrapping_add(3).wrapping_add(offset);
+                (Operand::BitBranch(byte as u16, target), false)
+            }
+            StackRelative => (Operand::Memory(self.stack_relative(byte) as u32), false),
+            StackRelativeIndirectIndexed => {
+                let ptr = self.stack_relative(byte);
+                let base = u16::from_le_bytes([self.read(ptr), self.read(ptr.wrapping_add(1))]);
+                (indexed(data(base), y).0, false)
+            }
+            DirectIndirectLong => (Operand::Memory(long_pointer(self.direct(byte, 0))), false),
+            DirectIndirectLongIndexed => (indexed(long_pointer(self.direct(byte, 0)), y).0, false),
+            AbsoluteLong => (Operand::Memory(long), false),
+            AbsoluteLongX => (indexed(long, x).0, false),
+            AbsoluteIndirectLong => (Operand::Memory(long_pointer(word as u32)), false),
+            RelativeLong => {
+                let target = pc.wrapping_add(3).wrapping_add(word);
+                (Operand::Memory(target as u32), false)
+            }
+            BlockMove => (
+                Operand::BlockMove(self.read(pc.wrapping_add(2)), byte),
+                false,
+            ),
+        }
+    }
+
+    /// Addresses wrap at 64K except on the 65816
+    fn address_mask(&self) -> u32 {
+        if self.cpu.has_native_mode() {
+            0xFF_FFFF
+        } else {
+            0xFFFF
+        }
+    }
+
+    /// Address of a direct-page (zero-page) byte; emulation mode wraps within the page
+    fn direct(&self, byte: u8, index: u16) -> u32 {
+        let d = self.registers.d;
+        if self.registers.emulation {
+            (d | byte.wrapping_add(index as u8) as u16) as u32
+        } else {
+            d.wrapping_add(byte as u16).wrapping_add(index) as u32
+        }
+    }
+
+    /// Pointer stored in the direct page
+    fn direct_word(&self, byte: u8, index: u16) -> u16 {
+        u16::from_le_bytes([
+            self.read_long(self.direct(byte, index)),
+            self.read_long(self.direct(byte, index.wrapping_add(1))),
+        ])
+    }
+
+    fn stack_relative(&self, offset: u8) -> u16 {
+        self.stack_pointer().wrapping_add(offset as u16)
+    }
+
+    fn accumulator(&self) -> u16 {
+        if self.registers.wide_accumulator() {
+            self.registers.c()
+        } else {
+            self.registers.a as u16
+        }
+    }
+
+    /// Set A, and B too while the accumulator is 16-bit
+    fn set_accumulator(&mut self, value: u16) {
+        self.registers.a = value as u8;
+        if self.registers.wide_accumulator() {
+            self.registers.b = (value >> 8) as u8;
+        }
+        self.registers
+            .set_nz_wide(value, self.registers.wide_accumulator());
+    }
+
+    fn index_x(&self) -> u16 {
+        u16::from_le_bytes([self.registers.x, self.registers.xh])
+    }
+
+    fn index_y(&self) -> u16 {
+        u16::from_le_bytes([self.registers.y, self.registers.yh])
+    }
+
+    fn set_index_x(&mut self, value: u16) {
+        let wide = self.registers.wide_index();
+        self.registers.x = value as u8;
+        self.registers.xh = if wide { (value >> 8) as u8 } else { 0 };
+        self.registers.set_nz_wide(value, wide);
+    }
+
+    fn set_index_y(&mut self, value: u16) {
+        let wide = self.registers.wide_index();
+        self.registers.y = value as u8;
+        self.registers.yh = if wide { (value >> 8) as u8 } else { 0 };
+        self.registers.set_nz_wide(value, wide);
+    }
+
+    /// Set P; M and X stay set in emulation mode, and 8-bit index registers
+    /// lose their high bytes
+    pub(super) fn set_status(&mut self, value: u8) {
+        if self.registers.emulation {
+            self.registers.status = value | Registers::UNUSED;
+        } else {
+            self.registers.status = value;
+            if self.registers.flag(Registers::INDEX_8) {
+                self.registers.xh = 0;
+                self.registers.yh = 0;
+            }
+        }
+    }
+
+    /// Status as pushed by PHP and BRK; emulation mode sets B
+    fn pushed_status(&self) -> u8 {
+        if self.registers.emulation {
+            self.registers.status | Registers::UNUSED | Registers::BREAK
+        } else {
+            self.registers.status
+        }
+    }
+
+    fn read_operand(&self, operand: &Operand, wide: bool) -> u16 {
+        match *operand {
+            Operand::Accumulator => self.accumulator(),
+            Operand::Immediate(value) => value,
+            Operand::Memory(addr) if wide => u16::from_le_bytes([
+                self.read_long(addr),
+                self.read_long((addr + 1) & self.address_mask()),
+            ]),
+            Operand::Memory(addr) => self.read_long(addr) as u16,
+            Operand::BitBranch(addr, _) => self.read(addr) as u16,
+            Operand::None | Operand::BlockMove(..) => 0,
+        }
+    }
+
+    fn write_operand(&mut self, operand: &Operand, value: u16, wide: bool) {
+        match *operand {
+            Operand::Accumulator => {
+                self.registers.a = value as u8;
+                if wide {
+                    self.registers.b = (value >> 8) as u8;
+                }
+            }
+            Operand::Memory(addr) => {
+                self.write_long(addr, value as u8);
+                if wide {
+                    self.write_long((addr + 1) & self.address_mask(), (value >> 8) as u8);
+                }
+            }
+            Operand::Immediate(_)
+            | Operand::BitBranch(..)
+            | Operand::BlockMove(..)
+            | Operand::None => {}
+        }
+    }
+
+    fn address(operand: &Operand) -> u16 {
+        match *operand {
+            Operand::Memory(addr) => addr as u16,
+            _ => 0,
+        }
+    }
+
+    /// Bit number of an RMBn/SMBn/BBRn/BBSn mnemonic
+    fn bit_number(mnemonic: &str) -> u8 {
+        mnemonic.as_bytes()[3] - b'0'
+    }
+
+    /// Enter an interrupt handler: BRK, COP, IRQ or NMI
+    ///
+    /// Native mode also pushes the program bank, and pushes P as it is.
+    pub(super) fn enter_interrupt(
+        &mut self,
+        return_address: u16,
+        status: u8,
+        vector: u16,
+        native_vector: u16,
+    ) {
+        let vector = if self.registers.emulation {
+            self.push_u16(return_address);
+            self.push(status);
+            vector
+        } else {
+            self.push(0);
+            self.push_u16(return_address);
+            self.push(self.registers.status);
+            native_vector
+        };
+        self.registers.set_flag(Registers::INTERRUPT, true);
+        if self.cpu.has_cmos_instructions() {
+            self.registers.set_flag(Registers::DECIMAL, false);
+        }
+        self.registers.pc = self.read_u16(vector);
+    }
+
+    /// Carry out an instruction; returns extra cycles from taken branches
+    fn execute(&mut self, mnemonic: &str, operand: Operand, next: u16) -> u8 {
+        let wide_a = self.registers.wide_accumulator();
+        let wide_index = self.registers.wide_index();
+        match mnemonic {
+            // Load/store
+            "LDA" => {
+                let value = self.read_operand(&operand, wide_a);
+                self.set_accumulator(value);
+            }
+            "LDX" => {
+                let value = self.read_operand(&operand, wide_index);
+                self.set_index_x(value);
+            }
+            "LDY" => {
+                let value = self.read_operand(&operand, wide_index);
+                self.set_index_y(value);
+            }
+            "STA" => self.write_operand(&operand, self.accumulator(), wide_a),
+            "STX" => self.write_operand(&operand, self.index_x(), wide_index),
+            "STY" => self.write_operand(&operand, self.index_y(), wide_index),
+            "STZ" => self.write_operand(&operand, 0, wide_a),
+            "LAX" => {
+                self.registers.a = self.read_operand(&operand, false) as u8;
+                self.registers.x = self.registers.a;
+                self.registers.set_nz(self.registers.a);
+            }
+            "SAX" => self.write_operand(
+                &operand,
+                (self.registers.a & self.registers.x) as u16,
+                false,
+            ),
+
+            // Arithmetic and logic
+            "ADC" => {
+                let value = self.read_operand(&operand, wide_a);
+                if wide_a {
+                    self.add_wide(value);
+                } else {
+                    self.add(value as u8);
+                }
+            }
+            "SBC" => {
+                let value = self.read_operand(&operand, wide_a);
+                if wide_a {
+                    self.subtract_wide(value);
+                } else {
+                    self.subtract(value as u8);
+                }
+            }
+            "AND" => {
+                let value = self.accumulator() & self.read_operand(&operand, wide_a);
+                self.set_accumulator(value);
+            }
+            "ORA" => {
+                let value = self.accumulator() | self.read_operand(&operand, wide_a);
+                self.set_accumulator(value);
+            }
+            "EOR" => {
+                let value = self.accumulator() ^ self.read_operand(&operand, wide_a);
+                self.set_accumulator(value);
+            }
+            "BIT" => {
+                let value = self.read_operand(&operand, wide_a);
+                let sign = if wide_a { 0x8000 } else { 0x80 };
+                self.registers
+                    .set_flag(Registers::ZERO, self.accumulator() & value == 0);
+                // BIT #imm only sets Z
+                if !matches!(operand, Operand::Immediate(_)) {
+                    self.registers
+                        .set_flag(Registers::NEGATIVE, value & sign != 0);
+                    self.registers
+                        .set_flag(Registers::OVERFLOW, value & (sign >> 1) != 0);
+                }
+            }
+            _ if mnemonic.starts_with("RMB") || mnemonic.starts_with("SMB") => {
+                let mask = 1 << Self::bit_number(mnemonic);
+                let value = self.read_operand(&operand, false);
+                let result = if mnemonic.starts_with("SMB") {
+                    value | mask
+                } else {
+                    value & !mask
+                };
+                self.write_operand(&operand, result, false);
+            }
+            "TSB" | "TRB" => {
+                let value = self.read_operand(&operand, wide_a);
+                let a = self.accumulator();
+                self.registers.set_flag(Registers::ZERO, a & value == 0);
+                let result = if mnemonic == "TSB" {
+                    value | a
+                } else {
+                    value & !a
+                };
+                self.write_operand(&operand, result, wide_a);
+            }
+
+            // Compare
+            "CMP" => {
+                let value = self.read_operand(&operand, wide_a);
+                self.compare(self.accumulator(), value, wide_a);
+            }
+            "CPX" => {
+                let value = self.read_operand(&operand, wide_index);
+                self.compare(self.index_x(), value, wide_index);
+            }
+            "CPY" => {
+                let value = self.read_operand(&operand, wide_index);
+                self.compare(self.index_y(), value, wide_index);
+            }
+
+            // Increment/decrement
+            "INC" | "DEC" => {
+                let value = self.read_operand(&operand, wide_a);
+                let mut result = if mnemonic == "INC" {
+                    value.wrapping_add(1)
+                } else {
+                    value.wrapping_sub(1)
+                };
+                if !wide_a {
+                    result &= 0xFF;
+                }
+                self.write_operand(&operand, result, wide_a);
+                self.registers.set_nz_wide(result, wide_a);
+            }
+            "DCP" => {
+                let result = (self.read_operand(&operand, false) as u8).wrapping_sub(1);
+                self.write_operand(&operand, result as u16, false);
+                self.compare(self.registers.a as u16, result as u16, false);
+            }
+            "ISC" => {
+                let result = (self.read_operand(&operand, false) as u8).wrapping_add(1);
+                self.write_operand(&operand, result as u16, false);
+                self.subtract(result);
+            }
+            "INX" => self.set_index_x(self.index_x().wrapping_add(1)),
+            "INY" => self.set_index_y(self.index_y().wrapping_add(1)),
+            "DEX" => self.set_index_x(self.index_x().wrapping_sub(1)),
+            "DEY" => self.set_index_y(self.index_y().wrapping_sub(1)),
+
+            // Shifts and rotates
+            "ASL" | "LSR" | "ROL" | "ROR" => {
+                let value = self.read_operand(&operand, wide_a);
+                let top = if wide_a { 15 } else { 7 };
+                let carry_in = self.registers.flag(Registers::CARRY) as u16;
+                let (result, carry_out) = match mnemonic {
+                    "ASL" => (value << 1, value >> top & 1 != 0),
+                    "LSR" => (value >> 1, value & 0x01 != 0),
+                    "ROL" => ((value << 1) | carry_in, value >> top & 1 != 0),
+                    _ => ((value >> 1) | (carry_in << top), value & 0x01 != 0),
+                };
+                let result = if wide_a { result } else { result & 0xFF };
+                self.write_operand(&operand, result, wide_a);
+                self.registers.set_flag(Registers::CARRY, carry_out);
+                self.registers.set_nz_wide(result, wide_a);
+            }
+
+            // Jumps and subroutines
+            "JMP" | "JML" | "BRL" => self.registers.pc = Self::address(&operand),
+            "JSR" => {
+                self.push_u16(next.wrapping_sub(1));
+                self.registers.pc = Self::address(&operand);
+            }
+            "JSL" => {
+                self.push(0);
+                self.push_u16(next.wrapping_sub(1));
+                self.registers.pc = Self::address(&operand);
+            }
+            "RTS" => self.registers.pc = self.pull_u16().wrapping_add(1),
+            "RTL" => {
+                self.registers.pc = self.pull_u16().wrapping_add(1);
+                self.pull();
+            }
+            "RTI" => {
+                let status = self.pull();
+                self.set_status(status);
+                self.registers.pc = self.pull_u16();
+                if !self.registers.emulation {
+                    self.pull();
+                }
+            }
+            "BRK" => {
+                self.enter_interrupt(next.wrapping_add(1), self.pushed_status(), 0xFFFE, 0xFFE6)
+            }
+            "COP" => self.enter_interrupt(next, self.pushed_status(), 0xFFF4, 0xFFE4),
+            // Both wait for good here
+            "WAI" | "STP" => self.registers.pc = next.wrapping_sub(1),
+
+            // Branches
+            "BRA" => {
+                let target = Self::address(&operand);
+                self.registers.pc = target;
+                return crosses_page(next, target) as u8;
+            }
+            _ if mnemonic.starts_with("BBR") || mnemonic.starts_with("BBS") => {
+                let bit_set =
+                    self.read_operand(&operand, false) & (1 << Self::bit_number(mnemonic)) != 0;
+                if let Operand::BitBranch(_, target) = operand
+                    && bit_set == mnemonic.starts_with("BBS")
+                {
+                    self.registers.pc = target;
+                    return 1 + crosses_page(next, target) as u8;
+                }
+            }
+            "BCC" | "BCS" | "BEQ" | "BNE" | "BMI" | "BPL" | "BVC" | "BVS" => {
+                let taken = match mnemonic {
+                    "BCC" => !self.registers.flag(Registers::CARRY),
+                    "BCS" => self.registers.flag(Registers::CARRY),
+                    "BEQ" => self.registers.flag(Registers::ZERO),
+                    "BNE" => !self.registers.flag(Registers::ZERO),
+                    "BMI" => self.registers.flag(Registers::NEGATIVE),
+                    "BPL" => !self.registers.flag(Registers::NEGATIVE),
+                    "BVC" => !self.registers.flag(Registers::OVERFLOW),
+                    _ => self.registers.flag(Registers::OVERFLOW),
+                };
+                if taken {
+                    let target = Self::address(&operand);
+                    self.registers.pc = target;
+                    return 1 + crosses_page(next, target) as u8;
+                }
+            }
+
+            // Register transfers; the destination decides the width
+            "TAX" => self.set_index_x(self.registers.c()),
+            "TAY" => self.set_index_y(self.registers.c()),
+            "TXA" => self.set_accumulator(self.index_x()),
+            "TYA" => self.set_accumulator(self.index_y()),
+            "TXY" => self.set_index_y(self.index_x()),
+            "TYX" => self.set_index_x(self.index_y()),
+            "TSX" => self.set_index_x(self.stack_pointer()),
+            "TXS" => self.set_stack_pointer(self.index_x()),
+            "TCS" => self.set_stack_pointer(self.registers.c()),
+            "TSC" => {
+                let [a, b] = self.stack_pointer().to_le_bytes();
+                (self.registers.a, self.registers.b) = (a, b);
+                self.registers.set_nz_wide(self.registers.c(), true);
+            }
+            "TCD" => {
+                self.registers.d = self.registers.c();
+                self.registers.set_nz_wide(self.registers.d, true);
+            }
+            "TDC" => {
+                let [a, b] = self.registers.d.to_le_bytes();
+                (self.registers.a, self.registers.b) = (a, b);
+                self.registers.set_nz_wide(self.registers.d, true);
+            }
+            "XBA" => {
+                let (a, b) = (self.registers.b, self.registers.a);
+                (self.registers.a, self.registers.b) = (a, b);
+                self.registers.set_nz(a);
+            }
+
+            // Stack
+            "PHA" if wide_a => self.push_u16(self.registers.c()),
+            "PHA" => self.push(self.registers.a),
+            "PHX" if wide_index => self.push_u16(self.index_x()),
+            "PHX" => self.push(self.registers.x),
+            "PHY" if wide_index => self.push_u16(self.index_y()),
+            "PHY" => self.push(self.registers.y),
+            "PHP" => self.push(self.pushed_status()),
+            "PLA" => {
+                let value = if wide_a {
+                    self.pull_u16()
+                } else {
+                    self.pull() as u16
+                };
+                self.set_accumulator(value);
+            }
+            "PLX" | "PLY" => {
+                let value = if wide_index {
+                    self.pull_u16()
+                } else {
+                    self.pull() as u16
+                };
+                if mnemonic == "PLX" {
+                    self.set_index_x(value);
+                } else {
+                    self.set_index_y(value);
+                }
+            }
+            "PLP" => {
+                let status = self.pull();
+                self.set_status(status);
+            }
+            "PHB" => self.push(self.registers.dbr),
+            "PLB" => {
+                self.registers.dbr = self.pull();
+                self.registers.set_nz(self.registers.dbr);
+            }
+            "PHD" => self.push_u16(self.registers.d),
+            "PLD" => {
+                self.registers.d = self.pull_u16();
+                self.registers.set_nz_wide(self.registers.d, true);
+            }
+            "PHK" => self.push(0),
+            "PEA" | "PEI" | "PER" => self.push_u16(Self::address(&operand)),
+
+            // Block moves, one byte per step until the count in C runs out
+            "MVN" | "MVP" => {
+                if let Operand::BlockMove(source, destination) = operand {
+                    let (x, y) = (self.index_x(), self.index_y());
+                    let value = self.read_long((source as u32) << 16 | x as u32);
+                    self.write_long((destination as u32) << 16 | y as u32, value);
+                    let step = if mnemonic == "MVN" { 1 } else { u16::MAX };
+                    let (flags, count) =
+                        (self.registers.status, self.registers.c().wrapping_sub(1));
+                    self.set_index_x(x.wrapping_add(step));
+                    self.set_index_y(y.wrapping_add(step));
+                    self.registers.status = flags;
+                    [self.registers.a, self.registers.b] = count.to_le_bytes();
+                    self.registers.dbr = destination;
+                    if count != 0xFFFF {
+                        self.registers.pc = next.wrapping_sub(3);
+                    }
+                }
+            }
+
+            // Flags and modes
+            "CLC" => self.registers.set_flag(Registers::CARRY, false),
+            "SEC" => self.registers.set_flag(Registers::CARRY, true),
+            "CLI" => self.registers.set_flag(Registers::INTERRUPT, false),
+            "SEI" => self.registers.set_flag(Registers::INTERRUPT, true),
+            "CLD" => self.registers.set_flag(Registers::DECIMAL, false),
+            "SED" => self.registers.set_flag(Registers::DECIMAL, true),
+            "CLV" => self.registers.set_flag(Registers::OVERFLOW, false),
+            "REP" | "SEP" => {
+                let mut bits = self.read_operand(&operand, false) as u8;
+                if self.registers.emulation {
+                    bits &= !(Registers::ACCUMULATOR_8 | Registers::INDEX_8);
+                }
+                let status = if mnemonic == "REP" {
+                    self.registers.status & !bits
+                } else {
+                    self.registers.status | bits
+                };
+                self.set_status(status);
+            }
+            "XCE" => {
+                let carry = self.registers.flag(Registers::CARRY);
+                self.registers
+                    .set_flag(Registers::CARRY, self.registers.emulation);
+                if carry != self.registers.emulation {
+                    // Either way the registers start out 8-bit
+                    self.registers.emulation = carry;
+                    self.registers.status |= Registers::ACCUMULATOR_8 | Registers::INDEX_8;
+                    self.registers.xh = 0;
+                    self.registers.yh = 0;
+                    if carry {
+                        self.registers.sph = 0x01;
+                    }
+                }
+            }
+
+            _ => {}
+        }
+        0
+    }
+
+    fn compare(&mut self, register: u16, value: u16, wide: bool) {
+        self.registers.set_flag(Registers::CARRY, register >= value);
+        self.registers
+            .set_nz_wide(register.wrapping_sub(value), wide);
+    }
+
+    /// 16-bit ADC: two 8-bit additions chained through the carry, with N and
+    /// Z from the whole result
+    fn add_wide(&mut self, value: u16) {
+        let high = self.registers.b;
+        self.add(value as u8);
+        let low = self.registers.a;
+        self.registers.a = high;
+        self.add((value >> 8) as u8);
+        self.registers.b = self.registers.a;
+        self.registers.a = low;
+        self.registers.set_nz_wide(self.registers.c(), true);
+    }
+
+    /// 16-bit SBC, chained like [`Simulator::add_wide`]
+    fn subtract_wide(&mut self, value: u16) {
+        let high = self.registers.b;
+        self.subtract(value as u8);
+        let low = self.registers.a;
+        self.registers.a = high;
+        self.subtract((value >> 8) as u8);
+        self.registers.b = self.registers.a;
+        self.registers.a = low;
+        self.registers.set_nz_wide(self.registers.c(), true);
+    }
+
+    /// ADC, including NMOS decimal mode (N, V and Z follow the binary sum)
+    ///
+    /// The 2A03 ignores the decimal flag.
+    fn add(&mut self, value: u8) {
+        let a = self.registers.a;
+        let carry = self.registers.flag(Registers::CARRY) as u16;
+        let binary = a as u16 + value as u16 + carry;
+
+        if self.registers.flag(Registers::DECIMAL) && self.cpu.has_decimal_mode() {
+            let mut lo = (a & 0x0F) as u16 + (value & 0x0F) as u16 + carry;
+            if lo > 9 {
+                lo += 6;
+            }
+            let mut hi = (a >> 4) as u16 + (value >> 4) as u16 + (lo > 0x0F) as u16;
+            let intermediate = ((hi << 4) | (lo & 0x0F)) as u8;
+            self.registers.set_flag(Registers::ZERO, binary & 0xFF == 0);
+            self.registers.set_flag(Registers::NEGATIVE, intermediate & 0x80 != 0);
+            self.registers.set_flag(
+                Registers::OVERFLOW,
+                (!(a ^ value) & (a ^ intermediate) & 0x80) != 0,
+            );
+            if hi > 9 {
+                hi += 6;
+            }
+            self.registers.set_flag(Registers::CARRY, hi > 0x0F);
+            self.registers.a = ((hi << 4) | (lo & 0x0F)) as u8;
+        } else {
+            let result = binary as u8;
+            self.registers.set_flag(Registers::CARRY, binary > 0xFF);
+            self.registers.set_flag(
+                Registers::OVERFLOW,
+                (!(a ^ value) & (a ^ result) & 0x80) != 0,
+            );
+            self.registers.a = result;
+            self.registers.set_nz(result);
+        }
+    }
+
+    /// SBC, including NMOS decimal mode (all flags follow the binary difference)
+    fn subtract(&mut self, value: u8) {
+        let a = self.registers.a;
+        let borrow = !self.registers.flag(Registers::CARRY) as i16;
+        let binary = a as i16 - value as i16 - borrow;
+        let result = binary as u8;
+
+        self.registers.set_flag(Registers::CARRY, binary >= 0);
+        self.registers.set_flag(
+            Registers::OVERFLOW,
+            ((a ^ value) & (a ^ result) & 0x80) != 0,
+        );
+        self.registers.set_nz(result);
+
+        if self.registers.flag(Registers::DECIMAL) && self.cpu.has_decimal_mode() {
+            let mut lo = (a & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+            let mut hi = (a >> 4) as i16 - (value >> 4) as i16;
+            if lo < 0 {
+                lo -= 6;
+                hi -= 1;
+            }
+            if hi < 0 {
+                hi -= 6;
+            }
+            self.registers.a = ((hi << 4) | (lo & 0x0F)) as u8;
+        } else {
+            self.registers.a = result;
+        }
+    }
+}
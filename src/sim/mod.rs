@@ -0,0 +1,433 @@
+//! 6502 Simulator
+//!
+//! A cycle-counting NMOS 6502 (or 65C02, or 65816) for running compiled
+//! programs. Load
+//! an [`Assembly`], start at the reset vector or call a named function, then
+//! inspect memory and registers. Decoding and timing use the assembler's
+//! opcode and cycle tables, so the simulator runs exactly what the built-in
+//! assembler can produce.
+
+mod cpu;
+
+pub use cpu::{Registers, Step};
+
+use crate::assembler::{Assembly, Cpu};
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+/// Address the simulator returns to when a called function finishes
+///
+/// `$FFFF` is the high byte of the IRQ vector, so code never lives there.
+const RETURN_ADDRESS: u16 = 0xFFFF;
+
+/// Default cycle budget for [`Simulator::run`] callers that don't care
+pub const DEFAULT_CYCLE_LIMIT: u64 = 10_000_000;
+
+/// Why a run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The function started by [`Simulator::call`] returned
+    Returned,
+    /// A jump or branch to itself (`loop {}`), which would spin forever
+    Halted { pc: u16 },
+    /// A `BRK` instruction was executed
+    Break { pc: u16 },
+    /// The cycle budget ran out
+    CycleLimit,
+}
+
+/// An error that stops the simulation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimError {
+    /// The byte at `pc` is not a documented opcode of the simulated CPU
+    InvalidOpcode { pc: u16, opcode: u8 },
+    /// No label or equate with this name was assembled
+    UnknownSymbol(String),
+}
+
+impl std::fmt::Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimError::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            SimError::UnknownSymbol(name) => write!(f, "unknown symbol '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+/// A 6502 with 64K of flat RAM, plus sparse memory above it for the 65816
+pub struct Simulator {
+    pub registers: Registers,
+    /// Instruction set being executed
+    pub cpu: Cpu,
+    /// Cycles executed since the simulator was created
+    pub cycles: u64,
+    memory: Vec<u8>,
+    /// Bytes written above bank 0
+    far: HashMap<u32, u8>,
+    symbols: HashMap<String, u16>,
+    watched: HashSet<u16>,
+    writes: Vec<(u16, u8)>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A CPU in its power-on state with all memory cleared
+    pub fn new() -> Self {
+        Self {
+            registers: Registers::default(),
+            cpu: Cpu::default(),
+            cycles: 0,
+            memory: vec![0; 0x10000],
+            far: HashMap::default(),
+            symbols: HashMap::default(),
+            watched: HashSet::default(),
+            writes: Vec::new(),
+        }
+    }
+
+    /// Create a simulator with an assembled program loaded
+    pub fn with_assembly(assembly: &Assembly) -> Self {
+        let mut sim = Self::new();
+        sim.load(assembly);
+        sim
+    }
+
+    /// Copy every assembled segment into memory and remember the symbols
+    ///
+    /// The simulated CPU becomes the one the program was assembled for.
+    pub fn load(&mut self, assembly: &Assembly) {
+        self.cpu = assembly.cpu;
+        for segment in &assembly.segments {
+            let start = segment.start as usize;
+            self.memory[start..start + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        self.symbols
+            .extend(assembly.symbols.iter().map(|(name, &addr)| (name.clone(), addr)));
+    }
+
+    /// Address of an assembled label or equate
+    pub fn symbol(&self, name: &str) -> Result<u16, SimError> {
+        self.symbols
+            .get(name)
+            .copied()
+            .ok_or_else(|| SimError::UnknownSymbol(name.to_string()))
+    }
+
+    // ========================================================================
+    // Memory
+    // ========================================================================
+
+    pub fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    /// Read a little-endian word
+    pub fn read_u16(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.read(addr), self.read(addr.wrapping_add(1))])
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        if self.watched.contains(&addr) {
+            self.writes.push((addr, value));
+        }
+        self.memory[addr as usize] = value;
+    }
+
+    /// Write a little-endian word
+    pub fn write_u16(&mut self, addr: u16, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.write(addr, lo);
+        self.write(addr.wrapping_add(1), hi);
+    }
+
+    /// Read a byte anywhere in the 65816's 24-bit address space
+    pub fn read_long(&self, addr: u32) -> u8 {
+        match u16::try_from(addr) {
+            Ok(addr) => self.read(addr),
+            Err(_) => self.far.get(&addr).copied().unwrap_or(0),
+        }
+    }
+
+    /// Write a byte anywhere in the 65816's 24-bit address space
+    pub fn write_long(&mut self, addr: u32, value: u8) {
+        match u16::try_from(addr) {
+            Ok(addr) => self.write(addr, value),
+            Err(_) => {
+                self.far.insert(addr, value);
+            }
+        }
+    }
+
+    /// The full 64K address space
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Record every write to `addr`, e.g. a memory-mapped output port
+    pub fn watch(&mut self, addr: u16) {
+        self.watched.insert(addr);
+    }
+
+    /// Values written to a watched address, oldest first
+    pub fn writes_to(&self, addr: u16) -> Vec<u8> {
+        self.writes
+            .iter()
+            .filter(|(a, _)| *a == addr)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    // ========================================================================
+    // Execution
+    // ========================================================================
+
+    /// Reset the CPU: load PC from $FFFC, set I and SP=$FD
+    ///
+    /// A 65816 also returns to emulation mode with a zero direct page and
+    /// data bank. Memory is left untouched, like a real reset.
+    pub fn reset(&mut self) {
+        self.registers.sp = 0xFD;
+        self.registers.sph = 0x01;
+        self.registers.emulation = true;
+        self.registers.d = 0;
+        self.registers.dbr = 0;
+        self.registers.xh = 0;
+        self.registers.yh = 0;
+        self.registers.status |= Registers::INTERRUPT | Registers::UNUSED;
+        self.registers.pc = self.read_u16(0xFFFC);
+        self.cycles += 7;
+    }
+
+    /// Reset, then run the program until it halts, breaks or the budget runs out
+    pub fn run_from_reset(&mut self, max_cycles: u64) -> Result<StopReason, SimError> {
+        self.reset();
+        self.run(max_cycles)
+    }
+
+    /// Call a named function as if by `JSR` and run until it returns
+    pub fn call(&mut self, name: &str, max_cycles: u64) -> Result<StopReason, SimError> {
+        let addr = self.symbol(name)?;
+        self.call_address(addr, max_cycles)
+    }
+
+    /// Call the code at `addr` as if by `JSR` and run until it returns
+    pub fn call_address(&mut self, addr: u16, max_cycles: u64) -> Result<StopReason, SimError> {
+        self.begin_call(addr);
+        self.run(max_cycles)
+    }
+
+    /// Set up a `JSR` to `addr` whose `RTS` ends the run, without running it
+    ///
+    /// A 65816 is put in native mode with 8-bit registers first, as the
+    /// compiled reset code would have left it.
+    pub fn begin_call(&mut self, addr: u16) {
+        if self.cpu.has_native_mode() && self.registers.emulation {
+            self.registers.emulation = false;
+            self.registers.status |= Registers::ACCUMULATOR_8 | Registers::INDEX_8;
+        }
+        self.push_u16(RETURN_ADDRESS.wrapping_sub(1));
+        self.registers.pc = addr;
+        self.cycles += 6;
+    }
+
+    /// Execute instructions until a stop condition, spending at most `max_cycles`
+    pub fn run(&mut self, max_cycles: u64) -> Result<StopReason, SimError> {
+        self.run_with(max_cycles, |_, _| {})
+    }
+
+    /// Like [`Simulator::run`], calling `observe` after every instruction
+    pub fn run_with(
+        &mut self,
+        max_cycles: u64,
+        mut observe: impl FnMut(&Simulator, &Step),
+    ) -> Result<StopReason, SimError> {
+        let limit = self.cycles.saturating_add(max_cycles);
+        while self.cycles < limit {
+            if self.registers.pc == RETURN_ADDRESS {
+                return Ok(StopReason::Returned);
+            }
+            let step = self.step()?;
+            observe(self, &step);
+            if step.mnemonic == "BRK" {
+                return Ok(StopReason::Break { pc: step.pc });
+            }
+            // MVN and MVP repeat themselves until the count runs out
+            if self.registers.pc == step.pc && !matches!(step.mnemonic, "MVN" | "MVP") {
+                return Ok(StopReason::Halted { pc: step.pc });
+            }
+        }
+        Ok(StopReason::CycleLimit)
+    }
+
+    /// Raise a non-maskable interrupt
+    pub fn nmi(&mut self) {
+        self.interrupt(0xFFFA);
+    }
+
+    /// Raise an IRQ; ignored while the I flag is set
+    pub fn irq(&mut self) {
+        if !self.registers.flag(Registers::INTERRUPT) {
+            self.interrupt(0xFFFE);
+        }
+    }
+
+    fn interrupt(&mut self, vector: u16) {
+        let status = (self.registers.status | Registers::UNUSED) & !Registers::BREAK;
+        // Native mode uses the vectors $10 bytes lower
+        self.enter_interrupt(self.registers.pc, status, vector, vector - 0x10);
+        self.cycles += if self.registers.emulation { 7 } else { 8 };
+    }
+
+    /// Push onto the stack; outside 65816 native mode it stays in page 1
+    fn push(&mut self, value: u8) {
+        let sp = self.stack_pointer();
+        self.write(sp, value);
+        self.set_stack_pointer(sp.wrapping_sub(1));
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.set_stack_pointer(self.stack_pointer().wrapping_add(1));
+        self.read(self.stack_pointer())
+    }
+
+    fn stack_pointer(&self) -> u16 {
+        u16::from_le_bytes([self.registers.sp, self.registers.sph])
+    }
+
+    fn set_stack_pointer(&mut self, sp: u16) {
+        self.registers.sp = sp as u8;
+        if !self.registers.emulation {
+            self.registers.sph = (sp >> 8) as u8;
+        }
+    }
+
+    fn push_u16(&mut self, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.push(hi);
+        self.push(lo);
+    }
+
+    fn pull_u16(&mut self) -> u16 {
+        let lo = self.pull();
+        let hi = self.pull();
+        u16::from_le_bytes([lo, hi])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn run(source: &str) -> Simulator {
+        let assembly = assemble(source).unwrap();
+        let mut sim = Simulator::with_assembly(&assembly);
+        let stop = sim.call("start", 100_000).unwrap();
+        assert_eq!(stop, StopReason::Returned);
+        sim
+    }
+
+    #[test]
+    fn test_loop_and_cycles() {
+        let sim = run(".ORG $8000\nstart:\n    LDX #$05\n    LDA #$00\nloop:\n    CLC\n    ADC #$03\n    DEX\n    BNE loop\n    STA $10\n    RTS\n");
+        assert_eq!(sim.read(0x10), 15);
+        assert_eq!(sim.registers.x, 0);
+        // JSR 6 + LDX 2 + LDA 2 + 5 * (CLC 2 + ADC 2 + DEX 2) + 4 taken (3) + 1 not taken (2)
+        // + STA 3 + RTS 6
+        assert_eq!(sim.cycles, 6 + 2 + 2 + 30 + 12 + 2 + 3 + 6);
+    }
+
+    #[test]
+    fn test_decimal_mode() {
+        let sim = run(".ORG $8000\nstart:\n    SED\n    CLC\n    LDA #$19\n    ADC #$28\n    STA $10\n    SEC\n    LDA #$42\n    SBC #$15\n    STA $11\n    CLD\n    RTS\n");
+        assert_eq!(sim.read(0x10), 0x47);
+        assert_eq!(sim.read(0x11), 0x27);
+    }
+
+    #[test]
+    fn test_2a03_has_no_decimal_mode() {
+        let sim = run(".SETCPU \"2A03\"\n.ORG $8000\nstart:\n    SED\n    CLC\n    LDA #$19\n    ADC #$28\n    STA $10\n    SEC\n    LDA #$42\n    SBC #$15\n    STA $11\n    CLD\n    RTS\n");
+        assert_eq!(sim.cpu, Cpu::Ricoh2A03);
+        assert_eq!(sim.read(0x10), 0x41);
+        assert_eq!(sim.read(0x11), 0x2D);
+    }
+
+    #[test]
+    fn test_reset_halt_and_interrupts() {
+        let source = ".ORG $8000\nreset:\n    CLI\nwait:\n    JMP wait\nirq:\n    INC $10\n    RTI\n.ORG $FFFA\n.WORD $0000\n.WORD reset\n.WORD irq\n";
+        let mut sim = Simulator::with_assembly(&assemble(source).unwrap());
+        assert_eq!(sim.run_from_reset(1000), Ok(StopReason::Halted { pc: 0x8001 }));
+        sim.irq();
+        assert_eq!(sim.run(1000), Ok(StopReason::Halted { pc: 0x8001 }));
+        assert_eq!(sim.read(0x10), 1);
+        assert_eq!(sim.registers.sp, 0xFD);
+    }
+
+    #[test]
+    fn test_indirect_jmp_page_bug() {
+        let mut sim = run(".ORG $8000\nstart:\n    RTS\n");
+        sim.write(0x02FF, 0x00);
+        sim.write(0x0200, 0x80);
+        sim.write(0x0300, 0x90);
+        sim.write(0x0400, 0x6C);
+        sim.write_u16(0x0401, 0x02FF);
+        assert_eq!(sim.call_address(0x0400, 100), Ok(StopReason::Returned));
+    }
+
+    #[test]
+    fn test_65c02_instructions() {
+        let sim = run(".SETCPU \"65C02\"\n.ORG $8000\nstart:\n    LDA #$F0\n    STA $10\n    LDA #$0C\n    TSB $10\n    LDA #$30\n    TRB $10\n    STZ $11\n    LDX #$07\n    PHX\n    LDX #$00\n    PLY\n    STY $12\n    INC A\n    BRA done\n    STA $11\ndone:\n    STA $13\n    RTS\n");
+        assert_eq!(sim.cpu, Cpu::Cmos65C02);
+        assert_eq!(sim.read(0x10), 0xCC);
+        assert_eq!(sim.read(0x11), 0x00);
+        assert_eq!(sim.read(0x12), 0x07);
+        assert_eq!(sim.read(0x13), 0x31);
+    }
+
+    #[test]
+    fn test_undocumented_instructions() {
+        let sim = run(".SETCPU \"6502X\"\n.ORG $8000\nstart:\n    LDA #$3C\n    STA $10\n    LDA #$01\n    STA $11\n    LDA #$05\n    STA $12\n    LAX $10\n    LDA #$0F\n    SAX $13\n    LDA #$04\n    DCP $12\n    PHP\n    PLA\n    STA $14\n    SEC\n    LDA #$10\n    ISC $11\n    STA $15\n    RTS\n");
+        assert_eq!(sim.cpu, Cpu::Nmos6502Undocumented);
+        // LAX loaded X with $3C, SAX stored $0F & $3C
+        assert_eq!(sim.read(0x13), 0x0C);
+        // DCP: $05 - 1 = $04, equal to A
+        assert_eq!(sim.read(0x12), 0x04);
+        assert_eq!(sim.read(0x14) & 0x03, 0x03);
+        // ISC: $01 + 1 = $02, $10 - $02 = $0E
+        assert_eq!(sim.read(0x11), 0x02);
+        assert_eq!(sim.read(0x15), 0x0E);
+    }
+
+    #[test]
+    fn test_bit_instructions() {
+        let sim = run(".SETCPU \"W65C02\"\n.ORG $8000\nstart:\n    LDA #$81\n    STA $10\n    SMB3 $10\n    RMB7 $10\n    LDA #$00\n    BBR3 $10,skip\n    LDA #$01\nskip:\n    STA $11\n    LDA #$00\n    BBS0 $10,taken\n    LDA #$01\ntaken:\n    STA $12\n    RTS\n");
+        assert_eq!(sim.cpu, Cpu::W65C02);
+        assert_eq!(sim.read(0x10), 0x09);
+        // BBR3 fell through, BBS0 was taken
+        assert_eq!(sim.read(0x11), 0x01);
+        assert_eq!(sim.read(0x12), 0x00);
+    }
+
+    #[test]
+    fn test_65816_native_mode() {
+        let sim = run(".SETCPU \"65816\"\n.ORG $8000\nstart:\n    REP #$30\n    LDA #$12FF\n    CLC\n    ADC #$0001\n    STA $10\n    LDX #$0300\n    STX $12\n    LDA #$0002\n    LDX #$0010\n    LDY #$0020\n    MVN $00,$00\n    SEP #$30\n    LDA #$AB\n    STA $123456\n    LDA #$00\n    LDA $123456\n    STA $14\n    RTS\n");
+        assert_eq!(sim.cpu, Cpu::W65C816);
+        assert_eq!(sim.read_u16(0x10), 0x1300);
+        assert_eq!(sim.read_u16(0x12), 0x0300);
+        // MVN copied the three bytes at $10 to $20
+        assert_eq!(sim.read(0x20), 0x00);
+        assert_eq!(sim.read(0x21), 0x13);
+        assert_eq!(sim.read(0x22), 0x00);
+        assert_eq!(sim.read_long(0x123456), 0xAB);
+        assert_eq!(sim.read(0x14), 0xAB);
+        assert!(!sim.registers.emulation);
+    }
+}
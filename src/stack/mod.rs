@@ -0,0 +1,560 @@
+//! Hardware Stack Depth
+//!
+//! The 6502 stack is the 256 bytes at `$0100-$01FF`, and an interrupt pushes
+//! on top of whatever the main code has pushed. Wraith passes arguments in
+//! zero page, so the stack only holds return addresses, the registers an
+//! interrupt handler saves, the compiler's short-lived `PHA`/`PHP` scratch
+//! and whatever inline assembly pushes. On the 65816 each function's frame
+//! of locals is on the stack too.
+//!
+//! This walks the call graph from each entry point and adds up the worst
+//! case. Recursion has no static bound, so any cycle other than a self tail
+//! call (which compiles to a loop) or a direct self call in a function
+//! declaring `#[recursion(N)]` is an error. Every call is charged its
+//! return address, even one the peephole optimizer turns into a `JMP`, so
+//! the result is an upper bound.
+
+use crate::assembler::Cpu;
+use crate::ast::{
+    BinaryOp, Expr, FnAttribute, Function, Item, Literal, SourceFile, Span, Spanned, Stmt,
+    VariantData,
+};
+use crate::codegen::stack_frame::{frame_locals, frame_size};
+use crate::sema::ProgramInfo;
+use rustc_hash::FxHashMap as HashMap;
+
+/// Size of the hardware stack in bytes
+pub const STACK_SIZE: u32 = 256;
+
+/// Bytes a `JSR` pushes (the return address)
+const CALL_BYTES: u32 = 2;
+
+/// Bytes above the stack pointer when the reset handler starts
+///
+/// Reset runs the interrupt sequence without writing and leaves SP at `$FD`.
+const RESET_BYTES: u32 = 2;
+
+/// Bytes the CPU pushes when it takes an interrupt (PC and P)
+const VECTOR_BYTES: u32 = 3;
+
+/// Bytes an interrupt handler's prologue pushes (A, X and Y)
+const PROLOGUE_BYTES: u32 = 3;
+
+/// Interrupt entry on the 65816: the CPU also pushes the program bank, and
+/// the prologue saves A, X and Y at 16 bits
+const NATIVE_INTERRUPT_BYTES: u32 = 4 + 6;
+
+/// A hardware vector that starts running Wraith code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPoint {
+    Reset,
+    Irq,
+    Nmi,
+}
+
+impl EntryPoint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryPoint::Reset => "reset",
+            EntryPoint::Irq => "irq",
+            EntryPoint::Nmi => "nmi",
+        }
+    }
+}
+
+/// Worst-case stack use from one entry point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryUsage {
+    pub entry: EntryPoint,
+    /// The handler function
+    pub function: String,
+    /// The handler's name in its declaration
+    pub span: Span,
+    /// Bytes used, including what the CPU pushes to enter the handler
+    pub depth: u32,
+    /// The call chain that reaches that depth, starting with the handler
+    pub path: Vec<String>,
+}
+
+/// Stack use of every entry point in a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackReport {
+    /// Entry points in declaration order
+    pub entries: Vec<EntryUsage>,
+}
+
+impl StackReport {
+    /// Bytes used when an NMI arrives during an IRQ that interrupted the main code
+    ///
+    /// IRQs are masked while an IRQ handler runs, so each kind of entry
+    /// point nests at most once.
+    pub fn worst_case(&self) -> u32 {
+        [EntryPoint::Reset, EntryPoint::Irq, EntryPoint::Nmi]
+            .iter()
+            .map(|entry| {
+                self.entries
+                    .iter()
+                    .filter(|usage| usage.entry == *entry)
+                    .map(|usage| usage.depth)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .sum()
+    }
+}
+
+/// Why the stack use is unbounded or too large
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackError {
+    /// Functions that call each other in a loop, first function repeated at
+    /// the end, and the call that closes the loop
+    Recursion { cycle: Vec<String>, span: Span },
+    /// The worst case doesn't fit in the hardware stack
+    Overflow(StackReport),
+}
+
+impl StackError {
+    /// Stable name of the kind of error, for machine-readable output
+    pub fn code(&self) -> &'static str {
+        match self {
+            StackError::Recursion { .. } => "unbounded-recursion",
+            StackError::Overflow(_) => "stack-overflow",
+        }
+    }
+
+    /// The call that closes the cycle, or the handler that goes deepest
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            StackError::Recursion { span, .. } => Some(*span),
+            StackError::Overflow(report) => report
+                .entries
+                .iter()
+                .max_by_key(|usage| usage.depth)
+                .map(|usage| usage.span),
+        }
+    }
+}
+
+impl std::fmt::Display for StackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackError::Recursion { cycle, .. } => write!(
+                f,
+                "recursion ({}) has no stack bound; only self tail calls and direct self calls in #[recursion(N)] functions are allowed",
+                cycle.join(" -> ")
+            ),
+            StackError::Overflow(report) => {
+                let entries: Vec<_> = report
+                    .entries
+                    .iter()
+                    .map(|usage| format!("{} {}", usage.entry.name(), usage.depth))
+                    .collect();
+                write!(
+                    f,
+                    "worst-case stack use is {} bytes ({}), over the {}-byte hardware stack",
+                    report.worst_case(),
+                    entries.join(", "),
+                    STACK_SIZE
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StackError {}
+
+/// Deepest point of a function, relative to the stack pointer on entry
+#[derive(Debug, Clone, Default)]
+struct Usage {
+    depth: u32,
+    /// Callees on the way to the deepest point
+    path: Vec<String>,
+}
+
+/// Walk state for one function body
+struct Frame<'a> {
+    function: &'a str,
+    /// Bytes inline assembly has pushed and not yet pulled
+    held: u32,
+    deepest: Usage,
+    /// Most stack one level of `#[recursion(N)]` adds for the next
+    recursion_step: u32,
+}
+
+impl Frame<'_> {
+    /// Note that `bytes` more are in use on top of what's held
+    fn reach(&mut self, bytes: u32, path: Vec<String>) {
+        if self.held + bytes > self.deepest.depth {
+            self.deepest = Usage {
+                depth: self.held + bytes,
+                path,
+            };
+        }
+    }
+}
+
+/// Computes worst-case stack use over the call graph
+pub struct StackAnalyzer<'a> {
+    program: &'a ProgramInfo,
+    cpu: Cpu,
+    functions: HashMap<&'a str, &'a Function>,
+    results: HashMap<String, Usage>,
+    in_progress: Vec<String>,
+}
+
+impl<'a> StackAnalyzer<'a> {
+    pub fn new(ast: &'a SourceFile, program: &'a ProgramInfo, cpu: Cpu) -> Self {
+        let mut functions = HashMap::default();
+        for item in ast.items.iter().chain(&program.imported_items) {
+            if let Item::Function(func) = &item.node {
+                functions.entry(func.name.node.as_str()).or_insert(&**func);
+            }
+        }
+        Self {
+            program,
+            cpu,
+            functions,
+            results: HashMap::default(),
+            in_progress: Vec::new(),
+        }
+    }
+
+    /// Stack use from every `#[reset]`, `#[irq]` and `#[nmi]` handler
+    pub fn report(&mut self, ast: &SourceFile) -> Result<StackReport, StackError> {
+        let mut entries = Vec::new();
+        for item in &ast.items {
+            let Item::Function(func) = &item.node else {
+                continue;
+            };
+            let interrupt = if self.cpu.has_native_mode() {
+                NATIVE_INTERRUPT_BYTES
+            } else {
+                VECTOR_BYTES + PROLOGUE_BYTES
+            };
+            let (entry, overhead) = if func.attributes.contains(&FnAttribute::Reset) {
+                (EntryPoint::Reset, RESET_BYTES)
+            } else if func.attributes.contains(&FnAttribute::Irq) {
+                (EntryPoint::Irq, interrupt)
+            } else if func.attributes.contains(&FnAttribute::Nmi) {
+                (EntryPoint::Nmi, interrupt)
+            } else {
+                continue;
+            };
+
+            let name = &func.name.node;
+            let usage = self.function(name, func.name.span)?;
+            let mut path = vec![name.clone()];
+            path.extend(usage.path);
+            entries.push(EntryUsage {
+                entry,
+                function: name.clone(),
+                span: func.name.span,
+                depth: overhead + usage.depth,
+                path,
+            });
+        }
+        Ok(StackReport { entries })
+    }
+
+    /// Deepest point of a call to `name` at `call`, excluding its return address
+    fn function(&mut self, name: &str, call: Span) -> Result<Usage, StackError> {
+        if let Some(usage) = self.results.get(name) {
+            return Ok(usage.clone());
+        }
+        let Some(func) = self.functions.get(name).copied() else {
+            return Ok(Usage::default());
+        };
+        self.enter(func, call)?;
+
+        // The 65816 keeps scalar locals in a frame on the stack; every
+        // candidate is charged, although some may stay in zero page
+        let locals = if self.cpu.has_native_mode() {
+            frame_size(&frame_locals(func, self.program)) as u32
+        } else {
+            0
+        };
+        let mut frame = Frame {
+            function: &func.name.node,
+            held: locals,
+            deepest: Usage::default(),
+            recursion_step: 0,
+        };
+        frame.reach(0, Vec::new());
+        self.walk_stmt(&mut frame, &func.body)?;
+
+        // Every nested call to itself repeats the frame up to the call site
+        let limit = func.attributes.iter().find_map(|attr| match attr {
+            FnAttribute::Recursion(limit) => Some(*limit),
+            _ => None,
+        });
+        if let Some(limit) = limit {
+            frame.deepest.depth += (limit - 1) * frame.recursion_step;
+        }
+
+        self.in_progress.pop();
+        self.results.insert(name.to_string(), frame.deepest.clone());
+        Ok(frame.deepest)
+    }
+
+    /// Start walking a function called at `call`, failing if it's already
+    /// being walked
+    fn enter(&mut self, func: &Function, call: Span) -> Result<(), StackError> {
+        let name = &func.name.node;
+        if let Some(start) = self.in_progress.iter().position(|f| f == name) {
+            let mut cycle = self.in_progress[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(StackError::Recursion { cycle, span: call });
+        }
+        self.in_progress.push(name.to_string());
+        Ok(())
+    }
+
+    fn call(&mut self, frame: &mut Frame<'a>, name: &str, span: Span) -> Result<(), StackError> {
+        let Some(func) = self.functions.get(name).copied() else {
+            return Ok(());
+        };
+
+        // A bounded self call is charged once the body has been walked
+        if name == frame.function
+            && func
+                .attributes
+                .iter()
+                .any(|attr| matches!(attr, FnAttribute::Recursion(_)))
+        {
+            frame.recursion_step = frame.recursion_step.max(frame.held + CALL_BYTES);
+            return Ok(());
+        }
+
+        // Inline functions are expanded in place, without a JSR
+        if func.attributes.contains(&FnAttribute::Inline) {
+            self.enter(func, span)?;
+            let caller = std::mem::replace(&mut frame.function, &func.name.node);
+            self.walk_stmt(frame, &func.body)?;
+            frame.function = caller;
+            self.in_progress.pop();
+            return Ok(());
+        }
+
+        let usage = self.function(name, span)?;
+        let mut path = vec![name.to_string()];
+        path.extend(usage.path);
+        frame.reach(CALL_BYTES + usage.depth, path);
+        Ok(())
+    }
+
+    fn walk_stmt(&mut self, frame: &mut Frame<'a>, stmt: &Spanned<Stmt>) -> Result<(), StackError> {
+        match &stmt.node {
+            Stmt::VarDecl { init, .. } => self.walk_expr(frame, init)?,
+            Stmt::Assign { target, value } => {
+                self.walk_expr(frame, target)?;
+                self.walk_expr(frame, value)?;
+            }
+            Stmt::Expr(expr) => self.walk_expr(frame, expr)?,
+            Stmt::Return(Some(expr)) => {
+                // A self tail call becomes a jump back to the top of the function
+                let tail_call = self
+                    .program
+                    .tail_call_info
+                    .get(frame.function)
+                    .is_some_and(|info| info.tail_recursive_returns.contains(&stmt.span));
+                match &expr.node {
+                    Expr::Call { function, args }
+                        if tail_call && function.node == frame.function =>
+                    {
+                        for arg in args {
+                            self.walk_expr(frame, arg)?;
+                        }
+                    }
+                    _ => self.walk_expr(frame, expr)?,
+                }
+            }
+            Stmt::Return(None) | Stmt::Break | Stmt::Continue => {}
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.walk_expr(frame, condition)?;
+                let held = frame.held;
+                self.walk_stmt(frame, then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    let after_then = std::mem::replace(&mut frame.held, held);
+                    self.walk_stmt(frame, else_branch)?;
+                    frame.held = frame.held.max(after_then);
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.walk_expr(frame, condition)?;
+                self.walk_stmt(frame, body)?;
+            }
+            Stmt::Loop { body, .. } => self.walk_stmt(frame, body)?,
+            Stmt::For { range, body, .. } => {
+                self.walk_expr(frame, &range.start)?;
+                self.walk_expr(frame, &range.end)?;
+                self.walk_stmt(frame, body)?;
+            }
+            Stmt::ForEach { iterable, body, .. } => {
+                self.walk_expr(frame, iterable)?;
+                self.walk_stmt(frame, body)?;
+            }
+            Stmt::Match { expr, arms } => {
+                self.walk_expr(frame, expr)?;
+                let held = frame.held;
+                let mut after = held;
+                for arm in arms {
+                    frame.held = held;
+                    self.walk_stmt(frame, &arm.body)?;
+                    after = after.max(frame.held);
+                }
+                frame.held = after;
+            }
+            Stmt::Block(stmts) => {
+                for stmt in stmts {
+                    self.walk_stmt(frame, stmt)?;
+                }
+            }
+            Stmt::Asm { lines } => {
+                for line in lines {
+                    for instruction in line.instruction.lines() {
+                        self.walk_asm(frame, instruction, stmt.span)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Track pushes, pulls and calls in one line of the inline assembly at `span`
+    fn walk_asm(
+        &mut self,
+        frame: &mut Frame<'a>,
+        line: &str,
+        span: Span,
+    ) -> Result<(), StackError> {
+        let code = line.split(';').next().unwrap_or("");
+        let mut parts = code
+            .split_whitespace()
+            .skip_while(|part| part.ends_with(':'));
+        let Some(mnemonic) = parts.next() else {
+            return Ok(());
+        };
+        match mnemonic.to_ascii_uppercase().as_str() {
+            "PHA" | "PHP" | "PHX" | "PHY" => {
+                frame.held += 1;
+                frame.reach(0, Vec::new());
+            }
+            "PLA" | "PLP" | "PLX" | "PLY" => frame.held = frame.held.saturating_sub(1),
+            "JSR" => {
+                let target = parts.next().unwrap_or("");
+                if self.functions.contains_key(target) {
+                    self.call(frame, target, span)?;
+                } else {
+                    frame.reach(CALL_BYTES, vec![target.to_string()]);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn walk_expr(&mut self, frame: &mut Frame<'a>, expr: &Spanned<Expr>) -> Result<(), StackError> {
+        match &expr.node {
+            Expr::Literal(Literal::Array(elements)) => {
+                for element in elements {
+                    self.walk_expr(frame, element)?;
+                }
+            }
+            Expr::Literal(Literal::ArrayFill { value, .. }) => self.walk_expr(frame, value)?,
+            Expr::Literal(_)
+            | Expr::Variable(_)
+            | Expr::CpuFlagCarry
+            | Expr::CpuFlagOverflow
+            | Expr::CpuFlagNegative => {}
+            // Read through PHP/PLA
+            Expr::CpuFlagZero => frame.reach(1, Vec::new()),
+            Expr::Binary { left, op, right } => {
+                self.walk_expr(frame, left)?;
+                self.walk_expr(frame, right)?;
+                let wide = self
+                    .program
+                    .resolved_types
+                    .get(&left.span)
+                    .is_some_and(|ty| ty.size() == 2);
+                if wide {
+                    match op {
+                        // The low byte of the result is parked with PHA
+                        BinaryOp::Add | BinaryOp::Sub => frame.reach(1, Vec::new()),
+                        BinaryOp::Mul => frame.reach(CALL_BYTES, vec!["mul16".to_string()]),
+                        BinaryOp::Div => frame.reach(CALL_BYTES, vec!["div16".to_string()]),
+                        BinaryOp::Mod => frame.reach(CALL_BYTES, vec!["mod16".to_string()]),
+                        _ => {}
+                    }
+                }
+            }
+            Expr::Unary { operand, .. } => self.walk_expr(frame, operand)?,
+            Expr::Cast { expr, .. }
+            | Expr::Field { object: expr, .. }
+            | Expr::SliceLen(expr)
+            | Expr::U16Low(expr)
+            | Expr::U16High(expr)
+            | Expr::Paren(expr) => self.walk_expr(frame, expr)?,
+            Expr::Index { object, index } => {
+                self.walk_expr(frame, object)?;
+                self.walk_expr(frame, index)?;
+            }
+            Expr::Slice {
+                object, start, end, ..
+            } => {
+                self.walk_expr(frame, object)?;
+                self.walk_expr(frame, start)?;
+                self.walk_expr(frame, end)?;
+            }
+            Expr::Call { function, args } => {
+                for arg in args {
+                    self.walk_expr(frame, arg)?;
+                }
+                self.call(frame, &function.node, expr.span)?;
+            }
+            Expr::StructInit { fields, .. } | Expr::AnonStructInit { fields } => {
+                for field in fields {
+                    self.walk_expr(frame, &field.value)?;
+                }
+            }
+            Expr::EnumVariant { data, .. } => match data {
+                VariantData::Unit => {}
+                VariantData::Tuple(values) => {
+                    for value in values {
+                        self.walk_expr(frame, value)?;
+                    }
+                }
+                VariantData::Struct(fields) => {
+                    for field in fields {
+                        self.walk_expr(frame, &field.value)?;
+                    }
+                }
+            },
+            Expr::Match { expr, arms } => {
+                self.walk_expr(frame, expr)?;
+                for arm in arms {
+                    self.walk_expr(frame, &arm.body)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Worst-case stack use of a program, failing on recursion or overflow
+pub fn analyze(
+    ast: &SourceFile,
+    program: &ProgramInfo,
+    cpu: Cpu,
+) -> Result<StackReport, StackError> {
+    let report = StackAnalyzer::new(ast, program, cpu).report(ast)?;
+    if report.worst_case() > STACK_SIZE {
+        return Err(StackError::Overflow(report));
+    }
+    Ok(report)
+}
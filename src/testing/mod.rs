@@ -0,0 +1,130 @@
+//! Wraith Unit Tests
+//!
+//! Finds `#[test]` functions in a program and its imports and runs each one
+//! in a fresh simulator. A test passes when it returns (and, for tests that
+//! return `bool`, returns `true`); `assert` traps with `BRK`, which is
+//! reported as a failure at the source line of the assertion.
+
+use crate::assembler::Assembly;
+use crate::ast::{FnAttribute, Item, SourceFile, SourceMap};
+use crate::codegen::CodegenOutput;
+use crate::codegen::source_map::{LineEntry, line_table};
+use crate::sema::ProgramInfo;
+use crate::sim::{DEFAULT_CYCLE_LIMIT, Simulator, StopReason};
+use rustc_hash::FxHashSet as HashSet;
+
+/// A `#[test]` function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub name: String,
+    /// The test reports its result by returning a `bool`
+    pub returns_bool: bool,
+    /// Defined in an imported module rather than the main file
+    pub imported: bool,
+}
+
+/// How a test ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    Failed(String),
+}
+
+/// The result of running one test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: TestOutcome,
+    /// Cycles from the call to the return (or failure)
+    pub cycles: u64,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == TestOutcome::Passed
+    }
+}
+
+/// All `#[test]` functions, main file first, in declaration order
+pub fn discover_tests(ast: &SourceFile, program: &ProgramInfo) -> Vec<TestCase> {
+    let mut seen = HashSet::default();
+    let main_items = ast.items.iter().map(|item| (item, false));
+    let imported_items = program.imported_items.iter().map(|item| (item, true));
+
+    main_items
+        .chain(imported_items)
+        .filter_map(|(item, imported)| match &item.node {
+            Item::Function(func) if func.attributes.contains(&FnAttribute::Test) => {
+                Some(TestCase {
+                    name: func.name.node.clone(),
+                    returns_bool: func.return_type.is_some(),
+                    imported,
+                })
+            }
+            _ => None,
+        })
+        .filter(|test| seen.insert(test.name.clone()))
+        .collect()
+}
+
+/// Runs tests against a test image (codegen output built with `include_tests`)
+pub struct TestRunner<'a> {
+    assembly: &'a Assembly,
+    lines: Vec<LineEntry>,
+    sources: &'a SourceMap,
+    /// Cycle budget for each test
+    pub max_cycles: u64,
+}
+
+impl<'a> TestRunner<'a> {
+    pub fn new(output: &CodegenOutput, assembly: &'a Assembly, sources: &'a SourceMap) -> Self {
+        Self {
+            assembly,
+            lines: line_table(output, assembly, sources),
+            sources,
+            max_cycles: DEFAULT_CYCLE_LIMIT,
+        }
+    }
+
+    /// Run every test, each in its own simulator
+    pub fn run_all(&self, tests: &[TestCase]) -> Vec<TestResult> {
+        tests.iter().map(|test| self.run(test)).collect()
+    }
+
+    /// Run a single test in a fresh simulator
+    pub fn run(&self, test: &TestCase) -> TestResult {
+        let mut sim = Simulator::with_assembly(self.assembly);
+        let outcome = match sim.call(&test.name, self.max_cycles) {
+            Ok(StopReason::Returned) if test.returns_bool && sim.registers.a == 0 => {
+                TestOutcome::Failed("returned false".to_string())
+            }
+            Ok(StopReason::Returned) => TestOutcome::Passed,
+            Ok(StopReason::Break { pc }) => {
+                TestOutcome::Failed(format!("assertion failed at {}", self.location(pc)))
+            }
+            Ok(StopReason::Halted { pc }) => TestOutcome::Failed(format!(
+                "never returned (stuck in a loop at {})",
+                self.location(pc)
+            )),
+            Ok(StopReason::CycleLimit) => {
+                TestOutcome::Failed(format!("did not return within {} cycles", self.max_cycles))
+            }
+            Err(e) => TestOutcome::Failed(e.to_string()),
+        };
+
+        TestResult {
+            name: test.name.clone(),
+            outcome,
+            cycles: sim.cycles,
+        }
+    }
+
+    /// Source location of an address, or the address itself
+    fn location(&self, pc: u16) -> String {
+        self.lines
+            .iter()
+            .find(|entry| entry.start <= pc && pc <= entry.end)
+            .map(|entry| format!("{}:{}", self.sources.name(entry.file), entry.line))
+            .unwrap_or_else(|| format!("${:04X}", pc))
+    }
+}
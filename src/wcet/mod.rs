@@ -0,0 +1,653 @@
+//! Worst-Case Execution Time
+//!
+//! Computes an upper bound on the cycles a function takes, from its first
+//! instruction to its `RTS`/`RTI`, by walking the control-flow graph of the
+//! assembled code. Loops are the back edges of that graph and are charged
+//! their iteration bound: one the user gave with `#[bound(N)]`, one inferred
+//! from a constant `for` range, or one the compiler knows for the loops it
+//! generates itself. Calls add the callee's worst case.
+//!
+//! The bound covers the function alone. An interrupt handler is also delayed
+//! by the 7-cycle interrupt sequence and by the instruction that was running
+//! when the interrupt arrived.
+//!
+//! On the 65816 the analysis does not follow REP/SEP, so every instruction
+//! that could work on a 16-bit register is charged as if it did.
+
+use crate::assembler::Assembly;
+use crate::assembler::cycles::{Cycles, crosses_page, opcode_cycles, wide_penalty};
+use crate::assembler::opcodes::AddressingMode;
+use crate::ast::{FnAttribute, Item, SourceFile, SourceMap, Span};
+use crate::codegen::CodegenOutput;
+use crate::codegen::source_map::{LineEntry, line_table};
+use crate::sema::ProgramInfo;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use std::collections::BTreeMap;
+
+/// Why a worst case could not be computed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WcetError {
+    /// No function with this name was placed
+    UnknownFunction(String),
+    /// A loop has no known iteration bound
+    UnboundedLoop {
+        function: String,
+        location: String,
+        span: Option<Span>,
+    },
+    /// The function calls itself, directly or through others
+    Recursion {
+        function: String,
+        span: Option<Span>,
+    },
+    /// `JSR` or `JMP` to code outside every function
+    UnknownTarget {
+        function: String,
+        location: String,
+        span: Option<Span>,
+    },
+    /// `JMP (addr)` or `JMP (addr,X)`, whose target is only known at runtime
+    IndirectJump {
+        function: String,
+        location: String,
+        span: Option<Span>,
+    },
+    /// Control flow that doesn't form properly nested loops
+    Irreducible {
+        function: String,
+        location: String,
+        span: Option<Span>,
+    },
+    /// Bytes that don't decode as an instruction
+    InvalidOpcode {
+        function: String,
+        location: String,
+        span: Option<Span>,
+    },
+    /// Every path loops forever
+    NeverReturns {
+        function: String,
+        span: Option<Span>,
+    },
+    /// The worst case is over the function's `#[max_cycles]` budget
+    OverBudget {
+        function: String,
+        cycles: u64,
+        budget: u32,
+        span: Option<Span>,
+    },
+}
+
+impl WcetError {
+    /// Stable name of the kind of error, for machine-readable output
+    pub fn code(&self) -> &'static str {
+        match self {
+            WcetError::UnknownFunction(_) => "unknown-function",
+            WcetError::UnboundedLoop { .. } => "unbounded-loop",
+            WcetError::Recursion { .. } => "timing-recursion",
+            WcetError::UnknownTarget { .. } => "unknown-jump-target",
+            WcetError::IndirectJump { .. } => "indirect-jump",
+            WcetError::Irreducible { .. } => "irreducible-control-flow",
+            WcetError::InvalidOpcode { .. } => "invalid-opcode",
+            WcetError::NeverReturns { .. } => "never-returns",
+            WcetError::OverBudget { .. } => "over-budget",
+        }
+    }
+
+    /// Where the problem is: the instruction, or the function's declaration
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            WcetError::UnknownFunction(_) => None,
+            WcetError::UnboundedLoop { span, .. }
+            | WcetError::Recursion { span, .. }
+            | WcetError::UnknownTarget { span, .. }
+            | WcetError::IndirectJump { span, .. }
+            | WcetError::Irreducible { span, .. }
+            | WcetError::InvalidOpcode { span, .. }
+            | WcetError::NeverReturns { span, .. }
+            | WcetError::OverBudget { span, .. } => *span,
+        }
+    }
+}
+
+impl std::fmt::Display for WcetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WcetError::UnknownFunction(name) => write!(f, "unknown function '{}'", name),
+            WcetError::UnboundedLoop {
+                function, location, ..
+            } => write!(
+                f,
+                "loop at {} in '{}' has no iteration bound (add #[bound(N)])",
+                location, function
+            ),
+            WcetError::Recursion { function, .. } => {
+                write!(
+                    f,
+                    "'{}' is recursive, so its worst case is unbounded",
+                    function
+                )
+            }
+            WcetError::UnknownTarget {
+                function, location, ..
+            } => write!(
+                f,
+                "'{}' jumps to code outside any function at {}",
+                function, location
+            ),
+            WcetError::IndirectJump {
+                function, location, ..
+            } => {
+                write!(f, "indirect jump at {} in '{}'", location, function)
+            }
+            WcetError::Irreducible {
+                function, location, ..
+            } => write!(
+                f,
+                "control flow at {} in '{}' is not a simple loop",
+                location, function
+            ),
+            WcetError::InvalidOpcode {
+                function, location, ..
+            } => {
+                write!(f, "invalid opcode at {} in '{}'", location, function)
+            }
+            WcetError::NeverReturns { function, .. } => write!(f, "'{}' never returns", function),
+            WcetError::OverBudget {
+                function,
+                cycles,
+                budget,
+                ..
+            } => write!(
+                f,
+                "'{}' takes up to {} cycles, over its #[max_cycles({})] budget",
+                function, cycles, budget
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WcetError {}
+
+/// A function with generated code and its `#[max_cycles]` budget, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedFunction {
+    pub name: String,
+    pub budget: Option<u32>,
+    /// The function's name in its declaration
+    pub span: Span,
+}
+
+/// Every generated function, main file first, in declaration order
+pub fn timed_functions(ast: &SourceFile, program: &ProgramInfo) -> Vec<TimedFunction> {
+    let mut seen = HashSet::default();
+    ast.items
+        .iter()
+        .chain(&program.imported_items)
+        .filter_map(|item| match &item.node {
+            Item::Function(func)
+                if !func.attributes.contains(&FnAttribute::Inline)
+                    && !func.attributes.contains(&FnAttribute::Test) =>
+            {
+                Some(TimedFunction {
+                    name: func.name.node.clone(),
+                    budget: func.attributes.iter().find_map(|attr| match attr {
+                        FnAttribute::MaxCycles(cycles) => Some(*cycles),
+                        _ => None,
+                    }),
+                    span: func.name.span,
+                })
+            }
+            _ => None,
+        })
+        .filter(|func| seen.insert(func.name.clone()))
+        .collect()
+}
+
+/// How control leaves an instruction
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    Next,
+    Branch(u16),
+    Jump(u16),
+    /// `JSR` to a function, with the callee's worst case
+    Call(u64),
+    /// `JMP` to another function's entry, with the callee's worst case
+    TailCall(u64),
+    Return,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    next: u16,
+    cycles: Cycles,
+    flow: Flow,
+}
+
+impl Instruction {
+    /// Each way out of the instruction, with its worst-case cost
+    ///
+    /// `None` leaves the function.
+    fn edges(&self) -> Vec<(Option<u16>, u64)> {
+        let base = (self.cycles.base + self.cycles.page_penalty as u8) as u64;
+        match self.flow {
+            Flow::Next => vec![(Some(self.next), base)],
+            Flow::Branch(target) => {
+                let taken = base + 1 + crosses_page(self.next, target) as u64;
+                vec![(Some(self.next), base), (Some(target), taken)]
+            }
+            Flow::Jump(target) => vec![(Some(target), base)],
+            Flow::Call(callee) => vec![(Some(self.next), base + callee)],
+            Flow::TailCall(callee) => vec![(None, base + callee)],
+            Flow::Return => vec![(None, base)],
+        }
+    }
+}
+
+/// Longest paths through a region of the control-flow graph
+#[derive(Default)]
+struct Region {
+    /// Longest path from the region's entry back to it (loops only)
+    back: Option<u64>,
+    /// Longest path from the entry out of the region
+    exit: Option<u64>,
+    /// Where those exits go (`None` leaves the function)
+    targets: Vec<Option<u16>>,
+}
+
+impl Region {
+    fn leave(&mut self, cycles: u64, target: Option<u16>) {
+        self.exit = Some(self.exit.map_or(cycles, |exit| exit.max(cycles)));
+        if !self.targets.contains(&target) {
+            self.targets.push(target);
+        }
+    }
+}
+
+/// Computes worst-case cycle counts for the functions of a program
+pub struct WcetAnalyzer<'a> {
+    assembly: &'a Assembly,
+    /// Size of the instruction at each address; 65816 immediates vary
+    sizes: HashMap<u16, u16>,
+    /// (start, end inclusive, name), sorted by start
+    functions: Vec<(u16, u16, String)>,
+    bounds: HashMap<u16, u32>,
+    lines: Vec<LineEntry>,
+    /// Source of the instruction at each address
+    spans: HashMap<u16, Span>,
+    sources: &'a SourceMap,
+    results: HashMap<u16, Result<u64, WcetError>>,
+    in_progress: Vec<u16>,
+}
+
+impl<'a> WcetAnalyzer<'a> {
+    pub fn new(output: &CodegenOutput, assembly: &'a Assembly, sources: &'a SourceMap) -> Self {
+        let mut functions: Vec<_> = output
+            .section_alloc
+            .allocations
+            .iter()
+            .filter(|allocation| assembly.symbol(&allocation.name) == Some(allocation.start))
+            .map(|allocation| (allocation.start, allocation.end, allocation.name.clone()))
+            .collect();
+        functions.sort_by_key(|&(start, _, _)| start);
+
+        let bounds = output
+            .loop_bounds
+            .iter()
+            .filter_map(|(label, bound)| Some((assembly.symbol(label)?, *bound)))
+            .collect();
+
+        let sizes = assembly
+            .lines
+            .iter()
+            .map(|line| (line.address, line.bytes.len() as u16))
+            .collect();
+
+        let spans = assembly
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let span = output.line_spans.get(line.line - 1).copied().flatten()?;
+                Some((line.address, span))
+            })
+            .collect();
+
+        Self {
+            assembly,
+            sizes,
+            functions,
+            bounds,
+            lines: line_table(output, assembly, sources),
+            spans,
+            sources,
+            results: HashMap::default(),
+            in_progress: Vec::new(),
+        }
+    }
+
+    /// Check every `#[max_cycles]` budget, returning the failures
+    pub fn check_budgets(&mut self, functions: &[TimedFunction]) -> Vec<WcetError> {
+        functions
+            .iter()
+            .filter_map(|func| {
+                let budget = func.budget?;
+                match self.function(&func.name) {
+                    Ok(cycles) if cycles > budget as u64 => Some(WcetError::OverBudget {
+                        function: func.name.clone(),
+                        cycles,
+                        budget,
+                        span: Some(func.span),
+                    }),
+                    Ok(_) => None,
+                    Err(e) => Some(e),
+                }
+            })
+            .collect()
+    }
+
+    /// Worst-case cycles of a call to `name`, excluding the `JSR` itself
+    pub fn function(&mut self, name: &str) -> Result<u64, WcetError> {
+        let index = self
+            .functions
+            .iter()
+            .position(|(_, _, function)| function == name)
+            .ok_or_else(|| WcetError::UnknownFunction(name.to_string()))?;
+        self.analyze(index)
+    }
+
+    fn analyze(&mut self, index: usize) -> Result<u64, WcetError> {
+        let start = self.functions[index].0;
+        if let Some(result) = self.results.get(&start) {
+            return result.clone();
+        }
+        if self.in_progress.contains(&start) {
+            return Err(WcetError::Recursion {
+                function: self.functions[index].2.clone(),
+                span: self.function_span(index),
+            });
+        }
+
+        self.in_progress.push(start);
+        let result = self.compute(index);
+        self.in_progress.pop();
+        self.results.insert(start, result.clone());
+        result
+    }
+
+    fn compute(&mut self, index: usize) -> Result<u64, WcetError> {
+        let (start, end, _) = self.functions[index];
+        let instructions = self.decode(index)?;
+
+        // Every jump back to an earlier address closes a loop; the loop runs
+        // from its target to the last jump back there
+        let mut loops: BTreeMap<u16, u16> = BTreeMap::new();
+        for (&addr, instruction) in &instructions {
+            if let Flow::Branch(target) | Flow::Jump(target) = instruction.flow
+                && target <= addr
+            {
+                let tail = loops.entry(target).or_insert(addr);
+                *tail = (*tail).max(addr);
+            }
+        }
+        self.check_nesting(index, &instructions, &loops)?;
+
+        let region = self.region(index, &instructions, &loops, start, end, false)?;
+        region.exit.ok_or_else(|| WcetError::NeverReturns {
+            function: self.functions[index].2.clone(),
+            span: self.function_span(index),
+        })
+    }
+
+    /// Decode every instruction reachable from the function's entry
+    fn decode(&mut self, index: usize) -> Result<BTreeMap<u16, Instruction>, WcetError> {
+        let (start, end, _) = self.functions[index];
+        let mut instructions = BTreeMap::new();
+        let mut pending = vec![start];
+
+        while let Some(addr) = pending.pop() {
+            if instructions.contains_key(&addr) {
+                continue;
+            }
+            if addr < start || addr > end {
+                return Err(self.error_at(index, addr, |function, location, span| {
+                    WcetError::UnknownTarget {
+                        function,
+                        location,
+                        span,
+                    }
+                }));
+            }
+            let cpu = self.assembly.cpu;
+            let Some(opcode) = self.assembly.read(addr).and_then(|code| cpu.decode(code)) else {
+                return Err(self.error_at(index, addr, |function, location, span| {
+                    WcetError::InvalidOpcode {
+                        function,
+                        location,
+                        span,
+                    }
+                }));
+            };
+            let mut cycles = opcode_cycles(cpu, opcode.code).expect("decoded opcodes have timings");
+            if cpu.has_native_mode() {
+                cycles.base += wide_penalty(opcode.mnemonic, opcode.mode, true, true);
+            }
+            let size = self.sizes.get(&addr).copied();
+            let next = addr.wrapping_add(size.unwrap_or_else(|| opcode.mode.instruction_size()));
+            let operand = self.assembly.read(addr.wrapping_add(1)).unwrap_or(0);
+            let absolute = u16::from_le_bytes([
+                operand,
+                self.assembly.read(addr.wrapping_add(2)).unwrap_or(0),
+            ]);
+
+            let flow = match (opcode.mnemonic, opcode.mode) {
+                ("RTS" | "RTL" | "RTI" | "BRK", _) => Flow::Return,
+                (
+                    "JMP" | "JML" | "JSR",
+                    AddressingMode::Indirect
+                    | AddressingMode::AbsoluteIndexedIndirect
+                    | AddressingMode::AbsoluteIndirectLong,
+                ) => {
+                    return Err(self.error_at(index, addr, |function, location, span| {
+                        WcetError::IndirectJump {
+                            function,
+                            location,
+                            span,
+                        }
+                    }));
+                }
+                ("JMP" | "JML", _) if absolute != start && self.entry(absolute).is_some() => {
+                    let callee = self.entry(absolute).expect("checked above");
+                    Flow::TailCall(self.analyze(callee)?)
+                }
+                ("JMP" | "JML", _) => Flow::Jump(absolute),
+                ("BRA", _) => Flow::Jump(next.wrapping_add(operand as i8 as u16)),
+                ("BRL", _) => Flow::Jump(next.wrapping_add(absolute)),
+                ("JSR" | "JSL", _) => match self.entry(absolute) {
+                    Some(callee) => Flow::Call(self.analyze(callee)?),
+                    None => {
+                        return Err(self.error_at(index, absolute, |function, location, span| {
+                            WcetError::UnknownTarget {
+                                function,
+                                location,
+                                span,
+                            }
+                        }));
+                    }
+                },
+                (_, AddressingMode::Relative) => {
+                    Flow::Branch(next.wrapping_add(operand as i8 as u16))
+                }
+                (_, AddressingMode::ZeroPageRelative) => {
+                    let offset = self.assembly.read(addr.wrapping_add(2)).unwrap_or(0);
+                    Flow::Branch(next.wrapping_add(offset as i8 as u16))
+                }
+                _ => Flow::Next,
+            };
+
+            match flow {
+                Flow::Next | Flow::Call(_) => pending.push(next),
+                Flow::Branch(target) => pending.extend([next, target]),
+                Flow::Jump(target) => pending.push(target),
+                Flow::TailCall(_) | Flow::Return => {}
+            }
+            instructions.insert(addr, Instruction { next, cycles, flow });
+        }
+
+        Ok(instructions)
+    }
+
+    /// Loops must nest, and may only be entered through their first instruction
+    fn check_nesting(
+        &self,
+        index: usize,
+        instructions: &BTreeMap<u16, Instruction>,
+        loops: &BTreeMap<u16, u16>,
+    ) -> Result<(), WcetError> {
+        let irreducible = |addr| {
+            self.error_at(index, addr, |function, location, span| {
+                WcetError::Irreducible {
+                    function,
+                    location,
+                    span,
+                }
+            })
+        };
+
+        let mut open: Vec<u16> = Vec::new();
+        for (&head, &tail) in loops {
+            while open.last().is_some_and(|&outer_tail| outer_tail < head) {
+                open.pop();
+            }
+            if open.last().is_some_and(|&outer_tail| tail > outer_tail) {
+                return Err(irreducible(head));
+            }
+            open.push(tail);
+        }
+
+        for (&addr, instruction) in instructions {
+            for (target, _) in instruction.edges() {
+                let Some(target) = target else { continue };
+                let enters_body = loops.iter().any(|(&head, &tail)| {
+                    head < target && target <= tail && !(head..=tail).contains(&addr)
+                });
+                if enters_body {
+                    return Err(irreducible(target));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Longest paths through `start..=end`, with inner loops collapsed
+    ///
+    /// For a loop region, `start` is the loop head and edges back to it are
+    /// the loop's iterations.
+    fn region(
+        &self,
+        index: usize,
+        instructions: &BTreeMap<u16, Instruction>,
+        loops: &BTreeMap<u16, u16>,
+        start: u16,
+        end: u16,
+        is_loop: bool,
+    ) -> Result<Region, WcetError> {
+        let mut region = Region::default();
+        let mut dist: HashMap<u16, u64> = HashMap::default();
+        dist.insert(start, 0);
+        let mut skip_to = None;
+
+        for (&addr, instruction) in instructions.range(start..=end) {
+            if skip_to.is_some_and(|tail| addr <= tail) {
+                continue;
+            }
+            let Some(&here) = dist.get(&addr) else {
+                continue;
+            };
+
+            // An inner loop is charged as a whole, then left through its exits
+            let edges = match loops.get(&addr) {
+                Some(&tail) if !(is_loop && addr == start) => {
+                    let inner = self.region(index, instructions, loops, addr, tail, true)?;
+                    let Some(&bound) = self.bounds.get(&addr) else {
+                        return Err(self.error_at(index, addr, |function, location, span| {
+                            WcetError::UnboundedLoop {
+                                function,
+                                location,
+                                span,
+                            }
+                        }));
+                    };
+                    let cost = bound as u64 * inner.back.unwrap_or(0) + inner.exit.unwrap_or(0);
+                    skip_to = Some(tail);
+                    inner
+                        .targets
+                        .into_iter()
+                        .map(|target| (target, cost))
+                        .collect()
+                }
+                _ => instruction.edges(),
+            };
+
+            for (target, cost) in edges {
+                let cycles = here + cost;
+                match target {
+                    Some(target) if is_loop && target == start => {
+                        region.back = Some(region.back.map_or(cycles, |back| back.max(cycles)));
+                    }
+                    Some(target) if (start..=end).contains(&target) => {
+                        if target <= addr {
+                            return Err(self.error_at(
+                                index,
+                                target,
+                                |function, location, span| WcetError::Irreducible {
+                                    function,
+                                    location,
+                                    span,
+                                },
+                            ));
+                        }
+                        let best = dist.entry(target).or_insert(cycles);
+                        *best = (*best).max(cycles);
+                    }
+                    target => region.leave(cycles, target),
+                }
+            }
+        }
+
+        Ok(region)
+    }
+
+    /// Index of the function whose entry is `addr`
+    fn entry(&self, addr: u16) -> Option<usize> {
+        self.functions
+            .binary_search_by_key(&addr, |&(start, _, _)| start)
+            .ok()
+    }
+
+    fn error_at(
+        &self,
+        index: usize,
+        addr: u16,
+        make: impl FnOnce(String, String, Option<Span>) -> WcetError,
+    ) -> WcetError {
+        make(
+            self.functions[index].2.clone(),
+            self.location(addr),
+            self.spans.get(&addr).copied(),
+        )
+    }
+
+    /// Declaration of the function at `index`, which its prologue belongs to
+    fn function_span(&self, index: usize) -> Option<Span> {
+        self.spans.get(&self.functions[index].0).copied()
+    }
+
+    /// Source location of an address, or the address itself
+    fn location(&self, addr: u16) -> String {
+        self.lines
+            .iter()
+            .find(|entry| entry.start <= addr && addr <= entry.end)
+            .map(|entry| format!("{}:{}", self.sources.name(entry.file), entry.line))
+            .unwrap_or_else(|| format!("${:04X}", addr))
+    }
+}
@@ -4,11 +4,14 @@
 //! and handle errors appropriately.
 
 use std::path::PathBuf;
-use wraith::ast::SourceFile;
-use wraith::codegen::{generate, CommentVerbosity};
+use wraith::assembler::{Assembly, Cpu, assemble};
+use wraith::ast::{SourceFile, SourceMap};
+use wraith::codegen::{generate, generate_with_options, CodegenOptions, CodegenOutput, CommentVerbosity, OutputMode};
 use wraith::lex;
 use wraith::parser::Parser;
-use wraith::sema::{analyze, analyze_with_path, ProgramInfo};
+use wraith::config::MemoryConfig;
+use wraith::sema::{analyze, analyze_for_cpu, analyze_program, analyze_with_path, ProgramInfo};
+use wraith::sim::{Simulator, StopReason, DEFAULT_CYCLE_LIMIT};
 
 /// Result of compiling a Wraith program
 #[derive(Debug)]
@@ -102,6 +105,121 @@ pub fn compile_success(source: &str) -> String {
     }
 }
 
+/// Compile source and assemble it with the built-in assembler, panicking on any error
+#[allow(dead_code)]
+pub fn assemble_success(source: &str) -> Assembly {
+    let asm = compile_success(source);
+    assemble(&asm).unwrap_or_else(|e| panic!("Assembly error: {}\nAssembly:\n{}", e, asm))
+}
+
+/// Compile and assemble source, then call `function` in the simulator until it returns
+#[allow(dead_code)]
+pub fn run_function(source: &str, function: &str) -> Simulator {
+    let mut sim = Simulator::with_assembly(&assemble_success(source));
+    match sim.call(function, DEFAULT_CYCLE_LIMIT) {
+        Ok(StopReason::Returned) => sim,
+        Ok(stop) => panic!("{} did not return: {:?}", function, stop),
+        Err(e) => panic!("Simulation error: {}", e),
+    }
+}
+
+/// Compile, assemble and run `main`, returning the simulator for inspection
+#[allow(dead_code)]
+pub fn run_success(source: &str) -> Simulator {
+    run_function(source, "main")
+}
+
+/// Compile, assemble and run from the reset vector until the program halts
+#[allow(dead_code)]
+pub fn run_from_reset(source: &str) -> Simulator {
+    let mut sim = Simulator::with_assembly(&assemble_success(source));
+    match sim.run_from_reset(DEFAULT_CYCLE_LIMIT) {
+        Ok(StopReason::Halted { .. }) => sim,
+        Ok(stop) => panic!("program did not halt: {:?}", stop),
+        Err(e) => panic!("Simulation error: {}", e),
+    }
+}
+
+/// Compile source with default options, keeping the full codegen output
+#[allow(dead_code)]
+pub fn compile_output_success(source: &str) -> CodegenOutput {
+    let (ast, program) = compile_to_sema(source).unwrap_or_else(|e| panic!("{}", e));
+    generate_with_options(&ast, &program, &CodegenOptions::default())
+        .unwrap_or_else(|e| panic!("Codegen error: {}", e))
+}
+
+/// Compile `main` after writing `files` (name, text) to the temp directory,
+/// where `{dir}` in any text is replaced by the directory, keeping the
+/// source map of every file the program loaded
+#[allow(dead_code)]
+pub fn compile_with_imports(main: &str, files: &[(&str, &str)]) -> (CodegenOutput, SourceMap) {
+    let dir = std::env::temp_dir().to_string_lossy().replace('\\', "/");
+    for (name, text) in files {
+        std::fs::write(format!("{}/{}", dir, name), text.replace("{dir}", &dir)).unwrap();
+    }
+    let main = main.replace("{dir}", &dir);
+    let ast = compile_to_ast(&main).unwrap_or_else(|e| panic!("{}", e));
+    let mut sources = SourceMap::new("main.wr", main.as_str());
+    let program = analyze_program(
+        &ast,
+        "main.wr".into(),
+        MemoryConfig::default(),
+        &[],
+        None,
+        &mut sources,
+    )
+    .unwrap_or_else(|diagnostics| panic!("{}", diagnostics.errors[0]));
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default())
+        .unwrap_or_else(|e| panic!("Codegen error: {}", e));
+    (output, sources)
+}
+
+/// Compile source for the given CPU
+#[allow(dead_code)]
+pub fn compile_for_cpu(source: &str, cpu: Cpu) -> Result<CodegenOutput, String> {
+    let ast = compile_to_ast(source)?;
+    let program =
+        analyze_for_cpu(&ast, cpu).map_err(|e| e.format_with_source_and_file(source, None))?;
+    let options = CodegenOptions {
+        cpu,
+        ..Default::default()
+    };
+    generate_with_options(&ast, &program, &options).map_err(|e| format!("Codegen error: {}", e))
+}
+
+/// Compile, assemble and run `main` for the given CPU, returning the simulator
+#[allow(dead_code)]
+pub fn run_for_cpu(source: &str, cpu: Cpu) -> Simulator {
+    let output = compile_for_cpu(source, cpu).unwrap_or_else(|e| panic!("{}", e));
+    let assembly =
+        assemble(&output.asm).unwrap_or_else(|e| panic!("Assembly error: {}\n{}", e, output.asm));
+    assert_eq!(assembly.cpu, cpu);
+    let mut sim = Simulator::with_assembly(&assembly);
+    match sim.call("main", DEFAULT_CYCLE_LIMIT) {
+        Ok(StopReason::Returned) => sim,
+        Ok(stop) => panic!("main did not return: {:?}", stop),
+        Err(e) => panic!("Simulation error: {}", e),
+    }
+}
+
+/// Compile source for the 65C02, panicking on any error
+#[allow(dead_code)]
+pub fn compile_cmos_success(source: &str) -> CodegenOutput {
+    compile_for_cpu(source, Cpu::Cmos65C02).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Compile source in ca65 segment mode, panicking on any error
+#[allow(dead_code)]
+pub fn compile_ca65_success(source: &str) -> CodegenOutput {
+    let (ast, program) = compile_to_sema(source).unwrap_or_else(|e| panic!("{}", e));
+    let options = CodegenOptions {
+        output_mode: OutputMode::Ca65,
+        ..Default::default()
+    };
+    generate_with_options(&ast, &program, &options)
+        .unwrap_or_else(|e| panic!("Codegen error: {}", e))
+}
+
 /// Compile source to AST only
 #[allow(dead_code)]
 pub fn compile_to_ast(source: &str) -> Result<SourceFile, String> {
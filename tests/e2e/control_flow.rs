@@ -37,19 +37,21 @@ fn if_else() {
 
 #[test]
 fn while_loop() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
             let x: u8 = 0;
             while x < 10 {
                 x = x + 1;
             }
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     assert_asm_contains(&asm, "CMP");
     assert_asm_contains(&asm, "JMP");
+    assert_eq!(run_success(source).read(0x6000), 10);
 }
 
 #[test]
@@ -74,8 +76,8 @@ fn for_range_loop() {
 
 #[test]
 fn while_loop_with_break() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
             let x: u8 = 0;
             while x < 10 {
@@ -84,13 +86,15 @@ fn while_loop_with_break() {
                 }
                 x = x + 1;
             }
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     // Should have loop labels and JMP for break
     assert_asm_contains(&asm, "JMP");
     assert_asm_contains(&asm, "CMP");
+    assert_eq!(run_success(source).read(0x6000), 5);
 }
 
 #[test]
@@ -137,43 +141,52 @@ fn for_loop_with_break() {
 
 #[test]
 fn for_loop_with_continue() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
+            let sum: u8 = 0;
             for i: u8 in 0..10 {
                 if i == 5 {
                     continue;
                 }
-                let x: u8 = i;
+                sum = sum + i;
             }
+            OUT = sum;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     // Continue should jump to loop increment
     assert_asm_contains(&asm, "INX");
     assert_asm_contains(&asm, "JMP");
+    // 0 + 1 + ... + 9 without the 5
+    assert_eq!(run_success(source).read(0x6000), 40);
 }
 
 #[test]
 fn nested_loop_break() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
+            let count: u8 = 0;
             for i: u8 in 0..10 {
                 for j: u8 in 0..10 {
                     if j == 2 {
                         break;
                     }
+                    count = count + 1;
                 }
             }
+            OUT = count;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     // Should have distinct loop labels for nested loops
     assert_asm_contains(&asm, "INX");
     assert_asm_contains(&asm, "JMP");
+    // The inner loop runs twice per outer iteration
+    assert_eq!(run_success(source).read(0x6000), 20);
 }
 
 // ============================================================
@@ -24,34 +24,56 @@ fn function_call_no_args() {
 
 #[test]
 fn function_call_with_args() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn add(a: u8, b: u8) -> u8 {
             return a + b;
         }
         fn main() {
             let result: u8 = add(5, 10);
+            OUT = result;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     assert_asm_contains(&asm, "JSR add");
     assert_asm_contains(&asm, "add:");
+    assert_eq!(run_success(source).read(0x6000), 15);
 }
 
 #[test]
 fn function_return_value() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn get_value() -> u8 {
             return 42;
         }
         fn main() {
             let x: u8 = get_value();
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     assert_asm_contains(&asm, "JSR get_value");
     assert_asm_contains(&asm, "RTS");
+    assert_eq!(run_success(source).read(0x6000), 42);
+}
+
+#[test]
+fn nested_calls_preserve_arguments() {
+    let source = r#"
+        const OUT: addr = 0x6000;
+        fn double(x: u8) -> u8 {
+            return x + x;
+        }
+        fn sum_doubled(a: u8, b: u8) -> u8 {
+            return double(a) + double(b);
+        }
+        fn main() {
+            OUT = sum_doubled(3, 4);
+        }
+    "#;
+
+    assert_eq!(run_success(source).read(0x6000), 14);
 }
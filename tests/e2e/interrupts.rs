@@ -91,3 +91,32 @@ fn all_interrupt_vectors() {
     assert_asm_contains(&asm, ".WORD reset_handler");
     assert_asm_contains(&asm, ".WORD irq_handler");
 }
+
+#[test]
+fn handlers_run_from_vectors() {
+    let source = r#"
+        const OUT: addr = 0x400;
+        const TICKS: addr = 0x401;
+        #[nmi]
+        fn nmi_handler() {
+            TICKS = TICKS + 1;
+        }
+        #[reset]
+        fn main() {
+            TICKS = 0;
+            OUT = 0x42;
+            loop {}
+        }
+    "#;
+
+    let mut sim = run_from_reset(source);
+    assert_eq!(sim.read(0x400), 0x42);
+
+    let registers = sim.registers;
+    sim.nmi();
+    sim.nmi();
+    sim.run(10_000).unwrap();
+    assert_eq!(sim.read(0x401), 2);
+    // The handler's prologue and epilogue restore every register
+    assert_eq!(sim.registers, registers);
+}
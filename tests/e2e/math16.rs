@@ -5,8 +5,7 @@ use crate::common::*;
 #[test]
 fn mul16_small_numbers() {
     // Test: 10 * 20 = 200 (0x00C8)
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { mul16 } from "std/math.wr";
 
         const RESULT_LO: addr = 0x6000;
@@ -17,64 +16,76 @@ fn mul16_small_numbers() {
             RESULT_LO = result.low;
             RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     // Check that mul16 function is called
     assert_asm_contains(&asm, "JSR mul16");
 
-    // Result should be 200 = 0x00C8
-    // Low byte and high byte should be stored
+    // Low byte and high byte should be stored, giving 200 = 0x00C8
     assert_asm_contains(&asm, "STA RESULT_LO");
     assert_asm_contains(&asm, "STA RESULT_HI");
+    assert_eq!(run_success(source).read_u16(0x6000), 200);
 }
 
 #[test]
 fn mul16_large_numbers() {
     // Test: 100 * 200 = 20000 (0x4E20)
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { mul16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = mul16(100, 200);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR mul16");
+    assert_asm_contains(&compile_success(source), "JSR mul16");
+    assert_eq!(run_success(source).read_u16(0x6000), 20000);
 }
 
 #[test]
 fn mul16_max_values() {
     // Test: 255 * 255 = 65025 (0xFE01)
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { mul16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = mul16(255, 255);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR mul16");
+    assert_asm_contains(&compile_success(source), "JSR mul16");
+    assert_eq!(run_success(source).read_u16(0x6000), 65025);
 }
 
 #[test]
 fn mul16_with_zero() {
     // Test: 1000 * 0 = 0
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { mul16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = mul16(1000, 0);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR mul16");
+    assert_asm_contains(&compile_success(source), "JSR mul16");
+    assert_eq!(run_success(source).read_u16(0x6000), 0);
 }
 
 #[test]
@@ -102,65 +113,81 @@ fn div16_even_division() {
 #[test]
 fn div16_large_numbers() {
     // Test: 20000 / 100 = 200
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { div16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = div16(20000, 100);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR div16");
+    assert_asm_contains(&compile_success(source), "JSR div16");
+    assert_eq!(run_success(source).read_u16(0x6000), 200);
 }
 
 #[test]
 fn div16_with_remainder() {
     // Test: 17 / 3 = 5 (remainder 2, but we only return quotient)
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { div16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = div16(17, 3);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR div16");
+    assert_asm_contains(&compile_success(source), "JSR div16");
+    assert_eq!(run_success(source).read_u16(0x6000), 5);
 }
 
 #[test]
 fn div16_by_zero() {
     // Test: 1000 / 0 = 0xFFFF (error value)
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { div16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = div16(1000, 0);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR div16");
+    assert_asm_contains(&compile_success(source), "JSR div16");
+    assert_eq!(run_success(source).read_u16(0x6000), 0xFFFF);
 }
 
 #[test]
 fn div16_by_one() {
     // Test: 12345 / 1 = 12345
-    let asm = compile_success(
-        r#"
+    let source = r#"
         import { div16 } from "std/math.wr";
 
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
         fn main() {
             let result: u16 = div16(12345, 1);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
         }
-    "#,
-    );
+    "#;
 
-    assert_asm_contains(&asm, "JSR div16");
+    assert_asm_contains(&compile_success(source), "JSR div16");
+    assert_eq!(run_success(source).read_u16(0x6000), 12345);
 }
 
 #[test]
@@ -180,3 +207,22 @@ fn mul16_and_div16_together() {
     assert_asm_contains(&asm, "JSR mul16");
     assert_asm_contains(&asm, "JSR div16");
 }
+
+#[test]
+fn div16_large_divisor() {
+    // Test: 65000 / 40000 = 1 (the remainder needs a 17th bit while shifting)
+    let source = r#"
+        import { div16 } from "std/math.wr";
+
+        const RESULT_LO: addr = 0x6000;
+        const RESULT_HI: addr = 0x6001;
+
+        fn main() {
+            let result: u16 = div16(65000, 40000);
+            RESULT_LO = result.low;
+            RESULT_HI = result.high;
+        }
+    "#;
+
+    assert_eq!(run_success(source).read_u16(0x6000), 1);
+}
@@ -38,32 +38,36 @@ fn compound_sub_assign() {
 
 #[test]
 fn compound_mul_assign() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
             let x: u8 = 10;
             x *= 2;
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     // Multiplication should be present (implementation detail)
     assert!(asm.contains("STA"));
+    assert_eq!(run_success(source).read(0x6000), 20);
 }
 
 #[test]
 fn compound_div_assign() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
             let x: u8 = 10;
             x /= 2;
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     // Division should be present (implementation detail)
     assert!(asm.contains("STA"));
+    assert_eq!(run_success(source).read(0x6000), 5);
 }
 
 #[test]
@@ -424,28 +428,32 @@ fn shift_right() {
 
 #[test]
 fn shift_left_variable() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
             let x: u8 = 4;
             x = x << 1;
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     assert_asm_contains(&asm, "ASL");
+    assert_eq!(run_success(source).read(0x6000), 8);
 }
 
 #[test]
 fn shift_right_variable() {
-    let asm = compile_success(
-        r#"
+    let source = r#"
+        const OUT: addr = 0x6000;
         fn main() {
             let x: u8 = 8;
             x = x >> 1;
+            OUT = x;
         }
-    "#,
-    );
+    "#;
+    let asm = compile_success(source);
 
     assert_asm_contains(&asm, "LSR");
+    assert_eq!(run_success(source).read(0x6000), 4);
 }
@@ -52,3 +52,16 @@ fn invalid_token() {
 fn error_contains_helpful_message() {
     assert_error_contains("fn main() {", "expected");
 }
+
+#[test]
+fn bound_only_on_loops() {
+    assert_error_contains(
+        r#"
+        fn main() {
+            #[bound(4)]
+            let x: u8 = 0;
+        }
+        "#,
+        "loop after #[bound]",
+    );
+}
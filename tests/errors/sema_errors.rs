@@ -237,6 +237,25 @@ fn invalid_address_range() {
     );
 }
 
+#[test]
+fn long_address_needs_the_65816() {
+    let source = r#"
+        const FAR: addr = 0x10000;
+        fn main() {
+            let p: u16 = FAR as u16;
+        }
+        "#;
+    let ast = compile_to_ast(source).unwrap();
+    let Err(error) = wraith::sema::analyze(&ast) else {
+        panic!("long address accepted for the 6502");
+    };
+    assert!(error.to_string().contains("must be 0-65535"), "{}", error);
+    let span = error.span().unwrap();
+    assert_eq!(&source[span.start..span.end], "0x10000");
+
+    assert!(wraith::sema::analyze_for_cpu(&ast, wraith::assembler::Cpu::W65C816).is_ok());
+}
+
 #[test]
 fn const_cannot_be_reassigned() {
     assert_error_contains(
@@ -564,3 +583,153 @@ fn error_has_source_context() {
         _ => panic!("Expected semantic error"),
     }
 }
+
+#[test]
+fn cycle_budget_on_inline_function() {
+    assert_error_contains(
+        r#"
+        #[inline]
+        #[max_cycles(10)]
+        fn fast() {}
+        "#,
+        "cannot have a cycle budget",
+    );
+}
+
+// ============================================================================
+// Recovery
+// ============================================================================
+
+fn all_errors(source: &str) -> Vec<wraith::sema::SemaError> {
+    let ast = compile_to_ast(source).unwrap();
+    match wraith::sema::analyze::SemanticAnalyzer::new().analyze_all(&ast) {
+        Ok(_) => panic!("expected semantic errors"),
+        Err(diagnostics) => diagnostics.errors,
+    }
+}
+
+#[test]
+fn every_error_is_reported_in_source_order() {
+    let errors = all_errors(
+        r#"
+        fn helper() {
+            let x: u8 = 1 + true;
+        }
+
+        const BAD: u8 = 300;
+
+        fn main() {
+            let flag: bool = 1;
+            break;
+            helper();
+        }
+        "#,
+    );
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(errors.len(), 4, "{:#?}", messages);
+    assert!(matches!(
+        errors[0],
+        wraith::sema::SemaError::InvalidBinaryOp { .. }
+    ));
+    assert!(matches!(
+        errors[1],
+        wraith::sema::SemaError::ConstantOverflow { .. }
+    ));
+    assert!(matches!(
+        errors[2],
+        wraith::sema::SemaError::TypeMismatch { .. }
+    ));
+    assert!(matches!(
+        errors[3],
+        wraith::sema::SemaError::BreakOutsideLoop { .. }
+    ));
+}
+
+#[test]
+fn failed_declarations_do_not_cascade() {
+    let errors = all_errors(
+        r#"
+        const BAD: u8 = 300;
+
+        fn main() {
+            let x: u8 = missing;
+            let y: u8 = x + BAD;
+            let z: u8 = y;
+        }
+
+        fn other() {
+            let w: u8 = x;
+        }
+        "#,
+    );
+    let names: Vec<String> = errors
+        .iter()
+        .map(|e| match e {
+            wraith::sema::SemaError::UndefinedSymbol { name, .. } => name.clone(),
+            other => other.to_string(),
+        })
+        .collect();
+    // `x` is only reported again outside the function that failed to declare it
+    assert_eq!(names.len(), 3, "{:#?}", names);
+    assert!(names[0].contains("300"));
+    assert_eq!(names[1], "missing");
+    assert_eq!(names[2], "x");
+}
+
+#[test]
+fn every_error_in_an_imported_module_is_reported() {
+    let dir = std::env::temp_dir();
+    let checked = dir.join("test_recovery_lib_sema.wr");
+    std::fs::write(
+        &checked,
+        r#"
+        pub fn helper() -> u8 {
+            let x: u8 = 1 + true;
+            return missing;
+        }
+        "#,
+    )
+    .unwrap();
+    let parsed = dir.join("test_recovery_lib_parse.wr");
+    std::fs::write(
+        &parsed,
+        "pub fn a() { let = 1; }\npub fn b() { let = 2; }\n",
+    )
+    .unwrap();
+
+    let errors = all_errors(&format!(
+        r#"
+        import {{ helper }} from "{}";
+        import {{ a }} from "{}";
+
+        fn main() {{
+            let flag: bool = 1;
+        }}
+        "#,
+        checked.to_string_lossy().replace('\\', "/"),
+        parsed.to_string_lossy().replace('\\', "/"),
+    ));
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    // Both sema errors of one module, then every parse error of the other
+    assert_eq!(errors.len(), 7, "{:#?}", messages);
+    assert!(matches!(
+        errors[0],
+        wraith::sema::SemaError::InvalidBinaryOp { .. }
+    ));
+    assert!(matches!(
+        &errors[1],
+        wraith::sema::SemaError::UndefinedSymbol { name, .. } if name == "missing"
+    ));
+    assert!(
+        errors[2..6]
+            .iter()
+            .all(|e| matches!(e, wraith::sema::SemaError::ImportError { .. })),
+        "{:#?}",
+        messages
+    );
+    assert!(messages[4].contains("41..42"), "{:#?}", messages);
+    assert!(matches!(
+        errors[6],
+        wraith::sema::SemaError::TypeMismatch { .. }
+    ));
+}
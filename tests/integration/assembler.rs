@@ -0,0 +1,83 @@
+//! Built-in assembler integration tests
+//!
+//! Compiles programs end-to-end and checks the assembled machine code.
+
+use crate::common::*;
+
+#[test]
+fn reset_vector_points_to_entry() {
+    let asm = assemble_success(
+        r#"
+        #[reset]
+        fn main() {
+            loop {}
+        }
+    "#,
+    );
+
+    let main = asm.symbol("main").expect("main label");
+    assert_eq!(asm.read(0xFFFC), Some(main as u8));
+    assert_eq!(asm.read(0xFFFD), Some((main >> 8) as u8));
+}
+
+#[test]
+fn functions_are_packed_with_exact_sizes() {
+    let asm = assemble_success(
+        r#"
+        const OUT: addr = 0x6000;
+        fn first() {
+            OUT = 1;
+        }
+        fn second() {
+            OUT = 2;
+        }
+    "#,
+    );
+
+    let first = asm.symbol("first").unwrap();
+    let second = asm.symbol("second").unwrap();
+    // LDA #$01 / STA OUT / RTS = 6 bytes, no padding before the next function
+    assert_eq!(second - first, 6);
+    assert_eq!(asm.read(second - 1), Some(0x60));
+}
+
+#[test]
+fn inline_asm_labels_resolve() {
+    let asm = assemble_success(
+        r#"
+        fn main() {
+            asm {
+                "LDX #$05",
+                "wait: DEX",
+                "BNE wait\nRTS"
+            }
+        }
+    "#,
+    );
+
+    let main = asm.symbol("main").unwrap();
+    let wait = asm.symbol("wait").unwrap();
+    assert_eq!(wait, main + 2);
+    // BNE back to the DEX: offset -3
+    assert_eq!(asm.read(wait + 1), Some(0xD0));
+    assert_eq!(asm.read(wait + 2), Some(0xFD));
+}
+
+#[test]
+fn stdlib_math_routines_assemble() {
+    let asm = assemble_success(
+        r#"
+        const OUT: addr = 0x6000;
+        fn main() {
+            let a: u16 = 300;
+            let b: u16 = 7;
+            let c: u16 = a * b + a / b + a % b;
+            OUT = c as u8;
+        }
+    "#,
+    );
+
+    assert!(asm.symbol("mul16").is_some());
+    assert!(asm.symbol("div16").is_some());
+    assert!(asm.symbol("mod16").is_some());
+}
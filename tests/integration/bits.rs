@@ -0,0 +1,86 @@
+//! Rockwell/WDC bit instruction tests
+//!
+//! Compiles single-bit updates and tests of zero-page bytes for the W65C02
+//! and checks that they become SMB/RMB and BBS/BBR, with the same results as
+//! the NMOS build.
+
+use crate::common::*;
+use wraith::assembler::Cpu;
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const OUT2: addr = 0x6002;
+const OUT3: addr = 0x6003;
+
+fn main() {
+    let flags: u8 = 0x01;
+    let count: u8 = 0;
+    flags = flags | 0x08;
+    flags = flags & ~0x01;
+    flags |= 0x80;
+    if (flags & 0x08) != 0 {
+        OUT = 1;
+    } else {
+        OUT = 2;
+    }
+    if 0 == (flags & 0x01) {
+        OUT1 = 3;
+    }
+    while (flags & 0x80) == 0x80 {
+        flags = flags & 0x7F;
+        count = count + 1;
+    }
+    OUT2 = flags;
+    OUT3 = count;
+}
+"#;
+
+#[test]
+fn bit_instructions_match_nmos_results() {
+    let bits = run_for_cpu(PROGRAM, Cpu::W65C02);
+    let nmos = run_success(PROGRAM);
+    for addr in 0x6000..0x6004 {
+        assert_eq!(bits.read(addr), nmos.read(addr), "${:04X}", addr);
+    }
+    assert_eq!(bits.read(0x6000), 1);
+    assert_eq!(bits.read(0x6001), 3);
+    assert_eq!(bits.read(0x6002), 0x08);
+    assert_eq!(bits.read(0x6003), 1);
+}
+
+#[test]
+fn bit_updates_and_tests_use_bit_instructions() {
+    let asm = compile_for_cpu(PROGRAM, Cpu::W65C02).unwrap().asm;
+    assert!(asm.starts_with(".SETCPU \"W65C02\""));
+    for mnemonic in ["SMB3 $", "RMB0 $", "SMB7 $", "RMB7 $", "BBS3 $", "BBR0 $", "BBS7 $"] {
+        assert_asm_contains(&asm, mnemonic);
+    }
+
+    // The plain 65C02 has no bit instructions
+    let asm = compile_for_cpu(PROGRAM, Cpu::Cmos65C02).unwrap().asm;
+    for mnemonic in ["SMB", "RMB", "BBS", "BBR"] {
+        assert_asm_not_contains(&asm, mnemonic);
+    }
+}
+
+#[test]
+fn other_masks_are_not_bit_instructions() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn main() {
+    let flags: u8 = 0x0F;
+    flags = flags | 0x30;
+    flags = flags & 0xFC;
+    if (flags & 0x0C) != 0 {
+        OUT = flags;
+    }
+}
+"#;
+    let asm = compile_for_cpu(source, Cpu::W65C02).unwrap().asm;
+    for mnemonic in ["SMB", "RMB", "BBS", "BBR"] {
+        assert_asm_not_contains(&asm, mnemonic);
+    }
+    assert_eq!(run_for_cpu(source, Cpu::W65C02).read(0x6000), 0x3C);
+}
@@ -0,0 +1,75 @@
+//! ca65 segment output tests
+//!
+//! Checks `.segment`/`.export`/`.import` output and the generated ld65 config.
+
+use crate::common::*;
+
+#[test]
+fn functions_are_placed_in_segments() {
+    let output = compile_ca65_success(
+        r#"
+        #[reset]
+        fn main() {
+            loop {}
+        }
+    "#,
+    );
+
+    assert!(output.asm.contains(".segment \"CODE\""));
+    assert!(output.asm.contains(".segment \"VECTORS\""));
+    assert!(!output.asm.contains(".ORG"));
+    assert!(output.asm.contains(".export main"));
+}
+
+#[test]
+fn only_public_functions_are_exported() {
+    let output = compile_ca65_success(
+        r#"
+        pub fn visible() {}
+        fn hidden() {}
+    "#,
+    );
+
+    assert!(output.asm.contains(".export visible"));
+    assert!(!output.asm.contains(".export hidden"));
+}
+
+#[test]
+fn external_symbols_are_imported() {
+    let output = compile_ca65_success(
+        r#"
+        fn main() {
+            asm {
+                "JSR putc",
+                "JMP done",
+                "done: RTS"
+            }
+        }
+    "#,
+    );
+
+    assert!(output.asm.starts_with(".import putc\n"));
+}
+
+#[test]
+fn org_functions_get_fixed_segments() {
+    let output = compile_ca65_success(
+        r#"
+        #[org(0x9000)]
+        fn fixed() {}
+    "#,
+    );
+
+    assert!(output.asm.contains(".segment \"ORG_9000\""));
+    let cfg = output.linker_config.expect("linker config");
+    assert!(cfg.contains("CODE: start = $8000"));
+    assert!(cfg.contains("ORG_9000: load = CODE, type = ro, start = $9000;"));
+}
+
+#[test]
+fn absolute_mode_has_no_linker_config() {
+    let (ast, program) = compile_to_sema("fn main() {}").unwrap();
+    let output = wraith::codegen::generate_with_options(&ast, &program, &Default::default()).unwrap();
+    assert!(output.linker_config.is_none());
+    assert!(output.asm.contains(".ORG $8000"));
+}
@@ -0,0 +1,106 @@
+//! 65C02 code generation tests
+//!
+//! Compiles for the WDC 65C02 and checks that the CMOS instructions are used
+//! and that the program still computes the same results in the simulator.
+
+use crate::common::*;
+use wraith::assembler::{Cpu, assemble};
+use wraith::sim::{Registers, Simulator, StopReason};
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const OUT2: addr = 0x6002;
+const OUT3: addr = 0x6003;
+
+enum Shape {
+    Circle(u8),
+    Square(u8),
+    Triangle(u8),
+}
+
+fn area(shape: Shape) -> u8 {
+    match shape {
+        Shape::Circle(r) => { return r; }
+        Shape::Square(s) => { return s + 1; }
+        Shape::Triangle(a) => { return a; }
+    }
+}
+
+#[irq]
+fn irq() {
+    OUT = 1;
+}
+
+fn main() {
+    let total: u8 = 0;
+    let flags: u8 = 3;
+    flags = flags | 0x80;
+    flags = flags & 0xFE;
+    let i: u8 = 0;
+    while i < 10 {
+        total = total + 2;
+        i = i + 1;
+    }
+    OUT = total;
+    OUT1 = i;
+    OUT2 = flags;
+    OUT3 = area(Shape::Square(7));
+}
+"#;
+
+#[test]
+fn cmos_program_matches_nmos_results() {
+    let cmos = run_for_cpu(PROGRAM, Cpu::Cmos65C02);
+    let nmos = run_success(PROGRAM);
+    for addr in 0x6000..0x6003 {
+        assert_eq!(cmos.read(addr), nmos.read(addr), "${:04X}", addr);
+    }
+    assert_eq!(cmos.read(0x6000), 20);
+    assert_eq!(cmos.read(0x6002), 0x82);
+    // The indexed JMP leaves the enum pointer at $30-$31 intact for the
+    // arm's binding (the NMOS dispatch reuses it for the jump address)
+    assert_eq!(cmos.read(0x6003), 8);
+}
+
+#[test]
+fn cmos_output_uses_cmos_instructions() {
+    let asm = compile_cmos_success(PROGRAM).asm;
+    assert!(asm.starts_with(".SETCPU \"65C02\""));
+    assert_asm_contains(&asm, "STZ");
+    assert_asm_contains(&asm, "BRA wc_");
+    assert_asm_contains(&asm, "JMP (match_0_jt,X)");
+    assert_instruction_sequence(&asm, &["PHA", "PHX", "PHY"]);
+    assert_instruction_sequence(&asm, &["PLY", "PLX", "PLA", "RTI"]);
+}
+
+#[test]
+fn nmos_output_is_unchanged_by_default() {
+    let asm = compile_success(PROGRAM);
+    assert_asm_not_contains(&asm, ".SETCPU");
+    for mnemonic in ["STZ", "BRA", "PHX", "PLY", "TSB", "TRB", "INC A"] {
+        assert_asm_not_contains(&asm, mnemonic);
+    }
+    assert_asm_contains(&asm, "JMP ($");
+}
+
+#[test]
+fn cmos_interrupt_handler_restores_registers() {
+    let output = compile_cmos_success(PROGRAM);
+    let mut sim = Simulator::with_assembly(&assemble(&output.asm).unwrap());
+    // JMP $0300, interrupted by an IRQ
+    sim.write(0x0300, 0x4C);
+    sim.write_u16(0x0301, 0x0300);
+    sim.registers.pc = 0x0300;
+    sim.registers.set_flag(Registers::INTERRUPT, false);
+    sim.registers.a = 0x11;
+    sim.registers.x = 0x22;
+    sim.registers.y = 0x33;
+    sim.irq();
+    assert_eq!(sim.run(1000), Ok(StopReason::Halted { pc: 0x0300 }));
+    assert_eq!(sim.read(0x6000), 1);
+    assert_eq!(
+        (sim.registers.a, sim.registers.x, sim.registers.y),
+        (0x11, 0x22, 0x33)
+    );
+}
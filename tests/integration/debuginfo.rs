@@ -0,0 +1,141 @@
+//! Debug symbol export tests
+//!
+//! Compiles programs and checks the collected symbols and file formats.
+
+use crate::common::*;
+use std::path::PathBuf;
+use wraith::assembler::assemble;
+use wraith::ast::Item;
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::debuginfo::{DebugSymbol, DebugSymbolKind, SymbolFormat, collect_symbols};
+use wraith::sema::{analyze, analyze_with_path};
+
+fn symbols(source: &str) -> Vec<DebugSymbol> {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    collect_symbols(&program, &output, &assembly)
+}
+
+fn find<'a>(symbols: &'a [DebugSymbol], name: &str) -> &'a DebugSymbol {
+    symbols
+        .iter()
+        .find(|s| s.qualified_name() == name)
+        .unwrap_or_else(|| panic!("no symbol {} in {:?}", name, symbols))
+}
+
+const PROGRAM: &str = r#"
+const LED: addr = 0x6000;
+const TABLE: [u8; 3] = [1, 2, 3];
+
+fn blink(times: u8) {
+    let count: u16 = 0;
+    LED = times;
+}
+
+#[reset]
+fn main() {
+    blink(TABLE[0]);
+}
+"#;
+
+#[test]
+fn collects_functions_data_addresses_and_locals() {
+    let symbols = symbols(PROGRAM);
+
+    assert_eq!(find(&symbols, "main").kind, DebugSymbolKind::Function);
+    assert_eq!(find(&symbols, "LED").address, 0x6000);
+    assert_eq!(find(&symbols, "TABLE").address, 0xC000);
+    assert_eq!(find(&symbols, "TABLE").size, Some(3));
+
+    let count = find(&symbols, "blink__count");
+    assert_eq!(count.kind, DebugSymbolKind::Variable);
+    assert_eq!(count.size, Some(2));
+    assert!(count.address < 0x100);
+    assert_eq!(
+        find(&symbols, "blink__times").kind,
+        DebugSymbolKind::Variable
+    );
+}
+
+#[test]
+fn function_addresses_match_assembled_labels() {
+    let (ast, program) = compile_to_sema(PROGRAM).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let symbols = collect_symbols(&program, &output, &assembly);
+
+    for name in ["main", "blink"] {
+        assert_eq!(Some(find(&symbols, name).address), assembly.symbol(name));
+    }
+}
+
+#[test]
+fn formats_render_every_symbol() {
+    let (ast, program) = compile_to_sema(PROGRAM).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let symbols = collect_symbols(&program, &output, &assembly);
+    let main = assembly.symbol("main").unwrap();
+
+    let vice = SymbolFormat::Vice.write(&symbols, &output, "test.wr");
+    assert!(vice.contains(&format!("al C:{:04X} .main\n", main)));
+    assert!(vice.contains("al C:6000 .LED\n"));
+
+    let mesen = SymbolFormat::Mesen.write(&symbols, &output, "test.wr");
+    assert!(mesen.contains(&format!("P:{:04X}:main\n", main - 0x8000)));
+    assert!(mesen.contains("P:4000-4002:TABLE\n"));
+
+    let dbg = SymbolFormat::Dbg.write(&symbols, &output, "test.wr");
+    assert!(dbg.starts_with("version\tmajor=2,minor=0\n"));
+    assert!(dbg.contains("scope\tid=1,name=\"blink\""));
+    assert!(dbg.contains("name=\"main\",addrsize=absolute"));
+    assert_eq!(dbg.matches("\nsym\t").count(), symbols.len());
+}
+
+#[test]
+fn skips_locals_of_functions_that_were_not_emitted() {
+    let source = r#"
+import {mul16} from "math.wr";
+
+#[reset]
+fn main() {
+    let x: u16 = mul16(3, 4);
+}
+"#;
+    let ast = compile_to_ast(source).unwrap();
+    let program = analyze_with_path(&ast, PathBuf::from("std/main.wr")).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let symbols = collect_symbols(&program, &output, &assembly);
+
+    // The rest of the module is analyzed but never emitted
+    assert_eq!(find(&symbols, "mul16__a").kind, DebugSymbolKind::Variable);
+    assert!(symbols.iter().all(|s| s.scope.as_deref() != Some("clamp")));
+    for symbol in symbols.iter().filter(|s| s.scope.is_some()) {
+        let function = symbol.scope.as_deref().unwrap();
+        assert_eq!(find(&symbols, function).kind, DebugSymbolKind::Function);
+    }
+}
+
+#[test]
+fn labels_mutable_statics() {
+    let mut ast = compile_to_ast(
+        "const COUNTER: u8 = 5;\n#[reset]\nfn main() {\n    let x: u8 = COUNTER;\n}\n",
+    )
+    .unwrap();
+    for item in &mut ast.items {
+        if let Item::Static(stat) = &mut item.node {
+            stat.mutable = true;
+        }
+    }
+    let program = analyze(&ast).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let symbols = collect_symbols(&program, &output, &assembly);
+
+    let counter = find(&symbols, "COUNTER");
+    assert_eq!(counter.kind, DebugSymbolKind::Variable);
+    assert_eq!(Some(counter.address), assembly.symbol("COUNTER"));
+    assert_eq!(counter.size, Some(1));
+}
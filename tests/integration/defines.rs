@@ -0,0 +1,121 @@
+//! Command-line define tests
+//!
+//! `-D NAME=VALUE` predeclares a constant typed by its value; a `const` of
+//! the same name in the program takes the defined value instead of its
+//! initializer.
+
+use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::ast::SourceMap;
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::config::MemoryConfig;
+use wraith::sema::{SemaError, analyze_program};
+use wraith::sim::{DEFAULT_CYCLE_LIMIT, Simulator, StopReason};
+
+fn analyze(source: &str, defines: &[(&str, i64)]) -> Result<wraith::sema::ProgramInfo, SemaError> {
+    let ast = compile_to_ast(source).unwrap();
+    let defines: Vec<(String, i64)> = defines
+        .iter()
+        .map(|(name, value)| (name.to_string(), *value))
+        .collect();
+    analyze_program(
+        &ast,
+        "main.wr".into(),
+        MemoryConfig::default(),
+        &defines,
+        None,
+        &mut SourceMap::new("main.wr", source),
+    )
+    .map_err(|diagnostics| diagnostics.errors[0].clone())
+}
+
+fn run(source: &str, defines: &[(&str, i64)]) -> Simulator {
+    let ast = compile_to_ast(source).unwrap();
+    let program = analyze(source, defines).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let mut sim = Simulator::with_assembly(&assemble(&output.asm).unwrap());
+    assert_eq!(
+        sim.call("main", DEFAULT_CYCLE_LIMIT),
+        Ok(StopReason::Returned)
+    );
+    sim
+}
+
+#[test]
+fn defines_are_predeclared_constants() {
+    let source = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+
+fn main() {
+    OUT = LEVEL + 1;
+    let speed: u16 = SPEED;
+    OUT1 = speed.high;
+}
+"#;
+    let sim = run(source, &[("LEVEL", 4), ("SPEED", 0x1234)]);
+    assert_eq!(sim.read(0x6000), 5);
+    assert_eq!(sim.read(0x6001), 0x12);
+
+    assert!(analyze(source, &[("LEVEL", 4)]).is_err());
+}
+
+#[test]
+fn defines_override_const_initializers() {
+    let source = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const LIVES: u8 = 3;
+const DEBUG: bool = false;
+
+fn main() {
+    OUT = LIVES;
+    if DEBUG {
+        OUT1 = 1;
+    }
+}
+"#;
+    let sim = run(source, &[]);
+    assert_eq!(sim.read(0x6000), 3);
+    assert_eq!(sim.read(0x6001), 0);
+
+    let sim = run(source, &[("LIVES", 9), ("DEBUG", 1)]);
+    assert_eq!(sim.read(0x6000), 9);
+    assert_eq!(sim.read(0x6001), 1);
+}
+
+#[test]
+fn defines_must_fit_the_declared_type() {
+    let source = r#"
+const LIVES: u8 = 3;
+const DEBUG: bool = false;
+
+fn main() {}
+"#;
+    assert!(matches!(
+        analyze(source, &[("LIVES", 300)]),
+        Err(SemaError::ConstantOverflow { value: 300, .. })
+    ));
+    let Err(error) = analyze(source, &[("DEBUG", 2)]) else {
+        panic!("-D DEBUG=2 accepted for a bool");
+    };
+    assert!(
+        error.to_string().contains("does not fit in type bool"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn defines_are_declared_at_most_once() {
+    let source = r#"
+const LIVES: u8 = 3;
+const LIVES: u8 = 4;
+
+fn main() {}
+"#;
+    assert!(matches!(
+        analyze(source, &[("LIVES", 5)]),
+        Err(SemaError::DuplicateSymbol { .. })
+    ));
+}
@@ -0,0 +1,245 @@
+//! Machine-readable diagnostic tests
+//!
+//! Every phase's errors and warnings convert to one `Diagnostic` shape and
+//! are written as single-line JSON objects.
+
+use crate::common::*;
+use wraith::ast::{FileId, SourceMap};
+use wraith::codegen::{CodegenError, CodegenOptions, generate_with_options};
+use wraith::config::MemoryConfig;
+use wraith::diagnostic::{Diagnostic, Severity};
+use wraith::sema::analyze::SemanticAnalyzer;
+use wraith::sema::{SemaError, analyze_program};
+use wraith::{Parser, lex};
+
+fn sema_diagnostics(source: &str) -> Vec<Diagnostic> {
+    let ast = compile_to_ast(source).unwrap();
+    let Err(diagnostics) = SemanticAnalyzer::new().analyze_all(&ast) else {
+        panic!("expected semantic errors");
+    };
+    let warnings = diagnostics.warnings.iter().map(Diagnostic::from_warning);
+    let errors = diagnostics
+        .errors
+        .iter()
+        .map(|error| Diagnostic::from_sema_error(error, source));
+    warnings.chain(errors).collect()
+}
+
+#[test]
+fn sema_errors_have_codes_and_positions() {
+    let source = "fn main() {\n    let flag: bool = 1;\n    let x: u8 = 1;\n    OUT = 2;\n}\n";
+    let diagnostics = sema_diagnostics(source);
+    let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+    assert_eq!(
+        codes,
+        ["unused-variable", "type-mismatch", "undefined-symbol"]
+    );
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(diagnostics[1].severity, Severity::Error);
+
+    assert_eq!(
+        diagnostics[1].to_json(source, "main.wr"),
+        concat!(
+            r#"{"severity":"error","code":"type-mismatch","message":"type mismatch","#,
+            r#""span":{"file":"main.wr","line":2,"column":22,"end_line":2,"end_column":23,"#,
+            r#""label":"expected bool, found u8"},"secondary":[],"notes":[]}"#
+        )
+    );
+    let json = diagnostics[2].to_json(source, "main.wr");
+    assert!(
+        json.contains(r#""message":"undefined symbol 'OUT'""#),
+        "{}",
+        json
+    );
+    assert!(json.contains(r#""line":4,"column":5"#), "{}", json);
+}
+
+#[test]
+fn duplicates_point_at_the_earlier_definition() {
+    let source = "fn helper() {}\nfn helper() {}\nfn main() { helper(); }\n";
+    let diagnostics = sema_diagnostics(source);
+    let duplicate = diagnostics
+        .iter()
+        .find(|d| d.code == "duplicate-symbol")
+        .unwrap();
+    assert_eq!(duplicate.message, "duplicate symbol 'helper'");
+    assert_eq!(duplicate.secondary.len(), 1);
+    let json = duplicate.to_json(source, "main.wr");
+    assert!(
+        json.contains(r#""secondary":[{"file":"main.wr","line":1,"column":4,"#),
+        "{}",
+        json
+    );
+}
+
+#[test]
+fn lex_and_parse_errors_convert() {
+    let error = lex("fn main() { let x: u8 = $; }").unwrap_err();
+    let diagnostic = Diagnostic::from_lex_error(&error);
+    assert_eq!(diagnostic.code, "invalid-token");
+    assert!(diagnostic.primary.is_some());
+
+    let tokens = lex("fn main() {\n    let x: u8 = 1\n}\n").unwrap();
+    let error = Parser::parse(&tokens).unwrap_err();
+    let diagnostics = Diagnostic::from_parse_error(&error);
+    assert!(!diagnostics.is_empty());
+    assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+    assert_eq!(diagnostics[0].code, "unexpected-token");
+}
+
+#[test]
+fn codegen_errors_have_no_span() {
+    let diagnostic =
+        Diagnostic::from_codegen_error(&CodegenError::SectionError("CODE is full".to_string()));
+    assert_eq!(
+        diagnostic.to_json("", "main.wr"),
+        concat!(
+            r#"{"severity":"error","code":"section-error","#,
+            r#""message":"section error: CODE is full","span":null,"secondary":[],"notes":[]}"#
+        )
+    );
+}
+
+#[test]
+fn codegen_errors_point_at_their_item() {
+    let source = "fn main() {}\n#[section(\"NOPE\")]\nfn helper() {}\n";
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let Err(error) = generate_with_options(&ast, &program, &CodegenOptions::default()) else {
+        panic!("unknown section accepted");
+    };
+    let span = error.span().unwrap();
+    assert_eq!(&source[span.start..span.end], "helper");
+    let json = Diagnostic::from_codegen_error(&error).to_json(source, "main.wr");
+    assert!(
+        json.starts_with(r#"{"severity":"error","code":"section-error","#),
+        "{}",
+        json
+    );
+    assert!(json.contains(r#""line":3,"column":4"#), "{}", json);
+}
+
+#[test]
+fn stack_errors_have_codes() {
+    let source = "fn f(n: u8) -> u8 {\n    return f(n) + 1;\n}\n#[reset]\nfn main() {\n    OUT = f(1);\n}\nconst OUT: addr = 0x6000;\n";
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let error =
+        wraith::stack::analyze(&ast, &program, wraith::assembler::Cpu::Nmos6502).unwrap_err();
+    let json = Diagnostic::from_stack_error(&error).to_json(source, "main.wr");
+    assert!(
+        json.starts_with(r#"{"severity":"error","code":"unbounded-recursion","#),
+        "{}",
+        json
+    );
+    // At the call that closes the cycle
+    assert!(json.contains(r#""line":2,"column":12"#), "{}", json);
+}
+
+#[test]
+fn budget_overruns_have_codes() {
+    let error = wraith::wcet::WcetError::OverBudget {
+        function: "tick".to_string(),
+        cycles: 120,
+        budget: 100,
+        span: None,
+    };
+    assert_eq!(
+        Diagnostic::from_wcet_error(&error).to_json("", "main.wr"),
+        concat!(
+            r#"{"severity":"error","code":"over-budget","#,
+            r#""message":"'tick' takes up to 120 cycles, over its #[max_cycles(100)] budget","#,
+            r#""span":null,"secondary":[],"notes":[]}"#
+        )
+    );
+    let warning = Diagnostic::warning("unchecked-budget", "not checked".to_string());
+    assert_eq!(warning.severity, Severity::Warning);
+}
+
+#[test]
+fn json_strings_are_escaped() {
+    let source = "fn main() {\n    let s: u8 = \"a\\\"b\";\n}\n";
+    let json: Vec<String> = sema_diagnostics(source)
+        .iter()
+        .map(|d| d.to_json(source, "dir\\main.wr"))
+        .collect();
+    assert!(json.iter().all(|line| !line.contains('\n')));
+    assert!(json[0].contains(r#""file":"dir\\main.wr""#), "{}", json[0]);
+}
+
+/// Analyze `main` after writing `files` (name, text) to the temp directory,
+/// where `{dir}` in any text is replaced by the directory
+fn analyze_with_imports(main: &str, files: &[(&str, &str)]) -> (SemaError, SourceMap) {
+    let dir = std::env::temp_dir().to_string_lossy().replace('\\', "/");
+    for (name, text) in files {
+        std::fs::write(format!("{}/{}", dir, name), text.replace("{dir}", &dir)).unwrap();
+    }
+    let main = main.replace("{dir}", &dir);
+    let ast = compile_to_ast(&main).unwrap();
+    let mut sources = SourceMap::new("main.wr", main.as_str());
+    let Err(diagnostics) = analyze_program(
+        &ast,
+        "main.wr".into(),
+        MemoryConfig::default(),
+        &[],
+        None,
+        &mut sources,
+    ) else {
+        panic!("expected an error");
+    };
+    (diagnostics.errors[0].clone(), sources)
+}
+
+#[test]
+fn imported_module_errors_point_into_their_file() {
+    let (error, sources) = analyze_with_imports(
+        "import { helper } from \"{dir}/test_diag_lib.wr\";\n\nfn main() {\n    helper();\n}\n",
+        &[
+            (
+                "test_diag_lib.wr",
+                "import { deep } from \"{dir}/test_diag_deep.wr\";\n\npub fn helper() {\n    deep();\n}\n",
+            ),
+            (
+                "test_diag_deep.wr",
+                "// deep\n\npub fn deep() {\n    let flag: bool = 1;\n}\n",
+            ),
+        ],
+    );
+    let span = error.span().unwrap();
+    assert_ne!(span.file, FileId::ROOT);
+    assert!(sources.name(span.file).ends_with("/test_diag_deep.wr"));
+
+    let text = error.format_with_sources(&sources);
+    assert!(text.contains("test_diag_deep.wr:4:22"), "{}", text);
+    assert!(text.contains("let flag: bool = 1;"), "{}", text);
+    let notes: Vec<&str> = text
+        .lines()
+        .filter(|line| line.contains("= note"))
+        .collect();
+    assert_eq!(notes.len(), 2, "{}", text);
+    assert!(notes[0].contains("test_diag_lib.wr:1:22"), "{}", text);
+    assert!(notes[1].ends_with("imported from main.wr:1:24"), "{}", text);
+
+    let json = Diagnostic::from_sema_error(&error, sources.source(span.file))
+        .to_json_with_sources(&sources);
+    assert!(json.contains(r#""line":4,"column":22"#), "{}", json);
+    assert!(
+        json.contains(r#""imported from main.wr:1:24"]"#),
+        "{}",
+        json
+    );
+}
+
+#[test]
+fn imported_module_parse_errors_point_into_their_file() {
+    let (error, sources) = analyze_with_imports(
+        "import { broken } from \"{dir}/test_diag_broken.wr\";\n\nfn main() {\n    broken();\n}\n",
+        &[(
+            "test_diag_broken.wr",
+            "pub fn broken() {\n    let x: u8 = ;\n}\n",
+        )],
+    );
+    assert_eq!(error.code(), "import-error");
+    let text = error.format_with_sources(&sources);
+    assert!(text.contains("test_diag_broken.wr:2:17"), "{}", text);
+    assert!(text.contains("expected expression"), "{}", text);
+    assert!(text.contains("imported from main.wr:1:24"), "{}", text);
+}
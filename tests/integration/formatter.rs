@@ -0,0 +1,227 @@
+//! Source formatter tests
+//!
+//! Formatting reprints the AST with the comments the lexer keeps as trivia;
+//! the result has to lex to the same tokens, keep every comment and be
+//! stable when formatted again.
+
+use std::fs;
+use std::path::Path;
+
+use wraith::formatter::{FormatError, format_source};
+use wraith::lexer::lex_with_comments;
+use wraith::{Parser, Token, lex};
+
+/// Tokens without the optional commas: those before closing delimiters
+/// and after block match arms
+fn significant_tokens(source: &str) -> Vec<Token> {
+    let tokens: Vec<Token> = lex(source).unwrap().into_iter().map(|t| t.token).collect();
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            **token != Token::Comma
+                || !(matches!(
+                    tokens.get(i + 1),
+                    Some(Token::RBrace | Token::RParen | Token::RBracket)
+                ) || tokens[i - 1] == Token::RBrace)
+        })
+        .map(|(_, token)| token.clone())
+        .collect()
+}
+
+fn comments(source: &str) -> Vec<String> {
+    let (_, comments) = lex_with_comments(source).unwrap();
+    comments
+        .into_iter()
+        .map(|span| source[span].trim_end().to_string())
+        .collect()
+}
+
+fn assert_formats_safely(source: &str, name: &str) {
+    let formatted = format_source(source).unwrap();
+    assert_eq!(
+        significant_tokens(&formatted),
+        significant_tokens(source),
+        "{} changed meaning:\n{}",
+        name,
+        formatted
+    );
+    assert_eq!(comments(&formatted), comments(source), "{}", name);
+    assert_eq!(
+        format_source(&formatted).unwrap(),
+        formatted,
+        "{} is not stable",
+        name
+    );
+}
+
+#[test]
+fn formats_items_and_statements() {
+    let source = r#"import {helper,LIMIT} from "./lib.wr";
+const OUT:write addr=0x6000;
+pub const SPEED :u16 = 0x0100;
+struct Point{x:u8,y:u8}
+enum Dir{North=0x10,Move(u8,u8),Jump{height:u8},}
+#[inline] #[ org ( 0x8000 ) ]
+fn step(p:Point,d:Dir)->u8{
+let total:u8=p.x+p.y*2;
+total+=1;
+if total>LIMIT{return 0;}else if total==0{total=1;}else{}
+#[bound(4)] while total<10{total=total<<1;}
+for i:u8 in 0..=3{OUT=i;}
+for (i,c) in "ab"{OUT=c;}
+match d{Dir::North=>helper(1,0),Dir::Move(a,b)=>{OUT=a;}Dir::Jump{height}=>helper(height,0),_=>{}}
+asm{"LDA #$01","STA $6000",}
+let q:Point=Point{x:-total,y:(total as u16).low};
+let v:u8=match d{Dir::North=>{ 1 },_=>helper(total,[0;4][0]),};
+return v;
+}
+"#;
+    let expected = r#"import { helper, LIMIT } from "./lib.wr";
+const OUT: write addr = 0x6000;
+pub const SPEED: u16 = 0x0100;
+
+struct Point {
+    x: u8,
+    y: u8,
+}
+
+enum Dir {
+    North = 0x10,
+    Move(u8, u8),
+    Jump { height: u8 },
+}
+
+#[inline]
+#[org(0x8000)]
+fn step(p: Point, d: Dir) -> u8 {
+    let total: u8 = p.x + p.y * 2;
+    total += 1;
+    if total > LIMIT {
+        return 0;
+    } else if total == 0 {
+        total = 1;
+    } else {}
+    #[bound(4)]
+    while total < 10 {
+        total = total << 1;
+    }
+    for i: u8 in 0..=3 {
+        OUT = i;
+    }
+    for (i, c) in "ab" {
+        OUT = c;
+    }
+    match d {
+        Dir::North => helper(1, 0),
+        Dir::Move(a, b) => {
+            OUT = a;
+        }
+        Dir::Jump { height } => helper(height, 0),
+        _ => {}
+    }
+    asm {
+        "LDA #$01",
+        "STA $6000",
+    }
+    let q: Point = Point { x: -total, y: (total as u16).low };
+    let v: u8 = match d {
+        Dir::North => { 1 },
+        _ => helper(total, [0; 4][0]),
+    };
+    return v;
+}
+"#;
+    assert_eq!(format_source(source).unwrap(), expected);
+    assert_formats_safely(source, "items");
+}
+
+#[test]
+fn keeps_comments_in_place() {
+    let source = r#"//! Module notes
+
+/// The output port
+const OUT: addr = 0x6000; // write only
+
+
+/// Entry point
+fn main() { // starts here
+    // first
+    let x: u8 = 1; /* inline */ // trailing
+
+    /* before the loop */
+    loop {
+        OUT = x;
+        // end of loop
+    }
+    // end of main
+}
+// end of file
+"#;
+    let expected = r#"//! Module notes
+
+/// The output port
+const OUT: addr = 0x6000; // write only
+
+/// Entry point
+fn main() { // starts here
+    // first
+    let x: u8 = 1; /* inline */ // trailing
+
+    /* before the loop */
+    loop {
+        OUT = x;
+        // end of loop
+    }
+    // end of main
+}
+// end of file
+"#;
+    assert_eq!(format_source(source).unwrap(), expected);
+    assert_formats_safely(source, "comments");
+}
+
+#[test]
+fn multiline_lists_keep_their_rows() {
+    let source = "const T: [u8; 6] = [\n  1, 2, 3,   // first\n  4,5,6 // second\n];\n";
+    let expected = "const T: [u8; 6] = [\n    1, 2, 3, // first\n    4, 5, 6, // second\n];\n";
+    assert_eq!(format_source(source).unwrap(), expected);
+    assert_formats_safely(source, "table");
+}
+
+#[test]
+fn example_programs_keep_their_meaning() {
+    let mut checked = 0;
+    for dir in ["examples", "tests/integration", "tests"] {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "wr"))
+            .collect();
+        files.sort();
+        for file in files {
+            let source = fs::read_to_string(&file).unwrap();
+            // Some examples use syntax the parser no longer accepts
+            if lex(&source).map_or(true, |tokens| Parser::parse(&tokens).is_err()) {
+                continue;
+            }
+            assert_formats_safely(&source, &file.display().to_string());
+            checked += 1;
+        }
+    }
+    assert!(checked > 20, "only {} files checked", checked);
+    assert!(Path::new("examples").exists());
+}
+
+#[test]
+fn reports_files_that_do_not_parse() {
+    assert!(matches!(
+        format_source("fn main() { let x: u8 = ; }"),
+        Err(FormatError::Parse(_))
+    ));
+    assert!(matches!(
+        format_source("fn main() { $ }"),
+        Err(FormatError::Lex(_))
+    ));
+}
@@ -0,0 +1,93 @@
+//! Binary image format tests
+//!
+//! Checks record encoding against known-good lines and how assembled bytes
+//! are grouped by memory section.
+
+use wraith::assembler::assemble;
+use wraith::assembler::image::{ImageFormat, Region, regions, write_intel_hex, write_srecords};
+use wraith::config::Section;
+
+fn sections() -> Vec<Section> {
+    vec![
+        Section::new("CODE", 0x8000, 0xBFFF),
+        Section::new("DATA", 0xD000, 0xEFFF),
+    ]
+}
+
+#[test]
+fn record_checksums() {
+    let hex = write_intel_hex(&[Region {
+        start: 0x0030,
+        data: vec![0x02, 0x33, 0x7A],
+    }]);
+    assert_eq!(hex, ":0300300002337A1E\n:00000001FF\n");
+
+    let mut data = vec![0x0A, 0x0A, 0x0D];
+    data.resize(16, 0);
+    let srec = write_srecords(
+        &[Region {
+            start: 0x7AF0,
+            data,
+        }],
+        "hello",
+        0x8000,
+    );
+    let lines: Vec<_> = srec.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "S008000068656C6C6FE3",
+            "S1137AF00A0A0D0000000000000000000000000061",
+            "S5030001FB",
+            "S90380007C",
+        ]
+    );
+}
+
+#[test]
+fn groups_bytes_by_section() {
+    let assembly = assemble(
+        ".ORG $8000\n    .BYTE $01, $02\n.ORG $8004\n    .BYTE $03\n.ORG $D000\n    .BYTE $04\n.ORG $FFFC\n    .WORD $8000\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        regions(&assembly, &sections()),
+        [
+            Region {
+                start: 0x8000,
+                data: vec![0x01, 0x02, 0xFF, 0xFF, 0x03],
+            },
+            Region {
+                start: 0xD000,
+                data: vec![0x04],
+            },
+            Region {
+                start: 0xFFFC,
+                data: vec![0x00, 0x80],
+            },
+        ]
+    );
+
+    let hex = String::from_utf8(ImageFormat::IntelHex.write(&assembly, &sections(), "t")).unwrap();
+    assert_eq!(
+        hex.lines().collect::<Vec<_>>(),
+        [
+            ":058000000102FFFF0377",
+            ":01D00000042B",
+            ":02FFFC00008083",
+            ":00000001FF",
+        ]
+    );
+
+    let srec = String::from_utf8(ImageFormat::SRecord.write(&assembly, &sections(), "t")).unwrap();
+    assert_eq!(srec.lines().last(), Some("S90380007C"));
+}
+
+#[test]
+fn prg_has_load_address() {
+    let assembly = assemble(".ORG $0801\n    .BYTE $0B, $08\n").unwrap();
+    let prg = ImageFormat::Prg.write(&assembly, &[Section::new("CODE", 0x0801, 0x9FFF)], "t");
+    assert_eq!(prg, [0x01, 0x08, 0x0B, 0x08]);
+    assert_eq!(ImageFormat::Raw.write(&assembly, &[], "t"), [0x0B, 0x08]);
+}
@@ -0,0 +1,86 @@
+//! Annotated listing tests
+//!
+//! Checks addresses, bytes, cycle counts and source lines in `.lst` output.
+
+use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::ast::SourceMap;
+use wraith::codegen::listing::generate_listing;
+
+fn listing(source: &str) -> String {
+    let output = compile_output_success(source);
+    let assembly = assemble(&output.asm).unwrap();
+    generate_listing(&output, &assembly, &SourceMap::new("test.wr", source))
+}
+
+#[test]
+fn instructions_show_address_bytes_and_cycles() {
+    let lst = listing(
+        r#"
+const OUT: addr = 0x6000;
+fn main() {
+    OUT = 5;
+}
+"#,
+    );
+
+    assert!(lst.contains("8000  A9 05     2         LDA #$05"), "{}", lst);
+    assert!(lst.contains("8002  8D 00 60  4         STA OUT"), "{}", lst);
+    assert!(lst.contains("8005  60        6         RTS"), "{}", lst);
+}
+
+#[test]
+fn source_lines_precede_their_instructions() {
+    let lst = listing(
+        r#"
+const OUT: addr = 0x6000;
+fn main() {
+    OUT = 5;
+}
+"#,
+    );
+
+    let source = lst.find("; test.wr:4  OUT = 5;").expect("source line");
+    let store = lst.find("STA OUT").unwrap();
+    assert!(source < store);
+}
+
+#[test]
+fn branches_and_indexed_reads_show_penalties() {
+    let lst = listing(
+        r#"
+fn main() {
+    asm {
+        "LDX #$03",
+        "wait: LDA $0200,X",
+        "DEX",
+        "BNE wait"
+    }
+}
+"#,
+    );
+
+    assert!(lst.contains("4+        LDA $0200,X"), "{}", lst);
+    assert!(lst.contains("2/3       BNE wait"), "{}", lst);
+}
+
+#[test]
+fn imported_code_shows_its_own_file() {
+    let (output, sources) = compile_with_imports(
+        "import { triple } from \"{dir}/test_listing_lib.wr\";\nconst OUT: addr = 0x6000;\nfn main() {\n    OUT = triple(2);\n}\n",
+        &[(
+            "test_listing_lib.wr",
+            "// triple\n\npub fn triple(x: u8) -> u8 {\n    return x + x + x;\n}\n",
+        )],
+    );
+    let assembly = assemble(&output.asm).unwrap();
+    let lst = generate_listing(&output, &assembly, &sources);
+
+    assert!(lst.starts_with("; Wraith listing for main.wr\n"), "{}", lst);
+    assert!(
+        lst.contains("test_listing_lib.wr:4  return x + x + x;"),
+        "{}",
+        lst
+    );
+    assert!(lst.contains("; main.wr:4  OUT = triple(2);"), "{}", lst);
+}
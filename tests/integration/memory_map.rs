@@ -0,0 +1,187 @@
+//! Memory map tests
+//!
+//! Compiles programs and checks the placed objects, the zero-page map and
+//! the JSON and text renderings.
+
+use crate::common::*;
+use std::path::PathBuf;
+use wraith::assembler::assemble;
+use wraith::codegen::memory_map::{
+    MemoryMap, ObjectKind, ZeroPageKind, build_memory_map, write_json, write_text,
+};
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::config::{MemoryConfig, Target};
+use wraith::sema::{analyze_with_config, analyze_with_path};
+
+fn memory_map(source: &str) -> MemoryMap {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    build_memory_map(&program, &output, &assembly)
+}
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+const TABLE: [u8; 3] = [1, 2, 3];
+
+fn scale(a: u16, b: u16) -> u16 {
+    return a * b;
+}
+
+#[reset]
+fn main() {
+    let msg: str = "hello";
+    let total: u16 = scale(300, 2);
+    OUT = TABLE[1] + msg.len.low;
+    OUT = total.low;
+    loop {}
+}
+"#;
+
+#[test]
+fn lists_objects_and_sections() {
+    let map = memory_map(PROGRAM);
+    let object = |name: &str| {
+        map.objects
+            .iter()
+            .find(|object| object.name == name)
+            .unwrap_or_else(|| panic!("no object {} in {:?}", name, map.objects))
+    };
+
+    assert_eq!(object("main").kind, ObjectKind::Function);
+    assert_eq!(object("main").section.as_deref(), Some("CODE"));
+    assert_eq!(object("mul16").kind, ObjectKind::Function);
+    assert_eq!(object("TABLE").kind, ObjectKind::ConstArray);
+    assert_eq!(object("TABLE").size, 3);
+
+    let string = map
+        .objects
+        .iter()
+        .find(|object| object.kind == ObjectKind::String)
+        .unwrap();
+    assert_eq!(string.size, 6);
+    assert_eq!(string.section.as_deref(), Some("DATA"));
+
+    let data = map.sections.iter().find(|s| s.name == "DATA").unwrap();
+    assert_eq!(data.used, 6);
+    assert!(map.objects.windows(2).all(|w| w[0].address <= w[1].address));
+}
+
+#[test]
+fn maps_zero_page() {
+    let map = memory_map(PROGRAM);
+    let entry = |function: Option<&str>, name: &str| {
+        map.zero_page
+            .iter()
+            .find(|entry| entry.function.as_deref() == function && entry.name == name)
+            .unwrap_or_else(|| panic!("no zero page entry {} in {:?}", name, map.zero_page))
+    };
+
+    let total = entry(Some("main"), "total");
+    assert_eq!((total.kind, total.size), (ZeroPageKind::Variable, 2));
+    let a = entry(Some("scale"), "a");
+    assert_eq!(
+        (a.kind, a.address, a.size),
+        (ZeroPageKind::Parameter, 0x80, 2)
+    );
+    let scratch = entry(None, "math_scratch");
+    assert_eq!(
+        (scratch.kind, scratch.address, scratch.size),
+        (ZeroPageKind::StdlibScratch, 0xD0, 9)
+    );
+    assert_eq!(entry(None, "temps").address, 0x20);
+    assert_eq!(entry(None, "software_stack_pointer").address, 0xFF);
+
+    // Without 16-bit math the stdlib scratch is free
+    let map = memory_map("#[reset]\nfn main() {\n    loop {}\n}\n");
+    assert!(
+        map.zero_page
+            .iter()
+            .all(|entry| entry.kind != ZeroPageKind::StdlibScratch)
+    );
+}
+
+#[test]
+fn lists_only_the_compiler_zero_page_in_use() {
+    let map = memory_map(
+        "const OUT: addr = 0x6000;\n#[reset]\nfn main() {\n    OUT = 1;\n    loop {}\n}\n",
+    );
+    let names: Vec<&str> = map
+        .zero_page
+        .iter()
+        .map(|entry| entry.name.as_str())
+        .collect();
+    assert_eq!(names, ["system", "software_stack_pointer"]);
+
+    // std/math.wr's mul16 is Wraith code and needs no stdlib scratch
+    let source = r#"
+import {mul16} from "math.wr";
+
+#[reset]
+fn main() {
+    let x: u16 = mul16(3, 4);
+}
+"#;
+    let ast = compile_to_ast(source).unwrap();
+    let program = analyze_with_path(&ast, PathBuf::from("std/main.wr")).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let map = build_memory_map(&program, &output, &assembly);
+    assert!(map.objects.iter().any(|object| object.name == "mul16"));
+    assert!(
+        map.zero_page
+            .iter()
+            .all(|entry| entry.kind != ZeroPageKind::StdlibScratch)
+    );
+}
+
+#[test]
+fn shows_the_target_reserved_zero_page() {
+    let ast = compile_to_ast(PROGRAM).unwrap();
+    let program = analyze_with_config(&ast, MemoryConfig::for_target(Target::C64)).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let map = build_memory_map(&program, &output, &assembly);
+
+    let reserved: Vec<(u8, u16)> = map
+        .zero_page
+        .iter()
+        .filter(|entry| entry.kind == ZeroPageKind::Reserved)
+        .map(|entry| (entry.address, entry.size))
+        .collect();
+    assert_eq!(reserved, [(0x00, 2), (0x90, 112)]);
+    assert!(
+        map.zero_page
+            .iter()
+            .filter(|entry| entry.kind != ZeroPageKind::Reserved)
+            .all(|entry| entry.address < 0x90)
+    );
+
+    let text = write_text(&map, "test.wr");
+    assert!(text.contains("  $90-$FF  112  reserved       c64\n"));
+    assert!(write_json(&map).contains(
+        "{\"name\": \"c64\", \"kind\": \"reserved\", \"address\": 144, \"size\": 112, \"function\": null}"
+    ));
+}
+
+#[test]
+fn writes_json_and_text() {
+    let map = memory_map(PROGRAM);
+    let main = map.objects.iter().find(|o| o.name == "main").unwrap();
+
+    let json = write_json(&map);
+    assert!(json.starts_with("{\n  \"sections\": [\n"));
+    assert!(json.contains(&format!(
+        "{{\"name\": \"main\", \"kind\": \"function\", \"address\": {}, \"size\": {}, \"section\": \"CODE\"}}",
+        main.address, main.size
+    )));
+    assert!(json.contains(
+        "{\"name\": \"a\", \"kind\": \"parameter\", \"address\": 128, \"size\": 2, \"function\": \"scale\"}"
+    ));
+    assert!(json.trim_end().ends_with('}'));
+
+    let text = write_text(&map, "test.wr");
+    assert!(text.starts_with("; Wraith memory map for test.wr\n"));
+    assert!(text.contains("  $80-$81    2  parameter      scale::a\n"));
+    assert!(text.contains("  $D0-$D8    9  stdlib         math_scratch\n"));
+}
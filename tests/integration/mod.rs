@@ -2,6 +2,26 @@
 //!
 //! Tests each phase in isolation to ensure correctness
 
+mod assembler;
+mod bits;
+mod ca65;
+mod cmos;
 mod codegen;
+mod debuginfo;
+mod defines;
+mod diagnostics;
+mod formatter;
+mod image;
+mod listing;
+mod memory_map;
 mod peephole;
+mod profile;
+mod ricoh;
+mod source_map;
+mod stack;
+mod targets;
+mod testing;
+mod undocumented;
+mod w65816;
+mod wcet;
 mod warnings;
@@ -3,6 +3,10 @@
 //! Tests the peephole optimizer's ability to eliminate redundant operations
 
 use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::codegen::peephole::{OptLevel, PeepholeOptions};
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::sim::{DEFAULT_CYCLE_LIMIT, Simulator, StopReason};
 
 // ============================================================================
 // Redundant Load Elimination
@@ -435,3 +439,126 @@ fn redundant_immediate_loads_eliminated() {
         asm
     );
 }
+
+// ============================================================================
+// Optimization Levels
+// ============================================================================
+
+/// Code size and the bytes written to $6000-$6003 when `main` runs
+fn run_at(source: &str, peephole: PeepholeOptions) -> (usize, Vec<u8>) {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let options = CodegenOptions {
+        peephole,
+        ..Default::default()
+    };
+    let output = generate_with_options(&ast, &program, &options).unwrap();
+    let assembly = assemble(&output.asm).unwrap_or_else(|e| panic!("{}\n{}", e, output.asm));
+    let size = assembly
+        .segments
+        .iter()
+        .map(|segment| segment.data.len())
+        .sum();
+    let mut sim = Simulator::with_assembly(&assembly);
+    assert_eq!(
+        sim.call("main", DEFAULT_CYCLE_LIMIT),
+        Ok(StopReason::Returned)
+    );
+    (size, (0x6000..0x6004).map(|addr| sim.read(addr)).collect())
+}
+
+#[test]
+fn every_level_computes_the_same_results() {
+    let source = r#"
+        const OUT: addr = 0x6000;
+        const OUT1: addr = 0x6001;
+        const OUT2: addr = 0x6002;
+        const OUT3: addr = 0x6003;
+        fn double(x: u8) -> u8 {
+            return x + x;
+        }
+        fn main() {
+            let total: u8 = 0;
+            let odd: u8 = 0;
+            for i in 0..10 {
+                total = total + i;
+                if (i & 1) == 0 {
+                    continue;
+                }
+                odd = odd + 1;
+            }
+            OUT = total;
+            OUT1 = odd;
+            OUT2 = double(total);
+            if total > 40 {
+                OUT3 = 1;
+            } else {
+                OUT3 = 2;
+            }
+        }
+    "#;
+    let at = |level| {
+        run_at(
+            source,
+            PeepholeOptions {
+                level,
+                ..Default::default()
+            },
+        )
+    };
+    let (o0, expected) = at(OptLevel::O0);
+    assert_eq!(expected, [45, 5, 90, 1]);
+    let mut previous = o0;
+    for level in [OptLevel::O1, OptLevel::O2, OptLevel::Os] {
+        let (size, results) = at(level);
+        assert_eq!(results, expected, "{:?}", level);
+        assert!(
+            size <= previous,
+            "{:?}: {} bytes after {}",
+            level,
+            size,
+            previous
+        );
+        previous = size;
+    }
+    assert!(previous < o0);
+
+    let disabled = run_at(
+        source,
+        PeepholeOptions {
+            level: OptLevel::Os,
+            disabled: vec!["branch-over-jump".to_string(), "tail-calls".to_string()],
+        },
+    );
+    assert_eq!(disabled.1, expected);
+    assert!(disabled.0 > previous);
+}
+
+#[test]
+fn pass_stats_cover_the_passes_that_ran() {
+    let (ast, program) = compile_to_sema(
+        r#"
+        const OUT: addr = 0x6000;
+        fn main() {
+            OUT = 0;
+            OUT = 0;
+        }
+    "#,
+    )
+    .unwrap();
+    let options = CodegenOptions {
+        pass_stats: true,
+        ..Default::default()
+    };
+    let output = generate_with_options(&ast, &program, &options).unwrap();
+    assert!(
+        output
+            .pass_stats
+            .iter()
+            .all(|stat| stat.name != "branch-over-jump")
+    );
+    let removed: i64 = output.pass_stats.iter().map(|stat| stat.bytes).sum();
+    assert!(removed > 0, "{:?}", output.pass_stats);
+
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    assert!(output.pass_stats.is_empty());
+}
@@ -0,0 +1,74 @@
+//! Cycle profiler tests
+//!
+//! Profiles simulated runs and checks per-function and per-line attribution.
+
+use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::ast::SourceMap;
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::profile::{Profile, ProfileEntry, Profiler, format_report};
+use wraith::sim::StopReason;
+
+fn profile(source: &str, entry: ProfileEntry) -> Profile {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    Profiler::new(&output, &assembly, &SourceMap::new("test.wr", source))
+        .run(&entry)
+        .unwrap()
+}
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+
+fn double(x: u8) -> u8 {
+    return x + x;
+}
+
+fn sum() -> u8 {
+    let total: u8 = 0;
+    let i: u8 = 0;
+    while i < 4 {
+        total = total + double(i);
+        i = i + 1;
+    }
+    return total;
+}
+
+#[reset]
+fn main() {
+    OUT = sum();
+    loop {}
+}
+"#;
+
+#[test]
+fn attributes_cycles_to_functions() {
+    let profile = profile(PROGRAM, ProfileEntry::Reset);
+    assert!(matches!(profile.stop, StopReason::Halted { .. }));
+
+    let main = profile.function("main").unwrap();
+    let sum = profile.function("sum").unwrap();
+    let double = profile.function("double").unwrap();
+    assert_eq!((main.calls, sum.calls, double.calls), (1, 1, 4));
+    assert_eq!(main.inclusive, profile.total_cycles);
+    assert_eq!(double.inclusive, double.exclusive);
+    assert_eq!(sum.inclusive, sum.exclusive + double.inclusive);
+    assert_eq!(
+        main.exclusive + sum.exclusive + double.exclusive,
+        profile.total_cycles
+    );
+    assert_eq!(profile.functions[0].name, "main");
+}
+
+#[test]
+fn profiles_a_single_call_and_hot_lines() {
+    let profile = profile(PROGRAM, ProfileEntry::Function("double".to_string()));
+    assert_eq!(profile.stop, StopReason::Returned);
+    assert_eq!(profile.functions.len(), 1);
+    assert_eq!(profile.lines[0].line, 5);
+
+    let report = format_report(&profile, &SourceMap::new("test.wr", PROGRAM), 5);
+    assert!(report.contains("returned"));
+    assert!(report.contains("test.wr:5  return x + x;"));
+}
@@ -0,0 +1,86 @@
+//! Ricoh 2A03 tests
+//!
+//! The NES processor ignores the decimal flag, so BCD arithmetic and SED in
+//! inline assembly must be rejected instead of silently computing in binary.
+
+use crate::common::*;
+use wraith::assembler::Cpu;
+use wraith::config::Target;
+use wraith::sema::analyze_for_cpu;
+
+#[test]
+fn nes_target_uses_the_2a03() {
+    assert_eq!(Target::NesNrom.cpu(), Cpu::Ricoh2A03);
+    assert_eq!(Target::C64.cpu(), Cpu::Nmos6502);
+}
+
+#[test]
+fn binary_arithmetic_matches_nmos_results() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn main() {
+    let a: u8 = 0x19;
+    let b: u8 = 0x28;
+    OUT = a + b;
+}
+"#;
+    let output = compile_for_cpu(source, Cpu::Ricoh2A03).unwrap();
+    assert!(output.asm.starts_with(".SETCPU \"2A03\""));
+    let sim = run_for_cpu(source, Cpu::Ricoh2A03);
+    assert_eq!(sim.read(0x6000), 0x41);
+    assert_eq!(sim.read(0x6000), run_success(source).read(0x6000));
+}
+
+#[test]
+fn bcd_arithmetic_is_an_error() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn main() {
+    let score: b8 = 19 as b8;
+    let points: b8 = 28 as b8;
+    score = score + points;
+    OUT = score as u8;
+}
+"#;
+    let Err(error) = compile_for_cpu(source, Cpu::Ricoh2A03) else {
+        panic!("b8 addition accepted for the 2A03");
+    };
+    assert!(error.contains("needs decimal mode"), "{}", error);
+    assert!(error.contains("2A03"), "{}", error);
+    assert!(compile_for_cpu(source, Cpu::Nmos6502).is_ok());
+
+    // Reported by sema, at the expression
+    let ast = compile_to_ast(source).unwrap();
+    let Err(error) = analyze_for_cpu(&ast, Cpu::Ricoh2A03) else {
+        panic!("sema accepted the program");
+    };
+    let span = error.span().unwrap();
+    assert_eq!(&source[span.start..span.end], "score + points");
+}
+
+#[test]
+fn inline_sed_is_an_error() {
+    let source = r#"
+fn main() {
+    asm {
+        "SED",
+        "CLD"
+    }
+}
+"#;
+    let Err(error) = compile_for_cpu(source, Cpu::Ricoh2A03) else {
+        panic!("SED accepted for the 2A03");
+    };
+    assert!(error.contains("inline asm uses SED"), "{}", error);
+    assert!(compile_for_cpu(source, Cpu::Nmos6502).is_ok());
+
+    // Reported by sema, at the asm statement
+    let ast = compile_to_ast(source).unwrap();
+    let Err(error) = analyze_for_cpu(&ast, Cpu::Ricoh2A03) else {
+        panic!("sema accepted the program");
+    };
+    let span = error.span().unwrap();
+    assert!(source[span.start..span.end].starts_with("asm {"));
+}
@@ -0,0 +1,80 @@
+//! Source map tests
+//!
+//! Checks the PC to source line table and `; file:line` markers.
+
+use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::ast::SourceMap;
+use wraith::codegen::source_map::{insert_markers, line_table, write_line_table};
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+const LED: addr = 0x6001;
+fn main() {
+    OUT = 5;
+    LED = 6;
+}
+"#;
+
+#[test]
+fn line_table_maps_addresses_to_statements() {
+    let output = compile_output_success(PROGRAM);
+    let assembly = assemble(&output.asm).unwrap();
+    let sources = SourceMap::new("test.wr", PROGRAM);
+    let table = line_table(&output, &assembly, &sources);
+
+    let first = table.iter().find(|entry| entry.line == 5).expect("line 5");
+    let second = table.iter().find(|entry| entry.line == 6).expect("line 6");
+    assert_eq!((first.start, first.end), (0x8000, 0x8004));
+    assert_eq!((second.start, second.end), (0x8005, 0x8009));
+
+    let text = write_line_table(&table, &sources);
+    assert!(text.contains("8000 8004 test.wr:5:5\n"), "{}", text);
+}
+
+#[test]
+fn markers_survive_assembly() {
+    let mut output = compile_output_success(PROGRAM);
+    let sources = SourceMap::new("test.wr", PROGRAM);
+    insert_markers(&mut output, &sources);
+    assert_eq!(output.asm.lines().count(), output.line_spans.len());
+
+    let marker = output.asm.find("; test.wr:5  OUT = 5;").expect("marker");
+    assert!(marker < output.asm.find("LDA #$05").unwrap());
+
+    // Spans stay aligned with the shifted lines
+    let assembly = assemble(&output.asm).unwrap();
+    let table = line_table(&output, &assembly, &sources);
+    assert!(table.iter().any(|entry| entry.line == 6 && entry.start == 0x8005));
+}
+
+#[test]
+fn imported_functions_map_to_their_module() {
+    let (mut output, sources) = compile_with_imports(
+        "import { triple } from \"{dir}/test_srcmap_lib.wr\";\nconst OUT: addr = 0x6000;\nfn main() {\n    OUT = triple(2);\n}\n",
+        &[(
+            "test_srcmap_lib.wr",
+            "// triple\n\npub fn triple(x: u8) -> u8 {\n    return x + x + x;\n}\n",
+        )],
+    );
+    let assembly = assemble(&output.asm).unwrap();
+    let table = line_table(&output, &assembly, &sources);
+
+    let triple = assembly.symbol("triple").expect("triple");
+    let entry = table
+        .iter()
+        .find(|entry| entry.start <= triple && triple <= entry.end)
+        .expect("line of triple");
+    assert!(sources.name(entry.file).ends_with("/test_srcmap_lib.wr"));
+    assert_eq!(entry.line, 4);
+    let text = write_line_table(&table, &sources);
+    assert!(text.contains("test_srcmap_lib.wr:4:5\n"), "{}", text);
+    assert!(text.contains(" main.wr:4:5\n"), "{}", text);
+
+    insert_markers(&mut output, &sources);
+    assert!(
+        output.asm.contains("test_srcmap_lib.wr:4  return x + x + x;"),
+        "{}",
+        output.asm
+    );
+}
@@ -0,0 +1,200 @@
+//! Hardware stack depth tests
+//!
+//! Checks call chains, interrupt entry, inline assembly pushes and recursion,
+//! and that the reported depth matches the simulator.
+
+use crate::common::*;
+use wraith::assembler::Cpu;
+use wraith::sim::{Simulator, StopReason};
+use wraith::stack::{EntryPoint, StackError, StackReport, analyze};
+
+fn stack_report(source: &str) -> Result<StackReport, StackError> {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    analyze(&ast, &program, Cpu::Nmos6502)
+}
+
+#[test]
+fn reset_depth_matches_simulation() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn scale(x: u16, k: u16) -> u16 {
+    let y: u16 = x * k;
+    OUT = y.high;
+    return y;
+}
+
+fn run(x: u16) -> u16 {
+    return scale(x, x) + x;
+}
+
+#[reset]
+fn main() {
+    let y: u16 = run(100);
+    OUT = y.low;
+    loop {}
+}
+"#;
+    let report = stack_report(source).unwrap();
+    let main = &report.entries[0];
+    assert_eq!(main.entry, EntryPoint::Reset);
+    assert_eq!(main.path, ["main", "run", "scale", "mul16"]);
+
+    let mut sim = Simulator::with_assembly(&assemble_success(source));
+    sim.reset();
+    let mut lowest = sim.registers.sp;
+    let stop = sim
+        .run_with(100_000, |sim, _| lowest = lowest.min(sim.registers.sp))
+        .unwrap();
+    assert!(matches!(stop, StopReason::Halted { .. }));
+    assert_eq!(main.depth, (0xFF - lowest) as u32);
+}
+
+#[test]
+fn interrupts_and_inline_asm() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn emit(x: u8) {
+    OUT = x;
+}
+
+#[irq]
+fn on_irq() {
+    asm {
+        "PHA",
+        "PHA"
+    }
+    emit(1);
+    asm {
+        "PLA",
+        "PLA"
+    }
+}
+
+#[nmi]
+fn on_nmi() {
+    emit(2);
+}
+
+#[reset]
+fn main() {
+    loop {}
+}
+"#;
+    let report = stack_report(source).unwrap();
+    let depths: Vec<_> = report
+        .entries
+        .iter()
+        .map(|usage| (usage.entry, usage.depth))
+        .collect();
+    // 3 pushed by the interrupt, 3 by the prologue, 2 by the asm, 2 for the JSR
+    assert_eq!(
+        depths,
+        [
+            (EntryPoint::Irq, 10),
+            (EntryPoint::Nmi, 8),
+            (EntryPoint::Reset, 2)
+        ]
+    );
+    assert_eq!(report.worst_case(), 20);
+}
+
+#[test]
+fn recursion_is_rejected_but_tail_calls_are_not() {
+    let source = r#"
+fn ping(n: u8) -> u8 {
+    if n == 0 {
+        return 0;
+    }
+    return pong(n - 1);
+}
+
+fn pong(n: u8) -> u8 {
+    return ping(n);
+}
+
+#[reset]
+fn main() {
+    let x: u8 = ping(3);
+    loop {}
+}
+"#;
+    let Err(StackError::Recursion { cycle, span }) = stack_report(source) else {
+        panic!("recursion accepted");
+    };
+    assert_eq!(cycle, ["ping", "pong", "ping"]);
+    // At the call in `pong` that closes the cycle
+    assert_eq!(&source[span.start..span.end], "ping(n)");
+
+    let source = r#"
+fn count(n: u8, acc: u8) -> u8 {
+    if n == 0 {
+        return acc;
+    }
+    return count(n - 1, acc + 1);
+}
+
+#[reset]
+fn main() {
+    let x: u8 = count(5, 0);
+    loop {}
+}
+"#;
+    let report = stack_report(source).unwrap();
+    assert_eq!(report.entries[0].depth, 4);
+}
+
+#[test]
+fn bounded_recursion_covers_simulation() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+#[recursion(5)]
+fn fib(n: u8) -> u16 {
+    if n <= 1 {
+        return n as u16;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+#[reset]
+fn main() {
+    let x: u16 = fib(5);
+    OUT = x.low;
+    loop {}
+}
+"#;
+    let report = stack_report(source).unwrap();
+    let main = &report.entries[0];
+
+    let mut sim = Simulator::with_assembly(&assemble_success(source));
+    sim.reset();
+    let mut lowest = sim.registers.sp;
+    let stop = sim
+        .run_with(1_000_000, |sim, _| lowest = lowest.min(sim.registers.sp))
+        .unwrap();
+    assert!(matches!(stop, StopReason::Halted { .. }));
+    let used = (0xFF - lowest) as u32;
+    assert!(
+        main.depth >= used && main.depth <= used + 8,
+        "reported {}, simulated {}",
+        main.depth,
+        used
+    );
+
+    // The bound only covers direct self calls
+    let one_level = stack_report(&source.replace("#[recursion(5)]", "#[recursion(1)]")).unwrap();
+    assert!(one_level.entries[0].depth < main.depth);
+}
+
+#[test]
+fn overflow_is_an_error() {
+    let pushes = "        \"PHA\",\n".repeat(260);
+    let source = format!(
+        "#[reset]\nfn main() {{\n    asm {{\n{}    }}\n    loop {{}}\n}}\n",
+        pushes
+    );
+    let result = stack_report(&source);
+    assert!(matches!(result, Err(StackError::Overflow(ref report)) if report.worst_case() == 262));
+}
@@ -0,0 +1,199 @@
+//! Target profile tests
+//!
+//! Compiles against each kind of target and checks the startup code, section
+//! placement, reserved zero page and predeclared I/O addresses.
+
+use crate::common::*;
+use wraith::assembler::{Assembly, assemble};
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::config::{MemoryConfig, Target};
+use wraith::sema::analyze_with_config;
+use wraith::sema::table::SymbolLocation;
+
+fn build(source: &str, target: Target) -> (wraith::sema::ProgramInfo, Assembly) {
+    let ast = compile_to_ast(source).unwrap();
+    let program = analyze_with_config(&ast, MemoryConfig::for_target(target)).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    (program, assembly)
+}
+
+#[test]
+fn c64_starts_from_a_basic_line() {
+    let source = r#"
+const COLORS: [u8; 2] = [6, 14];
+
+#[reset]
+fn main() {
+    VIC_BORDER = COLORS[1];
+    loop {}
+}
+"#;
+    let (_, assembly) = build(source, Target::C64);
+
+    // 10 SYS 2061, then JMP main
+    let stub: Vec<u8> = (0x0801..0x0810)
+        .map(|addr| assembly.read(addr).unwrap())
+        .collect();
+    let main = assembly.symbol("main").unwrap().to_le_bytes();
+    assert_eq!(
+        stub,
+        [
+            0x0B, 0x08, 0x0A, 0x00, 0x9E, b'2', b'0', b'6', b'1', 0x00, 0x00, 0x00, 0x4C, main[0],
+            main[1]
+        ]
+    );
+    assert_eq!(assembly.symbol("COLORS"), Some(0x9000));
+    assert_eq!(assembly.symbol("VIC_BORDER"), Some(0xD020));
+    assert_eq!(assembly.read(0xFFFC), None);
+}
+
+#[test]
+fn cartridge_targets_keep_the_vector_table() {
+    let source = r#"
+const PORTB: addr = 0x7000;
+
+#[reset]
+fn main() {
+    DDRB = 0xFF;
+    PORTB = 1;
+    loop {}
+}
+"#;
+    let (_, assembly) = build(source, Target::BenEater);
+    let main = assembly.symbol("main").unwrap();
+    assert_eq!(main, 0x8000);
+    assert_eq!(assembly.read(0xFFFC), Some(0x00));
+    assert_eq!(assembly.read(0xFFFD), Some(0x80));
+    // The program's own declaration wins over the target's
+    assert_eq!(assembly.symbol("PORTB"), Some(0x7000));
+    assert_eq!(assembly.symbol("DDRB"), Some(0x6002));
+}
+
+#[test]
+fn variables_avoid_reserved_zero_page() {
+    let source = r#"
+#[reset]
+fn main() {
+    let a: u8 = 1;
+    let b: u16 = 2;
+    COLBK = a + b.low;
+    loop {}
+}
+"#;
+    let (program, _) = build(source, Target::Atari800);
+    let reserved = Target::Atari800.reserved_zero_page();
+    let addresses: Vec<u8> = program
+        .resolved_symbols
+        .values()
+        .filter_map(|symbol| match symbol.location {
+            SymbolLocation::ZeroPage(addr) if symbol.containing_function.is_some() => Some(addr),
+            _ => None,
+        })
+        .collect();
+    assert!(!addresses.is_empty());
+    // $80-$FF is too small for the full 64-byte parameter region
+    assert_eq!(program.memory_layout.param_space(), 0x20);
+    assert!(
+        addresses.iter().all(|&addr| {
+            addr >= 0xC0 && !reserved.iter().any(|&(s, e)| (s..=e).contains(&addr))
+        })
+    );
+}
+
+/// Zero-page addresses the generated code reads or writes
+fn zero_page_operands(asm: &str) -> Vec<u8> {
+    let mut addresses = Vec::new();
+    for line in asm.lines() {
+        let code = line.split(';').next().unwrap();
+        if code.trim_start().starts_with('.') {
+            continue;
+        }
+        let mut rest = code;
+        while let Some(index) = rest.find('$') {
+            let immediate = rest[..index].ends_with('#');
+            let digits: String = rest[index + 1..]
+                .chars()
+                .take_while(|c| c.is_ascii_hexdigit())
+                .collect();
+            if !immediate && digits.len() == 2 {
+                addresses.push(u8::from_str_radix(&digits, 16).unwrap());
+            }
+            rest = &rest[index + 1..];
+        }
+    }
+    addresses
+}
+
+#[test]
+fn generated_code_avoids_reserved_zero_page() {
+    let source = r#"
+const OUT: addr = 0x0400;
+
+enum Shape {
+    Dot,
+    Line(u8),
+}
+
+fn scale(a: u16, b: u16) -> u16 {
+    let three: u16 = 3;
+    return a * b / three + a % b;
+}
+
+#[recursion(4)]
+fn depth(n: u8) -> u8 {
+    if n == 0 {
+        return 0;
+    }
+    return depth(n - 1) + 1;
+}
+
+fn area(shape: Shape) -> u8 {
+    let size: u8 = 1;
+    match shape {
+        Shape::Dot => {}
+        Shape::Line(length) => {
+            size = length;
+        }
+    }
+    return size;
+}
+
+#[reset]
+fn main() {
+    let total: u16 = scale(300, 7);
+    OUT = total.low;
+    OUT = depth(3);
+    OUT = area(Shape::Line(5));
+    let text: str = "hi";
+    for (i, c) in text {
+        OUT = c + i;
+    }
+    loop {}
+}
+"#;
+    for target in Target::ALL {
+        let ast = compile_to_ast(source).unwrap();
+        let program = analyze_with_config(&ast, MemoryConfig::for_target(target))
+            .unwrap_or_else(|e| panic!("{}: {:?}", target.name(), e));
+        let output = generate_with_options(&ast, &program, &CodegenOptions::default())
+            .unwrap_or_else(|e| panic!("{}: {:?}", target.name(), e));
+        assemble(&output.asm).unwrap_or_else(|e| panic!("{}: {:?}", target.name(), e));
+
+        let addresses = zero_page_operands(&output.asm);
+        assert!(addresses.contains(&program.memory_layout.stack_pointer));
+        for &(start, end) in target.reserved_zero_page() {
+            let touched: Vec<u8> = addresses
+                .iter()
+                .copied()
+                .filter(|addr| (start..=end).contains(addr))
+                .collect();
+            assert!(
+                touched.is_empty(),
+                "{} touches reserved zero page {:02X?}",
+                target.name(),
+                touched
+            );
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! `#[test]` function tests
+//!
+//! Builds test images and checks discovery, outcomes and cycle counts.
+
+use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::ast::SourceMap;
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::testing::{TestOutcome, TestResult, TestRunner, discover_tests};
+
+fn run_tests(source: &str) -> Vec<TestResult> {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let options = CodegenOptions {
+        include_tests: true,
+        ..Default::default()
+    };
+    let output = generate_with_options(&ast, &program, &options).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let tests = discover_tests(&ast, &program);
+    let sources = SourceMap::new("test.wr", source);
+    TestRunner::new(&output, &assembly, &sources).run_all(&tests)
+}
+
+const PROGRAM: &str = r#"
+import { assert } from "intrinsics.wr";
+
+fn add(a: u8, b: u8) -> u8 {
+    return a + b;
+}
+
+#[test]
+fn adds() {
+    assert(add(2, 3) == 5);
+}
+
+#[test]
+fn compares() -> bool {
+    return add(1, 1) == 3;
+}
+
+#[test]
+fn asserts() {
+    let sum: u8 = add(1, 1);
+    assert(sum == 3);
+}
+"#;
+
+#[test]
+fn outcomes_follow_returns_and_assertions() {
+    let results = run_tests(PROGRAM);
+    let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["adds", "compares", "asserts"]);
+
+    assert_eq!(results[0].outcome, TestOutcome::Passed);
+    assert_eq!(
+        results[1].outcome,
+        TestOutcome::Failed("returned false".to_string())
+    );
+    assert_eq!(
+        results[2].outcome,
+        TestOutcome::Failed("assertion failed at test.wr:21".to_string())
+    );
+    assert!(results.iter().all(|r| r.cycles > 6));
+}
+
+#[test]
+fn tests_are_left_out_of_normal_builds() {
+    let asm = compile_success(PROGRAM);
+    assert!(!asm.contains("adds:"));
+    assert!(asm.contains("add:"));
+}
+
+#[test]
+fn tests_take_no_parameters() {
+    assert_error_contains(
+        r#"
+        #[test]
+        fn takes_input(x: u8) {}
+    "#,
+        "must take no parameters",
+    );
+}
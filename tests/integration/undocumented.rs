@@ -0,0 +1,81 @@
+//! Undocumented NMOS opcode tests
+//!
+//! Compiles with the stable undocumented opcodes enabled and checks that LAX,
+//! SAX and DCP are used, that results match the documented-only build, and
+//! that inline assembly may only use them when they are enabled.
+
+use crate::common::*;
+use wraith::assembler::{Cpu, assemble};
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const OUT2: addr = 0x6002;
+
+fn main() {
+    let count: u8 = 5;
+    let total: u8 = 0;
+    let a: u8 = 0x3C;
+    let b: u8 = 0x0F;
+    let masked: u8 = 0;
+    loop {
+        total = total + 3;
+        count = count - 1;
+        if count == 0 {
+            break;
+        }
+    }
+    masked = a & b;
+    OUT = total;
+    OUT1 = masked;
+    OUT2 = count;
+}
+"#;
+
+#[test]
+fn undocumented_program_matches_nmos_results() {
+    let fast = run_for_cpu(PROGRAM, Cpu::Nmos6502Undocumented);
+    let nmos = run_success(PROGRAM);
+    for addr in 0x6000..0x6003 {
+        assert_eq!(fast.read(addr), nmos.read(addr), "${:04X}", addr);
+    }
+    assert_eq!(fast.read(0x6000), 15);
+    assert_eq!(fast.read(0x6001), 0x0C);
+}
+
+#[test]
+fn undocumented_output_uses_sax_and_dcp() {
+    let asm = compile_for_cpu(PROGRAM, Cpu::Nmos6502Undocumented)
+        .unwrap()
+        .asm;
+    assert!(asm.starts_with(".SETCPU \"6502X\""));
+    assert_instruction_sequence(&asm, &["LDA $", "LDX $", "SAX $"]);
+    assert_asm_contains(&asm, "DCP $");
+
+    let asm = compile_success(PROGRAM);
+    for mnemonic in ["SAX", "DCP", "LAX", ".SETCPU"] {
+        assert_asm_not_contains(&asm, mnemonic);
+    }
+}
+
+#[test]
+fn inline_asm_needs_the_flag_for_undocumented_opcodes() {
+    let source = r#"
+fn main() {
+    asm {
+        "LAX $40",
+        "SAX $41"
+    }
+}
+"#;
+    for cpu in [Cpu::Nmos6502, Cpu::Cmos65C02] {
+        let Err(error) = compile_for_cpu(source, cpu) else {
+            panic!("LAX accepted for {}", cpu.name());
+        };
+        assert!(error.contains("undocumented opcode LAX"), "{}", error);
+    }
+
+    let output = compile_for_cpu(source, Cpu::Nmos6502Undocumented).unwrap();
+    assert_asm_contains(&output.asm, "LAX $40");
+    assert!(assemble(&output.asm).is_ok());
+}
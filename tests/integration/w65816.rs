@@ -0,0 +1,339 @@
+//! 65816 tests
+//!
+//! Word assignments and binary expressions on u16/i16 run with a 16-bit
+//! accumulator between REP and SEP, locals live in stack frames, and
+//! `addr` declarations above $FFFF are reached with long addressing.
+//! Results must match the NMOS build.
+
+use crate::common::*;
+use wraith::assembler::{Cpu, assemble};
+use wraith::codegen::peephole::parse_assembly;
+use wraith::codegen::stack_frame::check_accesses;
+use wraith::sema::analyze_for_cpu;
+use wraith::sim::{DEFAULT_CYCLE_LIMIT, Registers, Simulator, StopReason};
+use wraith::stack::analyze;
+
+const PROGRAM: &str = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const OUT2: addr = 0x6002;
+const OUT3: addr = 0x6003;
+
+fn main() {
+    let a: u16 = 0x12FF;
+    let b: u16 = 0x0101;
+    let c: u16 = a + b;
+    c = c - 0x0200;
+    c += 1 as u16;
+    let d: i16 = 100 as i16;
+    let e: i16 = 300 as i16;
+    d = d - e;
+    OUT = c.low;
+    OUT1 = c.high;
+    OUT2 = c.high + c.low;
+    OUT3 = d.low;
+}
+"#;
+
+#[test]
+fn word_arithmetic_matches_nmos_results() {
+    let native = run_for_cpu(PROGRAM, Cpu::W65C816);
+    let nmos = run_success(PROGRAM);
+    for addr in 0x6000..0x6004 {
+        assert_eq!(native.read(addr), nmos.read(addr), "${:04X}", addr);
+    }
+    assert_eq!(native.read(0x6000), 0x01);
+    assert_eq!(native.read(0x6001), 0x12);
+    assert_eq!(native.read(0x6002), 0x13);
+    assert_eq!(native.read(0x6003), 0x38);
+}
+
+#[test]
+fn word_bitwise_operations_cover_both_bytes() {
+    let source = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+
+fn main() {
+    let a: u16 = 0x1201;
+    let b: u16 = a | 0x0FF0;
+    let c: u16 = b & 0xF00F;
+    c = c ^ 0x0101;
+    OUT = c.low;
+    OUT1 = c.high;
+}
+"#;
+    let sim = run_for_cpu(source, Cpu::W65C816);
+    assert_eq!(sim.read(0x6000), 0x00);
+    assert_eq!(sim.read(0x6001), 0x11);
+}
+
+#[test]
+fn word_assignments_use_the_16_bit_accumulator() {
+    let asm = compile_for_cpu(PROGRAM, Cpu::W65C816).unwrap().asm;
+    assert!(asm.starts_with(".SETCPU \"65816\""));
+    assert_asm_contains(&asm, "REP #$20");
+    assert_asm_contains(&asm, "LDA #$12FF");
+    assert_asm_contains(&asm, "SEP #$20");
+    // c += 1 is a single 16-bit INC
+    assert_asm_contains(&asm, "INC $");
+    assert_asm_not_contains(&asm, "STY");
+}
+
+#[test]
+fn reset_enters_native_mode() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+#[reset]
+fn main() {
+    let x: u16 = 0x0102;
+    x = x + 0x0304;
+    OUT = x.high;
+    loop {}
+}
+
+#[irq]
+fn tick() {
+    OUT = 1;
+}
+"#;
+    let output = compile_for_cpu(source, Cpu::W65C816).unwrap();
+    assert_asm_contains(&output.asm, "XCE");
+    let assembly = assemble(&output.asm).unwrap();
+    // Native IRQ vector at $FFEE, emulation vectors still at $FFFA
+    let tick = assembly.symbol("tick").unwrap();
+    let mut sim = Simulator::with_assembly(&assembly);
+    assert_eq!(sim.read_u16(0xFFEE), tick);
+    assert_eq!(sim.read_u16(0xFFFE), tick);
+
+    assert!(matches!(
+        sim.run_from_reset(DEFAULT_CYCLE_LIMIT),
+        Ok(StopReason::Halted { .. })
+    ));
+    assert!(!sim.registers.emulation);
+    assert_eq!(sim.read(0x6000), 0x04);
+
+    sim.registers.set_flag(Registers::INTERRUPT, false);
+    sim.irq();
+    assert!(matches!(
+        sim.run(DEFAULT_CYCLE_LIMIT),
+        Ok(StopReason::Halted { .. })
+    ));
+    assert_eq!(sim.read(0x6000), 1);
+    assert_eq!(sim.registers.sp, 0xFD);
+}
+
+#[test]
+fn long_addresses_need_the_65816() {
+    let source = r#"
+const FAR: addr = 0x123456;
+
+fn main() {
+    FAR = 0xAB;
+}
+"#;
+    let sim = run_for_cpu(source, Cpu::W65C816);
+    assert_eq!(sim.read_long(0x123456), 0xAB);
+    assert_eq!(sim.read(0x3456), 0x00);
+
+    let Err(error) = compile_for_cpu(source, Cpu::Cmos65C02) else {
+        panic!("long address accepted for the 65C02");
+    };
+    assert!(error.contains("must be 0-65535"), "{}", error);
+}
+
+#[test]
+fn locals_live_in_stack_frames() {
+    let source = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const OUT2: addr = 0x6002;
+
+// sum(20) runs 21 levels deep, n = 20 down to 0
+#[recursion(21)]
+fn sum(n: u8) -> u16 {
+    if n == 0 {
+        return 0 as u16;
+    }
+    let here: u16 = n as u16;
+    let rest: u16 = sum(n - 1);
+    let total: u16 = here + rest;
+    return total;
+}
+
+fn mix(a: u8, b: u8) -> u8 {
+    let x: u8 = a + b;
+    let y: u8 = x ^ a;
+    return (x + y) - (a & b);
+}
+
+fn main() {
+    let s: u16 = sum(20);
+    OUT = s.low;
+    OUT1 = s.high;
+    OUT2 = mix(3, 9);
+}
+
+#[reset]
+fn start() {
+    main();
+    loop {}
+}
+"#;
+    let native = run_for_cpu(source, Cpu::W65C816);
+    // 1 + 2 + ... + 20 = 210: each level keeps its own `here`, where in
+    // zero page every level shares one
+    assert_eq!(native.read(0x6000), 210);
+    assert_eq!(native.read(0x6001), 0);
+    assert_eq!(native.read(0x6002), 26);
+    // Every frame is given back
+    let nmos = run_success(source);
+    assert_eq!(native.read(0x6002), nmos.read(0x6002));
+    assert_eq!(native.registers.sp, nmos.registers.sp);
+
+    let asm = compile_for_cpu(source, Cpu::W65C816).unwrap().asm;
+    assert_asm_contains(&asm, "TCS");
+    assert_asm_contains(&asm, "STA $01,S");
+    assert_asm_contains(&asm, "ADC $03,S");
+
+    // The stack analysis charges each level of `sum` its frame
+    let ast = compile_to_ast(source).unwrap();
+    let program = analyze_for_cpu(&ast, Cpu::W65C816).unwrap();
+    let report = analyze(&ast, &program, Cpu::W65C816).unwrap();
+    let start = &report.entries[0];
+    assert_eq!(start.path, ["start", "main", "sum"]);
+
+    let mut sim = Simulator::with_assembly(&assemble(&asm).unwrap());
+    sim.reset();
+    let mut lowest = sim.registers.sp;
+    let stop = sim
+        .run_with(DEFAULT_CYCLE_LIMIT, |sim, _| {
+            lowest = lowest.min(sim.registers.sp)
+        })
+        .unwrap();
+    assert!(matches!(stop, StopReason::Halted { .. }));
+    assert_eq!(sim.read(0x6000), 210);
+    // The bound also counts a PHA the 16-bit add doesn't need here
+    let used = (0xFF - lowest) as u32;
+    assert!(
+        start.depth >= used && start.depth <= used + 1,
+        "reported {}, simulated {}",
+        start.depth,
+        used
+    );
+}
+
+#[test]
+fn inline_locals_join_the_frame_and_handlers_stay_in_zero_page() {
+    let source = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+
+#[inline]
+fn double(n: u8) -> u8 {
+    let twice: u8 = n + n;
+    return twice;
+}
+
+#[recursion(6)]
+fn count(n: u8) -> u8 {
+    if n == 0 {
+        return 0;
+    }
+    let twice: u8 = double(n);
+    let rest: u8 = count(n - 1);
+    return twice + rest;
+}
+
+#[reset]
+fn main() {
+    let total: u8 = count(5);
+    OUT = total;
+    loop {}
+}
+
+#[irq]
+fn tick() {
+    let seen: u8 = OUT1;
+    OUT1 = seen + 1;
+}
+"#;
+    let output = compile_for_cpu(source, Cpu::W65C816).unwrap();
+    // `double` runs inside each activation of `count` and shares its frame
+    let functions: Vec<&str> = output
+        .stack_locals
+        .iter()
+        .map(|(function, _)| function.as_str())
+        .collect();
+    assert_eq!(functions, ["count", "count", "count"]);
+
+    let mut sim = Simulator::with_assembly(&assemble(&output.asm).unwrap());
+    assert!(matches!(
+        sim.run_from_reset(DEFAULT_CYCLE_LIMIT),
+        Ok(StopReason::Halted { .. })
+    ));
+    assert_eq!(sim.read(0x6000), 30);
+}
+
+#[test]
+fn frame_accesses_need_everything_pulled() {
+    let check = |asm: &str| check_accesses("f", &parse_assembly(asm));
+    assert!(check("    LDA $01,S\n    PHA\n    PLA\n    STA $02,S\n").is_ok());
+    // The flags saved around an index register store
+    assert!(check("    PHP\n    XBA\n    TXA\n    STA $02,S\n    XBA\n    PLP\n").is_ok());
+
+    let Err(error) = check("    PHA\n    LDA $01,S\n    PLA\n") else {
+        panic!("access with A pushed accepted");
+    };
+    assert!(error.to_string().contains("with PHA pushed"), "{}", error);
+    assert!(check("    PLA\n    LDA $01,S\n").is_err());
+}
+
+#[test]
+fn word_binary_expressions_use_the_16_bit_accumulator() {
+    let source = r#"
+const OUT: addr = 0x6000;
+const OUT1: addr = 0x6001;
+const OUT2: addr = 0x6002;
+const OUT3: addr = 0x6003;
+
+fn main() {
+    let a: u16 = 0x12F0;
+    let b: u16 = 0x0123;
+    let mask: u16 = 0x0F0F;
+    OUT = ((a + b) & mask).low;
+    OUT1 = ((a - b) ^ mask).high;
+    OUT2 = (a | (b + 0x0100)).high;
+    OUT3 = (a - b).low;
+}
+"#;
+    let sim = run_for_cpu(source, Cpu::W65C816);
+    assert_eq!(sim.read(0x6000), 0x03);
+    assert_eq!(sim.read(0x6001), 0x1E);
+    assert_eq!(sim.read(0x6002), 0x12);
+    assert_eq!(sim.read(0x6003), 0xCD);
+
+    // Each result's high byte comes back from B
+    let asm = compile_for_cpu(source, Cpu::W65C816).unwrap().asm;
+    assert_asm_contains(&asm, "XBA");
+    assert_asm_contains(&asm, "AND $");
+    assert_asm_contains(&asm, "EOR $");
+}
+
+#[test]
+fn consecutive_word_statements_share_one_width_switch() {
+    let asm = compile_for_cpu(PROGRAM, Cpu::W65C816).unwrap().asm;
+    let instructions: Vec<&str> = asm
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .collect();
+    assert!(
+        !instructions
+            .windows(2)
+            .any(|pair| pair == ["SEP #$20", "REP #$20"]),
+        "{}",
+        asm
+    );
+}
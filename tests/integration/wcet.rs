@@ -0,0 +1,121 @@
+//! Worst-case timing tests
+//!
+//! Checks loop bounds, calls and budgets, and that the bound matches the
+//! simulator on code without data-dependent branches.
+
+use crate::common::*;
+use wraith::assembler::assemble;
+use wraith::ast::{SourceMap, Span};
+use wraith::codegen::{CodegenOptions, generate_with_options};
+use wraith::sim::{Simulator, StopReason};
+use wraith::wcet::{WcetAnalyzer, WcetError, timed_functions};
+
+/// Worst case of each named function, plus the errors from checking budgets
+fn analyze(source: &str, names: &[&str]) -> (Vec<Result<u64, WcetError>>, Vec<WcetError>) {
+    let (ast, program) = compile_to_sema(source).unwrap();
+    let output = generate_with_options(&ast, &program, &CodegenOptions::default()).unwrap();
+    let assembly = assemble(&output.asm).unwrap();
+    let sources = SourceMap::new("test.wr", source);
+    let mut analyzer = WcetAnalyzer::new(&output, &assembly, &sources);
+    let results = names.iter().map(|name| analyzer.function(name)).collect();
+    let failures = analyzer.check_budgets(&timed_functions(&ast, &program));
+    (results, failures)
+}
+
+#[test]
+fn constant_for_loop_matches_simulation() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn fill() {
+    for i: u8 in 0..20 {
+        OUT = i;
+    }
+}
+"#;
+    let (results, _) = analyze(source, &["fill"]);
+
+    let mut sim = Simulator::with_assembly(&assemble_success(source));
+    assert_eq!(sim.call("fill", 100_000), Ok(StopReason::Returned));
+    assert_eq!(results[0], Ok(sim.cycles - 6));
+}
+
+#[test]
+fn bounds_calls_and_budgets() {
+    let source = r#"
+const OUT: addr = 0x6000;
+
+fn twice(x: u8) -> u8 {
+    return x + x;
+}
+
+#[irq]
+#[max_cycles(100)]
+fn on_irq() {
+    let n: u8 = OUT;
+    #[bound(10)]
+    while n > 0 {
+        n = n - 1;
+        OUT = twice(n);
+    }
+}
+
+fn forever() {
+    let n: u8 = OUT;
+    while n > 0 {
+        n = n - 1;
+    }
+}
+
+#[reset]
+fn main() {
+    loop {}
+}
+"#;
+    let (results, failures) = analyze(source, &["twice", "on_irq", "forever"]);
+
+    let twice = results[0].clone().unwrap();
+    let on_irq = results[1].clone().unwrap();
+    assert!(on_irq > 10 * (twice + 6));
+    let Err(WcetError::UnboundedLoop {
+        span: Some(span), ..
+    }) = results[2]
+    else {
+        panic!("expected an unbounded loop, got {:?}", results[2]);
+    };
+    assert!(
+        source[span.start..span.end].starts_with("while n > 0"),
+        "{:?}",
+        span
+    );
+
+    let name = source.find("on_irq").unwrap();
+    assert_eq!(
+        failures,
+        vec![WcetError::OverBudget {
+            function: "on_irq".to_string(),
+            cycles: on_irq,
+            budget: 100,
+            span: Some(Span::new(name, name + "on_irq".len())),
+        }]
+    );
+    assert!(
+        failures[0]
+            .to_string()
+            .contains("over its #[max_cycles(100)] budget")
+    );
+}
+
+#[test]
+fn recursion_is_unbounded() {
+    let source = r#"
+fn countdown(n: u8) -> u8 {
+    if n == 0 {
+        return 0;
+    }
+    return countdown(n - 1) + 1;
+}
+"#;
+    let (results, _) = analyze(source, &["countdown"]);
+    assert!(matches!(results[0], Err(WcetError::Recursion { .. })));
+}
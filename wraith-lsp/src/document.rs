@@ -0,0 +1,222 @@
+//! Open documents and their analysis
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+use wraith::ast::{FileId, SourceMap, Span};
+use wraith::config::{Config, MemoryConfig};
+use wraith::diagnostic::Diagnostic;
+use wraith::sema::ProgramInfo;
+use wraith::sema::analyze::SemanticAnalyzer;
+use wraith::{Parser, SourceFile, lex};
+
+/// A parsed and analyzed version of a document
+pub struct Analysis {
+    /// The text that was analyzed; spans are byte offsets into it
+    pub text: String,
+    pub ast: SourceFile,
+    /// Partial when analysis found errors
+    pub info: ProgramInfo,
+}
+
+pub struct Document {
+    pub path: PathBuf,
+    pub text: String,
+    /// The latest version that parsed, which may be older than `text`
+    pub analysis: Option<Analysis>,
+    /// Errors and warnings in `text` and the modules it imports
+    pub diagnostics: Vec<Diagnostic>,
+    /// `text` and the modules it imports, for the spans of `diagnostics`
+    pub sources: SourceMap,
+}
+
+impl Document {
+    pub fn new(path: PathBuf, text: String) -> Self {
+        let mut document = Self {
+            path,
+            text: String::new(),
+            analysis: None,
+            diagnostics: Vec::new(),
+            sources: SourceMap::default(),
+        };
+        document.update(text);
+        document
+    }
+
+    /// Replace the text and analyze it again
+    pub fn update(&mut self, text: String) {
+        self.text = text;
+        self.diagnostics.clear();
+        self.sources = SourceMap::new(self.path.display().to_string(), self.text.as_str());
+
+        let tokens = match lex(&self.text) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                self.diagnostics.push(Diagnostic::from_lex_error(&e));
+                return;
+            }
+        };
+        let ast = match Parser::parse(&tokens) {
+            Ok(ast) => ast,
+            Err(e) => {
+                self.diagnostics.extend(Diagnostic::from_parse_error(&e));
+                return;
+            }
+        };
+
+        let mut analyzer = SemanticAnalyzer::with_base_path(self.path.clone())
+            .with_memory_config(memory_config(&self.path));
+        analyzer.sources = std::mem::take(&mut self.sources);
+        let result = analyzer.analyze_all(&ast);
+        self.sources = std::mem::take(&mut analyzer.sources);
+        let info = match result {
+            Ok(info) => {
+                self.diagnostics
+                    .extend(info.warnings.iter().map(Diagnostic::from_warning));
+                info
+            }
+            Err(diagnostics) => {
+                self.diagnostics
+                    .extend(diagnostics.warnings.iter().map(Diagnostic::from_warning));
+                self.diagnostics
+                    .extend(diagnostics.errors.iter().map(|error| {
+                        let file = error.span().map_or(FileId::ROOT, |span| span.file);
+                        Diagnostic::from_sema_error(error, self.sources.source(file))
+                    }));
+                analyzer.program_info()
+            }
+        };
+        self.analysis = Some(Analysis {
+            text: self.text.clone(),
+            ast,
+            info,
+        });
+    }
+
+    /// The analysis, if it is of the current text
+    pub fn current(&self) -> Option<&Analysis> {
+        self.analysis
+            .as_ref()
+            .filter(|analysis| analysis.text == self.text)
+    }
+}
+
+/// The `wraith.toml` in the file's directory or the nearest one above it
+fn memory_config(path: &Path) -> MemoryConfig {
+    let config = path
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("wraith.toml"))
+        .find(|file| file.exists())
+        .and_then(|file| Config::from_file(file).ok())
+        .unwrap_or_default();
+    MemoryConfig::from_config(config)
+}
+
+/// Byte offset of an LSP position, whose column counts UTF-16 code units
+pub fn offset(text: &str, line: u64, character: u64) -> usize {
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(newline) => start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (index, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + index;
+        }
+        units += c.len_utf16() as u64;
+    }
+    text.len()
+}
+
+/// LSP position of a byte offset
+pub fn position(text: &str, offset: usize) -> Value {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+pub fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// `file://` URI of a path
+pub fn uri_from_path(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Path of a `file://` URI
+pub fn path_from_uri(uri: &str) -> PathBuf {
+    let encoded = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "let a = 1;\n// é😀 x\nend";
+        let x = text.find('x').unwrap();
+        assert_eq!(position(text, x), json!({"line": 1, "character": 7}));
+        assert_eq!(offset(text, 1, 7), x);
+        assert_eq!(offset(text, 0, 99), text.find('\n').unwrap());
+        assert_eq!(offset(text, 9, 0), text.len());
+    }
+
+    #[test]
+    fn uris_round_trip() {
+        let path = PathBuf::from("/home/me/my game/main.wr");
+        let uri = uri_from_path(&path);
+        assert_eq!(uri, "file:///home/me/my%20game/main.wr");
+        assert_eq!(path_from_uri(&uri), path);
+    }
+
+    #[test]
+    fn keeps_the_last_analysis_while_the_text_does_not_parse() {
+        let mut document = Document::new(
+            PathBuf::from("main.wr"),
+            "fn main() {\n    let x: u8 = 1;\n}\n".to_string(),
+        );
+        assert!(document.current().is_some());
+
+        document.update("fn main() {\n    let x: u8 = \n}\n".to_string());
+        assert!(document.current().is_none());
+        assert!(document.analysis.is_some());
+        assert_eq!(document.diagnostics[0].code, "unexpected-token");
+    }
+}
@@ -0,0 +1,812 @@
+//! Hover, go-to-definition, completion and document symbols
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+use wraith::ast::{EnumVariant, FileId, Item, Pattern, Span, Stmt};
+use wraith::sema::ProgramInfo;
+use wraith::sema::analyze::SemanticAnalyzer;
+use wraith::sema::table::{SymbolInfo, SymbolKind, SymbolLocation};
+use wraith::sema::type_defs::{FieldInfo, VariantData, VariantInfo};
+use wraith::sema::types::Type;
+use wraith::{Parser, SourceFile, lex};
+
+use crate::document::{Analysis, range};
+
+// LSP `CompletionItemKind` and `SymbolKind` values
+const COMPLETION_FIELD: u32 = 5;
+const COMPLETION_ENUM_MEMBER: u32 = 20;
+const SYMBOL_ENUM: u32 = 10;
+const SYMBOL_FIELD: u32 = 8;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_CONSTANT: u32 = 14;
+const SYMBOL_STRUCT: u32 = 23;
+const SYMBOL_ENUM_MEMBER: u32 = 22;
+
+/// Markdown describing the identifier or expression at `offset`, and the
+/// span it covers
+pub fn hover(analysis: &Analysis, offset: usize) -> Option<(String, Span)> {
+    let text = analysis.text.as_str();
+    let info = &analysis.info;
+    let Some(span) = word_at(text, offset) else {
+        // Not on an identifier: the type of the innermost expression
+        let (span, ty) = info
+            .resolved_types
+            .iter()
+            .filter(|(span, _)| span.start <= offset && offset < span.end)
+            .min_by_key(|(span, _)| span.end - span.start)?;
+        return Some((code_block(&ty.display_name()), *span));
+    };
+    let word = &text[span.start..span.end];
+
+    if let Some(symbol) = info.resolved_symbols.get(&span)
+        && symbol.name == word
+    {
+        return Some((describe_symbol(analysis, symbol), span));
+    }
+    if let Some((parent, field)) = member_field(analysis, text, span) {
+        let markdown = format!(
+            "{}\nfield of `{}` at offset {}",
+            code_block(&format!("{}: {}", field.name, field.ty.display_name())),
+            parent,
+            field.offset
+        );
+        return Some((markdown, span));
+    }
+    if let Some((parent, variant)) = enum_variant(info, text, span) {
+        let markdown = format!(
+            "{}\nvariant of `{}` with tag {}",
+            code_block(&variant_signature(variant)),
+            parent.name,
+            variant.tag
+        );
+        return Some((markdown, span));
+    }
+    if let Some(symbol) = info.table.lookup(word) {
+        return Some((describe_symbol(analysis, symbol), span));
+    }
+    describe_type(info, word).map(|markdown| (markdown, span))
+}
+
+/// The file and span declaring the identifier at `offset`
+pub fn definition(analysis: &Analysis, path: &Path, offset: usize) -> Option<(PathBuf, Value)> {
+    let text = analysis.text.as_str();
+    let span = word_at(text, offset)?;
+    let word = &text[span.start..span.end];
+
+    let target = if let Some((parent, _)) = member_field(analysis, text, span) {
+        Target::Member { parent, name: word }
+    } else if let Some((parent, _)) = enum_variant(&analysis.info, text, span) {
+        Target::Member {
+            parent: &parent.name,
+            name: word,
+        }
+    } else {
+        if let Some(local) = local_declaration(&analysis.ast, word, offset) {
+            return Some((path.to_path_buf(), range(text, local)));
+        }
+        Target::Item(word)
+    };
+    locate(path, text, &analysis.ast, target, &mut HashSet::new())
+}
+
+/// Struct fields after `.` and enum variants after `::`
+pub fn completion(analysis: &Analysis, text: &str, offset: usize) -> Vec<Value> {
+    let info = &analysis.info;
+    let start = word_start(text, offset);
+    let before = &text[..start];
+
+    if let Some(receiver) = before.strip_suffix('.') {
+        let chain = receiver_chain(text, receiver.len());
+        let Some(Type::Named(name)) = chain_type(analysis, &chain, offset) else {
+            return Vec::new();
+        };
+        let Some(def) = info.type_registry.get_struct(&name) else {
+            return Vec::new();
+        };
+        return def
+            .fields
+            .iter()
+            .map(|field| {
+                json!({
+                    "label": field.name,
+                    "kind": COMPLETION_FIELD,
+                    "detail": field.ty.display_name(),
+                })
+            })
+            .collect();
+    }
+    if let Some(path) = before.strip_suffix("::") {
+        let name = &text[word_start(text, path.len())..path.len()];
+        let Some(def) = info.type_registry.get_enum(name) else {
+            return Vec::new();
+        };
+        return def
+            .variants
+            .iter()
+            .map(|variant| {
+                json!({
+                    "label": variant.name,
+                    "kind": COMPLETION_ENUM_MEMBER,
+                    "detail": variant_signature(variant),
+                })
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+/// Top-level items, with struct fields and enum variants as children
+pub fn document_symbols(analysis: &Analysis) -> Vec<Value> {
+    let text = analysis.text.as_str();
+    let info = &analysis.info;
+    let symbol = |name: &str, detail: String, kind: u32, span: Span, name_span: Span| {
+        json!({
+            "name": name,
+            "detail": detail,
+            "kind": kind,
+            "range": range(text, span),
+            "selectionRange": range(text, name_span),
+        })
+    };
+    let type_of = |name: &str| {
+        info.table
+            .lookup(name)
+            .map_or(String::new(), |symbol| symbol.ty.display_name())
+    };
+
+    analysis
+        .ast
+        .items
+        .iter()
+        .filter_map(|item| match &item.node {
+            Item::Function(function) => Some(symbol(
+                &function.name.node,
+                type_of(&function.name.node),
+                SYMBOL_FUNCTION,
+                item.span,
+                function.name.span,
+            )),
+            Item::Static(stat) => Some(symbol(
+                &stat.name.node,
+                type_of(&stat.name.node),
+                SYMBOL_CONSTANT,
+                item.span,
+                stat.name.span,
+            )),
+            Item::Address(addr) => Some(symbol(
+                &addr.name.node,
+                "addr".to_string(),
+                SYMBOL_CONSTANT,
+                item.span,
+                addr.name.span,
+            )),
+            Item::Struct(s) => {
+                let def = info.type_registry.get_struct(&s.name.node);
+                let fields: Vec<Value> = s
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let ty = def
+                            .and_then(|def| def.get_field(&field.name.node))
+                            .map_or(String::new(), |field| field.ty.display_name());
+                        symbol(
+                            &field.name.node,
+                            ty,
+                            SYMBOL_FIELD,
+                            field.name.span.merge(field.ty.span),
+                            field.name.span,
+                        )
+                    })
+                    .collect();
+                let mut value = symbol(
+                    &s.name.node,
+                    "struct".to_string(),
+                    SYMBOL_STRUCT,
+                    item.span,
+                    s.name.span,
+                );
+                value["children"] = fields.into();
+                Some(value)
+            }
+            Item::Enum(e) => {
+                let variants: Vec<Value> = e
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let name = variant_name(variant);
+                        symbol(
+                            &name.node,
+                            String::new(),
+                            SYMBOL_ENUM_MEMBER,
+                            name.span,
+                            name.span,
+                        )
+                    })
+                    .collect();
+                let mut value = symbol(
+                    &e.name.node,
+                    "enum".to_string(),
+                    SYMBOL_ENUM,
+                    item.span,
+                    e.name.span,
+                );
+                value["children"] = variants.into();
+                Some(value)
+            }
+            Item::Import(_) => None,
+        })
+        .collect()
+}
+
+fn code_block(code: &str) -> String {
+    format!("```wraith\n{}\n```", code)
+}
+
+fn describe_symbol(analysis: &Analysis, symbol: &SymbolInfo) -> String {
+    let name = &symbol.name;
+    let ty = symbol.ty.display_name();
+    let declaration = match symbol.kind {
+        SymbolKind::Variable if symbol.containing_function.is_some() => {
+            format!("let {}: {}", name, ty)
+        }
+        SymbolKind::Variable => format!("{}: {}", name, ty),
+        SymbolKind::Constant => format!("const {}: {}", name, ty),
+        SymbolKind::Address => {
+            let access = match symbol.access_mode {
+                Some(wraith::ast::AccessMode::Read) => "read ",
+                Some(wraith::ast::AccessMode::Write) => "write ",
+                _ => "",
+            };
+            format!("const {}: {}addr", name, access)
+        }
+        SymbolKind::Function => function_signature(analysis, symbol),
+        SymbolKind::Type => {
+            return describe_type(&analysis.info, name).unwrap_or_else(|| code_block(name));
+        }
+    };
+
+    let location = match symbol.location {
+        SymbolLocation::ZeroPage(address) => Some(format!("zero page `${:02X}`", address)),
+        SymbolLocation::Absolute(address) if symbol.kind == SymbolKind::Address => {
+            Some(format!("absolute `${:04X}`", address))
+        }
+        SymbolLocation::Long(address) => Some(format!("long `${:06X}`", address)),
+        _ => None,
+    };
+    match location {
+        Some(location) => format!("{}\n{}", code_block(&declaration), location),
+        None => code_block(&declaration),
+    }
+}
+
+/// `fn name(a: u8, b: u8) -> u8`, with parameter names when the function is
+/// declared in this file
+fn function_signature(analysis: &Analysis, symbol: &SymbolInfo) -> String {
+    let Type::Function(params, ret) = &symbol.ty else {
+        return format!("fn {}", symbol.name);
+    };
+    let names: Vec<&str> = analysis
+        .ast
+        .items
+        .iter()
+        .find_map(|item| match &item.node {
+            Item::Function(function) if function.name.node == symbol.name => Some(
+                function
+                    .params
+                    .iter()
+                    .map(|param| param.name.node.as_str())
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let params: Vec<String> = params
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| match names.get(i) {
+            Some(name) => format!("{}: {}", name, ty.display_name()),
+            None => ty.display_name(),
+        })
+        .collect();
+    match ret.as_ref() {
+        Type::Void => format!("fn {}({})", symbol.name, params.join(", ")),
+        ret => format!(
+            "fn {}({}) -> {}",
+            symbol.name,
+            params.join(", "),
+            ret.display_name()
+        ),
+    }
+}
+
+/// A struct or enum definition from the type registry
+fn describe_type(info: &ProgramInfo, name: &str) -> Option<String> {
+    if let Some(def) = info.type_registry.get_struct(name) {
+        let fields: String = def
+            .fields
+            .iter()
+            .map(|field| format!("    {}: {},\n", field.name, field.ty.display_name()))
+            .collect();
+        let declaration = format!("struct {} {{\n{}}}", name, fields);
+        return Some(format!(
+            "{}\n{} bytes",
+            code_block(&declaration),
+            def.total_size
+        ));
+    }
+    let def = info.type_registry.get_enum(name)?;
+    let variants: String = def
+        .variants
+        .iter()
+        .map(|variant| format!("    {},\n", variant_signature(variant)))
+        .collect();
+    let declaration = format!("enum {} {{\n{}}}", name, variants);
+    Some(format!(
+        "{}\n{} bytes",
+        code_block(&declaration),
+        def.total_size
+    ))
+}
+
+fn variant_signature(variant: &VariantInfo) -> String {
+    match &variant.data {
+        VariantData::Unit => variant.name.clone(),
+        VariantData::Tuple(types) => {
+            let types: Vec<String> = types.iter().map(Type::display_name).collect();
+            format!("{}({})", variant.name, types.join(", "))
+        }
+        VariantData::Struct(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name, field.ty.display_name()))
+                .collect();
+            format!("{} {{ {} }}", variant.name, fields.join(", "))
+        }
+    }
+}
+
+fn variant_name(variant: &EnumVariant) -> &wraith::Spanned<String> {
+    match variant {
+        EnumVariant::Unit { name, .. }
+        | EnumVariant::Tuple { name, .. }
+        | EnumVariant::Struct { name, .. } => name,
+    }
+}
+
+fn is_ident_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Start of the identifier ending at `offset`
+fn word_start(text: &str, offset: usize) -> usize {
+    let bytes = text.as_bytes();
+    let mut start = offset.min(bytes.len());
+    while start > 0 && is_ident_byte(bytes[start - 1]) {
+        start -= 1;
+    }
+    start
+}
+
+/// The identifier touching `offset`
+fn word_at(text: &str, offset: usize) -> Option<Span> {
+    let bytes = text.as_bytes();
+    let start = word_start(text, offset);
+    let mut end = offset.min(bytes.len());
+    while end < bytes.len() && is_ident_byte(bytes[end]) {
+        end += 1;
+    }
+    let starts_like_ident = bytes.get(start).is_some_and(|b| !b.is_ascii_digit());
+    (start < end && starts_like_ident).then(|| Span::new(start, end))
+}
+
+/// `a.b.c` before the `.` at `dot`, as `["a", "b", "c"]`
+fn receiver_chain(text: &str, dot: usize) -> Vec<&str> {
+    let mut chain = Vec::new();
+    let mut end = dot;
+    loop {
+        let start = word_start(text, end);
+        if start == end {
+            return Vec::new();
+        }
+        chain.push(&text[start..end]);
+        if start > 0 && text.as_bytes()[start - 1] == b'.' {
+            end = start - 1;
+        } else {
+            break;
+        }
+    }
+    chain.reverse();
+    chain
+}
+
+/// Type of a variable followed by field accesses
+fn chain_type(analysis: &Analysis, chain: &[&str], offset: usize) -> Option<Type> {
+    let (first, fields) = chain.split_first()?;
+    let mut ty = variable_type(analysis, first, offset)?;
+    for field in fields {
+        ty = struct_field(&analysis.info, &ty, field)?.ty.clone();
+    }
+    Some(ty)
+}
+
+/// Type of the variable `name` as seen from `offset`: the nearest earlier
+/// use or declaration in the enclosing function, else a global
+fn variable_type(analysis: &Analysis, name: &str, offset: usize) -> Option<Type> {
+    let function = analysis.ast.items.iter().find_map(|item| match &item.node {
+        Item::Function(function) if item.span.start <= offset && offset <= item.span.end => {
+            Some(function.name.node.as_str())
+        }
+        _ => None,
+    });
+    let local = analysis
+        .info
+        .resolved_symbols
+        .iter()
+        .filter(|(span, symbol)| {
+            symbol.name == name
+                && symbol.containing_function.as_deref() == function
+                && span.file == FileId::ROOT
+                && span.start <= offset
+        })
+        .max_by_key(|(span, _)| span.start);
+    match local {
+        Some((_, symbol)) => Some(symbol.ty.clone()),
+        None => analysis
+            .info
+            .table
+            .lookup(name)
+            .map(|symbol| symbol.ty.clone()),
+    }
+}
+
+fn struct_field<'a>(info: &'a ProgramInfo, ty: &Type, field: &str) -> Option<&'a FieldInfo> {
+    let Type::Named(name) = ty else {
+        return None;
+    };
+    info.type_registry.get_struct(name)?.get_field(field)
+}
+
+/// The struct and field named by `span` when it follows a `.`
+fn member_field<'a>(
+    analysis: &'a Analysis,
+    text: &str,
+    span: Span,
+) -> Option<(&'a str, &'a FieldInfo)> {
+    let receiver = text[..span.start].strip_suffix('.')?;
+    let chain = receiver_chain(text, receiver.len());
+    let Type::Named(parent) = chain_type(analysis, &chain, span.start)? else {
+        return None;
+    };
+    let def = analysis.info.type_registry.structs.get_key_value(&parent)?;
+    Some((
+        def.0.as_str(),
+        def.1.get_field(&text[span.start..span.end])?,
+    ))
+}
+
+/// The enum and variant named by `span` when it follows `Enum::`
+fn enum_variant<'a>(
+    info: &'a ProgramInfo,
+    text: &str,
+    span: Span,
+) -> Option<(&'a wraith::sema::type_defs::EnumDef, &'a VariantInfo)> {
+    let path = text[..span.start].strip_suffix("::")?;
+    let def = info
+        .type_registry
+        .get_enum(&text[word_start(text, path.len())..path.len()])?;
+    Some((def, def.get_variant(&text[span.start..span.end])?))
+}
+
+/// A top-level item, or a field or variant of one
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Item(&'a str),
+    Member { parent: &'a str, name: &'a str },
+}
+
+impl Target<'_> {
+    /// The name an `import` has to list to bring the target in
+    fn item_name(&self) -> &str {
+        match self {
+            Target::Item(name) | Target::Member { parent: name, .. } => name,
+        }
+    }
+}
+
+/// Find the target in this file or, through its imports, in another one
+fn locate(
+    path: &Path,
+    text: &str,
+    ast: &SourceFile,
+    target: Target,
+    visited: &mut HashSet<PathBuf>,
+) -> Option<(PathBuf, Value)> {
+    if !visited.insert(path.to_path_buf()) {
+        return None;
+    }
+    if let Some(span) = declaration(ast, target) {
+        return Some((path.to_path_buf(), range(text, span)));
+    }
+
+    let resolver = SemanticAnalyzer::with_base_path(path.to_path_buf());
+    ast.items.iter().find_map(|item| {
+        let Item::Import(import) = &item.node else {
+            return None;
+        };
+        if !import
+            .symbols
+            .iter()
+            .any(|symbol| symbol.node == target.item_name())
+        {
+            return None;
+        }
+        let imported = resolver.resolve_import_path(&import.path.node);
+        let text = fs::read_to_string(&imported).ok()?;
+        let ast = Parser::parse(&lex(&text).ok()?).ok()?;
+        locate(&imported, &text, &ast, target, visited)
+    })
+}
+
+fn declaration(ast: &SourceFile, target: Target) -> Option<Span> {
+    ast.items
+        .iter()
+        .find_map(|item| match (&item.node, target) {
+            (Item::Function(function), Target::Item(name)) if function.name.node == name => {
+                Some(function.name.span)
+            }
+            (Item::Static(stat), Target::Item(name)) if stat.name.node == name => {
+                Some(stat.name.span)
+            }
+            (Item::Address(addr), Target::Item(name)) if addr.name.node == name => {
+                Some(addr.name.span)
+            }
+            (Item::Struct(s), Target::Item(name)) if s.name.node == name => Some(s.name.span),
+            (Item::Enum(e), Target::Item(name)) if e.name.node == name => Some(e.name.span),
+            (Item::Struct(s), Target::Member { parent, name }) if s.name.node == parent => s
+                .fields
+                .iter()
+                .find(|field| field.name.node == name)
+                .map(|field| field.name.span),
+            (Item::Enum(e), Target::Member { parent, name }) if e.name.node == parent => e
+                .variants
+                .iter()
+                .map(variant_name)
+                .find(|variant| variant.node == name)
+                .map(|variant| variant.span),
+            _ => None,
+        })
+}
+
+/// The declaration of a local `name` in scope at `offset`
+fn local_declaration(ast: &SourceFile, name: &str, offset: usize) -> Option<Span> {
+    let function = ast.items.iter().find_map(|item| match &item.node {
+        Item::Function(function) if item.span.start <= offset && offset <= item.span.end => {
+            Some(function)
+        }
+        _ => None,
+    })?;
+    let mut locals: Vec<&wraith::Spanned<String>> =
+        function.params.iter().map(|param| &param.name).collect();
+    collect_locals(&function.body, offset, &mut locals);
+    locals
+        .iter()
+        .rev()
+        .find(|local| local.node == name)
+        .map(|local| local.span)
+}
+
+/// Push the variables declared in `stmt` that are visible at `offset`, in
+/// declaration order
+fn collect_locals<'a>(
+    stmt: &'a wraith::Spanned<Stmt>,
+    offset: usize,
+    locals: &mut Vec<&'a wraith::Spanned<String>>,
+) {
+    let inside = |span: Span| span.start <= offset && offset <= span.end;
+    match &stmt.node {
+        Stmt::VarDecl { name, .. } if stmt.span.end <= offset || inside(name.span) => {
+            locals.push(name)
+        }
+        Stmt::Block(stmts) if inside(stmt.span) => {
+            for stmt in stmts {
+                collect_locals(stmt, offset, locals);
+            }
+        }
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            collect_locals(then_branch, offset, locals);
+            if let Some(else_branch) = else_branch {
+                collect_locals(else_branch, offset, locals);
+            }
+        }
+        Stmt::While { body, .. } | Stmt::Loop { body, .. } => collect_locals(body, offset, locals),
+        Stmt::For { var_name, body, .. } => {
+            if inside(stmt.span) {
+                locals.push(var_name);
+            }
+            collect_locals(body, offset, locals);
+        }
+        Stmt::ForEach {
+            var_name,
+            index_var,
+            body,
+            ..
+        } => {
+            if inside(stmt.span) {
+                locals.extend(index_var);
+                locals.push(var_name);
+            }
+            collect_locals(body, offset, locals);
+        }
+        Stmt::Match { arms, .. } => {
+            for arm in arms {
+                if !inside(arm.pattern.span.merge(arm.body.span)) {
+                    continue;
+                }
+                if let Pattern::EnumVariant { bindings, .. } = &arm.pattern.node {
+                    locals.extend(bindings.iter().map(|binding| &binding.name));
+                }
+                collect_locals(&arm.body, offset, locals);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    const SOURCE: &str = r#"const OUT: write addr = 0x6000;
+
+struct Point {
+    x: u8,
+    y: u8,
+}
+
+enum Dir {
+    North,
+    Move(u8),
+}
+
+fn shift(p: Point, by: u8) -> u8 {
+    let sum: u8 = p.x + by;
+    return sum;
+}
+
+fn main() {
+    let p: Point = Point { x: 1, y: 2 };
+    let d: Dir = Dir::North;
+    OUT = shift(p, p.y);
+}
+"#;
+
+    fn analysis(source: &str) -> Analysis {
+        let document = Document::new(PathBuf::from("main.wr"), source.to_string());
+        assert!(
+            document
+                .diagnostics
+                .iter()
+                .all(|d| d.severity == wraith::diagnostic::Severity::Warning),
+            "{:?}",
+            document.diagnostics
+        );
+        document.analysis.unwrap()
+    }
+
+    fn at(source: &str, needle: &str) -> usize {
+        source.find(needle).unwrap()
+    }
+
+    #[test]
+    fn hover_shows_types_and_locations() {
+        let analysis = analysis(SOURCE);
+        let (markdown, _) = hover(&analysis, at(SOURCE, "sum;")).unwrap();
+        assert!(markdown.contains("let sum: u8"), "{}", markdown);
+        assert!(markdown.contains("zero page `$"), "{}", markdown);
+
+        let (markdown, span) = hover(&analysis, at(SOURCE, "OUT =") + 1).unwrap();
+        assert!(markdown.contains("const OUT: write addr"), "{}", markdown);
+        assert!(markdown.contains("absolute `$6000`"), "{}", markdown);
+        assert_eq!(&SOURCE[span.start..span.end], "OUT");
+
+        let (markdown, _) = hover(&analysis, at(SOURCE, "shift(p,")).unwrap();
+        assert!(
+            markdown.contains("fn shift(p: Point, by: u8) -> u8"),
+            "{}",
+            markdown
+        );
+
+        let (markdown, _) = hover(&analysis, at(SOURCE, "y);")).unwrap();
+        assert!(markdown.contains("y: u8"), "{}", markdown);
+        assert!(
+            markdown.contains("field of `Point` at offset 1"),
+            "{}",
+            markdown
+        );
+
+        let (markdown, _) = hover(&analysis, at(SOURCE, "Dir = ")).unwrap();
+        assert!(markdown.contains("Move(u8)"), "{}", markdown);
+    }
+
+    #[test]
+    fn definitions_of_locals_members_and_items() {
+        let analysis = analysis(SOURCE);
+        let path = Path::new("main.wr");
+        let line = |offset: usize| {
+            let (_, range) = definition(&analysis, path, offset).unwrap();
+            range["start"]["line"].as_u64().unwrap()
+        };
+        assert_eq!(line(at(SOURCE, "sum;")), 13);
+        assert_eq!(line(at(SOURCE, "shift(p,") + 6), 18);
+        assert_eq!(line(at(SOURCE, "p.y") + 2), 4);
+        assert_eq!(line(at(SOURCE, "North;")), 8);
+        assert_eq!(line(at(SOURCE, "shift(p,")), 12);
+    }
+
+    #[test]
+    fn definitions_follow_imports() {
+        let dir = std::env::temp_dir().join(format!("wraith-lsp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("lib.wr"),
+            "pub struct Pair {\n    a: u8,\n    b: u8,\n}\n\npub fn twice(x: u8) -> u8 {\n    return x + x;\n}\n",
+        )
+        .unwrap();
+        let source = "import { Pair, twice } from \"./lib.wr\";\n\nfn main() {\n    let p: Pair = Pair { a: 1, b: 2 };\n    let t: u8 = twice(p.b);\n}\n";
+        let path = dir.join("main.wr");
+        let document = Document::new(path.clone(), source.to_string());
+        let analysis = document.analysis.unwrap();
+
+        let (file, range) = definition(&analysis, &path, at(source, "twice(p")).unwrap();
+        assert_eq!(file, dir.join("./lib.wr"));
+        assert_eq!(range["start"], json!({"line": 5, "character": 7}));
+
+        let (_, range) = definition(&analysis, &path, at(source, "b);")).unwrap();
+        assert_eq!(range["start"], json!({"line": 2, "character": 4}));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn completes_fields_and_variants() {
+        let analysis = analysis(SOURCE);
+        let labels = |text: &str, offset: usize| {
+            completion(&analysis, text, offset)
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let edited = SOURCE.replace("OUT = shift(p, p.y);", "OUT = p.");
+        assert_eq!(labels(&edited, at(&edited, "p.\n") + 2), ["x", "y"]);
+        let edited = SOURCE.replace("OUT = shift(p, p.y);", "let e: Dir = Dir::M");
+        assert_eq!(
+            labels(&edited, at(&edited, "Dir::M") + 6),
+            ["North", "Move"]
+        );
+        assert!(labels(SOURCE, at(SOURCE, "0x6000")).is_empty());
+    }
+
+    #[test]
+    fn lists_document_symbols() {
+        let analysis = analysis(SOURCE);
+        let symbols = document_symbols(&analysis);
+        let names: Vec<&str> = symbols
+            .iter()
+            .map(|symbol| symbol["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["OUT", "Point", "Dir", "shift", "main"]);
+        assert_eq!(symbols[1]["children"][1]["name"], "y");
+        assert_eq!(symbols[1]["children"][1]["detail"], "u8");
+        assert_eq!(symbols[2]["children"][1]["name"], "Move");
+        assert_eq!(symbols[3]["detail"], "fn(Point, u8) -> u8");
+    }
+}
@@ -0,0 +1,369 @@
+//! Wraith language server
+//!
+//! Speaks the Language Server Protocol over stdin and stdout, reusing the
+//! compiler's lexer, parser and semantic analysis for diagnostics, hover,
+//! go-to-definition, completion and document symbols.
+
+mod document;
+mod features;
+mod protocol;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process;
+
+use serde_json::{Value, json};
+use wraith::ast::{FileId, SourceMap, Span};
+use wraith::diagnostic::{Diagnostic, Severity};
+
+use document::{Document, path_from_uri, range, uri_from_path};
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Default)]
+struct Server {
+    /// Open documents by URI
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl Server {
+    /// Handle one message, returning the response and any notifications
+    fn handle(&mut self, message: Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+
+        if self.shutdown {
+            return vec![error(id, INVALID_REQUEST, "server is shut down")];
+        }
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": { "triggerCharacters": [".", ":"] },
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "wraith-lsp", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/hover" => self.hover(params).unwrap_or(Value::Null),
+            "textDocument/definition" => self.definition(params).unwrap_or(Value::Null),
+            "textDocument/completion" => self.completion(params).unwrap_or(json!([])),
+            "textDocument/documentSymbol" => self.document_symbols(params).unwrap_or(json!([])),
+            _ => {
+                return vec![error(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("unknown method {}", method),
+                )];
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "exit" => {
+                self.exit = true;
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                let document = Document::new(path_from_uri(uri), text.to_string());
+                self.documents.insert(uri.to_string(), document);
+                self.publish(uri).into_iter().collect()
+            }
+            "textDocument/didChange" => {
+                // Full sync: the last change holds the whole text
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+                if let (Some(document), Some(text)) = (self.documents.get_mut(uri), text) {
+                    document.update(text.to_string());
+                }
+                self.publish(uri).into_iter().collect()
+            }
+            "textDocument/didSave" => {
+                // Other open documents may import the saved file
+                for document in self.documents.values_mut() {
+                    document.update(document.text.clone());
+                }
+                self.documents
+                    .keys()
+                    .filter_map(|uri| self.publish(uri))
+                    .collect()
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![publish_diagnostics(uri, Vec::new())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn publish(&self, uri: &str) -> Option<Value> {
+        let document = self.documents.get(uri)?;
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(uri, &document.sources, diagnostic))
+            .collect();
+        Some(publish_diagnostics(uri, diagnostics))
+    }
+
+    /// The document named in the request and the byte offset of its position
+    fn position(&self, params: &Value) -> Option<(&Document, usize)> {
+        let document = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let line = params["position"]["line"].as_u64()?;
+        let character = params["position"]["character"].as_u64()?;
+        Some((document, document::offset(&document.text, line, character)))
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (document, offset) = self.position(params)?;
+        let analysis = document.current()?;
+        let (markdown, span) = features::hover(analysis, offset)?;
+        Some(json!({
+            "contents": { "kind": "markdown", "value": markdown },
+            "range": range(&analysis.text, span),
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (document, offset) = self.position(params)?;
+        let analysis = document.current()?;
+        let (path, range) = features::definition(analysis, &document.path, offset)?;
+        Some(json!({ "uri": uri_from_path(&path), "range": range }))
+    }
+
+    fn completion(&self, params: &Value) -> Option<Value> {
+        let (document, offset) = self.position(params)?;
+        // The text being typed rarely parses, so complete from the last
+        // version that did
+        let analysis = document.analysis.as_ref()?;
+        Some(features::completion(analysis, &document.text, offset).into())
+    }
+
+    fn document_symbols(&self, params: &Value) -> Option<Value> {
+        let document = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        Some(features::document_symbols(document.current()?).into())
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// A diagnostic for the document at `uri`; one inside an imported module is
+/// shown on the document's import of it, with the real location attached
+fn lsp_diagnostic(uri: &str, sources: &SourceMap, diagnostic: &Diagnostic) -> Value {
+    let location = |span: Span| {
+        let uri = if span.file == FileId::ROOT {
+            uri.to_string()
+        } else {
+            uri_from_path(Path::new(sources.name(span.file)))
+        };
+        json!({ "uri": uri, "range": range(sources.source(span.file), span) })
+    };
+
+    let mut message = diagnostic.message.clone();
+    if let Some(primary) = &diagnostic.primary
+        && primary.message != diagnostic.message
+    {
+        message = format!("{}: {}", message, primary.message);
+    }
+    for note in &diagnostic.notes {
+        message = format!("{}\n{}", message, note);
+    }
+    let mut related: Vec<Value> = diagnostic
+        .secondary
+        .iter()
+        .map(|label| json!({ "location": location(label.span), "message": label.message }))
+        .collect();
+
+    let mut span = diagnostic
+        .primary
+        .as_ref()
+        .map_or(Span::new(0, 0), |primary| primary.span);
+    if let Some(&import) = sources.import_chain(span.file).last() {
+        message = format!("in {}: {}", sources.format_location(span), message);
+        related.insert(
+            0,
+            json!({ "location": location(span), "message": diagnostic.message }),
+        );
+        span = import;
+    }
+    json!({
+        "range": range(sources.source(FileId::ROOT), span),
+        "severity": match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        },
+        "code": diagnostic.code,
+        "source": "wraith",
+        "message": message,
+        "relatedInformation": related,
+    })
+}
+
+/// Answer messages until `exit` or the end of input
+///
+/// A message that isn't valid JSON gets a parse error reply and the server
+/// reads on; only failing to read or write the stream ends it early.
+fn serve(server: &mut Server, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    while !server.exit {
+        let replies = match protocol::read_message(input) {
+            Ok(Some(message)) => server.handle(message),
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                vec![error(Value::Null, PARSE_ERROR, &e.to_string())]
+            }
+            Err(e) => return Err(e),
+        };
+        for reply in replies {
+            protocol::write_message(output, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut output = io::stdout().lock();
+    let mut server = Server::default();
+
+    if let Err(e) = serve(&mut server, &mut input, &mut output) {
+        eprintln!("wraith-lsp: {}", e);
+        process::exit(1);
+    }
+    process::exit(if server.shutdown { 0 } else { 1 });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(server: &mut Server, uri: &str, text: &str) -> Vec<Value> {
+        server.handle(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "wraith", "version": 1, "text": text } },
+        }))
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = Server::default();
+        let uri = "file:///tmp/main.wr";
+        let replies = open(
+            &mut server,
+            uri,
+            "fn main() {\n    let flag: bool = 1;\n}\n",
+        );
+        let params = &replies[0]["params"];
+        assert_eq!(params["uri"], uri);
+        let diagnostic = &params["diagnostics"][0];
+        assert_eq!(diagnostic["code"], "type-mismatch");
+        assert_eq!(diagnostic["severity"], 1);
+        assert_eq!(
+            diagnostic["message"],
+            "type mismatch: expected bool, found u8"
+        );
+        assert_eq!(
+            diagnostic["range"]["start"],
+            json!({"line": 1, "character": 21})
+        );
+
+        let replies = server.handle(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didClose",
+            "params": { "textDocument": { "uri": uri } },
+        }));
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn answers_requests_until_shutdown() {
+        let mut server = Server::default();
+        let reply = &server
+            .handle(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}))[0];
+        assert_eq!(reply["result"]["capabilities"]["hoverProvider"], true);
+
+        let uri = "file:///tmp/main.wr";
+        open(&mut server, uri, "fn main() {\n    let x: u8 = 1;\n}\n");
+        let reply = &server.handle(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/hover",
+            "params": { "textDocument": { "uri": uri }, "position": { "line": 1, "character": 8 } },
+        }))[0];
+        assert!(
+            reply["result"]["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("let x: u8"),
+            "{}",
+            reply
+        );
+
+        let reply =
+            &server.handle(json!({"jsonrpc": "2.0", "id": 3, "method": "workspace/symbol"}))[0];
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+
+        server.handle(json!({"jsonrpc": "2.0", "id": 4, "method": "shutdown"}));
+        let reply =
+            &server.handle(json!({"jsonrpc": "2.0", "id": 5, "method": "textDocument/hover"}))[0];
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        server.handle(json!({"jsonrpc": "2.0", "method": "exit"}));
+        assert!(server.exit);
+    }
+    #[test]
+    fn replies_to_malformed_messages_and_reads_on() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"Content-Length: 9\r\n\r\n{\"id\": 1,");
+        protocol::write_message(
+            &mut input,
+            &json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+        )
+        .unwrap();
+        protocol::write_message(&mut input, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+
+        let mut server = Server::default();
+        let mut output = Vec::new();
+        serve(&mut server, &mut io::Cursor::new(input), &mut output).unwrap();
+        assert!(server.shutdown && server.exit);
+
+        let mut output = io::Cursor::new(output);
+        let reply = protocol::read_message(&mut output).unwrap().unwrap();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+        let reply = protocol::read_message(&mut output).unwrap().unwrap();
+        assert_eq!(reply["id"], 2);
+        assert_eq!(reply["result"], Value::Null);
+    }
+}
@@ -0,0 +1,78 @@
+//! JSON-RPC framing
+//!
+//! Language server messages are JSON bodies preceded by a `Content-Length`
+//! header and a blank line.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Read one message, or `None` at end of input
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trips_messages() {
+        let mut buffer = Vec::new();
+        let first = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"});
+        let second = json!({"jsonrpc": "2.0", "method": "exit"});
+        write_message(&mut buffer, &first).unwrap();
+        write_message(&mut buffer, &second).unwrap();
+
+        let mut input = io::Cursor::new(buffer);
+        assert_eq!(read_message(&mut input).unwrap(), Some(first));
+        assert_eq!(read_message(&mut input).unwrap(), Some(second));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn ignores_other_headers() {
+        let body = r#"{"id":2}"#;
+        let message = format!(
+            "Content-Type: application/vscode-jsonrpc; charset=utf-8\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut input = io::Cursor::new(message.into_bytes());
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"id": 2})));
+    }
+}